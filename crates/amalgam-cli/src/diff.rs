@@ -0,0 +1,108 @@
+//! Schema diff between two versions of a package
+
+use amalgam_core::diff::{diff_ir, DiffReport};
+use amalgam_core::IR;
+use amalgam_parser::{
+    crd::{CRDParser, CRD},
+    Parser,
+};
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::fs;
+use std::path::Path;
+
+/// Load an IR from a file or directory
+///
+/// Files may be IR JSON documents or CRD YAML/JSON (possibly multi-document).
+/// Directories are walked recursively and every schema found is merged.
+pub fn load_ir(path: &Path) -> Result<IR> {
+    if path.is_dir() {
+        let mut files: Vec<_> = walkdir::WalkDir::new(path)
+            .into_iter()
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_type().is_file())
+            .map(|e| e.into_path())
+            .filter(|p| {
+                p.extension()
+                    .is_some_and(|ext| ext == "json" || ext == "yaml" || ext == "yml")
+            })
+            .collect();
+        files.sort();
+
+        let mut ir = IR::new();
+        for file in files {
+            ir = ir.merge(load_ir_file(&file)?);
+        }
+        Ok(ir)
+    } else {
+        load_ir_file(path)
+    }
+}
+
+fn load_ir_file(path: &Path) -> Result<IR> {
+    let content = fs::read_to_string(path).with_context(|| format!("Failed to read {:?}", path))?;
+
+    // JSON that is not a CRD must be an IR bundle; its errors, such as an
+    // unsupported format version, are reported rather than read as no types
+    if let Ok(value) = serde_json::from_str::<serde_json::Value>(&content) {
+        if value.get("kind").and_then(|k| k.as_str()) != Some("CustomResourceDefinition") {
+            return IR::from_json(&content)
+                .with_context(|| format!("Invalid IR bundle {:?}", path));
+        }
+    }
+
+    // Otherwise treat the file as one or more CRD documents
+    let parser = CRDParser::new();
+    let mut ir = IR::new();
+    for document in serde_yaml::Deserializer::from_str(&content) {
+        let value = serde_yaml::Value::deserialize(document)
+            .with_context(|| format!("Failed to parse {:?}", path))?;
+        if value.get("kind").and_then(|k| k.as_str()) != Some("CustomResourceDefinition") {
+            continue;
+        }
+        let crd: CRD =
+            serde_yaml::from_value(value).with_context(|| format!("Invalid CRD in {:?}", path))?;
+        ir = ir.merge(parser.parse(crd.with_source(path.display().to_string()))?);
+    }
+    Ok(ir)
+}
+
+/// Compare two schema sets and print the report
+///
+/// Returns the report so the caller can decide whether to fail on breaking changes.
+pub fn run_diff(old: &Path, new: &Path, format: &str) -> Result<DiffReport> {
+    let old_ir = load_ir(old)?;
+    let new_ir = load_ir(new)?;
+    let report = diff_ir(&old_ir, &new_ir);
+
+    match format {
+        "json" => println!("{}", serde_json::to_string_pretty(&report)?),
+        "text" => print_report(&report),
+        _ => anyhow::bail!("Unsupported diff format: {}", format),
+    }
+
+    Ok(report)
+}
+
+fn print_report(report: &DiffReport) {
+    if report.is_empty() {
+        println!("No schema changes detected");
+        return;
+    }
+
+    let breaking: Vec<_> = report.breaking().collect();
+    if !breaking.is_empty() {
+        println!("✗ {} breaking changes:", breaking.len());
+        for change in breaking {
+            println!("  - {}: {}", change.path, change.description);
+        }
+    }
+
+    let non_breaking: Vec<_> = report.non_breaking().collect();
+    if !non_breaking.is_empty() {
+        println!("✓ {} non-breaking changes:", non_breaking.len());
+        for change in non_breaking {
+            println!("  - {}: {}", change.path, change.description);
+        }
+    }
+}
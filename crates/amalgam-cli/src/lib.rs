@@ -1,15 +1,25 @@
 //! Library interface for amalgam CLI components
 
+pub mod diff;
 pub mod manifest;
+pub mod render;
 pub mod validate;
 mod vendor;
 
-use amalgam_codegen::nickel::NickelCodegen;
+use amalgam_codegen::nickel::{EnumMode, NickelCodegen};
+use amalgam_codegen::nickel_render::{lib_module, ResourceKind, LIB_FILE};
 use amalgam_codegen::Codegen;
+use amalgam_core::visit::{references, rename_references};
+use amalgam_parser::imports::{K8sLayout, TypeReference};
+use amalgam_parser::input::{input_definition, resource_api_version};
 use amalgam_parser::k8s_types::K8sTypesFetcher;
-use anyhow::Result;
+use amalgam_parser::olm::OlmExample;
+use amalgam_parser::package::NamespacedPackage;
+use amalgam_parser::transforms::TransformRule;
+use anyhow::{Context, Result};
+use std::collections::BTreeMap;
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use tracing::info;
 
 fn is_core_k8s_type(name: &str) -> bool {
@@ -49,83 +59,113 @@ fn is_unversioned_k8s_type(name: &str) -> bool {
     )
 }
 
-fn collect_type_references(
-    ty: &amalgam_core::types::Type,
-    refs: &mut std::collections::HashSet<String>,
-) {
-    use amalgam_core::types::Type;
+/// Read transform rules from a TOML file of `[[transforms]]` tables,
+/// written like the manifest's `[[config.transforms]]`
+pub fn read_transforms(path: &Path) -> Result<Vec<TransformRule>> {
+    #[derive(serde::Deserialize)]
+    struct TransformsFile {
+        #[serde(default)]
+        transforms: Vec<TransformRule>,
+    }
+
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read transforms file {:?}", path))?;
+    let file: TransformsFile =
+        toml::from_str(&content).with_context(|| format!("Invalid transforms file {:?}", path))?;
+    Ok(file.transforms)
+}
+
+/// Write an IR bundle to disk for later use with `amalgam generate`
+pub fn write_ir_bundle(ir: &amalgam_core::IR, path: &Path) -> Result<()> {
+    ir.save_to_file(path)
+        .map_err(|e| anyhow::anyhow!("Failed to write IR bundle {:?}: {}", path, e))?;
+    info!("Wrote IR bundle to {:?}", path);
+    Ok(())
+}
+
+/// Write a generated CRD package as group/version/kind files under `output`
+pub fn write_crd_package(package: &NamespacedPackage, output: &Path) -> Result<()> {
+    fs::create_dir_all(output)?;
+
+    // Write main module file and rendering helpers
+    fs::write(output.join("mod.ncl"), package.generate_main_module())?;
+    fs::write(output.join(LIB_FILE), package.generate_lib_module())?;
+
+    // Create group/version/kind structure
+    for group in package.groups() {
+        let group_dir = output.join(&group);
+        fs::create_dir_all(&group_dir)?;
 
-    match ty {
-        Type::Reference(name) => {
-            refs.insert(name.clone());
+        if let Some(group_mod) = package.generate_group_module(&group) {
+            fs::write(group_dir.join("mod.ncl"), group_mod)?;
         }
-        Type::Array(inner) => collect_type_references(inner, refs),
-        Type::Optional(inner) => collect_type_references(inner, refs),
-        Type::Map { value, .. } => collect_type_references(value, refs),
-        Type::Record { fields, .. } => {
-            for field in fields.values() {
-                collect_type_references(&field.ty, refs);
-            }
+
+        if let Some(conversions) = package.generate_conversions_module(&group) {
+            fs::write(group_dir.join("conversions.ncl"), conversions)?;
         }
-        Type::Union(types) => {
-            for t in types {
-                collect_type_references(t, refs);
+
+        for version in package.versions(&group) {
+            let version_dir = group_dir.join(&version);
+            fs::create_dir_all(&version_dir)?;
+
+            if let Some(version_mod) = package.generate_version_module(&group, &version) {
+                fs::write(version_dir.join("mod.ncl"), version_mod)?;
             }
-        }
-        Type::TaggedUnion { variants, .. } => {
-            for t in variants.values() {
-                collect_type_references(t, refs);
+
+            for kind in package.kinds(&group, &version) {
+                if let Some(kind_content) = package.generate_kind_file(&group, &version, &kind) {
+                    fs::write(version_dir.join(format!("{}.ncl", kind)), kind_content)?;
+                }
             }
         }
-        Type::Contract { base, .. } => collect_type_references(base, refs),
-        _ => {}
     }
-}
 
-fn apply_type_replacements(
-    ty: &mut amalgam_core::types::Type,
-    replacements: &std::collections::HashMap<String, String>,
-) {
-    use amalgam_core::types::Type;
+    Ok(())
+}
 
-    match ty {
-        Type::Reference(name) => {
-            if let Some(replacement) = replacements.get(name) {
-                *name = replacement.clone();
-            }
+/// Write OLM examples under `group/version/examples/` of a generated CRD package
+///
+/// Examples whose kind was not generated, e.g. because a filter left it out,
+/// are skipped. Returns the number of examples written.
+pub fn write_olm_examples(examples: &[OlmExample], output: &Path) -> Result<usize> {
+    let mut written = 0;
+    let mut stems: BTreeMap<PathBuf, usize> = BTreeMap::new();
+    for example in examples {
+        let version_dir = output.join(&example.group).join(&example.version);
+        if !version_dir
+            .join(format!("{}.ncl", example.kind.to_lowercase()))
+            .exists()
+        {
+            continue;
         }
-        Type::Array(inner) => apply_type_replacements(inner, replacements),
-        Type::Optional(inner) => apply_type_replacements(inner, replacements),
-        Type::Map { value, .. } => apply_type_replacements(value, replacements),
-        Type::Record { fields, .. } => {
-            for field in fields.values_mut() {
-                apply_type_replacements(&mut field.ty, replacements);
-            }
-        }
-        Type::Union(types) => {
-            for t in types {
-                apply_type_replacements(t, replacements);
-            }
-        }
-        Type::TaggedUnion { variants, .. } => {
-            for t in variants.values_mut() {
-                apply_type_replacements(t, replacements);
-            }
+
+        let examples_dir = version_dir.join("examples");
+        fs::create_dir_all(&examples_dir)?;
+        let mut path = examples_dir.join(format!("{}.ncl", example.file_stem()));
+        // Examples sharing a name are numbered
+        let seen = stems.entry(path.clone()).or_default();
+        *seen += 1;
+        if *seen > 1 {
+            path = examples_dir.join(format!("{}-{}.ncl", example.file_stem(), seen));
         }
-        Type::Contract { base, .. } => apply_type_replacements(base, replacements),
-        _ => {}
+        fs::write(&path, example.to_nickel())?;
+        written += 1;
     }
+    Ok(written)
 }
 
+/// Import the Kubernetes core types and return the IR that was generated
 pub async fn handle_k8s_core_import(
     version: &str,
     output_dir: &Path,
     nickel_package: bool,
-) -> Result<()> {
+    layout: K8sLayout,
+    enum_mode: EnumMode,
+) -> Result<amalgam_core::IR> {
     info!("Fetching Kubernetes {} core types...", version);
 
     // Create fetcher
-    let fetcher = K8sTypesFetcher::new();
+    let fetcher = K8sTypesFetcher::new()?;
 
     // Fetch the OpenAPI schema
     let openapi = fetcher.fetch_k8s_openapi(version).await?;
@@ -133,159 +173,247 @@ pub async fn handle_k8s_core_import(
     // Extract core types
     let types = fetcher.extract_core_types(&openapi)?;
 
+    write_k8s_core_package(
+        types,
+        version,
+        output_dir,
+        nickel_package,
+        layout,
+        enum_mode,
+    )
+}
+
+/// Write extracted Kubernetes core types as a Nickel package
+///
+/// With [`K8sLayout::Flat`] every API group shares one directory per version.
+/// With [`K8sLayout::Grouped`] each API group gets its own `group/version/`
+/// directory, and the root module keeps a per-version index so that
+/// `k8s.v1.Pod` still resolves as it does with the flat layout.
+pub fn write_k8s_core_package(
+    types: std::collections::HashMap<TypeReference, amalgam_core::ir::TypeDefinition>,
+    version: &str,
+    output_dir: &Path,
+    nickel_package: bool,
+    layout: K8sLayout,
+    enum_mode: EnumMode,
+) -> Result<amalgam_core::IR> {
     let total_types = types.len();
     info!("Extracted {} core types", total_types);
 
-    // Group types by version
-    let mut types_by_version: std::collections::HashMap<
-        String,
-        Vec<(
-            amalgam_parser::imports::TypeReference,
-            amalgam_core::ir::TypeDefinition,
-        )>,
-    > = std::collections::HashMap::new();
+    // Group types by the directory they are written to
+    let mut types_by_dir: K8sTypesByDir = BTreeMap::new();
 
     for (type_ref, type_def) in types {
-        types_by_version
-            .entry(type_ref.version.clone())
+        let dir = layout.module_dir(
+            type_ref.k8s_api_group().unwrap_or("core"),
+            &type_ref.version,
+        );
+        types_by_dir
+            .entry(dir)
             .or_default()
             .push((type_ref, type_def));
     }
+    for dir_types in types_by_dir.values_mut() {
+        dir_types.sort_by(|(a, _), (b, _)| a.kind.cmp(&b.kind));
+    }
 
-    // Generate files for each version
-    for (version, version_types) in &types_by_version {
-        let version_dir = output_dir.join(version);
-        fs::create_dir_all(&version_dir)?;
+    // Directory holding a kind referenced from `dir`, if it was generated
+    let locate = |dir: &Vec<String>, version: &str, kind: &str| -> Option<Vec<String>> {
+        let has_kind = |d: &Vec<String>| {
+            types_by_dir
+                .get(d)
+                .is_some_and(|types| types.iter().any(|(tr, _)| tr.kind == kind))
+        };
+        if has_kind(dir) {
+            return Some(dir.clone());
+        }
+        // Other groups of the same version, core first
+        let mut candidates: Vec<&Vec<String>> = types_by_dir
+            .keys()
+            .filter(|d| d.last().map(String::as_str) == Some(version) && has_kind(d))
+            .collect();
+        candidates.sort_by_key(|d| d.first().map(String::as_str) != Some("core"));
+        if let Some(found) = candidates.first() {
+            return Some((*found).clone());
+        }
+        let fallback = if is_core_k8s_type(kind) {
+            // Common core types are usually in v1 even when referenced from other versions
+            layout.module_dir("meta", "v1")
+        } else if is_unversioned_k8s_type(kind) {
+            // Unversioned types (like RawExtension) are placed in the v0 directory
+            layout.module_dir("core", "v0")
+        } else {
+            return None;
+        };
+        (&fallback != dir).then_some(fallback)
+    };
+
+    // Every generated module is also collected into an IR bundle
+    let mut bundle = amalgam_core::IR::new();
+    // Resource kinds indexed by the rendering helpers
+    let mut resource_kinds = Vec::new();
+
+    // Generate files for each directory
+    for (dir, dir_types) in &types_by_dir {
+        let module_dir = dir.iter().fold(output_dir.to_path_buf(), |p, d| p.join(d));
+        fs::create_dir_all(&module_dir)?;
 
         let mut mod_imports = Vec::new();
 
         // Generate each type in its own file
-        for (type_ref, type_def) in version_types {
-            // Check if this type references other types in the same version
+        for (type_ref, type_def) in dir_types {
             let mut imports = Vec::new();
             let mut type_replacements = std::collections::HashMap::new();
 
-            // Collect any references to other types in the same module
-            let mut referenced_types = std::collections::HashSet::new();
-            collect_type_references(&type_def.ty, &mut referenced_types);
+            // Collect any references to other types
+            let referenced_types = references(&type_def.ty);
 
-            // For each referenced type, check if it exists in the same version
             for referenced in &referenced_types {
-                // Check if this is a simple type name (not a full path)
-                if !referenced.contains('.') && referenced != &type_ref.kind {
-                    // Check if this type exists in the same version
-                    if version_types.iter().any(|(tr, _)| tr.kind == *referenced) {
-                        // Add import for the type in the same directory
-                        let alias = referenced.to_lowercase();
-                        imports.push(amalgam_core::ir::Import {
-                            path: format!("./{}.ncl", alias),
-                            alias: Some(alias.clone()),
-                            items: vec![referenced.clone()],
-                        });
-
-                        // Store replacement: ManagedFieldsEntry -> managedfieldsentry.ManagedFieldsEntry
-                        type_replacements
-                            .insert(referenced.clone(), format!("{}.{}", alias, referenced));
-                    } else if is_core_k8s_type(referenced) {
-                        // Check if this is a core k8s type that should be imported from v1
-                        // Common core types are usually in v1 even when referenced from other versions
-                        let source_version = "v1";
-                        if version != source_version {
-                            // Import from v1 directory
-                            let alias = referenced; // Use the actual type name as alias
-                            imports.push(amalgam_core::ir::Import {
-                                path: format!(
-                                    "../{}/{}.ncl",
-                                    source_version,
-                                    referenced.to_lowercase()
-                                ),
-                                alias: Some(alias.to_string()),
-                                items: vec![],
-                            });
-
-                            // Store replacement: Type remains as Type (e.g., ObjectMeta remains as ObjectMeta)
-                            // No need to qualify since we're importing with the same name
-                        }
-                    } else if is_unversioned_k8s_type(referenced) {
-                        // Check if this is an unversioned k8s type (like RawExtension)
-                        // These types are placed in v0 directory
-                        let source_version = "v0";
-                        if version != source_version {
-                            // Import from v0 directory
-                            let alias = referenced; // Use the actual type name as alias
-                            imports.push(amalgam_core::ir::Import {
-                                path: format!(
-                                    "../{}/{}.ncl",
-                                    source_version,
-                                    referenced.to_lowercase()
-                                ),
-                                alias: Some(alias.to_string()),
-                                items: vec![],
-                            });
-                        }
-                    }
+                // Only simple type names (not full paths) are resolved here
+                if referenced.contains('.') || referenced == &type_ref.kind {
+                    continue;
+                }
+                let Some(target) = locate(dir, &type_ref.version, referenced) else {
+                    continue;
+                };
+                let filename = referenced.to_lowercase();
+
+                if &target == dir {
+                    // Add import for the type in the same directory
+                    imports.push(amalgam_core::ir::Import {
+                        path: format!("./{}.ncl", filename),
+                        alias: Some(filename.clone()),
+                        items: vec![referenced.clone()],
+                    });
+
+                    // Store replacement: ManagedFieldsEntry -> managedfieldsentry.ManagedFieldsEntry
+                    type_replacements
+                        .insert(referenced.clone(), format!("{}.{}", filename, referenced));
+                } else {
+                    // Import from the other directory under the type's own name,
+                    // e.g. ObjectMeta remains as ObjectMeta
+                    imports.push(amalgam_core::ir::Import {
+                        path: format!(
+                            "{}{}/{}.ncl",
+                            "../".repeat(dir.len()),
+                            target.join("/"),
+                            filename
+                        ),
+                        alias: Some(referenced.clone()),
+                        items: vec![],
+                    });
                 }
             }
 
-            // Apply type replacements to the type definition
+            // Apply type replacements to the type definition and to the
+            // input variant of resources
             let mut updated_type_def = type_def.clone();
-            apply_type_replacements(&mut updated_type_def.ty, &type_replacements);
+            rename_references(&mut updated_type_def.ty, |name| {
+                type_replacements.get(name).cloned()
+            });
+            let input = input_definition(type_def).map(|mut input| {
+                rename_references(&mut input.ty, |name| type_replacements.get(name).cloned());
+                input
+            });
 
             // Create a module with the type and its imports
+            let module_prefix = match layout {
+                K8sLayout::Flat => "k8s.io",
+                K8sLayout::Grouped => type_ref.group.as_str(),
+            };
             let module = amalgam_core::ir::Module {
                 name: format!(
-                    "k8s.io.{}.{}",
+                    "{}.{}.{}",
+                    module_prefix,
                     type_ref.version,
                     type_ref.kind.to_lowercase()
                 ),
                 imports,
-                types: vec![updated_type_def],
+                types: std::iter::once(updated_type_def)
+                    .chain(input.clone())
+                    .collect(),
                 constants: vec![],
                 metadata: Default::default(),
             };
 
             // Create IR with the module
             let mut ir = amalgam_core::IR::new();
-            ir.add_module(module);
+            ir.add_module(module.clone());
+            bundle.add_module(module);
 
             // Generate Nickel code
-            let mut codegen = NickelCodegen::new();
+            let mut codegen = NickelCodegen::new().with_enum_mode(enum_mode);
             let code = codegen.generate(&ir)?;
 
             // Write to file
             let filename = format!("{}.ncl", type_ref.kind.to_lowercase());
-            let file_path = version_dir.join(&filename);
+            let file_path = module_dir.join(&filename);
             fs::write(&file_path, code)?;
 
             info!("Generated {:?}", file_path);
 
-            // Add to module imports
+            // Add to module imports, with the input variant and constructor
+            // of resources
             mod_imports.push(format!(
                 "  {} = (import \"./{}\").{},",
                 type_ref.kind, filename, type_ref.kind
             ));
+            let contract = input.as_ref().map_or(&type_ref.kind, |input| &input.name);
+            if let Some(input) = &input {
+                mod_imports.push(format!(
+                    "  {} = (import \"./{}\").{},",
+                    input.name, filename, input.name
+                ));
+            }
+
+            if let Some(api_version) = resource_api_version(type_def) {
+                resource_kinds.push(ResourceKind {
+                    api_version: api_version.to_string(),
+                    kind: type_ref.kind.clone(),
+                    contract: format!("(import \"./{}/{}\").{}", dir.join("/"), filename, contract),
+                });
+                mod_imports.push(format!(
+                    "  mk_{} = {},",
+                    type_ref.kind,
+                    NickelCodegen::new().constructor(api_version, &type_ref.kind, contract, 1)
+                ));
+            }
         }
 
         // Generate mod.ncl for this version
         let mod_content = format!(
-            "# Kubernetes core {} types\n{{\n{}\n}}\n",
-            version,
+            "# Kubernetes {} types\n{{\n{}\n}}\n",
+            dir.join("/"),
             mod_imports.join("\n")
         );
-        fs::write(version_dir.join("mod.ncl"), mod_content)?;
-    }
-
-    // Generate top-level mod.ncl with all versions
-    let mut version_imports = Vec::new();
-    for version in types_by_version.keys() {
-        version_imports.push(format!("  {} = import \"./{}/mod.ncl\",", version, version));
+        fs::write(module_dir.join("mod.ncl"), mod_content)?;
     }
 
-    let root_mod_content = format!(
-        "# Kubernetes core types\n{{\n{}\n}}\n",
-        version_imports.join("\n")
-    );
+    // Generate top-level mod.ncl
+    let root_mod_content = match layout {
+        K8sLayout::Flat => {
+            let version_imports: Vec<String> = types_by_dir
+                .keys()
+                .map(|dir| format!("  {} = import \"./{}/mod.ncl\",", dir[0], dir[0]))
+                .collect();
+            format!(
+                "# Kubernetes core types\n{{\n{}\n}}\n",
+                version_imports.join("\n")
+            )
+        }
+        K8sLayout::Grouped => {
+            write_k8s_group_modules(&types_by_dir, output_dir)?;
+            k8s_compat_index(&types_by_dir)
+        }
+    };
     fs::write(output_dir.join("mod.ncl"), root_mod_content)?;
+    fs::write(
+        output_dir.join(LIB_FILE),
+        lib_module(
+            &format!("Kubernetes {} core types", version),
+            &resource_kinds,
+        ),
+    )?;
 
     // Generate Nickel package manifest if requested
     if nickel_package {
@@ -310,10 +438,13 @@ pub async fn handle_k8s_core_import(
         let generator = NickelPackageGenerator::new(config);
 
         // Convert types to modules for manifest generation
-        let modules: Vec<amalgam_core::ir::Module> = types_by_version
+        let modules: Vec<amalgam_core::ir::Module> = types_by_dir
             .keys()
-            .map(|ver| amalgam_core::ir::Module {
-                name: ver.clone(),
+            .map(|dir| dir[0].clone())
+            .collect::<std::collections::BTreeSet<_>>()
+            .into_iter()
+            .map(|name| amalgam_core::ir::Module {
+                name,
                 imports: Vec::new(),
                 types: Vec::new(),
                 constants: Vec::new(),
@@ -336,5 +467,80 @@ pub async fn handle_k8s_core_import(
     if nickel_package {
         info!("  with Nickel package manifest");
     }
+    Ok(bundle)
+}
+
+/// Types of the k8s-core package keyed by the directory they are written to
+type K8sTypesByDir = BTreeMap<Vec<String>, Vec<(TypeReference, amalgam_core::ir::TypeDefinition)>>;
+
+/// Write one `mod.ncl` per API group listing its versions
+fn write_k8s_group_modules(types_by_dir: &K8sTypesByDir, output_dir: &Path) -> Result<()> {
+    let mut versions_by_group: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+    for dir in types_by_dir.keys() {
+        versions_by_group
+            .entry(dir[0].as_str())
+            .or_default()
+            .push(dir[1].as_str());
+    }
+
+    for (group, versions) in versions_by_group {
+        let entries: Vec<String> = versions
+            .iter()
+            .map(|v| format!("  {} = import \"./{}/mod.ncl\",", v, v))
+            .collect();
+        fs::write(
+            output_dir.join(group).join("mod.ncl"),
+            format!(
+                "# Kubernetes {} API group\n{{\n{}\n}}\n",
+                group,
+                entries.join("\n")
+            ),
+        )?;
+    }
     Ok(())
 }
+
+/// Root module of the grouped layout
+///
+/// Lists every API group, plus one record per version with the kinds of all
+/// groups, as the flat layout exposes them. When several groups define the
+/// same kind in a version, the core group wins, then the first group
+/// alphabetically; the others stay reachable through their group.
+fn k8s_compat_index(types_by_dir: &K8sTypesByDir) -> String {
+    let groups: std::collections::BTreeSet<&str> =
+        types_by_dir.keys().map(|dir| dir[0].as_str()).collect();
+
+    let mut dirs: Vec<&Vec<String>> = types_by_dir.keys().collect();
+    dirs.sort_by_key(|dir| (dir[0] != "core", dir[0].clone()));
+
+    let mut by_version: BTreeMap<&str, BTreeMap<&str, String>> = BTreeMap::new();
+    for dir in dirs {
+        let kinds = by_version.entry(dir[1].as_str()).or_default();
+        for (type_ref, _) in &types_by_dir[dir] {
+            kinds.entry(type_ref.kind.as_str()).or_insert_with(|| {
+                format!(
+                    "(import \"./{}/{}.ncl\").{}",
+                    dir.join("/"),
+                    type_ref.kind.to_lowercase(),
+                    type_ref.kind
+                )
+            });
+        }
+    }
+
+    let mut lines = Vec::new();
+    for group in groups {
+        lines.push(format!("  {} = import \"./{}/mod.ncl\",", group, group));
+    }
+    lines.push(String::new());
+    lines.push("  # Per-version index, compatible with the flat layout".to_string());
+    for (version, kinds) in by_version {
+        lines.push(format!("  {} = {{", version));
+        for (kind, path) in kinds {
+            lines.push(format!("    {} = {},", kind, path));
+        }
+        lines.push("  },".to_string());
+    }
+
+    format!("# Kubernetes core types\n{{\n{}\n}}\n", lines.join("\n"))
+}
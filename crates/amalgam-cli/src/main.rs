@@ -1,17 +1,27 @@
 use anyhow::{Context, Result};
-use clap::{Parser, Subcommand};
+use clap::{Args, Parser, Subcommand};
 use std::fs;
 use std::path::PathBuf;
-use tracing::info;
+use tracing::{info, warn};
 
-use amalgam_codegen::{go::GoCodegen, nickel::NickelCodegen, Codegen};
+use amalgam_codegen::{
+    go::GoCodegen,
+    nickel::{EnumMode, NickelCodegen},
+    Codegen,
+};
 use amalgam_parser::{
     crd::{CRDParser, CRD},
+    http::HttpClient,
+    imports::K8sLayout,
+    oci::{OciClient, OciReference},
     openapi::OpenAPIParser,
+    transforms::apply_transforms,
     Parser as SchemaParser,
 };
 
+mod diff;
 mod manifest;
+mod render;
 mod validate;
 mod vendor;
 
@@ -42,17 +52,21 @@ enum Commands {
 
     /// Generate code from IR
     Generate {
-        /// Input IR file (JSON format)
+        /// Input IR bundle (JSON format, as written by --emit-ir)
         #[arg(short, long)]
         input: PathBuf,
 
-        /// Output file path
+        /// Output file path, or a directory to write one file per module
         #[arg(short, long)]
         output: PathBuf,
 
         /// Target language
         #[arg(short, long, default_value = "nickel")]
         target: String,
+
+        /// Comment types and fields with where they were parsed from
+        #[arg(long)]
+        source_comments: bool,
     },
 
     /// Convert from one format to another
@@ -95,6 +109,48 @@ enum Commands {
         verbose: bool,
     },
 
+    /// Compare two versions of a schema and report breaking changes
+    Diff {
+        /// Old schema (IR JSON or CRD file, or a directory of them)
+        old: PathBuf,
+
+        /// New schema (IR JSON or CRD file, or a directory of them)
+        new: PathBuf,
+
+        /// Output format (text, json)
+        #[arg(short, long, default_value = "text")]
+        format: String,
+
+        /// Exit with an error if any breaking change is found
+        #[arg(long)]
+        fail_on_breaking: bool,
+    },
+
+    /// Render the Kubernetes resources of a Nickel file as multi-document YAML
+    Render {
+        /// Nickel file holding the resources, anywhere in nested records and arrays
+        file: PathBuf,
+
+        /// Generated package whose lib.ncl checks resources of its kinds (repeatable)
+        #[arg(short, long)]
+        package: Vec<PathBuf>,
+
+        /// Write the YAML to this file instead of stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+
+    /// Push a generated package to an OCI registry
+    Push {
+        /// Package directory
+        #[arg(short, long)]
+        path: PathBuf,
+
+        /// Target reference (e.g. ghcr.io/org/pkgs/widgets:v1.0.0)
+        #[arg(short, long)]
+        reference: String,
+    },
+
     /// Generate packages from a manifest file
     GenerateFromManifest {
         /// Path to the manifest file (TOML format)
@@ -108,6 +164,10 @@ enum Commands {
         /// Dry run - show what would be generated without doing it
         #[arg(long)]
         dry_run: bool,
+
+        /// Number of packages to generate concurrently
+        #[arg(short, long, default_value_t = 4)]
+        jobs: usize,
     },
 }
 
@@ -126,6 +186,25 @@ enum ImportSource {
         /// Generate as submittable package (with package imports)
         #[arg(long)]
         package_mode: bool,
+
+        /// Leave out versions the CRD marks as deprecated
+        #[arg(long)]
+        skip_deprecated: bool,
+
+        /// Also write the parsed IR bundle (JSON) to this path
+        #[arg(long)]
+        emit_ir: Option<PathBuf>,
+
+        /// Comment types and fields with their location in the CRD
+        #[arg(long)]
+        source_comments: bool,
+
+        /// Enums as checked strings ("strings") or as enum tags ("tags")
+        #[arg(long, default_value = "strings")]
+        enum_mode: EnumMode,
+        /// TOML file of [[transforms]] rules to apply to the generated types
+        #[arg(long)]
+        transforms: Option<PathBuf>,
     },
 
     /// Import CRDs from URL (GitHub repo, directory, or direct file)
@@ -142,9 +221,102 @@ enum ImportSource {
         #[arg(short, long)]
         package: Option<String>,
 
-        /// Generate Nickel package manifest (experimental)
+        #[command(flatten)]
+        options: CrdPackageOptions,
+    },
+
+    /// Import CRDs from a local clone of a git repository
+    Git {
+        /// Repository URL (https, ssh, or file://)
+        #[arg(short, long)]
+        repo: String,
+
+        /// Branch, tag, or commit to check out (defaults to the remote HEAD)
+        #[arg(long = "ref")]
+        git_ref: Option<String>,
+
+        /// Globs selecting CRD files in the repository (defaults to all YAML files)
+        #[arg(long = "path")]
+        paths: Vec<String>,
+
+        /// Output directory for package
+        #[arg(short, long)]
+        output: PathBuf,
+
+        /// Package name (defaults to the repository name)
+        #[arg(short, long)]
+        package: Option<String>,
+
+        /// Directory to keep clones in
         #[arg(long)]
-        nickel_package: bool,
+        cache_dir: Option<PathBuf>,
+
+        #[command(flatten)]
+        options: CrdPackageOptions,
+    },
+
+    /// Import CRDs from a Helm chart directory or packaged .tgz
+    ///
+    /// CRDs are read from the chart's crds/ directory and from templates that
+    /// are static YAML apart from conditionals; templates are not rendered.
+    Helm {
+        /// Chart directory or .tgz archive
+        #[arg(short, long)]
+        chart: PathBuf,
+
+        /// Output directory for package
+        #[arg(short, long)]
+        output: PathBuf,
+
+        /// Package name (defaults to the chart name)
+        #[arg(short, long)]
+        package: Option<String>,
+
+        #[command(flatten)]
+        options: CrdPackageOptions,
+    },
+
+    /// Import the owned CRDs of an OLM bundle directory
+    ///
+    /// Field docs are filled in from the CSV's spec and status descriptors,
+    /// and its alm-examples are written as example Nickel files.
+    Olm {
+        /// Bundle directory (with manifests/ and metadata/)
+        #[arg(short, long)]
+        bundle: PathBuf,
+
+        /// Output directory for package
+        #[arg(short, long)]
+        output: PathBuf,
+
+        /// Package name (defaults to the bundle's operator package)
+        #[arg(short, long)]
+        package: Option<String>,
+
+        #[command(flatten)]
+        options: CrdPackageOptions,
+    },
+
+    /// Import CRDs from the layers of an OCI image or artifact
+    Oci {
+        /// Image reference (e.g. ghcr.io/org/crds:v1.2.0; http:// for plain-HTTP registries)
+        #[arg(short, long)]
+        reference: String,
+
+        /// Globs selecting CRD files in the layers (defaults to all YAML files)
+        #[arg(long = "path")]
+        paths: Vec<String>,
+
+        /// Output directory for package
+        #[arg(short, long)]
+        output: PathBuf,
+
+        /// Package name (defaults to the repository name)
+        #[arg(short, long)]
+        package: Option<String>,
+
+        #[command(flatten)]
+        options: CrdPackageOptions,
     },
 
     /// Import from OpenAPI specification
@@ -156,6 +328,19 @@ enum ImportSource {
         /// Output file path
         #[arg(short, long)]
         output: Option<PathBuf>,
+
+        /// Also write the parsed IR bundle (JSON) to this path
+        #[arg(long)]
+        emit_ir: Option<PathBuf>,
+
+        /// Enums as checked strings ("strings") or as enum tags ("tags")
+        #[arg(long, default_value = "strings")]
+        enum_mode: EnumMode,
+
+        /// TOML file of [[transforms]] rules to apply to the generated types,
+        /// which are targeted as `api/v1/<Kind>`
+        #[arg(long)]
+        transforms: Option<PathBuf>,
     },
 
     /// Import core Kubernetes types from upstream OpenAPI
@@ -175,6 +360,18 @@ enum ImportSource {
         /// Generate Nickel package manifest (experimental)
         #[arg(long)]
         nickel_package: bool,
+
+        /// Directory layout: "flat" (version/kind.ncl) or "grouped" (group/version/kind.ncl)
+        #[arg(long, default_value = "flat")]
+        layout: K8sLayout,
+
+        /// Enums as checked strings ("strings") or as enum tags ("tags")
+        #[arg(long, default_value = "strings")]
+        enum_mode: EnumMode,
+
+        /// Also write the parsed IR bundle (JSON) to this path
+        #[arg(long)]
+        emit_ir: Option<PathBuf>,
     },
 
     /// Import from Kubernetes cluster (not implemented)
@@ -193,6 +390,30 @@ enum ImportSource {
     },
 }
 
+/// Options shared by the imports that generate a CRD package
+#[derive(Args)]
+struct CrdPackageOptions {
+    /// Generate Nickel package manifest (experimental)
+    #[arg(long)]
+    nickel_package: bool,
+
+    /// Leave out versions the CRDs mark as deprecated
+    #[arg(long)]
+    skip_deprecated: bool,
+
+    /// Also write the parsed IR bundle (JSON) to this path
+    #[arg(long)]
+    emit_ir: Option<PathBuf>,
+
+    /// Enums as checked strings ("strings") or as enum tags ("tags")
+    #[arg(long, default_value = "strings")]
+    enum_mode: EnumMode,
+
+    /// TOML file of [[transforms]] rules to apply to the generated types
+    #[arg(long)]
+    transforms: Option<PathBuf>,
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
     let cli = Cli::parse();
@@ -217,7 +438,8 @@ async fn main() -> Result<()> {
             input,
             output,
             target,
-        }) => handle_generate(input, output, &target),
+            source_comments,
+        }) => handle_generate(input, output, &target, source_comments),
         Some(Commands::Convert {
             input,
             from,
@@ -234,11 +456,38 @@ async fn main() -> Result<()> {
             package_path,
             verbose: _,
         }) => validate::run_validation_with_package_path(&path, package_path.as_deref()),
+        Some(Commands::Diff {
+            old,
+            new,
+            format,
+            fail_on_breaking,
+        }) => {
+            let report = diff::run_diff(&old, &new, &format)?;
+            if fail_on_breaking && report.has_breaking() {
+                anyhow::bail!("Breaking schema changes detected");
+            }
+            Ok(())
+        }
+        Some(Commands::Render {
+            file,
+            package,
+            output,
+        }) => {
+            let yaml = render::render_file(&file, &package)?;
+            match output {
+                Some(output) => fs::write(&output, yaml)
+                    .with_context(|| format!("Failed to write {:?}", output))?,
+                None => print!("{}", yaml),
+            }
+            Ok(())
+        }
+        Some(Commands::Push { path, reference }) => handle_push(path, &reference).await,
         Some(Commands::GenerateFromManifest {
             manifest,
             packages,
             dry_run,
-        }) => handle_manifest_generation(manifest, packages, dry_run).await,
+            jobs,
+        }) => handle_manifest_generation(manifest, packages, dry_run, jobs).await,
         None => {
             // No command provided, show help
             use clap::CommandFactory;
@@ -254,7 +503,7 @@ async fn handle_import(source: ImportSource) -> Result<()> {
             url,
             output,
             package,
-            nickel_package,
+            options,
         } => {
             info!("Fetching CRDs from URL: {}", url);
 
@@ -275,83 +524,126 @@ async fn handle_import(source: ImportSource) -> Result<()> {
 
             info!("Found {} CRDs", crds.len());
 
-            // Generate package structure
-            let mut generator = amalgam_parser::package::PackageGenerator::new(
-                package_name.clone(),
-                output.clone(),
-            );
-            generator.add_crds(crds);
-
-            let package_structure = generator.generate_package()?;
-
-            // Create output directory structure
-            fs::create_dir_all(&output)?;
+            write_crd_import(&package_name, crds, output, options)
+        }
 
-            // Write main module file
-            let main_module = package_structure.generate_main_module();
-            fs::write(output.join("mod.ncl"), main_module)?;
+        ImportSource::Git {
+            repo,
+            git_ref,
+            paths,
+            output,
+            package,
+            cache_dir,
+            options,
+        } => {
+            info!("Checking out {}", repo);
 
-            // Create group/version/kind structure
-            for group in package_structure.groups() {
-                let group_dir = output.join(&group);
-                fs::create_dir_all(&group_dir)?;
+            let package_name = package.unwrap_or_else(|| {
+                repo.trim_end_matches('/')
+                    .trim_end_matches(".git")
+                    .rsplit('/')
+                    .next()
+                    .unwrap_or("generated")
+                    .to_string()
+            });
 
-                // Write group module
-                if let Some(group_mod) = package_structure.generate_group_module(&group) {
-                    fs::write(group_dir.join("mod.ncl"), group_mod)?;
-                }
+            let mut source = amalgam_parser::git::GitSource::new(repo)
+                .with_ref(git_ref)
+                .with_paths(paths);
+            if let Some(cache_dir) = cache_dir {
+                source = source.with_cache_dir(cache_dir);
+            }
+            // Cloning blocks, so keep it off the async runtime's threads
+            let checkout = tokio::task::spawn_blocking(move || source.checkout()).await??;
+            let crds = checkout.crds()?;
+            info!("Found {} CRDs at commit {}", crds.len(), checkout.commit);
 
-                // Create version directories
-                for version in package_structure.versions(&group) {
-                    let version_dir = group_dir.join(&version);
-                    fs::create_dir_all(&version_dir)?;
+            write_crd_import(&package_name, crds, output, options)
+        }
 
-                    // Write version module
-                    if let Some(version_mod) =
-                        package_structure.generate_version_module(&group, &version)
-                    {
-                        fs::write(version_dir.join("mod.ncl"), version_mod)?;
-                    }
+        ImportSource::Helm {
+            chart,
+            output,
+            package,
+            options,
+        } => {
+            info!("Reading Helm chart {:?}", chart);
+            let helm_chart = amalgam_parser::helm::HelmChart::load(&chart)?;
+            info!(
+                "Found {} CRDs in chart {} {}",
+                helm_chart.crds.len(),
+                helm_chart.name,
+                helm_chart.version
+            );
 
-                    // Write individual kind files
-                    for kind in package_structure.kinds(&group, &version) {
-                        if let Some(kind_content) =
-                            package_structure.generate_kind_file(&group, &version, &kind)
-                        {
-                            fs::write(version_dir.join(format!("{}.ncl", kind)), kind_content)?;
-                        }
-                    }
-                }
-            }
+            let package_name = package.unwrap_or_else(|| helm_chart.name.clone());
+            write_crd_import(&package_name, helm_chart.crds, output, options)
+        }
 
-            // Generate Nickel package manifest if requested
-            if nickel_package {
-                info!("Generating Nickel package manifest (experimental)");
-                let manifest = package_structure.generate_nickel_manifest(None);
-                fs::write(output.join("Nickel-pkg.ncl"), manifest)?;
-                info!("✓ Generated Nickel-pkg.ncl");
+        ImportSource::Olm {
+            bundle,
+            output,
+            package,
+            options,
+        } => {
+            info!("Reading OLM bundle {:?}", bundle);
+            let olm_bundle = amalgam_parser::olm::OlmBundle::load(&bundle)?;
+            info!(
+                "Found {} owned CRDs in {}",
+                olm_bundle.crds.len(),
+                olm_bundle.name
+            );
+            for required in &olm_bundle.required {
+                info!("Requires {} (not generated)", required);
             }
 
-            info!("Generated package '{}' in {:?}", package_name, output);
-            info!("Package structure:");
-            for group in package_structure.groups() {
-                info!("  {}/", group);
-                for version in package_structure.versions(&group) {
-                    let kinds = package_structure.kinds(&group, &version);
-                    info!("    {}/: {} types", version, kinds.len());
-                }
-            }
-            if nickel_package {
-                info!("  Nickel-pkg.ncl (package manifest)");
-            }
+            let package_name = package
+                .or_else(|| olm_bundle.package.clone())
+                .unwrap_or_else(|| olm_bundle.name.clone());
+            write_crd_import(&package_name, olm_bundle.crds, output.clone(), options)?;
 
+            let examples = write_olm_examples(&olm_bundle.examples, &output)?;
+            info!("Wrote {} examples", examples);
             Ok(())
         }
 
+        ImportSource::Oci {
+            reference,
+            paths,
+            output,
+            package,
+            options,
+        } => {
+            let reference: OciReference = reference.parse()?;
+            info!("Pulling {}", reference);
+
+            let package_name = package.unwrap_or_else(|| {
+                reference
+                    .repository
+                    .rsplit('/')
+                    .next()
+                    .unwrap_or("generated")
+                    .to_string()
+            });
+
+            let client = OciClient::new(HttpClient::from_env()?);
+            let mut artifact = client.pull(&reference).await?;
+            artifact.retain_paths(&paths);
+            let crds = artifact.crds()?;
+            info!("Found {} CRDs in {}", crds.len(), artifact.manifest_digest);
+
+            write_crd_import(&package_name, crds, output, options)
+        }
+
         ImportSource::Crd {
             file,
             output,
             package_mode,
+            skip_deprecated,
+            emit_ir,
+            source_comments,
+            enum_mode,
+            transforms,
         } => {
             info!("Importing CRD from {:?}", file);
 
@@ -363,9 +655,24 @@ async fn handle_import(source: ImportSource) -> Result<()> {
             } else {
                 serde_yaml::from_str(&content)?
             };
+            let crd = crd.with_source(file.display().to_string());
 
-            let parser = CRDParser::new();
+            let parser = CRDParser::new().with_skip_deprecated(skip_deprecated);
             let mut ir = parser.parse(crd.clone())?;
+            for module in &ir.modules {
+                if let Some(notice) = module.types.iter().find_map(|t| t.deprecation_notice()) {
+                    warn!("Deprecated API version {}: {}", module.name, notice);
+                }
+            }
+            if let Some(transforms) = transforms {
+                let rules = read_transforms(&transforms)?;
+                for module in &mut ir.modules {
+                    let version = module.metadata.version.clone().unwrap_or_default();
+                    for type_def in &mut module.types {
+                        apply_transforms(&rules, &crd.spec.group, &version, type_def);
+                    }
+                }
+            }
 
             // Add imports for any k8s type references
             use amalgam_core::ir::Import;
@@ -453,9 +760,15 @@ async fn handle_import(source: ImportSource) -> Result<()> {
                 NickelCodegen::new().with_package_mode(package_mode)
             } else {
                 NickelCodegen::new()
-            };
+            }
+            .with_source_comments(source_comments)
+            .with_enum_mode(enum_mode);
             let code = codegen.generate(&ir)?;
 
+            if let Some(ir_path) = emit_ir {
+                write_ir_bundle(&ir, &ir_path)?;
+            }
+
             if let Some(output_path) = output {
                 fs::write(&output_path, code)
                     .with_context(|| format!("Failed to write output: {:?}", output_path))?;
@@ -467,7 +780,13 @@ async fn handle_import(source: ImportSource) -> Result<()> {
             Ok(())
         }
 
-        ImportSource::OpenApi { file, output } => {
+        ImportSource::OpenApi {
+            file,
+            output,
+            emit_ir,
+            enum_mode,
+            transforms,
+        } => {
             info!("Importing OpenAPI spec from {:?}", file);
 
             let content = fs::read_to_string(&file)
@@ -479,8 +798,14 @@ async fn handle_import(source: ImportSource) -> Result<()> {
                 serde_yaml::from_str(&content)?
             };
 
-            let parser = OpenAPIParser::new();
+            let parser = OpenAPIParser::new().with_source(file.display().to_string());
             let mut ir = parser.parse(spec)?;
+            if let Some(transforms) = transforms {
+                let rules = read_transforms(&transforms)?;
+                for type_def in ir.modules.iter_mut().flat_map(|m| &mut m.types) {
+                    apply_transforms(&rules, "api", "v1", type_def);
+                }
+            }
 
             // Add imports for any k8s type references
             use amalgam_core::ir::Import;
@@ -527,9 +852,13 @@ async fn handle_import(source: ImportSource) -> Result<()> {
             }
 
             // Generate Nickel code by default
-            let mut codegen = NickelCodegen::new();
+            let mut codegen = NickelCodegen::new().with_enum_mode(enum_mode);
             let code = codegen.generate(&ir)?;
 
+            if let Some(ir_path) = emit_ir {
+                write_ir_bundle(&ir, &ir_path)?;
+            }
+
             if let Some(output_path) = output {
                 fs::write(&output_path, code)
                     .with_context(|| format!("Failed to write output: {:?}", output_path))?;
@@ -546,8 +875,15 @@ async fn handle_import(source: ImportSource) -> Result<()> {
             output,
             types: _,
             nickel_package,
+            layout,
+            enum_mode,
+            emit_ir,
         } => {
-            handle_k8s_core_import(&version, &output, nickel_package).await?;
+            let ir = handle_k8s_core_import(&version, &output, nickel_package, layout, enum_mode)
+                .await?;
+            if let Some(ir_path) = emit_ir {
+                write_ir_bundle(&ir, &ir_path)?;
+            }
             Ok(())
         }
 
@@ -557,13 +893,79 @@ async fn handle_import(source: ImportSource) -> Result<()> {
     }
 }
 
+/// Generate a CRD package and write it, shared by the CRD source imports
+fn write_crd_import(
+    package_name: &str,
+    crds: Vec<CRD>,
+    output: PathBuf,
+    options: CrdPackageOptions,
+) -> Result<()> {
+    let CrdPackageOptions {
+        nickel_package,
+        skip_deprecated,
+        emit_ir,
+        enum_mode,
+        transforms,
+    } = options;
+    let transforms = match transforms {
+        Some(path) => read_transforms(&path)?,
+        None => Vec::new(),
+    };
+
+    // Generate package structure
+    let mut generator =
+        amalgam_parser::package::PackageGenerator::new(package_name.to_string(), output.clone())
+            .with_skip_deprecated(skip_deprecated)
+            .with_enum_mode(enum_mode)
+            .with_transforms(transforms);
+    generator.add_crds(crds);
+
+    let package_structure = generator.generate_package()?;
+    for notice in package_structure.deprecations() {
+        warn!("Deprecated API version: {}", notice);
+    }
+
+    write_crd_package(&package_structure, &output)?;
+
+    // Generate Nickel package manifest if requested
+    if nickel_package {
+        info!("Generating Nickel package manifest (experimental)");
+        let manifest = package_structure.generate_nickel_manifest(None);
+        fs::write(output.join("Nickel-pkg.ncl"), manifest)?;
+        info!("✓ Generated Nickel-pkg.ncl");
+    }
+
+    if let Some(ir_path) = emit_ir {
+        write_ir_bundle(&package_structure.to_ir(), &ir_path)?;
+    }
+
+    info!("Generated package '{}' in {:?}", package_name, output);
+    info!("Package structure:");
+    for group in package_structure.groups() {
+        info!("  {}/", group);
+        for version in package_structure.versions(&group) {
+            let kinds = package_structure.kinds(&group, &version);
+            info!("    {}/: {} types", version, kinds.len());
+        }
+    }
+    if nickel_package {
+        info!("  Nickel-pkg.ncl (package manifest)");
+    }
+
+    Ok(())
+}
+
 // Moved to lib.rs to avoid duplication
-use amalgam::handle_k8s_core_import;
+use amalgam::{
+    handle_k8s_core_import, read_transforms, write_crd_package, write_ir_bundle,
+    write_k8s_core_package, write_olm_examples,
+};
 
 async fn handle_manifest_generation(
     manifest_path: PathBuf,
     packages: Vec<String>,
     dry_run: bool,
+    jobs: usize,
 ) -> Result<()> {
     use crate::manifest::Manifest;
 
@@ -589,7 +991,7 @@ async fn handle_manifest_generation(
     }
 
     // Generate all packages
-    let report = manifest.generate_all().await?;
+    let report = manifest.generate_all(jobs).await?;
     report.print_summary();
 
     if !report.failed.is_empty() {
@@ -599,30 +1001,67 @@ async fn handle_manifest_generation(
     Ok(())
 }
 
-fn handle_generate(input: PathBuf, output: PathBuf, target: &str) -> Result<()> {
-    info!("Generating {} code from {:?}", target, input);
+async fn handle_push(path: PathBuf, reference: &str) -> Result<()> {
+    let reference: OciReference = reference.parse()?;
+    if !path.is_dir() {
+        anyhow::bail!("Package directory not found: {:?}", path);
+    }
 
-    let ir_content = fs::read_to_string(&input)
-        .with_context(|| format!("Failed to read IR file: {:?}", input))?;
+    info!("Pushing {:?} to {}", path, reference);
+    let client = OciClient::new(HttpClient::from_env()?);
+    let digest = client
+        .push_package(&path, &reference)
+        .await
+        .with_context(|| format!("Failed to push {:?} to {}", path, reference))?;
+    println!("Pushed {}@{}", reference, digest);
 
-    let ir: amalgam_core::IR =
-        serde_json::from_str(&ir_content).with_context(|| "Failed to parse IR JSON")?;
+    Ok(())
+}
 
-    let code = match target {
-        "nickel" => {
-            let mut codegen = NickelCodegen::new();
-            codegen.generate(&ir)?
-        }
-        "go" => {
-            let mut codegen = GoCodegen::new();
-            codegen.generate(&ir)?
-        }
+fn handle_generate(
+    input: PathBuf,
+    output: PathBuf,
+    target: &str,
+    source_comments: bool,
+) -> Result<()> {
+    info!("Generating {} code from {:?}", target, input);
+
+    let ir = amalgam_core::IR::load_from_file(&input)
+        .with_context(|| format!("Failed to load IR bundle: {:?}", input))?;
+
+    let extension = match target {
+        "nickel" => "ncl",
+        "go" => "go",
         _ => {
             anyhow::bail!("Unsupported target language: {}", target);
         }
     };
 
-    fs::write(&output, code).with_context(|| format!("Failed to write output: {:?}", output))?;
+    let generate = |ir: &amalgam_core::IR| -> Result<String> {
+        Ok(match target {
+            "go" => GoCodegen::new()
+                .with_source_comments(source_comments)
+                .generate(ir)?,
+            _ => NickelCodegen::new()
+                .with_source_comments(source_comments)
+                .generate(ir)?,
+        })
+    };
+
+    // A directory output gets one file per module so bundles with many
+    // modules produce loadable files rather than one concatenated blob
+    if output.is_dir() {
+        for module in &ir.modules {
+            let mut module_ir = amalgam_core::IR::new();
+            module_ir.add_module(module.clone());
+            let path = output.join(format!("{}.{}", module.name, extension));
+            fs::write(&path, generate(&module_ir)?)
+                .with_context(|| format!("Failed to write output: {:?}", path))?;
+        }
+    } else {
+        fs::write(&output, generate(&ir)?)
+            .with_context(|| format!("Failed to write output: {:?}", output))?;
+    }
 
     info!("Generated code written to {:?}", output);
     Ok(())
@@ -642,7 +1081,7 @@ fn handle_convert(input: PathBuf, from: &str, output: PathBuf, to: &str) -> Resu
             } else {
                 serde_yaml::from_str(&content)?
             };
-            CRDParser::new().parse(crd)?
+            CRDParser::new().parse(crd.with_source(input.display().to_string()))?
         }
         "openapi" => {
             let spec: openapiv3::OpenAPI = if input.extension().is_some_and(|ext| ext == "json") {
@@ -650,7 +1089,9 @@ fn handle_convert(input: PathBuf, from: &str, output: PathBuf, to: &str) -> Resu
             } else {
                 serde_yaml::from_str(&content)?
             };
-            OpenAPIParser::new().parse(spec)?
+            OpenAPIParser::new()
+                .with_source(input.display().to_string())
+                .parse(spec)?
         }
         _ => {
             anyhow::bail!("Unsupported input format: {}", from);
@@ -667,7 +1108,7 @@ fn handle_convert(input: PathBuf, from: &str, output: PathBuf, to: &str) -> Resu
             let mut codegen = GoCodegen::new();
             codegen.generate(&ir)?
         }
-        "ir" => serde_json::to_string_pretty(&ir)?,
+        "ir" => ir.to_json()?,
         _ => {
             anyhow::bail!("Unsupported output format: {}", to);
         }
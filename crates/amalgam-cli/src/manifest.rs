@@ -1,10 +1,30 @@
 //! Manifest-based package generation for CI/CD workflows
 
+use amalgam_codegen::nickel::EnumMode;
+use amalgam_core::dedup::Deduplicator;
+use amalgam_core::fingerprint::{FingerprintBuilder, PublishedArtifact};
+use amalgam_core::nested::NestedTypeExtractor;
+use amalgam_core::IR;
+use amalgam_parser::crd::CRD;
+use amalgam_parser::fetch::CRDFetcher;
+use amalgam_parser::filter::CrdFilter;
+use amalgam_parser::git::{GitCheckout, GitSource};
+use amalgam_parser::helm::HelmChart;
+use amalgam_parser::http::HttpClient;
+use amalgam_parser::imports::K8sLayout;
+use amalgam_parser::k8s_types::core_type_shapes;
+use amalgam_parser::oci::{OciClient, OciReference};
+use amalgam_parser::olm::OlmBundle;
+use amalgam_parser::overrides::{apply_overrides, TypeOverride};
+use amalgam_parser::package::PackageGenerator;
+use amalgam_parser::transforms::{apply_transforms, TransformRule};
 use anyhow::{Context, Result};
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::fs;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use tracing::{info, warn};
 
 /// Main manifest configuration
@@ -34,6 +54,53 @@ pub struct ManifestConfig {
     /// When set, generates Path dependencies instead of Index dependencies
     #[serde(default)]
     pub local_package_prefix: Option<String>,
+
+    /// Write the parsed IR bundle into each package directory
+    #[serde(default)]
+    pub emit_ir: bool,
+
+    /// Leave out CRD versions marked as deprecated
+    #[serde(default)]
+    pub skip_deprecated: bool,
+
+    /// Directory layout of k8s-core packages ("flat" or "grouped")
+    #[serde(default)]
+    pub k8s_layout: K8sLayout,
+
+    /// Hoist record shapes repeated across a package's CRDs into shared
+    /// types, and reference k8s-core types for inlined copies of them
+    ///
+    /// Every other package then depends on the manifest's k8s-core packages,
+    /// which always write their IR bundle.
+    #[serde(default)]
+    pub deduplicate: bool,
+
+    /// Lift nested records of CRD schemas into named types such as
+    /// `WidgetSpecTemplate`, each written to its own file
+    #[serde(default)]
+    pub extract_nested_types: bool,
+
+    /// Comment generated types and fields with the file and schema location
+    /// they were generated from
+    #[serde(default)]
+    pub source_comments: bool,
+
+    /// Generate enums as checked strings ("strings") or as enum tags that
+    /// export as strings ("tags")
+    #[serde(default)]
+    pub enum_mode: EnumMode,
+
+    /// Transform rules applied to the types of every package, before the
+    /// package's own
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub transforms: Vec<TransformRule>,
+
+    /// Where git packages are cloned (defaults to the user's cache directory)
+    ///
+    /// It only affects where clones live, not the generated output, so it is
+    /// not part of change detection: moving it does not regenerate packages.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_dir: Option<PathBuf>,
 }
 
 /// Definition of a package to generate
@@ -42,20 +109,25 @@ pub struct PackageDefinition {
     /// Package name
     pub name: String,
 
-    /// Type of source (k8s-core, url, crd, openapi)
+    /// Type of source (k8s-core, url, git, helm, oci, olm, crd, openapi)
     #[serde(rename = "type")]
     pub source_type: SourceType,
 
     /// Version (for k8s-core and package versioning)
     pub version: Option<String>,
 
-    /// URL (for url type)
+    /// URL (for url type, the repository for git, or the image reference for oci)
     pub url: Option<String>,
 
-    /// Git ref (tag, branch, or commit) for URL sources
+    /// Git ref (tag, branch, or commit) for URL and git sources
     pub git_ref: Option<String>,
 
-    /// File path (for crd/openapi types)
+    /// Globs selecting CRD files in the repository or image layers (for git and oci types)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub paths: Vec<String>,
+
+    /// File path (for crd/openapi types, the chart directory or .tgz for helm,
+    /// or the bundle directory for olm)
     pub file: Option<PathBuf>,
 
     /// Output directory name
@@ -74,6 +146,94 @@ pub struct PackageDefinition {
     /// Whether this package is enabled
     #[serde(default = "default_true")]
     pub enabled: bool,
+
+    /// Which CRDs and versions to generate (url, git, helm, oci and olm packages)
+    #[serde(default, skip_serializing_if = "CrdFilter::is_empty")]
+    pub filter: CrdFilter,
+
+    /// Fixes applied to upstream types before code generation
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub overrides: Vec<TypeOverride>,
+
+    /// Transform rules applied to this package's types after the overrides
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub transforms: Vec<TransformRule>,
+
+    /// OCI reference to push the generated package to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub push: Option<String>,
+}
+
+impl PackageDefinition {
+    /// Settings beyond the source content that change the generated output
+    ///
+    /// They are folded into the package fingerprint so that editing them
+    /// triggers regeneration. Only settings that differ from their defaults
+    /// are included, so that adding a setting leaves existing fingerprints
+    /// valid.
+    ///
+    /// `core_bundles` holds the hashes of the k8s-core IR bundles the package
+    /// is deduplicated against, by package name.
+    fn fingerprint_settings(
+        &self,
+        config: &ManifestConfig,
+        core_bundles: &BTreeMap<String, String>,
+    ) -> Option<String> {
+        let mut settings = serde_json::Map::new();
+        if !self.filter.is_empty() {
+            settings.insert("filter".to_string(), serde_json::json!(self.filter));
+        }
+        if !self.overrides.is_empty() {
+            settings.insert("overrides".to_string(), serde_json::json!(self.overrides));
+        }
+        let transforms = self.transforms(config);
+        if !transforms.is_empty() {
+            settings.insert("transforms".to_string(), serde_json::json!(transforms));
+        }
+        if config.skip_deprecated {
+            settings.insert("skip_deprecated".to_string(), serde_json::json!(true));
+        }
+        if config.k8s_layout != K8sLayout::default() {
+            settings.insert(
+                "k8s_layout".to_string(),
+                serde_json::json!(config.k8s_layout),
+            );
+        }
+        if config.deduplicate {
+            settings.insert("deduplicate".to_string(), serde_json::json!(true));
+        }
+        if !core_bundles.is_empty() {
+            settings.insert("core_bundles".to_string(), serde_json::json!(core_bundles));
+        }
+        if config.extract_nested_types {
+            settings.insert("extract_nested_types".to_string(), serde_json::json!(true));
+        }
+        if config.source_comments {
+            settings.insert("source_comments".to_string(), serde_json::json!(true));
+        }
+        if config.enum_mode != EnumMode::default() {
+            settings.insert("enum_mode".to_string(), serde_json::json!(config.enum_mode));
+        }
+        if config.emit_ir {
+            settings.insert("emit_ir".to_string(), serde_json::json!(true));
+        }
+
+        if settings.is_empty() {
+            None
+        } else {
+            Some(serde_json::Value::Object(settings).to_string())
+        }
+    }
+
+    /// The manifest-wide transform rules followed by the package's own
+    fn transforms(&self, config: &ManifestConfig) -> Vec<TransformRule> {
+        config
+            .transforms
+            .iter()
+            .chain(&self.transforms)
+            .cloned()
+            .collect()
+    }
 }
 
 /// Dependency specification with version constraints
@@ -95,6 +255,10 @@ pub enum DependencySpec {
 pub enum SourceType {
     K8sCore,
     Url,
+    Git,
+    Helm,
+    Oci,
+    Olm,
     Crd,
     OpenApi,
 }
@@ -104,6 +268,10 @@ impl std::fmt::Display for SourceType {
         match self {
             SourceType::K8sCore => write!(f, "k8s-core"),
             SourceType::Url => write!(f, "url"),
+            SourceType::Git => write!(f, "git"),
+            SourceType::Helm => write!(f, "helm"),
+            SourceType::Oci => write!(f, "oci"),
+            SourceType::Olm => write!(f, "olm"),
             SourceType::Crd => write!(f, "crd"),
             SourceType::OpenApi => write!(f, "openapi"),
         }
@@ -114,6 +282,106 @@ fn default_true() -> bool {
     true
 }
 
+/// Progress bar counting generated packages, shown only on a terminal
+fn overall_progress(multi_progress: &MultiProgress, total: usize) -> Option<ProgressBar> {
+    if !atty::is(atty::Stream::Stdout) {
+        return None;
+    }
+    let pb = multi_progress.add(ProgressBar::new(total as u64));
+    pb.set_style(
+        ProgressStyle::default_bar()
+            .template("{spinner:.cyan} [{bar:30.cyan/blue}] {pos}/{len} packages {msg}")
+            .unwrap()
+            .progress_chars("#>-"),
+    );
+    pb.enable_steady_tick(std::time::Duration::from_millis(100));
+    Some(pb)
+}
+
+/// URL to fetch a url-type package from, with its git ref applied
+fn fetch_url(package: &PackageDefinition) -> Result<String> {
+    let url = package
+        .url
+        .as_ref()
+        .ok_or_else(|| anyhow::anyhow!("URL required for url type package"))?;
+
+    // Build URL with git ref if specified
+    let fetch_url = if let Some(ref git_ref) = package.git_ref {
+        // Replace /tree/main or /tree/master with the specified ref
+        if url.contains("/tree/") {
+            let parts: Vec<&str> = url.split("/tree/").collect();
+            if parts.len() == 2 {
+                let base = parts[0];
+                let path_parts: Vec<&str> = parts[1].split('/').collect();
+                if path_parts.len() > 1 {
+                    // Reconstruct with new ref
+                    format!("{}/tree/{}/{}", base, git_ref, path_parts[1..].join("/"))
+                } else {
+                    format!("{}/tree/{}", base, git_ref)
+                }
+            } else {
+                url.clone()
+            }
+        } else {
+            // Append ref if no /tree/ found
+            format!("{}/tree/{}", url.trim_end_matches('/'), git_ref)
+        }
+    } else {
+        url.clone()
+    };
+    Ok(fetch_url)
+}
+
+/// Check out the repository of a git-type package at its ref
+async fn git_checkout(config: &ManifestConfig, package: &PackageDefinition) -> Result<GitCheckout> {
+    let url = package
+        .url
+        .clone()
+        .ok_or_else(|| anyhow::anyhow!("URL required for git type package"))?;
+    let mut source = GitSource::new(url)
+        .with_ref(package.git_ref.clone())
+        .with_paths(package.paths.clone());
+    if let Some(cache_dir) = &config.cache_dir {
+        source = source.with_cache_dir(cache_dir.clone());
+    }
+
+    // Cloning blocks, so keep it off the threads driving other packages
+    Ok(tokio::task::spawn_blocking(move || source.checkout()).await??)
+}
+
+/// Chart directory or archive of a helm-type package
+fn helm_chart_path(package: &PackageDefinition) -> Result<&Path> {
+    package
+        .file
+        .as_deref()
+        .ok_or_else(|| anyhow::anyhow!("File path to the chart required for helm type package"))
+}
+
+/// Bundle directory of an olm-type package
+fn olm_bundle_path(package: &PackageDefinition) -> Result<&Path> {
+    package
+        .file
+        .as_deref()
+        .ok_or_else(|| anyhow::anyhow!("File path to the bundle required for olm type package"))
+}
+
+/// Image or artifact reference of an oci-type package
+fn oci_reference(package: &PackageDefinition) -> Result<OciReference> {
+    let reference = package
+        .url
+        .as_ref()
+        .ok_or_else(|| anyhow::anyhow!("URL (image reference) required for oci type package"))?;
+    Ok(reference.parse()?)
+}
+
+/// A package's fingerprint source, with content fetched for change
+/// detection that generation reuses
+struct PackageSource {
+    fingerprint: Box<dyn amalgam_core::fingerprint::Fingerprintable>,
+    /// OpenAPI spec of a k8s-core package, when it was downloaded
+    k8s_openapi: Option<String>,
+}
+
 impl Manifest {
     /// Load manifest from file
     pub fn from_file(path: &Path) -> Result<Self> {
@@ -125,7 +393,14 @@ impl Manifest {
     }
 
     /// Generate all packages defined in the manifest
-    pub async fn generate_all(&self) -> Result<GenerationReport> {
+    ///
+    /// Packages are generated concurrently, at most `jobs` at a time. A
+    /// package whose `dependencies` name other packages of the manifest
+    /// starts only once those have been generated, and fails if one of them
+    /// failed. All packages share one HTTP client and progress display.
+    pub async fn generate_all(&self, jobs: usize) -> Result<GenerationReport> {
+        use futures::stream::{FuturesUnordered, StreamExt};
+
         let mut report = GenerationReport::default();
 
         // Create output base directory
@@ -136,48 +411,181 @@ impl Manifest {
             )
         })?;
 
+        let mut packages = Vec::new();
         for package in &self.packages {
-            if !package.enabled {
+            if package.enabled {
+                packages.push(package);
+            } else {
                 info!("Skipping disabled package: {}", package.name);
                 report.skipped.push(package.name.clone());
-                continue;
             }
+        }
+
+        // Dependencies on other enabled packages, by index into `packages`
+        let dependencies: Vec<Vec<usize>> = packages
+            .iter()
+            .enumerate()
+            .map(|(index, package)| {
+                package
+                    .dependencies
+                    .keys()
+                    .filter_map(|dep| {
+                        packages
+                            .iter()
+                            .position(|p| &p.name == dep || &p.output == dep)
+                    })
+                    // Deduplication reads the IR bundles of k8s-core packages
+                    .chain(
+                        self.core_packages_of(package)
+                            .into_iter()
+                            .filter_map(|core| {
+                                packages.iter().position(|p| std::ptr::eq(*p, core))
+                            }),
+                    )
+                    .filter(|&dep| dep != index)
+                    .collect()
+            })
+            .collect();
+
+        let client = HttpClient::from_env()?;
+        let fetcher = CRDFetcher::with_client(client, Arc::new(MultiProgress::new()));
+        let fetcher = &fetcher;
+        let overall = overall_progress(&fetcher.multi_progress(), packages.len());
+
+        // None while pending, then whether generation succeeded
+        let mut outcomes: Vec<Option<bool>> = vec![None; packages.len()];
+        let mut started = vec![false; packages.len()];
+        let mut running = FuturesUnordered::new();
+        let jobs = jobs.max(1);
+
+        loop {
+            // Start every package whose dependencies are done, up to the job limit
+            let mut progressed = true;
+            while progressed && running.len() < jobs {
+                progressed = false;
+                for index in 0..packages.len() {
+                    if started[index] || running.len() >= jobs {
+                        continue;
+                    }
+                    if dependencies[index]
+                        .iter()
+                        .any(|&dep| outcomes[dep].is_none())
+                    {
+                        continue;
+                    }
+                    started[index] = true;
+                    progressed = true;
 
-            info!("Generating package: {}", package.name);
+                    let package = packages[index];
+                    if let Some(&failed) = dependencies[index]
+                        .iter()
+                        .find(|&&dep| outcomes[dep] == Some(false))
+                    {
+                        let reason = format!("dependency {} failed", packages[failed].name);
+                        warn!("✗ Failed to generate {}: {}", package.name, reason);
+                        report.failed.push((package.name.clone(), reason));
+                        outcomes[index] = Some(false);
+                        if let Some(pb) = &overall {
+                            pb.inc(1);
+                        }
+                        continue;
+                    }
 
-            match self.generate_package(package).await {
-                Ok(output_path) => {
+                    info!("Generating package: {}", package.name);
+                    running.push(
+                        async move { (index, self.generate_package(package, fetcher).await) },
+                    );
+                }
+            }
+
+            let Some((index, result)) = running.next().await else {
+                break;
+            };
+            let package = packages[index];
+            match result {
+                Ok(generated) => {
                     info!(
                         "✓ Successfully generated {} at {:?}",
-                        package.name, output_path
+                        package.name, generated.path
                     );
                     report.successful.push(package.name.clone());
+                    report.deprecations.extend(
+                        generated
+                            .deprecations
+                            .into_iter()
+                            .map(|notice| (package.name.clone(), notice)),
+                    );
+                    outcomes[index] = Some(true);
                 }
                 Err(e) => {
                     warn!("✗ Failed to generate {}: {}", package.name, e);
                     report.failed.push((package.name.clone(), e.to_string()));
+                    outcomes[index] = Some(false);
                 }
             }
+            if let Some(pb) = &overall {
+                pb.inc(1);
+                pb.set_message(package.name.clone());
+            }
         }
 
+        // Whatever never started is waiting on a dependency cycle
+        for (index, package) in packages.iter().enumerate() {
+            if !started[index] {
+                warn!("✗ Failed to generate {}: dependency cycle", package.name);
+                report
+                    .failed
+                    .push((package.name.clone(), "dependency cycle".to_string()));
+            }
+        }
+
+        if let Some(pb) = overall {
+            pb.finish_with_message(format!("Generated {} packages", report.successful.len()));
+        }
+        fetcher.finish();
+
         Ok(report)
     }
 
     /// Generate a single package
-    async fn generate_package(&self, package: &PackageDefinition) -> Result<PathBuf> {
-        use amalgam_parser::incremental::{detect_change_type, save_fingerprint, ChangeType};
+    async fn generate_package(
+        &self,
+        package: &PackageDefinition,
+        fetcher: &CRDFetcher,
+    ) -> Result<GeneratedPackage> {
+        use amalgam_core::fingerprint::ContentFingerprint;
+        use amalgam_parser::incremental::{
+            detect_change_type, save_fingerprint, ChangeType, PublishedSource,
+        };
 
         let output_path = self.config.output_base.join(&package.output);
 
         // Check if we need to regenerate using intelligent change detection
-        let source = self.create_fingerprint_source(package).await?;
+        let PackageSource {
+            fingerprint: source,
+            k8s_openapi,
+        } = self.create_fingerprint_source(package, fetcher).await?;
         let change_type = detect_change_type(&output_path, source.as_ref())
             .map_err(|e| anyhow::anyhow!("Failed to detect changes: {}", e))?;
 
         match change_type {
             ChangeType::NoChange => {
                 info!("📦 {} - No changes detected, skipping", package.name);
-                return Ok(output_path);
+
+                // Still push when the package has not been pushed to this reference yet
+                if let Some(target) = &package.push {
+                    let target = target.parse::<OciReference>()?.to_string();
+                    let previous = ContentFingerprint::load_from_file(
+                        &ContentFingerprint::fingerprint_path(&output_path),
+                    )
+                    .map_err(|e| anyhow::anyhow!("Failed to load fingerprint: {}", e))?;
+                    if previous.published.map(|p| p.reference) != Some(target) {
+                        let published = self.publish(package, &output_path, fetcher).await?;
+                        save_fingerprint(&output_path, &PublishedSource { source, published })
+                            .map_err(|e| anyhow::anyhow!("Failed to save fingerprint: {}", e))?;
+                    }
+                }
+                return Ok(GeneratedPackage::new(output_path));
             }
             ChangeType::MetadataOnly => {
                 info!(
@@ -188,10 +596,11 @@ impl Manifest {
                 if self.config.package_mode {
                     self.generate_package_manifest(package, &output_path)?;
                 }
+                let published = self.publish(package, &output_path, fetcher).await?;
                 // Save new fingerprint with updated metadata
-                save_fingerprint(&output_path, source.as_ref())
+                save_fingerprint(&output_path, &PublishedSource { source, published })
                     .map_err(|e| anyhow::anyhow!("Failed to save fingerprint: {}", e))?;
-                return Ok(output_path);
+                return Ok(GeneratedPackage::new(output_path));
             }
             ChangeType::ContentChanged => {
                 info!("📦 {} - Content changed, regenerating", package.name);
@@ -203,8 +612,15 @@ impl Manifest {
 
         // Build the command based on source type
         let result = match package.source_type {
-            SourceType::K8sCore => self.generate_k8s_core(package, &output_path).await,
-            SourceType::Url => self.generate_from_url(package, &output_path).await,
+            SourceType::K8sCore => {
+                self.generate_k8s_core(package, &output_path, fetcher, k8s_openapi)
+                    .await
+            }
+            SourceType::Url => self.generate_from_url(package, &output_path, fetcher).await,
+            SourceType::Git => self.generate_from_git(package, &output_path).await,
+            SourceType::Helm => self.generate_from_helm(package, &output_path),
+            SourceType::Oci => self.generate_from_oci(package, &output_path, fetcher).await,
+            SourceType::Olm => self.generate_from_olm(package, &output_path),
             SourceType::Crd => self.generate_from_crd(package, &output_path).await,
             SourceType::OpenApi => self.generate_from_openapi(package, &output_path).await,
         };
@@ -212,35 +628,79 @@ impl Manifest {
         // Generate package manifest if successful
         if result.is_ok() && self.config.package_mode {
             self.generate_package_manifest(package, &output_path)?;
+        }
+        // Push the complete package, then record where it went
+        let published = match &result {
+            Ok(_) => self.publish(package, &output_path, fetcher).await?,
+            Err(_) => None,
+        };
+        if result.is_ok() && self.config.package_mode {
             // Save fingerprint after successful generation
-            save_fingerprint(&output_path, source.as_ref())
+            save_fingerprint(&output_path, &PublishedSource { source, published })
                 .map_err(|e| anyhow::anyhow!("Failed to save fingerprint: {}", e))?;
         }
 
         result
     }
 
+    /// Push a generated package to its `push` reference, if it has one
+    async fn publish(
+        &self,
+        package: &PackageDefinition,
+        output: &Path,
+        fetcher: &CRDFetcher,
+    ) -> Result<Option<PublishedArtifact>> {
+        let Some(target) = &package.push else {
+            return Ok(None);
+        };
+        let reference: OciReference = target.parse()?;
+
+        let client = OciClient::new(fetcher.client().clone());
+        let digest = client
+            .push_package(output, &reference)
+            .await
+            .with_context(|| format!("Failed to push {} to {}", package.name, reference))?;
+        info!("📤 {} - Pushed to {} ({})", package.name, reference, digest);
+
+        Ok(Some(PublishedArtifact {
+            reference: reference.to_string(),
+            digest,
+        }))
+    }
+
     /// Create a fingerprint source for change detection
     async fn create_fingerprint_source(
         &self,
         package: &PackageDefinition,
-    ) -> Result<Box<dyn amalgam_core::fingerprint::Fingerprintable>> {
+        fetcher: &CRDFetcher,
+    ) -> Result<PackageSource> {
+        use amalgam_core::fingerprint::{ContentFingerprint, Fingerprintable};
         use amalgam_parser::incremental::*;
 
-        match package.source_type {
+        let settings =
+            package.fingerprint_settings(&self.config, &self.core_bundle_hashes(package)?);
+
+        // Validators and digests from the last run allow skipping downloads,
+        // but only while the generation settings stay the same
+        let previous = ContentFingerprint::load_from_file(&ContentFingerprint::fingerprint_path(
+            &self.config.output_base.join(&package.output),
+        ))
+        .ok()
+        .filter(|previous| {
+            previous.settings_hash == settings.as_deref().map(ContentFingerprint::hash_settings)
+        });
+        let mut k8s_openapi = None;
+
+        let source: Box<dyn Fingerprintable> = match package.source_type {
             SourceType::K8sCore => {
                 let version = package.version.as_deref().unwrap_or("v1.31.0");
-                // For k8s core, we would fetch the OpenAPI spec and hash it
-                let spec_url = format!(
-                    "https://dl.k8s.io/{}/api/openapi-spec/swagger.json",
-                    version
-                );
-                let source = K8sCoreSource {
-                    version: version.to_string(),
-                    openapi_spec: "".to_string(), // Would be fetched in real implementation
-                    spec_url,
-                };
-                Ok(Box::new(source))
+                match fetch_k8s_core_source(fetcher, version, previous.as_ref()).await? {
+                    K8sCoreFetch::Unchanged(source) => Box::new(source),
+                    K8sCoreFetch::Fetched(source) => {
+                        k8s_openapi = Some(source.openapi_spec.clone());
+                        Box::new(source)
+                    }
+                }
             }
             SourceType::Url => {
                 let url = package
@@ -257,13 +717,42 @@ impl Manifest {
                     url.clone()
                 };
 
-                // For URL sources, we would fetch all the URLs and hash their content
-                let source = UrlSource {
-                    base_url: fingerprint_url.clone(),
-                    urls: vec![fingerprint_url], // Simplified - would list all files
-                    contents: vec!["".to_string()], // Would be actual content
-                };
-                Ok(Box::new(source))
+                fetch_url_source(
+                    fetcher,
+                    &fetch_url(package)?,
+                    &fingerprint_url,
+                    previous.as_ref(),
+                )
+                .await?
+            }
+            SourceType::Git => Box::new(GitRepoSource::from_checkout(
+                &git_checkout(&self.config, package).await?,
+            )),
+            SourceType::Oci => {
+                let client = OciClient::new(fetcher.client().clone());
+                fetch_oci_source(
+                    &client,
+                    &oci_reference(package)?,
+                    &package.paths,
+                    previous.as_ref(),
+                )
+                .await?
+            }
+            SourceType::Helm => {
+                let chart = helm_chart_path(package)?;
+                let helm_chart = HelmChart::load(chart)?;
+                Box::new(HelmChartSource::from_chart(
+                    chart.to_string_lossy(),
+                    &helm_chart,
+                ))
+            }
+            SourceType::Olm => {
+                let bundle = olm_bundle_path(package)?;
+                let olm_bundle = OlmBundle::load(bundle)?;
+                Box::new(OlmBundleSource::from_bundle(
+                    bundle.to_string_lossy(),
+                    &olm_bundle,
+                ))
             }
             SourceType::Crd | SourceType::OpenApi => {
                 // For file-based sources
@@ -280,130 +769,270 @@ impl Manifest {
                     String::new()
                 };
 
-                let source = LocalFilesSource {
+                Box::new(LocalFilesSource {
                     paths: vec![file.to_string_lossy().to_string()],
                     contents: vec![content],
-                };
-                Ok(Box::new(source))
+                })
             }
-        }
+        };
+
+        Ok(PackageSource {
+            fingerprint: match settings {
+                Some(settings) => Box::new(ConfiguredSource { source, settings }),
+                None => source,
+            },
+            k8s_openapi,
+        })
     }
 
     async fn generate_k8s_core(
         &self,
         package: &PackageDefinition,
         output: &Path,
-    ) -> Result<PathBuf> {
-        use crate::handle_k8s_core_import;
+        fetcher: &CRDFetcher,
+        openapi: Option<String>,
+    ) -> Result<GeneratedPackage> {
+        use crate::{write_ir_bundle, write_k8s_core_package};
+        use amalgam_parser::k8s_types::K8sTypesFetcher;
 
         let version = package.version.as_deref().unwrap_or("v1.31.0");
 
-        info!("Fetching Kubernetes {} core types...", version);
-        handle_k8s_core_import(version, output, true).await?;
+        let k8s_fetcher =
+            K8sTypesFetcher::with_client(fetcher.client().clone(), fetcher.multi_progress());
+        // Reuse the spec change detection downloaded
+        let openapi = match openapi {
+            Some(openapi) => serde_json::from_str(&openapi)
+                .with_context(|| format!("Invalid Kubernetes {} OpenAPI spec", version))?,
+            None => {
+                info!("Fetching Kubernetes {} core types...", version);
+                k8s_fetcher.fetch_k8s_openapi(version).await?
+            }
+        };
+        let mut types = k8s_fetcher.extract_core_types(&openapi)?;
+        let transforms = package.transforms(&self.config);
+        for (type_ref, type_def) in types.iter_mut() {
+            apply_overrides(
+                &package.overrides,
+                &type_ref.group,
+                &type_ref.version,
+                type_def,
+            )?;
+            apply_transforms(&transforms, &type_ref.group, &type_ref.version, type_def);
+        }
+        let ir = write_k8s_core_package(
+            types,
+            version,
+            output,
+            true,
+            self.config.k8s_layout,
+            self.config.enum_mode,
+        )?;
+
+        // Deduplicated packages read the core types from the bundle
+        if self.config.emit_ir || self.config.deduplicate {
+            write_ir_bundle(&ir, &amalgam_core::IR::bundle_path(output))?;
+        }
 
-        Ok(output.to_path_buf())
+        Ok(GeneratedPackage::new(output.to_path_buf()))
     }
 
     async fn generate_from_url(
         &self,
         package: &PackageDefinition,
         output: &Path,
-    ) -> Result<PathBuf> {
-        let url = package
-            .url
-            .as_ref()
-            .ok_or_else(|| anyhow::anyhow!("URL required for url type package"))?;
-
-        // Build URL with git ref if specified
-        let fetch_url = if let Some(ref git_ref) = package.git_ref {
-            // Replace /tree/main or /tree/master with the specified ref
-            if url.contains("/tree/") {
-                let parts: Vec<&str> = url.split("/tree/").collect();
-                if parts.len() == 2 {
-                    let base = parts[0];
-                    let path_parts: Vec<&str> = parts[1].split('/').collect();
-                    if path_parts.len() > 1 {
-                        // Reconstruct with new ref
-                        format!("{}/tree/{}/{}", base, git_ref, path_parts[1..].join("/"))
-                    } else {
-                        format!("{}/tree/{}", base, git_ref)
-                    }
-                } else {
-                    url.clone()
-                }
-            } else {
-                // Append ref if no /tree/ found
-                format!("{}/tree/{}", url.trim_end_matches('/'), git_ref)
-            }
-        } else {
-            url.clone()
-        };
+        fetcher: &CRDFetcher,
+    ) -> Result<GeneratedPackage> {
+        let fetch_url = fetch_url(package)?;
 
         info!("Fetching CRDs from URL: {}", fetch_url);
-        if package.git_ref.is_some() {
-            info!("Using git ref: {}", package.git_ref.as_ref().unwrap());
+        if let Some(git_ref) = &package.git_ref {
+            info!("Using git ref: {}", git_ref);
         }
 
-        // Use the existing URL import functionality
-        use amalgam_parser::fetch::CRDFetcher;
-        use amalgam_parser::package::PackageGenerator;
-
-        let fetcher = CRDFetcher::new()?;
         let crds = fetcher.fetch_from_url(&fetch_url).await?;
-        fetcher.finish();
 
         info!("Found {} CRDs", crds.len());
 
-        // Generate package structure
-        let mut generator = PackageGenerator::new(package.name.clone(), output.to_path_buf());
-        generator.add_crds(crds);
+        self.write_crds(package, crds, output)
+    }
 
-        let package_structure = generator.generate_package()?;
+    async fn generate_from_git(
+        &self,
+        package: &PackageDefinition,
+        output: &Path,
+    ) -> Result<GeneratedPackage> {
+        let checkout = git_checkout(&self.config, package).await?;
+        let crds = checkout.crds()?;
+        info!(
+            "Found {} CRDs in {} at {}",
+            crds.len(),
+            checkout.url,
+            checkout.commit
+        );
 
-        // Create output directory structure
-        fs::create_dir_all(output)?;
+        self.write_crds(package, crds, output)
+    }
 
-        // Write main module file
-        let main_module = package_structure.generate_main_module();
-        fs::write(output.join("mod.ncl"), main_module)?;
+    fn generate_from_helm(
+        &self,
+        package: &PackageDefinition,
+        output: &Path,
+    ) -> Result<GeneratedPackage> {
+        let chart = helm_chart_path(package)?;
+
+        info!("Reading CRDs from Helm chart {:?}", chart);
+        let helm_chart = HelmChart::load(chart)?;
+        info!(
+            "Found {} CRDs in chart {} {}",
+            helm_chart.crds.len(),
+            helm_chart.name,
+            helm_chart.version
+        );
 
-        // Generate group/version/kind structure
-        for group in package_structure.groups() {
-            let group_dir = output.join(&group);
-            fs::create_dir_all(&group_dir)?;
+        self.write_crds(package, helm_chart.crds, output)
+    }
 
-            if let Some(group_mod) = package_structure.generate_group_module(&group) {
-                fs::write(group_dir.join("mod.ncl"), group_mod)?;
-            }
+    fn generate_from_olm(
+        &self,
+        package: &PackageDefinition,
+        output: &Path,
+    ) -> Result<GeneratedPackage> {
+        let bundle = olm_bundle_path(package)?;
+
+        info!("Reading OLM bundle {:?}", bundle);
+        let olm_bundle = OlmBundle::load(bundle)?;
+        info!(
+            "Found {} owned CRDs in {}",
+            olm_bundle.crds.len(),
+            olm_bundle.name
+        );
 
-            for version in package_structure.versions(&group) {
-                let version_dir = group_dir.join(&version);
-                fs::create_dir_all(&version_dir)?;
+        let generated = self.write_crds(package, olm_bundle.crds, output)?;
+        crate::write_olm_examples(&olm_bundle.examples, output)?;
+        Ok(generated)
+    }
 
-                if let Some(version_mod) =
-                    package_structure.generate_version_module(&group, &version)
-                {
-                    fs::write(version_dir.join("mod.ncl"), version_mod)?;
-                }
+    async fn generate_from_oci(
+        &self,
+        package: &PackageDefinition,
+        output: &Path,
+        fetcher: &CRDFetcher,
+    ) -> Result<GeneratedPackage> {
+        let reference = oci_reference(package)?;
+
+        info!("Pulling CRDs from {}", reference);
+        let client = OciClient::new(fetcher.client().clone());
+        let mut artifact = client.pull(&reference).await?;
+        artifact.retain_paths(&package.paths);
+        let crds = artifact.crds()?;
+        info!(
+            "Found {} CRDs in {} ({})",
+            crds.len(),
+            reference,
+            artifact.manifest_digest
+        );
 
-                for kind in package_structure.kinds(&group, &version) {
-                    if let Some(kind_content) =
-                        package_structure.generate_kind_file(&group, &version, &kind)
-                    {
-                        fs::write(version_dir.join(format!("{}.ncl", kind)), kind_content)?;
-                    }
-                }
-            }
+        self.write_crds(package, crds, output)
+    }
+
+    /// Generate and write a package from CRDs, applying the package's filter,
+    /// overrides and transforms
+    fn write_crds(
+        &self,
+        package: &PackageDefinition,
+        crds: Vec<CRD>,
+        output: &Path,
+    ) -> Result<GeneratedPackage> {
+        let mut generator = PackageGenerator::new(package.name.clone(), output.to_path_buf())
+            .with_skip_deprecated(self.config.skip_deprecated)
+            .with_k8s_layout(self.config.k8s_layout)
+            .with_filter(package.filter.clone())
+            .with_overrides(package.overrides.clone())
+            .with_transforms(package.transforms(&self.config))
+            .with_source_comments(self.config.source_comments)
+            .with_enum_mode(self.config.enum_mode);
+        if self.config.deduplicate {
+            generator = generator.with_deduplicator(self.deduplicator(package)?);
+        }
+        if self.config.extract_nested_types {
+            generator = generator.with_nested_type_extractor(NestedTypeExtractor::new());
+        }
+        generator.add_crds(crds);
+
+        let package_structure = generator.generate_package()?;
+        crate::write_crd_package(&package_structure, output)?;
+
+        if self.config.emit_ir {
+            crate::write_ir_bundle(
+                &package_structure.to_ir(),
+                &amalgam_core::IR::bundle_path(output),
+            )?;
         }
 
-        Ok(output.to_path_buf())
+        Ok(GeneratedPackage {
+            path: output.to_path_buf(),
+            deprecations: package_structure.deprecations(),
+        })
+    }
+
+    /// Enabled k8s-core packages whose types `package` is deduplicated against
+    ///
+    /// With `deduplicate`, every other package depends on them: their IR
+    /// bundles must be written before it is generated.
+    fn core_packages_of(&self, package: &PackageDefinition) -> Vec<&PackageDefinition> {
+        if !self.config.deduplicate || package.source_type == SourceType::K8sCore {
+            return Vec::new();
+        }
+        self.packages
+            .iter()
+            .filter(|p| p.enabled && p.source_type == SourceType::K8sCore)
+            .collect()
+    }
+
+    /// IR bundle of a k8s-core package, which must have been generated
+    fn core_bundle(&self, core: &PackageDefinition) -> Result<String> {
+        let bundle = IR::bundle_path(&self.config.output_base.join(&core.output));
+        fs::read_to_string(&bundle).with_context(|| {
+            format!(
+                "Deduplication needs the IR bundle of k8s-core package {} at {}",
+                core.name,
+                bundle.display()
+            )
+        })
+    }
+
+    /// Hashes of the k8s-core IR bundles `package` is deduplicated against
+    fn core_bundle_hashes(&self, package: &PackageDefinition) -> Result<BTreeMap<String, String>> {
+        self.core_packages_of(package)
+            .into_iter()
+            .map(|core| {
+                let bundle = self.core_bundle(core)?;
+                let hash = FingerprintBuilder::new()
+                    .add_content_str(&bundle)
+                    .build()
+                    .content_hash;
+                Ok((core.name.clone(), hash))
+            })
+            .collect()
+    }
+
+    /// Deduplicator knowing the shapes of the k8s-core types `package` is
+    /// deduplicated against
+    fn deduplicator(&self, package: &PackageDefinition) -> Result<Deduplicator> {
+        let mut deduplicator = Deduplicator::new();
+        for core in self.core_packages_of(package) {
+            let ir = IR::from_json(&self.core_bundle(core)?)
+                .with_context(|| format!("Invalid IR bundle of k8s-core package {}", core.name))?;
+            deduplicator = deduplicator.with_known_types(core_type_shapes(&ir));
+        }
+        Ok(deduplicator)
     }
 
     async fn generate_from_crd(
         &self,
         package: &PackageDefinition,
         output: &Path,
-    ) -> Result<PathBuf> {
+    ) -> Result<GeneratedPackage> {
         let file = package
             .file
             .as_ref()
@@ -414,14 +1043,14 @@ impl Manifest {
         // TODO: Implement CRD file import
         // This would use the existing CRD import functionality
 
-        Ok(output.to_path_buf())
+        Ok(GeneratedPackage::new(output.to_path_buf()))
     }
 
     async fn generate_from_openapi(
         &self,
         package: &PackageDefinition,
         output: &Path,
-    ) -> Result<PathBuf> {
+    ) -> Result<GeneratedPackage> {
         let file = package
             .file
             .as_ref()
@@ -432,7 +1061,7 @@ impl Manifest {
         // TODO: Implement OpenAPI import
         // This would use the existing OpenAPI import functionality
 
-        Ok(output.to_path_buf())
+        Ok(GeneratedPackage::new(output.to_path_buf()))
     }
 
     fn generate_package_manifest(&self, package: &PackageDefinition, output: &Path) -> Result<()> {
@@ -647,12 +1276,30 @@ impl Manifest {
     }
 }
 
+/// Output of generating a single package
+struct GeneratedPackage {
+    path: PathBuf,
+    /// Deprecated API versions written into the package
+    deprecations: Vec<String>,
+}
+
+impl GeneratedPackage {
+    fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            deprecations: Vec::new(),
+        }
+    }
+}
+
 /// Report of package generation results
 #[derive(Debug, Default)]
 pub struct GenerationReport {
     pub successful: Vec<String>,
     pub failed: Vec<(String, String)>,
     pub skipped: Vec<String>,
+    /// (package, notice) for every deprecated API version that was generated
+    pub deprecations: Vec<(String, String)>,
 }
 
 impl GenerationReport {
@@ -684,6 +1331,16 @@ impl GenerationReport {
             }
         }
 
+        if !self.deprecations.is_empty() {
+            println!(
+                "\n⚠ {} deprecated API versions generated:",
+                self.deprecations.len()
+            );
+            for (name, notice) in &self.deprecations {
+                println!("  - {}: {}", name, notice);
+            }
+        }
+
         let total = self.successful.len() + self.failed.len() + self.skipped.len();
         println!("\nTotal: {} packages processed", total);
     }
@@ -0,0 +1,62 @@
+//! Rendering Nickel configurations as Kubernetes manifests
+
+use crate::validate::find_nickel_binary;
+use amalgam_codegen::nickel_render::{render_program, LIB_FILE};
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Render the resources of a Nickel file as multi-document YAML
+///
+/// Resources are found anywhere in the file's value, checked against the
+/// contracts indexed by the `lib.ncl` of each of `packages`, and ordered
+/// with CRDs and namespaces first.
+pub fn render_file(file: &Path, packages: &[PathBuf]) -> Result<String> {
+    let nickel_binary = find_nickel_binary()?;
+
+    let file = file
+        .canonicalize()
+        .with_context(|| format!("Failed to find {:?}", file))?;
+    let libs = packages
+        .iter()
+        .map(|package| {
+            let lib = package.join(LIB_FILE);
+            lib.canonicalize()
+                .map(|lib| lib.display().to_string())
+                .with_context(|| format!("No {} in package {:?}", LIB_FILE, package))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let temp_dir = tempfile::tempdir()?;
+    let program = temp_dir.path().join("render.ncl");
+    fs::write(&program, render_program(&libs, &file.display().to_string()))?;
+
+    let output = Command::new(&nickel_binary)
+        .arg("export")
+        .arg("--format")
+        .arg("json")
+        .arg(&program)
+        .output()
+        .with_context(|| format!("Failed to run {}", nickel_binary))?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "Failed to render {:?}:\n{}",
+            file,
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+
+    let documents: Vec<serde_json::Value> = serde_json::from_slice(&output.stdout)
+        .context("Nickel did not export an array of resources")?;
+    to_multi_document_yaml(&documents)
+}
+
+/// Join documents into one YAML stream, separated by `---`
+pub fn to_multi_document_yaml(documents: &[serde_json::Value]) -> Result<String> {
+    let documents = documents
+        .iter()
+        .map(serde_yaml::to_string)
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(documents.join("---\n"))
+}
@@ -31,7 +31,7 @@ pub fn run_validation_with_package_path(path: &Path, package_path: Option<&Path>
 }
 
 /// Find the nickel binary to use
-fn find_nickel_binary() -> Result<String> {
+pub(crate) fn find_nickel_binary() -> Result<String> {
     // First, check if we have a local override in nickel/
     let local_nickel = Path::new("nickel/target/release/nickel");
     if local_nickel.exists() {
@@ -371,6 +371,10 @@ impl VendorManager {
         // Write main module file
         let mod_content = package.generate_main_module();
         fs::write(package_dir.join("mod.ncl"), mod_content)?;
+        fs::write(
+            package_dir.join(amalgam_codegen::nickel_render::LIB_FILE),
+            package.generate_lib_module(),
+        )?;
 
         // Write group/version/kind structure
         for group in package.groups() {
@@ -382,6 +386,10 @@ impl VendorManager {
                 fs::write(group_dir.join("mod.ncl"), group_mod)?;
             }
 
+            if let Some(conversions) = package.generate_conversions_module(&group) {
+                fs::write(group_dir.join("conversions.ncl"), conversions)?;
+            }
+
             // Create version directories
             for version in package.versions(&group) {
                 let version_dir = group_dir.join(&version);
@@ -0,0 +1,360 @@
+//! Tests for the `amalgam import` subcommands, run through the binary
+
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use tempfile::TempDir;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+const CRD_YAML: &str = r#"apiVersion: apiextensions.k8s.io/v1
+kind: CustomResourceDefinition
+metadata:
+  name: widgets.example.com
+spec:
+  group: example.com
+  names:
+    kind: Widget
+    plural: widgets
+    singular: widget
+  versions:
+    - name: v1
+      served: true
+      storage: true
+      schema:
+        openAPIV3Schema:
+          type: object
+          properties:
+            spec:
+              type: object
+              properties:
+                size:
+                  type: integer
+"#;
+
+/// Renames `spec.size` of every type to `replicas`
+const TRANSFORMS_TOML: &str = r#"[[transforms]]
+field = "spec.size"
+action = "rename"
+to = "replicas"
+"#;
+
+const OPENAPI_SPEC: &str = r#"openapi: 3.0.0
+info:
+  title: Widgets
+  version: 1.0.0
+paths: {}
+components:
+  schemas:
+    Widget:
+      type: object
+      properties:
+        name:
+          type: string
+        policy:
+          type: string
+          enum: [Always, Never]
+"#;
+
+fn import(args: &[&str], dir: &Path) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_amalgam"))
+        .arg("import")
+        .args(args)
+        .current_dir(dir)
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8(output.stdout).unwrap()
+}
+
+/// Like [`import`], without blocking the runtime serving the mock server
+async fn import_async(args: &[&str], dir: &Path) {
+    let output = tokio::process::Command::new(env!("CARGO_BIN_EXE_amalgam"))
+        .arg("import")
+        .args(args)
+        .current_dir(dir)
+        .output()
+        .await
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+/// A temporary directory holding the transforms file
+fn transforms_dir() -> TempDir {
+    let temp_dir = TempDir::new().unwrap();
+    fs::write(temp_dir.path().join("transforms.toml"), TRANSFORMS_TOML).unwrap();
+    temp_dir
+}
+
+/// Check the Widget type generated under `out` had `spec.size` renamed
+fn assert_renamed(dir: &Path) {
+    let content = fs::read_to_string(dir.join("out/example.com/v1/widget.ncl")).unwrap();
+    assert_renamed_in(&content);
+}
+
+fn assert_renamed_in(content: &str) {
+    assert!(content.contains("replicas"), "{}", content);
+    assert!(!content.contains("size"), "{}", content);
+}
+
+fn sha256_digest(data: &[u8]) -> String {
+    format!("sha256:{:x}", Sha256::digest(data))
+}
+
+#[test]
+fn test_openapi_import_enum_mode() {
+    let temp_dir = TempDir::new().unwrap();
+    fs::write(temp_dir.path().join("spec.yaml"), OPENAPI_SPEC).unwrap();
+
+    let strings = import(&["open-api", "--file", "spec.yaml"], temp_dir.path());
+    assert!(!strings.contains("std.enum.TagOrString"), "{}", strings);
+
+    let tags = import(
+        &["open-api", "--file", "spec.yaml", "--enum-mode", "tags"],
+        temp_dir.path(),
+    );
+    assert!(
+        tags.contains("std.contract.Sequence [ std.enum.TagOrString, [| 'Always, 'Never |] ]"),
+        "{}",
+        tags
+    );
+}
+
+#[test]
+fn test_crd_import_applies_transforms() {
+    let temp_dir = transforms_dir();
+    fs::write(temp_dir.path().join("widgets.yaml"), CRD_YAML).unwrap();
+
+    let code = import(
+        &[
+            "crd",
+            "--file",
+            "widgets.yaml",
+            "--transforms",
+            "transforms.toml",
+        ],
+        temp_dir.path(),
+    );
+    assert_renamed_in(&code);
+}
+
+#[test]
+fn test_openapi_import_applies_transforms() {
+    let temp_dir = transforms_dir();
+    fs::write(temp_dir.path().join("spec.yaml"), OPENAPI_SPEC).unwrap();
+    fs::write(
+        temp_dir.path().join("transforms.toml"),
+        "[[transforms]]\ntarget = \"api/v1/Widget\"\nfield = \"name\"\naction = \"rename\"\nto = \"title\"\n",
+    )
+    .unwrap();
+
+    let code = import(
+        &[
+            "open-api",
+            "--file",
+            "spec.yaml",
+            "--transforms",
+            "transforms.toml",
+        ],
+        temp_dir.path(),
+    );
+    assert!(code.contains("title"), "{}", code);
+    assert!(!code.contains("name"), "{}", code);
+}
+
+#[test]
+fn test_helm_import_applies_transforms() {
+    let temp_dir = transforms_dir();
+    let chart = temp_dir.path().join("widgets");
+    fs::create_dir_all(chart.join("crds")).unwrap();
+    fs::write(
+        chart.join("Chart.yaml"),
+        "apiVersion: v2\nname: widgets\nversion: 0.1.0\n",
+    )
+    .unwrap();
+    fs::write(chart.join("crds/widgets.yaml"), CRD_YAML).unwrap();
+
+    import(
+        &[
+            "helm",
+            "--chart",
+            "widgets",
+            "--output",
+            "out",
+            "--transforms",
+            "transforms.toml",
+        ],
+        temp_dir.path(),
+    );
+    assert_renamed(temp_dir.path());
+}
+
+#[test]
+fn test_olm_import_applies_transforms() {
+    let temp_dir = transforms_dir();
+    let bundle = temp_dir.path().join("bundle");
+    fs::create_dir_all(bundle.join("manifests")).unwrap();
+    fs::create_dir_all(bundle.join("metadata")).unwrap();
+    fs::write(
+        bundle.join("metadata/annotations.yaml"),
+        "annotations:\n  operators.operatorframework.io.bundle.package.v1: widget-operator\n",
+    )
+    .unwrap();
+    fs::write(bundle.join("manifests/widgets.crd.yaml"), CRD_YAML).unwrap();
+    fs::write(
+        bundle.join("manifests/widget-operator.clusterserviceversion.yaml"),
+        r#"apiVersion: operators.coreos.com/v1alpha1
+kind: ClusterServiceVersion
+metadata:
+  name: widget-operator.v0.3.0
+spec:
+  version: 0.3.0
+  customresourcedefinitions:
+    owned:
+      - name: widgets.example.com
+        version: v1
+        kind: Widget
+"#,
+    )
+    .unwrap();
+
+    import(
+        &[
+            "olm",
+            "--bundle",
+            "bundle",
+            "--output",
+            "out",
+            "--transforms",
+            "transforms.toml",
+        ],
+        temp_dir.path(),
+    );
+    assert_renamed(temp_dir.path());
+}
+
+#[test]
+fn test_git_import_applies_transforms() {
+    let temp_dir = transforms_dir();
+    let repo = temp_dir.path().join("upstream");
+    fs::create_dir_all(repo.join("crds")).unwrap();
+    fs::write(repo.join("crds/widgets.yaml"), CRD_YAML).unwrap();
+    for args in [
+        &["init", "--quiet"][..],
+        &["add", "-A"],
+        &["commit", "--quiet", "-m", "widgets"],
+    ] {
+        let status = Command::new("git")
+            .arg("-C")
+            .arg(&repo)
+            .args(["-c", "user.name=test", "-c", "user.email=test@example.com"])
+            .args(args)
+            .status()
+            .unwrap();
+        assert!(status.success(), "git {:?} failed", args);
+    }
+
+    import(
+        &[
+            "git",
+            "--repo",
+            &format!("file://{}", repo.display()),
+            "--cache-dir",
+            "cache",
+            "--output",
+            "out",
+            "--transforms",
+            "transforms.toml",
+        ],
+        temp_dir.path(),
+    );
+    assert_renamed(temp_dir.path());
+}
+
+#[tokio::test]
+async fn test_url_import_applies_transforms() {
+    let temp_dir = transforms_dir();
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/widgets.yaml"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(CRD_YAML))
+        .mount(&server)
+        .await;
+
+    import_async(
+        &[
+            "url",
+            "--url",
+            &format!("{}/widgets.yaml", server.uri()),
+            "--output",
+            "out",
+            "--transforms",
+            "transforms.toml",
+        ],
+        temp_dir.path(),
+    )
+    .await;
+    assert_renamed(temp_dir.path());
+}
+
+#[tokio::test]
+async fn test_oci_import_applies_transforms() {
+    const OCI_MANIFEST: &str = "application/vnd.oci.image.manifest.v1+json";
+
+    let temp_dir = transforms_dir();
+    let server = MockServer::start().await;
+    let layer = CRD_YAML.as_bytes().to_vec();
+    let manifest = serde_json::to_vec(&serde_json::json!({
+        "schemaVersion": 2,
+        "mediaType": OCI_MANIFEST,
+        "config": {
+            "mediaType": "application/vnd.oci.empty.v1+json",
+            "digest": sha256_digest(b"{}"),
+            "size": 2
+        },
+        "layers": [{
+            "mediaType": "application/yaml",
+            "digest": sha256_digest(&layer),
+            "size": layer.len(),
+            "annotations": { "org.opencontainers.image.title": "widgets.yaml" }
+        }],
+    }))
+    .unwrap();
+    Mock::given(method("GET"))
+        .and(path("/v2/org/widgets/manifests/v1"))
+        .respond_with(ResponseTemplate::new(200).set_body_raw(manifest, OCI_MANIFEST))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path(format!(
+            "/v2/org/widgets/blobs/{}",
+            sha256_digest(&layer)
+        )))
+        .respond_with(ResponseTemplate::new(200).set_body_bytes(layer))
+        .mount(&server)
+        .await;
+
+    import_async(
+        &[
+            "oci",
+            "--reference",
+            &format!("{}/org/widgets:v1", server.uri()),
+            "--output",
+            "out",
+            "--transforms",
+            "transforms.toml",
+        ],
+        temp_dir.path(),
+    )
+    .await;
+    assert_renamed(temp_dir.path());
+}
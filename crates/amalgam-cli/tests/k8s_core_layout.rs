@@ -0,0 +1,202 @@
+//! Test the flat and grouped directory layouts of the k8s-core package
+
+use amalgam::write_k8s_core_package;
+use amalgam_codegen::nickel::EnumMode;
+use amalgam_core::{
+    ir::{TypeDefinition, API_VERSION_ANNOTATION},
+    types::{Field, Type},
+};
+use amalgam_parser::imports::{K8sLayout, TypeReference};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use tempfile::tempdir;
+
+fn record(fields: &[(&str, Type)]) -> Type {
+    Type::Record {
+        fields: fields
+            .iter()
+            .map(|(name, ty)| {
+                (
+                    name.to_string(),
+                    Field {
+                        ty: ty.clone(),
+                        required: false,
+                        description: None,
+                        default: None,
+                        read_only: false,
+                        provenance: None,
+                    },
+                )
+            })
+            .collect(),
+        open: false,
+    }
+}
+
+fn sample_types() -> HashMap<TypeReference, TypeDefinition> {
+    let reference = |name: &str| Type::Reference(name.to_string());
+    let types = [
+        (
+            "meta.k8s.io",
+            "ObjectMeta",
+            record(&[("name", Type::String)]),
+        ),
+        ("k8s.io", "PodSpec", record(&[("hostname", Type::String)])),
+        (
+            "k8s.io",
+            "PodTemplateSpec",
+            record(&[
+                ("metadata", reference("ObjectMeta")),
+                ("spec", reference("PodSpec")),
+            ]),
+        ),
+        ("k8s.io", "Event", record(&[("message", Type::String)])),
+        ("events.k8s.io", "Event", record(&[("note", Type::String)])),
+        (
+            "apps.k8s.io",
+            "Deployment",
+            record(&[
+                ("apiVersion", Type::String),
+                ("kind", Type::String),
+                ("metadata", reference("ObjectMeta")),
+                ("template", reference("PodTemplateSpec")),
+            ]),
+        ),
+    ];
+
+    types
+        .into_iter()
+        .map(|(group, kind, ty)| {
+            (
+                TypeReference::new(group.to_string(), "v1".to_string(), kind.to_string()),
+                TypeDefinition {
+                    name: kind.to_string(),
+                    ty,
+                    documentation: None,
+                    annotations: match kind {
+                        "Deployment" => {
+                            BTreeMap::from([(API_VERSION_ANNOTATION.to_string(), "apps/v1".into())])
+                        }
+                        _ => BTreeMap::new(),
+                    },
+                    provenance: None,
+                },
+            )
+        })
+        .collect()
+}
+
+#[test]
+fn test_flat_layout_shares_version_directory() {
+    let temp_dir = tempdir().expect("Failed to create temp dir");
+    let output_dir = temp_dir.path();
+
+    write_k8s_core_package(
+        sample_types(),
+        "v1.33.4",
+        output_dir,
+        false,
+        K8sLayout::Flat,
+        EnumMode::default(),
+    )
+    .expect("Failed to write k8s core package");
+
+    assert!(output_dir.join("v1/deployment.ncl").exists());
+    assert!(output_dir.join("v1/objectmeta.ncl").exists());
+    assert!(!output_dir.join("apps").exists());
+
+    let root = fs::read_to_string(output_dir.join("mod.ncl")).unwrap();
+    assert!(root.contains("v1 = import \"./v1/mod.ncl\""));
+
+    // Only resource kinds are indexed by the rendering helpers
+    let lib = fs::read_to_string(output_dir.join("lib.ncl")).unwrap();
+    assert!(lib.contains(
+        "    \"apps/v1\" = {\n      Deployment = (import \"./v1/deployment.ncl\").DeploymentInput,\n    },\n"
+    ));
+    assert!(!lib.contains("PodTemplateSpec"));
+
+    // Resources get an input variant and a constructor in their version module
+    let deployment = fs::read_to_string(output_dir.join("v1/deployment.ncl")).unwrap();
+    assert!(deployment.contains("DeploymentInput = {"), "{}", deployment);
+    let version_module = fs::read_to_string(output_dir.join("v1/mod.ncl")).unwrap();
+    assert!(version_module
+        .contains("  DeploymentInput = (import \"./deployment.ncl\").DeploymentInput,\n"));
+    assert!(
+        version_module.contains(
+            "  mk_Deployment = fun { name, ..rest } =>\n    ({ apiVersion = \"apps/v1\", kind = \"Deployment\", metadata.name = name } & rest)\n    | DeploymentInput,\n"
+        ),
+        "{}",
+        version_module
+    );
+    assert!(!version_module.contains("mk_PodTemplateSpec"));
+}
+
+#[test]
+fn test_grouped_layout_separates_api_groups() {
+    let temp_dir = tempdir().expect("Failed to create temp dir");
+    let output_dir = temp_dir.path();
+
+    let ir = write_k8s_core_package(
+        sample_types(),
+        "v1.33.4",
+        output_dir,
+        false,
+        K8sLayout::Grouped,
+        EnumMode::default(),
+    )
+    .expect("Failed to write k8s core package");
+
+    for path in [
+        "apps/v1/deployment.ncl",
+        "core/v1/podtemplatespec.ncl",
+        "core/v1/event.ncl",
+        "events/v1/event.ncl",
+        "meta/v1/objectmeta.ncl",
+        "apps/mod.ncl",
+        "apps/v1/mod.ncl",
+    ] {
+        assert!(output_dir.join(path).exists(), "{} should exist", path);
+    }
+    assert!(ir
+        .modules
+        .iter()
+        .any(|m| m.name == "apps.k8s.io.v1.deployment"));
+
+    // Types in other groups are imported across group directories
+    let deployment = fs::read_to_string(output_dir.join("apps/v1/deployment.ncl")).unwrap();
+    assert!(
+        deployment.contains("ObjectMeta = import \"../../meta/v1/objectmeta.ncl\""),
+        "Deployment should import ObjectMeta from meta/v1. Content: {}",
+        deployment
+    );
+    assert!(deployment.contains("PodTemplateSpec = import \"../../core/v1/podtemplatespec.ncl\""));
+
+    // Types in the same group and version are imported from the same directory
+    let template = fs::read_to_string(output_dir.join("core/v1/podtemplatespec.ncl")).unwrap();
+    assert!(template.contains("import \"./podspec.ncl\""));
+
+    // The root module lists groups and keeps a per-version index
+    let root = fs::read_to_string(output_dir.join("mod.ncl")).unwrap();
+    assert!(root.contains("apps = import \"./apps/mod.ncl\","));
+    assert!(root.contains("Deployment = (import \"./apps/v1/deployment.ncl\").Deployment,"));
+    assert!(root.contains("Event = (import \"./core/v1/event.ncl\").Event,"));
+    assert!(!root.contains("./events/v1/event.ncl"));
+
+    let lib = fs::read_to_string(output_dir.join("lib.ncl")).unwrap();
+    assert!(lib.contains("Deployment = (import \"./apps/v1/deployment.ncl\").DeploymentInput,"));
+}
+
+#[test]
+fn test_invalid_http_configuration_is_an_error() {
+    let temp_dir = tempdir().unwrap();
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_amalgam"))
+        .args(["import", "k8s-core", "--output"])
+        .arg(temp_dir.path().join("k8s_io"))
+        .env("AMALGAM_HTTP_CONFIG", temp_dir.path().join("missing.toml"))
+        .output()
+        .unwrap();
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success());
+    assert!(!stderr.contains("panicked"), "{}", stderr);
+}
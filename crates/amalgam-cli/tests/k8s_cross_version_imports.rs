@@ -1,6 +1,8 @@
 //! Test that k8s types properly import cross-version dependencies
 
 use amalgam::handle_k8s_core_import;
+use amalgam_codegen::nickel::EnumMode;
+use amalgam_parser::imports::K8sLayout;
 use std::fs;
 use tempfile::tempdir;
 
@@ -11,9 +13,15 @@ async fn test_k8s_cross_version_imports() {
     let output_dir = temp_dir.path();
 
     // Generate k8s core types
-    handle_k8s_core_import("v1.33.4", output_dir, true)
-        .await
-        .expect("Failed to generate k8s core types");
+    handle_k8s_core_import(
+        "v1.33.4",
+        output_dir,
+        true,
+        K8sLayout::Flat,
+        EnumMode::default(),
+    )
+    .await
+    .expect("Failed to generate k8s core types");
 
     // Check that v1 contains ObjectMeta
     let v1_objectmeta = output_dir.join("v1/objectmeta.ncl");
@@ -37,6 +37,7 @@ fn test_package_definition_creation() {
         file: None,
         version: Some("1.0.0".to_string()),
         git_ref: Some("v1.0.0".to_string()),
+        paths: Vec::new(),
         description: "Test package".to_string(),
         keywords: vec!["test".to_string()],
         dependencies: {
@@ -48,6 +49,10 @@ fn test_package_definition_creation() {
             deps
         },
         enabled: true,
+        filter: Default::default(),
+        overrides: Vec::new(),
+        transforms: Vec::new(),
+        push: None,
     };
 
     assert_eq!(package.name, "test-package");
@@ -64,6 +69,15 @@ fn test_manifest_config_creation() {
         base_package_id: "github:test/packages".to_string(),
         package_mode: true,
         local_package_prefix: None,
+        emit_ir: false,
+        skip_deprecated: false,
+        k8s_layout: Default::default(),
+        deduplicate: false,
+        extract_nested_types: false,
+        source_comments: false,
+        enum_mode: Default::default(),
+        transforms: Vec::new(),
+        cache_dir: None,
     };
 
     assert_eq!(config.base_package_id, "github:test/packages");
@@ -89,6 +103,7 @@ mod end_to_end_tests {
             file: None,
             version: Some("1.0.0".to_string()),
             git_ref: Some("v1.0.0".to_string()),
+            paths: Vec::new(),
             description: "Test package".to_string(),
             keywords: vec!["test".to_string()],
             dependencies: {
@@ -100,6 +115,10 @@ mod end_to_end_tests {
                 deps
             },
             enabled: true,
+            filter: Default::default(),
+            overrides: Vec::new(),
+            transforms: Vec::new(),
+            push: None,
         };
 
         // Test that we can create the package structure
@@ -116,3 +135,508 @@ mod end_to_end_tests {
         assert!(pkg_dir.join("mod.ncl").exists());
     }
 }
+
+#[tokio::test]
+async fn test_generate_all_follows_dependencies() {
+    use amalgam::manifest::Manifest;
+
+    let temp_dir = TempDir::new().unwrap();
+    let manifest_path = temp_dir.path().join("manifest.toml");
+    let package = |name: &str, source: &str, deps: &str| {
+        format!(
+            "[[packages]]\nname = \"{name}\"\ntype = \"{source}\"\nfile = \"{name}.yaml\"\n\
+             output = \"{name}\"\ndescription = \"\"\nkeywords = []\ndependencies = {{ {deps} }}\n"
+        )
+    };
+    let content = [
+        format!(
+            "[config]\noutput_base = {:?}\nbase_package_id = \"github:test/pkgs\"\npackage_mode = false\n",
+            temp_dir.path().join("out")
+        ),
+        package("base", "crd", ""),
+        package("child", "crd", "base = \"1.0.0\""),
+        // A url package without a url fails to generate
+        package("broken", "url", ""),
+        package("dependent", "crd", "broken = \"1.0.0\""),
+        package("loop-a", "crd", "loop-b = \"1.0.0\""),
+        package("loop-b", "crd", "loop-a = \"1.0.0\""),
+    ]
+    .join("\n");
+    fs::write(&manifest_path, content).unwrap();
+
+    let manifest = Manifest::from_file(&manifest_path).unwrap();
+    let report = manifest.generate_all(2).await.unwrap();
+
+    let mut successful = report.successful.clone();
+    successful.sort();
+    assert_eq!(successful, vec!["base", "child"]);
+
+    let failure = |name: &str| {
+        report
+            .failed
+            .iter()
+            .find(|(package, _)| package == name)
+            .map(|(_, reason)| reason.clone())
+            .unwrap_or_else(|| panic!("{} should have failed", name))
+    };
+    assert!(failure("broken").contains("URL required"));
+    assert_eq!(failure("dependent"), "dependency broken failed");
+    assert_eq!(failure("loop-a"), "dependency cycle");
+    assert_eq!(failure("loop-b"), "dependency cycle");
+}
+
+#[tokio::test]
+async fn test_generate_helm_package() {
+    use amalgam::manifest::Manifest;
+
+    let temp_dir = TempDir::new().unwrap();
+    let chart_dir = temp_dir.path().join("widgets");
+    fs::create_dir_all(chart_dir.join("crds")).unwrap();
+    fs::write(
+        chart_dir.join("Chart.yaml"),
+        "apiVersion: v2\nname: widgets\nversion: 0.4.0\n",
+    )
+    .unwrap();
+    fs::write(
+        chart_dir.join("crds/widgets.yaml"),
+        r#"apiVersion: apiextensions.k8s.io/v1
+kind: CustomResourceDefinition
+metadata:
+  name: widgets.example.com
+spec:
+  group: example.com
+  names:
+    kind: Widget
+    plural: widgets
+    singular: widget
+  versions:
+    - name: v1
+      served: true
+      storage: true
+      schema:
+        openAPIV3Schema:
+          type: object
+          properties:
+            spec:
+              type: object
+              properties:
+                size:
+                  type: integer
+"#,
+    )
+    .unwrap();
+
+    let manifest_path = temp_dir.path().join("manifest.toml");
+    fs::write(
+        &manifest_path,
+        format!(
+            "[config]\noutput_base = {:?}\nbase_package_id = \"github:test/pkgs\"\npackage_mode = false\n\n\
+             [[packages]]\nname = \"widgets\"\ntype = \"helm\"\nfile = {:?}\noutput = \"widgets\"\n\
+             description = \"\"\nkeywords = []\n",
+            temp_dir.path().join("out"),
+            chart_dir
+        ),
+    )
+    .unwrap();
+
+    let manifest = Manifest::from_file(&manifest_path).unwrap();
+    assert_eq!(manifest.packages[0].source_type, SourceType::Helm);
+
+    let report = manifest.generate_all(1).await.unwrap();
+    assert_eq!(report.successful, vec!["widgets"], "{:?}", report.failed);
+
+    let kind_file = temp_dir
+        .path()
+        .join("out/widgets/example.com/v1/widget.ncl");
+    let content = fs::read_to_string(&kind_file).expect("Widget should be generated");
+    assert!(content.contains("size"));
+}
+
+#[tokio::test]
+async fn test_toggling_skip_deprecated_regenerates() {
+    use amalgam::manifest::Manifest;
+
+    let temp_dir = TempDir::new().unwrap();
+    let chart_dir = temp_dir.path().join("widgets");
+    fs::create_dir_all(chart_dir.join("crds")).unwrap();
+    fs::write(
+        chart_dir.join("Chart.yaml"),
+        "apiVersion: v2\nname: widgets\nversion: 0.4.0\n",
+    )
+    .unwrap();
+    fs::write(
+        chart_dir.join("crds/widgets.yaml"),
+        r#"apiVersion: apiextensions.k8s.io/v1
+kind: CustomResourceDefinition
+metadata:
+  name: widgets.example.com
+spec:
+  group: example.com
+  names:
+    kind: Widget
+    plural: widgets
+    singular: widget
+  versions:
+    - name: v1beta1
+      served: true
+      storage: false
+      deprecated: true
+      schema:
+        openAPIV3Schema:
+          type: object
+    - name: v1
+      served: true
+      storage: true
+      schema:
+        openAPIV3Schema:
+          type: object
+"#,
+    )
+    .unwrap();
+
+    let manifest_path = temp_dir.path().join("manifest.toml");
+    let write_manifest = |skip_deprecated: bool| {
+        fs::write(
+            &manifest_path,
+            format!(
+                "[config]\noutput_base = {:?}\nbase_package_id = \"github:test/pkgs\"\n\
+                 package_mode = true\nskip_deprecated = {}\n\n\
+                 [[packages]]\nname = \"widgets\"\ntype = \"helm\"\nfile = {:?}\noutput = \"widgets\"\n\
+                 description = \"\"\nkeywords = []\n",
+                temp_dir.path().join("out"),
+                skip_deprecated,
+                chart_dir
+            ),
+        )
+        .unwrap();
+    };
+    let group_module = temp_dir.path().join("out/widgets/example.com/mod.ncl");
+
+    write_manifest(false);
+    let report = Manifest::from_file(&manifest_path)
+        .unwrap()
+        .generate_all(1)
+        .await
+        .unwrap();
+    assert_eq!(report.successful, vec!["widgets"], "{:?}", report.failed);
+    assert!(fs::read_to_string(&group_module)
+        .unwrap()
+        .contains("v1beta1"));
+
+    // Only the setting changed, yet the package must be regenerated
+    write_manifest(true);
+    let report = Manifest::from_file(&manifest_path)
+        .unwrap()
+        .generate_all(1)
+        .await
+        .unwrap();
+    assert_eq!(report.successful, vec!["widgets"], "{:?}", report.failed);
+    assert!(!fs::read_to_string(&group_module)
+        .unwrap()
+        .contains("v1beta1"));
+}
+
+#[tokio::test]
+async fn test_generate_deduplicated_package() {
+    use amalgam::manifest::Manifest;
+
+    let temp_dir = TempDir::new().unwrap();
+    let chart_dir = temp_dir.path().join("storage");
+    fs::create_dir_all(chart_dir.join("crds")).unwrap();
+    fs::write(
+        chart_dir.join("Chart.yaml"),
+        "apiVersion: v2\nname: storage\nversion: 1.0.0\n",
+    )
+    .unwrap();
+    for kind in ["Bucket", "Database"] {
+        fs::write(
+            chart_dir.join(format!("crds/{}.yaml", kind.to_lowercase())),
+            format!(
+                r#"apiVersion: apiextensions.k8s.io/v1
+kind: CustomResourceDefinition
+metadata:
+  name: {plural}.example.com
+spec:
+  group: example.com
+  names:
+    kind: {kind}
+    plural: {plural}
+    singular: {singular}
+  versions:
+    - name: v1
+      served: true
+      storage: true
+      schema:
+        openAPIV3Schema:
+          type: object
+          properties:
+            spec:
+              type: object
+              properties:
+                credentialsRef:
+                  type: object
+                  required: [name, key]
+                  properties:
+                    name:
+                      type: string
+                    key:
+                      type: string
+"#,
+                kind = kind,
+                plural = format!("{}s", kind.to_lowercase()),
+                singular = kind.to_lowercase(),
+            ),
+        )
+        .unwrap();
+    }
+
+    let manifest_path = temp_dir.path().join("manifest.toml");
+    fs::write(
+        &manifest_path,
+        format!(
+            "[config]\noutput_base = {:?}\nbase_package_id = \"github:test/pkgs\"\npackage_mode = false\n\
+             deduplicate = true\n\n\
+             [[packages]]\nname = \"storage\"\ntype = \"helm\"\nfile = {:?}\noutput = \"storage\"\n\
+             description = \"\"\nkeywords = []\n",
+            temp_dir.path().join("out"),
+            chart_dir
+        ),
+    )
+    .unwrap();
+
+    let manifest = Manifest::from_file(&manifest_path).unwrap();
+    let report = manifest.generate_all(1).await.unwrap();
+    assert_eq!(report.successful, vec!["storage"], "{:?}", report.failed);
+
+    let version_dir = temp_dir.path().join("out/storage/example.com/v1");
+    let shared = fs::read_to_string(version_dir.join("credentialsref.ncl"))
+        .expect("CredentialsRef should be hoisted into its own file");
+    assert!(shared.contains("CredentialsRef"));
+    for kind in ["bucket", "database"] {
+        let content = fs::read_to_string(version_dir.join(format!("{}.ncl", kind))).unwrap();
+        assert!(
+            content.contains("import \"./credentialsref.ncl\""),
+            "{}",
+            content
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_deduplicated_packages_wait_for_k8s_core() {
+    use amalgam::manifest::Manifest;
+
+    let temp_dir = TempDir::new().unwrap();
+    let chart_dir = temp_dir.path().join("widgets");
+    fs::create_dir_all(chart_dir.join("crds")).unwrap();
+    fs::write(
+        chart_dir.join("Chart.yaml"),
+        "apiVersion: v2\nname: widgets\nversion: 0.1.0\n",
+    )
+    .unwrap();
+
+    // The k8s-core package fails, as no such Kubernetes version exists
+    let manifest_path = temp_dir.path().join("manifest.toml");
+    fs::write(
+        &manifest_path,
+        format!(
+            "[config]\noutput_base = {:?}\nbase_package_id = \"github:test/pkgs\"\npackage_mode = false\n\
+             deduplicate = true\n\n\
+             [[packages]]\nname = \"widgets\"\ntype = \"helm\"\nfile = {:?}\noutput = \"widgets\"\n\
+             description = \"\"\nkeywords = []\n\n\
+             [[packages]]\nname = \"k8s\"\ntype = \"k8s-core\"\nversion = \"v0.0.0-missing\"\n\
+             output = \"k8s_io\"\ndescription = \"\"\nkeywords = []\n",
+            temp_dir.path().join("out"),
+            chart_dir
+        ),
+    )
+    .unwrap();
+
+    let manifest = Manifest::from_file(&manifest_path).unwrap();
+    let report = manifest.generate_all(2).await.unwrap();
+    assert!(report.successful.is_empty(), "{:?}", report.successful);
+    assert!(
+        report
+            .failed
+            .contains(&("widgets".to_string(), "dependency k8s failed".to_string())),
+        "{:?}",
+        report.failed
+    );
+}
+
+#[tokio::test]
+async fn test_generate_olm_bundle_package() {
+    use amalgam::manifest::Manifest;
+
+    let temp_dir = TempDir::new().unwrap();
+    let bundle_dir = temp_dir.path().join("bundle");
+    fs::create_dir_all(bundle_dir.join("manifests")).unwrap();
+    fs::create_dir_all(bundle_dir.join("metadata")).unwrap();
+    fs::write(
+        bundle_dir.join("metadata/annotations.yaml"),
+        "annotations:\n  operators.operatorframework.io.bundle.package.v1: widget-operator\n",
+    )
+    .unwrap();
+    fs::write(
+        bundle_dir.join("manifests/widgets.crd.yaml"),
+        r#"apiVersion: apiextensions.k8s.io/v1
+kind: CustomResourceDefinition
+metadata:
+  name: widgets.example.com
+spec:
+  group: example.com
+  names:
+    kind: Widget
+    plural: widgets
+    singular: widget
+  versions:
+    - name: v1
+      served: true
+      storage: true
+      schema:
+        openAPIV3Schema:
+          type: object
+          properties:
+            spec:
+              type: object
+              properties:
+                size:
+                  type: integer
+"#,
+    )
+    .unwrap();
+    fs::write(
+        bundle_dir.join("manifests/widget-operator.clusterserviceversion.yaml"),
+        r#"apiVersion: operators.coreos.com/v1alpha1
+kind: ClusterServiceVersion
+metadata:
+  name: widget-operator.v0.3.0
+  annotations:
+    alm-examples: '[{"apiVersion": "example.com/v1", "kind": "Widget", "metadata": {"name": "small"}, "spec": {"size": 1}}]'
+spec:
+  version: 0.3.0
+  customresourcedefinitions:
+    owned:
+      - name: widgets.example.com
+        version: v1
+        kind: Widget
+        specDescriptors:
+          - path: size
+            displayName: Size
+            description: Number of widget replicas
+"#,
+    )
+    .unwrap();
+
+    let manifest_path = temp_dir.path().join("manifest.toml");
+    fs::write(
+        &manifest_path,
+        format!(
+            "[config]\noutput_base = {:?}\nbase_package_id = \"github:test/pkgs\"\npackage_mode = false\n\n\
+             [[packages]]\nname = \"widgets\"\ntype = \"olm\"\nfile = {:?}\noutput = \"widgets\"\n\
+             description = \"\"\nkeywords = []\n",
+            temp_dir.path().join("out"),
+            bundle_dir
+        ),
+    )
+    .unwrap();
+
+    let manifest = Manifest::from_file(&manifest_path).unwrap();
+    assert_eq!(manifest.packages[0].source_type, SourceType::Olm);
+
+    let report = manifest.generate_all(1).await.unwrap();
+    assert_eq!(report.successful, vec!["widgets"], "{:?}", report.failed);
+
+    let version_dir = temp_dir.path().join("out/widgets/example.com/v1");
+    let content = fs::read_to_string(version_dir.join("widget.ncl")).unwrap();
+    assert!(content.contains("Number of widget replicas"));
+
+    let example = fs::read_to_string(version_dir.join("examples/small.ncl"))
+        .expect("alm-examples should be written");
+    assert!(example.contains("size = 1"));
+    assert!(example.contains("| widget.Widget"));
+}
+
+#[tokio::test]
+async fn test_generate_git_package_at_ref() {
+    use amalgam::manifest::Manifest;
+    use std::process::Command;
+
+    let temp_dir = TempDir::new().unwrap();
+    let repo = temp_dir.path().join("upstream");
+    let git = |args: &[&str]| {
+        let status = Command::new("git")
+            .arg("-C")
+            .arg(&repo)
+            .args(["-c", "user.name=test", "-c", "user.email=test@example.com"])
+            .args(args)
+            .status()
+            .unwrap();
+        assert!(status.success(), "git {:?} failed", args);
+    };
+    let crd = |size_type: &str| {
+        format!(
+            r#"apiVersion: apiextensions.k8s.io/v1
+kind: CustomResourceDefinition
+metadata:
+  name: widgets.example.com
+spec:
+  group: example.com
+  names:
+    kind: Widget
+    plural: widgets
+    singular: widget
+  versions:
+    - name: v1
+      served: true
+      storage: true
+      schema:
+        openAPIV3Schema:
+          type: object
+          properties:
+            size:
+              type: {}
+"#,
+            size_type
+        )
+    };
+
+    fs::create_dir_all(repo.join("crds")).unwrap();
+    git(&["init", "--quiet"]);
+    fs::write(repo.join("crds/widgets.yaml"), crd("integer")).unwrap();
+    git(&["add", "-A"]);
+    git(&["commit", "--quiet", "-m", "v1"]);
+    git(&["tag", "v1.0.0"]);
+    fs::write(repo.join("crds/widgets.yaml"), crd("string")).unwrap();
+    git(&["commit", "--quiet", "-am", "v2"]);
+
+    let manifest_path = temp_dir.path().join("manifest.toml");
+    fs::write(
+        &manifest_path,
+        format!(
+            "[config]\noutput_base = {:?}\nbase_package_id = \"github:test/pkgs\"\npackage_mode = false\n\
+             cache_dir = {:?}\n\n\
+             [[packages]]\nname = \"widgets\"\ntype = \"git\"\nurl = \"file://{}\"\ngit_ref = \"v1.0.0\"\n\
+             paths = [\"crds/*.yaml\"]\noutput = \"widgets\"\ndescription = \"\"\nkeywords = []\n",
+            temp_dir.path().join("out"),
+            temp_dir.path().join("cache"),
+            repo.display()
+        ),
+    )
+    .unwrap();
+
+    let manifest = Manifest::from_file(&manifest_path).unwrap();
+    assert_eq!(manifest.packages[0].source_type, SourceType::Git);
+
+    let report = manifest.generate_all(1).await.unwrap();
+    assert_eq!(report.successful, vec!["widgets"], "{:?}", report.failed);
+
+    // The tagged commit is generated, not the newer branch head
+    let content = fs::read_to_string(
+        temp_dir
+            .path()
+            .join("out/widgets/example.com/v1/widget.ncl"),
+    )
+    .unwrap();
+    assert!(content.contains("Number"), "Content: {}", content);
+}
@@ -3,6 +3,8 @@
 //! NOTE: These tests use the Nickel CLI binary for validation, not the library API.
 //! The nickel-lang-core library API is unstable and changes frequently between versions.
 //! For actual validation, we rely on the CLI implementation in src/validate.rs
+//!
+//! They fail without the CLI; set `AMALGAM_SKIP_NICKEL_TESTS=1` to skip them.
 
 use std::fs;
 use std::path::{Path, PathBuf};
@@ -18,6 +20,21 @@ fn nickel_cli_available() -> bool {
         .unwrap_or(false)
 }
 
+/// Whether the nickel CLI is there to run a test with
+///
+/// A missing CLI fails the test, unless `AMALGAM_SKIP_NICKEL_TESTS` is set,
+/// in which case the test reports itself skipped.
+fn require_nickel() -> bool {
+    if nickel_cli_available() {
+        return true;
+    }
+    if std::env::var_os("AMALGAM_SKIP_NICKEL_TESTS").is_some() {
+        eprintln!("SKIPPED: Nickel CLI not available (AMALGAM_SKIP_NICKEL_TESTS is set)");
+        return false;
+    }
+    panic!("Nickel CLI not available; install it, or set AMALGAM_SKIP_NICKEL_TESTS=1 to skip");
+}
+
 /// Test helper to validate a Nickel file using the CLI
 fn validate_nickel_file_cli(file: &Path) -> Result<(), String> {
     if !nickel_cli_available() {
@@ -45,8 +62,7 @@ mod tests {
     /// This test verifies our validation approach works
     #[test]
     fn test_simple_nickel_validation() {
-        if !nickel_cli_available() {
-            eprintln!("Skipping test: Nickel CLI not available");
+        if !require_nickel() {
             return;
         }
 
@@ -64,8 +80,7 @@ mod tests {
 
     #[test]
     fn test_validate_k8s_io_package() {
-        if !nickel_cli_available() {
-            eprintln!("Skipping test: Nickel CLI not available");
+        if !require_nickel() {
             return;
         }
 
@@ -100,8 +115,7 @@ mod tests {
 
     #[test]
     fn test_validate_crossplane_package() {
-        if !nickel_cli_available() {
-            eprintln!("Skipping test: Nickel CLI not available");
+        if !require_nickel() {
             return;
         }
 
@@ -136,8 +150,7 @@ mod tests {
 
     #[test]
     fn test_validate_individual_files() {
-        if !nickel_cli_available() {
-            eprintln!("Skipping test: Nickel CLI not available");
+        if !require_nickel() {
             return;
         }
 
@@ -176,8 +189,7 @@ mod tests {
 
     #[test]
     fn test_import_resolution() {
-        if !nickel_cli_available() {
-            eprintln!("Skipping test: Nickel CLI not available");
+        if !require_nickel() {
             return;
         }
 
@@ -219,8 +231,7 @@ mod tests {
 
     #[test]
     fn test_cross_package_imports() {
-        if !nickel_cli_available() {
-            eprintln!("Skipping test: Nickel CLI not available");
+        if !require_nickel() {
             return;
         }
 
@@ -249,4 +260,234 @@ mod tests {
             }
         }
     }
+
+    /// Export `value` checked against a type of the generated `types.ncl`
+    fn export_checked(dir: &Path, contract: &str, value: &str) -> Result<(), String> {
+        let file = dir.join("check.ncl");
+        fs::write(
+            &file,
+            format!(
+                "let types = import \"types.ncl\" in\n({}) | types.{}\n",
+                value, contract
+            ),
+        )
+        .unwrap();
+        let output = Command::new("nickel")
+            .arg("export")
+            .arg(&file)
+            .output()
+            .map_err(|e| format!("Failed to run nickel: {}", e))?;
+        if output.status.success() {
+            Ok(())
+        } else {
+            Err(String::from_utf8_lossy(&output.stderr).to_string())
+        }
+    }
+
+    #[test]
+    fn test_contracts_and_tagged_unions_evaluate() {
+        use amalgam_codegen::{nickel::NickelCodegen, Codegen};
+        use amalgam_core::ir::IRBuilder;
+        use amalgam_core::types::{Field, Type};
+        use std::collections::BTreeMap;
+
+        if !require_nickel() {
+            return;
+        }
+
+        let field = |ty: Type| Field {
+            ty,
+            required: true,
+            description: None,
+            default: None,
+            read_only: false,
+            provenance: None,
+        };
+        let record = |name: &str, ty: Type| Type::Record {
+            fields: BTreeMap::from([(name.to_string(), field(ty))]),
+            open: false,
+        };
+        let ir = IRBuilder::new()
+            .module("contracts")
+            .add_type(
+                "Port",
+                Type::Contract {
+                    base: Box::new(Type::Integer),
+                    predicate: "fun port => port > 0 && port < 65536".to_string(),
+                },
+            )
+            .add_type(
+                "Name",
+                Type::Contract {
+                    base: Box::new(Type::String),
+                    predicate: "std.string.NonEmpty".to_string(),
+                },
+            )
+            .add_type(
+                "Source",
+                Type::TaggedUnion {
+                    tag_field: "kind".to_string(),
+                    variants: BTreeMap::from([
+                        ("git".to_string(), record("url", Type::String)),
+                        ("s3".to_string(), record("bucket", Type::String)),
+                    ]),
+                },
+            )
+            .build();
+
+        let temp_dir = TempDir::new().unwrap();
+        let code = NickelCodegen::new().generate(&ir).unwrap();
+        fs::write(temp_dir.path().join("types.ncl"), &code).unwrap();
+        let dir = temp_dir.path();
+
+        export_checked(dir, "Port", "8080").unwrap();
+        assert!(export_checked(dir, "Port", "70000").is_err());
+        export_checked(dir, "Name", "\"web\"").unwrap();
+        assert!(export_checked(dir, "Name", "\"\"").is_err());
+        export_checked(
+            dir,
+            "Source",
+            "{ kind = \"git\", url = \"https://example.com\" }",
+        )
+        .unwrap();
+        export_checked(dir, "Source", "{ kind = \"s3\", bucket = \"artifacts\" }").unwrap();
+        assert!(
+            export_checked(dir, "Source", "{ kind = \"git\", bucket = \"artifacts\" }").is_err()
+        );
+        assert!(export_checked(dir, "Source", "{ kind = \"svn\", url = \"x\" }").is_err());
+        assert!(export_checked(dir, "Source", "{ url = \"x\" }").is_err());
+    }
+
+    #[test]
+    fn test_enum_modes_evaluate() {
+        use amalgam_codegen::{
+            nickel::{EnumMode, NickelCodegen},
+            Codegen,
+        };
+        use amalgam_core::ir::IRBuilder;
+        use amalgam_core::types::Type;
+
+        if !require_nickel() {
+            return;
+        }
+
+        let ir = IRBuilder::new()
+            .module("enums")
+            .add_type(
+                "RestartPolicy",
+                Type::Enum(vec!["Always".into(), "OnFailure".into(), "Never".into()]),
+            )
+            .build();
+
+        for mode in [EnumMode::Strings, EnumMode::Tags] {
+            let temp_dir = TempDir::new().unwrap();
+            let code = NickelCodegen::new()
+                .with_enum_mode(mode)
+                .generate(&ir)
+                .unwrap();
+            fs::write(temp_dir.path().join("types.ncl"), &code).unwrap();
+            let dir = temp_dir.path();
+
+            export_checked(dir, "RestartPolicy", "\"Always\"").unwrap();
+            assert!(export_checked(dir, "RestartPolicy", "\"Sometimes\"").is_err());
+            if mode == EnumMode::Tags {
+                export_checked(dir, "RestartPolicy", "'Never").unwrap();
+                assert!(export_checked(dir, "RestartPolicy", "'Sometimes").is_err());
+            }
+        }
+    }
+
+    #[test]
+    fn test_documents_join_as_yaml_stream() {
+        let yaml = amalgam::render::to_multi_document_yaml(&[
+            serde_json::json!({ "apiVersion": "v1", "kind": "Namespace" }),
+            serde_json::json!({ "apiVersion": "v1", "kind": "ConfigMap" }),
+        ])
+        .unwrap();
+        assert_eq!(
+            yaml,
+            "apiVersion: v1\nkind: Namespace\n---\napiVersion: v1\nkind: ConfigMap\n"
+        );
+    }
+
+    #[test]
+    fn test_render_orders_and_checks_resources() {
+        use amalgam_parser::{crd::CRD, package::PackageGenerator};
+
+        if !require_nickel() {
+            return;
+        }
+
+        let crd: CRD = serde_yaml::from_str(
+            r#"
+apiVersion: apiextensions.k8s.io/v1
+kind: CustomResourceDefinition
+metadata:
+  name: caches.test.io
+spec:
+  group: test.io
+  names:
+    kind: Cache
+    plural: caches
+    singular: cache
+  versions:
+  - name: v1
+    served: true
+    storage: true
+    schema:
+      openAPIV3Schema:
+        type: object
+        properties:
+          apiVersion:
+            type: string
+          kind:
+            type: string
+          metadata:
+            type: object
+            properties:
+              name:
+                type: string
+          spec:
+            type: object
+            properties:
+              size:
+                type: integer
+"#,
+        )
+        .unwrap();
+
+        let temp_dir = TempDir::new().unwrap();
+        let package_dir = temp_dir.path().join("caches");
+        let mut generator = PackageGenerator::new("caches".to_string(), package_dir.clone());
+        generator.add_crd(crd);
+        amalgam::write_crd_package(&generator.generate_package().unwrap(), &package_dir).unwrap();
+
+        let packages = [package_dir];
+        let config = temp_dir.path().join("config.ncl");
+        fs::write(
+            &config,
+            r#"let caches = import "caches/test.io/v1/mod.ncl" in
+{
+  app = {
+    cache = caches.mk_Cache { name = "sessions", spec.size = 3 },
+  },
+  namespace = { apiVersion = "v1", kind = "Namespace", metadata.name = "app" },
+}
+"#,
+        )
+        .unwrap();
+        let yaml = amalgam::render::render_file(&config, &packages).unwrap();
+        let documents: Vec<&str> = yaml.split("---\n").collect();
+        assert_eq!(documents.len(), 2);
+        assert!(documents[0].contains("kind: Namespace"));
+        assert!(documents[1].contains("kind: Cache"));
+        assert!(documents[1].contains("size: 3"));
+
+        fs::write(
+            &config,
+            r#"{ cache = { apiVersion = "test.io/v1", kind = "Cache", spec.size = "big" } }"#,
+        )
+        .unwrap();
+        assert!(amalgam::render::render_file(&config, &packages).is_err());
+    }
 }
@@ -0,0 +1,55 @@
+//! Test loading the schemas compared by `amalgam diff`
+
+use amalgam::diff::load_ir;
+use amalgam_core::ir::IR_FORMAT_VERSION;
+use std::fs;
+use tempfile::tempdir;
+
+#[test]
+fn test_invalid_ir_bundles_are_errors() {
+    let temp_dir = tempdir().unwrap();
+
+    let newer = temp_dir.path().join("newer.json");
+    fs::write(
+        &newer,
+        format!(
+            r#"{{"format_version": {}, "modules": []}}"#,
+            IR_FORMAT_VERSION + 1
+        ),
+    )
+    .unwrap();
+    let error = format!("{:#}", load_ir(&newer).unwrap_err());
+    assert!(error.contains("newer.json"), "{}", error);
+    assert!(error.contains("format version"), "{}", error);
+
+    let malformed = temp_dir.path().join("malformed.json");
+    fs::write(&malformed, r#"{"modules": "none"}"#).unwrap();
+    assert!(load_ir(&malformed).is_err());
+}
+
+#[test]
+fn test_json_crds_are_parsed() {
+    let temp_dir = tempdir().unwrap();
+    let crd = temp_dir.path().join("widgets.json");
+    fs::write(
+        &crd,
+        serde_json::json!({
+            "apiVersion": "apiextensions.k8s.io/v1",
+            "kind": "CustomResourceDefinition",
+            "metadata": { "name": "widgets.example.io" },
+            "spec": {
+                "group": "example.io",
+                "names": { "kind": "Widget", "plural": "widgets", "singular": "widget" },
+                "versions": [{
+                    "name": "v1",
+                    "served": true,
+                    "storage": true,
+                    "schema": { "openAPIV3Schema": { "type": "object" } }
+                }]
+            }
+        })
+        .to_string(),
+    )
+    .unwrap();
+    assert!(load_ir(&crd).unwrap().find_type("Widget").is_some());
+}
@@ -1,3 +1,4 @@
+use amalgam_core::Provenance;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -16,4 +17,24 @@ pub enum CodegenError {
 
     #[error("Format error: {0}")]
     Fmt(#[from] std::fmt::Error),
+
+    #[error("{type_name} ({location}): {source}")]
+    InType {
+        type_name: String,
+        location: String,
+        source: Box<CodegenError>,
+    },
+}
+
+impl CodegenError {
+    /// Name the type, and where it came from, that failed to generate
+    pub fn in_type(self, type_name: &str, provenance: Option<&Provenance>) -> Self {
+        CodegenError::InType {
+            type_name: type_name.to_string(),
+            location: provenance
+                .map(ToString::to_string)
+                .unwrap_or_else(|| "unknown source".to_string()),
+            source: Box::new(self),
+        }
+    }
 }
@@ -9,11 +9,22 @@ use std::fmt::Write;
 
 pub struct GoCodegen {
     indent_size: usize,
+    source_comments: bool,
 }
 
 impl GoCodegen {
     pub fn new() -> Self {
-        Self { indent_size: 4 }
+        Self {
+            indent_size: 4,
+            source_comments: false,
+        }
+    }
+
+    /// Precede types and fields with a `// Source:` comment naming where
+    /// they were generated from
+    pub fn with_source_comments(mut self, enabled: bool) -> Self {
+        self.source_comments = enabled;
+        self
     }
 
     fn indent(&self, level: usize) -> String {
@@ -69,10 +80,10 @@ impl GoCodegen {
 
             Type::Reference(name) => Ok(name.clone()),
 
-            Type::Contract { .. } => {
-                // Contracts become interfaces in Go
-                Ok("interface{}".to_string())
-            }
+            // Go cannot check the predicate, only the type it refines
+            Type::Contract { base, .. } => self.type_to_go(base),
+
+            Type::Enum(values) => self.type_to_go(&Type::enum_base(values)),
         }
     }
 
@@ -100,6 +111,9 @@ impl GoCodegen {
             result.push_str(&format!(" `{}`", tags.join(" ")));
         }
 
+        if let Some(at) = field.provenance.as_ref().filter(|_| self.source_comments) {
+            result = format!("{}// Source: {}\n{}", indent, at, result);
+        }
         if let Some(desc) = &field.description {
             result = format!("{}// {}\n{}", indent, desc, result);
         }
@@ -151,11 +165,26 @@ impl Codegen for GoCodegen {
             // Generate type definitions
             for type_def in &module.types {
                 if let Some(doc) = &type_def.documentation {
-                    writeln!(output, "// {}", doc)
+                    for line in doc.lines() {
+                        writeln!(output, "// {}", line)
+                            .map_err(|e| CodegenError::Generation(e.to_string()))?;
+                    }
+                }
+                if let Some(notice) = type_def.deprecation_notice() {
+                    writeln!(output, "// Deprecated: {}", notice)
                         .map_err(|e| CodegenError::Generation(e.to_string()))?;
                 }
+                if let Some(at) = type_def
+                    .provenance
+                    .as_ref()
+                    .filter(|_| self.source_comments)
+                {
+                    writeln!(output, "// Source: {}", at)?;
+                }
 
-                let type_str = self.type_to_go(&type_def.ty)?;
+                let type_str = self
+                    .type_to_go(&type_def.ty)
+                    .map_err(|e| e.in_type(&type_def.name, type_def.provenance.as_ref()))?;
                 writeln!(output, "type {} {}", type_def.name, type_str)
                     .map_err(|e| CodegenError::Generation(e.to_string()))?;
                 writeln!(output).map_err(|e| CodegenError::Generation(e.to_string()))?;
@@ -4,6 +4,7 @@ pub mod error;
 pub mod go;
 pub mod nickel;
 pub mod nickel_package;
+pub mod nickel_render;
 pub mod package_mode;
 pub mod resolver;
 
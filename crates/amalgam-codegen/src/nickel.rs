@@ -7,12 +7,45 @@ use amalgam_core::{
     types::{Field, Type},
     IR,
 };
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::fmt::Write;
+use std::str::FromStr;
+
+/// How schema enums are represented in generated Nickel
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EnumMode {
+    /// Plain strings, checked against the allowed values:
+    /// `restartPolicy = "Always"`
+    #[default]
+    Strings,
+    /// Enum tags, also accepting the allowed strings, which export as
+    /// strings: `restartPolicy = 'Always`
+    Tags,
+}
+
+impl FromStr for EnumMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "strings" => Ok(EnumMode::Strings),
+            "tags" => Ok(EnumMode::Tags),
+            other => Err(format!(
+                "unknown enum mode '{}', expected 'strings' or 'tags'",
+                other
+            )),
+        }
+    }
+}
 
 pub struct NickelCodegen {
     indent_size: usize,
     resolver: TypeResolver,
     package_mode: PackageMode,
+    source_comments: bool,
+    enum_mode: EnumMode,
 }
 
 impl NickelCodegen {
@@ -21,6 +54,8 @@ impl NickelCodegen {
             indent_size: 2,
             resolver: TypeResolver::new(),
             package_mode: PackageMode::default(),
+            source_comments: false,
+            enum_mode: EnumMode::default(),
         }
     }
 
@@ -29,20 +64,64 @@ impl NickelCodegen {
         self
     }
 
+    /// Precede types and fields with a `# Source:` comment naming the
+    /// document and schema location they were generated from
+    pub fn with_source_comments(mut self, enabled: bool) -> Self {
+        self.source_comments = enabled;
+        self
+    }
+
+    /// Represent enums as checked strings or as enum tags
+    pub fn with_enum_mode(mut self, mode: EnumMode) -> Self {
+        self.enum_mode = mode;
+        self
+    }
+
+    /// A function building a resource of `kind` from its name and the rest
+    /// of its fields
+    ///
+    /// The function fills in `apiVersion`, `kind` and `metadata.name`, then
+    /// applies `contract`, which also fills in the schema's defaults:
+    /// `mk_Widget { name = "demo", spec.size = 3 }`.
+    pub fn constructor(
+        &self,
+        api_version: &str,
+        kind: &str,
+        contract: &str,
+        indent_level: usize,
+    ) -> String {
+        let body = self.indent(indent_level + 1);
+        format!(
+            "fun {{ name, ..rest }} =>\n{body}({{ apiVersion = {}, kind = {}, metadata.name = name }} & rest)\n{body}| {}",
+            format_string(api_version),
+            format_string(kind),
+            contract,
+            body = body
+        )
+    }
+
     fn indent(&self, level: usize) -> String {
         " ".repeat(level * self.indent_size)
     }
 
-    /// Escape field names that are reserved keywords or start with special characters
+    /// Escape field names that are reserved keywords or not valid identifiers
     fn escape_field_name(&self, name: &str) -> String {
-        // Fields starting with $ need to be quoted
-        if name.starts_with('$') || self.is_reserved_keyword(name) {
-            format!("\"{}\"", name)
+        // Fields such as `$ref` or `app.kubernetes.io/name` need to be quoted
+        if !is_identifier(name) || self.is_reserved_keyword(name) {
+            format_string(name)
         } else {
             name.to_string()
         }
     }
 
+    /// Format a JSON value as a Nickel expression
+    ///
+    /// Objects become records spread over several lines, indented from
+    /// `indent_level`.
+    pub fn format_value(&self, value: &serde_json::Value, indent_level: usize) -> String {
+        format_json_value_impl(value, indent_level, self)
+    }
+
     /// Check if a field name is a Nickel reserved keyword
     fn is_reserved_keyword(&self, name: &str) -> bool {
         matches!(
@@ -151,14 +230,7 @@ impl NickelCodegen {
             Type::TaggedUnion {
                 tag_field,
                 variants,
-            } => {
-                let mut contracts = Vec::new();
-                for (tag, variant_type) in variants {
-                    let variant_str = self.type_to_nickel(variant_type, module, indent_level)?;
-                    contracts.push(format!("({} == \"{}\" && {})", tag_field, tag, variant_str));
-                }
-                Ok(contracts.join(" | "))
-            }
+            } => self.tagged_union_to_nickel(tag_field, variants, module, indent_level),
 
             Type::Reference(name) => {
                 // Use the resolver to get the proper reference
@@ -171,10 +243,121 @@ impl NickelCodegen {
             }
 
             Type::Contract { base, predicate } => {
+                let check = predicate_contract(predicate);
+                if matches!(**base, Type::Any) {
+                    return Ok(check);
+                }
                 let base_type = self.type_to_nickel(base, module, indent_level)?;
-                Ok(format!("{} | Contract({})", base_type, predicate))
+                Ok(format!(
+                    "std.contract.Sequence [ {}, {} ]",
+                    base_type, check
+                ))
+            }
+
+            Type::Enum(values) => Ok(self.enum_to_nickel(values)),
+        }
+    }
+
+    /// A contract accepting only the allowed values of an enum
+    ///
+    /// In [`EnumMode::Tags`], enums of strings become enum types that also
+    /// accept the strings, converting them to tags. Nickel exports the tags
+    /// as strings again. Other enums are always checked by value.
+    fn enum_to_nickel(&self, values: &[serde_json::Value]) -> String {
+        let tags: Option<Vec<&str>> = values.iter().map(|value| value.as_str()).collect();
+        match tags {
+            Some(tags) if self.enum_mode == EnumMode::Tags && !tags.is_empty() => {
+                // Tags are quoted like field names: `'"Ready-To-Use"`
+                let tags: Vec<String> = tags
+                    .iter()
+                    .map(|tag| format!("'{}", self.escape_field_name(tag)))
+                    .collect();
+                format!(
+                    "std.contract.Sequence [ std.enum.TagOrString, [| {} |] ]",
+                    tags.join(", ")
+                )
+            }
+            _ => {
+                let values: Vec<String> = values
+                    .iter()
+                    .map(|value| self.format_value(value, 0))
+                    .collect();
+                format!(
+                    "std.contract.from_predicate (fun value => std.array.elem value [ {} ])",
+                    values.join(", ")
+                )
+            }
+        }
+    }
+
+    /// A contract checking the tag field of a record, then applying the
+    /// contract of the variant it names
+    ///
+    /// Variant records that do not declare the tag field get it added, so
+    /// that closed records accept the tag.
+    fn tagged_union_to_nickel(
+        &mut self,
+        tag_field: &str,
+        variants: &BTreeMap<String, Type>,
+        module: &amalgam_core::ir::Module,
+        indent_level: usize,
+    ) -> Result<String, CodegenError> {
+        let body = self.indent(indent_level + 1);
+        let nested = self.indent(indent_level + 2);
+        let tag = self.escape_field_name(tag_field);
+
+        let mut result = String::from("std.contract.custom (fun label value =>\n");
+        result.push_str(&format!("{}let variants = {{\n", body));
+        for (name, variant) in variants {
+            let mut variant = variant.clone();
+            if let Type::Record { fields, .. } = &mut variant {
+                fields
+                    .entry(tag_field.to_string())
+                    .or_insert_with(|| Field {
+                        ty: Type::Enum(vec![serde_json::Value::String(name.clone())]),
+                        required: true,
+                        description: None,
+                        default: None,
+                        read_only: false,
+                        provenance: None,
+                    });
             }
+            let contract = self.type_to_nickel(&variant, module, indent_level + 2)?;
+            result.push_str(&format!(
+                "{}{} = {},\n",
+                nested,
+                self.escape_field_name(name),
+                contract
+            ));
         }
+        result.push_str(&format!("{}}} in\n", body));
+
+        let missing = format_string(&format!("expected a record with a `{}` field", tag_field));
+        let unknown = format_string(&format!(
+            "`{}` must be one of: {}",
+            tag_field,
+            variants.keys().cloned().collect::<Vec<_>>().join(", ")
+        ));
+        result.push_str(&format!(
+            "{}if !(std.is_record value) || !(std.record.has_field {} value) then\n",
+            body,
+            format_string(tag_field)
+        ));
+        result.push_str(&format!("{}'Error {{ message = {} }}\n", nested, missing));
+        result.push_str(&format!(
+            "{}else if !(std.is_string value.{tag}) || !(std.record.has_field value.{tag} variants) then\n",
+            body,
+            tag = tag
+        ));
+        result.push_str(&format!("{}'Error {{ message = {} }}\n", nested, unknown));
+        result.push_str(&format!("{}else\n", body));
+        result.push_str(&format!(
+            "{}'Ok (std.contract.apply (std.record.get value.{} variants) label value)\n",
+            nested, tag
+        ));
+        result.push_str(&self.indent(indent_level));
+        result.push(')');
+        Ok(result)
     }
 
     fn field_to_nickel(
@@ -191,7 +374,13 @@ impl NickelCodegen {
 
         // Field name - escape reserved keywords and fields starting with $
         let field_name = self.escape_field_name(name);
-        parts.push(format!("{}{}", indent, field_name));
+        match &field.provenance {
+            Some(at) if self.source_comments => parts.push(format!(
+                "{}# Source: {}\n{}{}",
+                indent, at, indent, field_name
+            )),
+            _ => parts.push(format!("{}{}", indent, field_name)),
+        }
 
         // In Nickel, a field with a default value is implicitly optional
         // So we only add 'optional' if there's no default value
@@ -217,6 +406,55 @@ impl NickelCodegen {
     }
 }
 
+/// The Nickel contract for a [`Type::Contract`] predicate
+///
+/// A path whose last segment is capitalized, such as `std.string.NonEmpty`
+/// or `refs.ObjectRef`, names a contract and is used as is. Anything else is
+/// a predicate, such as `std.number.is_integer` or `fun x => x > 0`, and is
+/// turned into a contract with `std.contract.from_predicate`.
+fn predicate_contract(predicate: &str) -> String {
+    let predicate = predicate.trim();
+    let segments: Vec<&str> = predicate.split('.').collect();
+    let is_path = segments.iter().all(|segment| is_identifier(segment));
+    match segments.last() {
+        Some(last) if is_path && last.starts_with(|c: char| c.is_ascii_uppercase()) => {
+            predicate.to_string()
+        }
+        _ if is_path => format!("std.contract.from_predicate {}", predicate),
+        _ => format!("std.contract.from_predicate ({})", predicate),
+    }
+}
+
+/// Whether a name can be used as a Nickel identifier without quoting
+fn is_identifier(name: &str) -> bool {
+    let rest = name.trim_start_matches('_');
+    rest.starts_with(|c: char| c.is_ascii_alphabetic())
+        && rest
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '\''))
+}
+
+/// A double-quoted Nickel string literal
+pub(crate) fn format_string(s: &str) -> String {
+    let mut result = String::with_capacity(s.len() + 2);
+    result.push('"');
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            '\r' => result.push_str("\\r"),
+            '\t' => result.push_str("\\t"),
+            // `%{` would start an interpolation
+            '%' if chars.peek() == Some(&'{') => result.push_str("\\%"),
+            c => result.push(c),
+        }
+    }
+    result.push('"');
+    result
+}
+
 /// Format a JSON value for Nickel with proper field name escaping
 fn format_json_value_impl(
     value: &serde_json::Value,
@@ -227,7 +465,7 @@ fn format_json_value_impl(
         serde_json::Value::Null => "null".to_string(),
         serde_json::Value::Bool(b) => b.to_string(),
         serde_json::Value::Number(n) => n.to_string(),
-        serde_json::Value::String(s) => format!("\"{}\"", s.replace('"', "\\\"")),
+        serde_json::Value::String(s) => format_string(s),
         serde_json::Value::Array(arr) => {
             let items: Vec<String> = arr
                 .iter()
@@ -324,9 +562,22 @@ impl Codegen for NickelCodegen {
                             .map_err(|e| CodegenError::Generation(e.to_string()))?;
                     }
                 }
+                if let Some(notice) = type_def.deprecation_notice() {
+                    writeln!(output, "{}# DEPRECATED: {}", self.indent(1), notice)
+                        .map_err(|e| CodegenError::Generation(e.to_string()))?;
+                }
+                if let Some(at) = type_def
+                    .provenance
+                    .as_ref()
+                    .filter(|_| self.source_comments)
+                {
+                    writeln!(output, "{}# Source: {}", self.indent(1), at)?;
+                }
 
                 // Generate the type with proper indentation
-                let type_str = self.type_to_nickel(&type_def.ty, module, 1)?;
+                let type_str = self
+                    .type_to_nickel(&type_def.ty, module, 1)
+                    .map_err(|e| e.in_type(&type_def.name, type_def.provenance.as_ref()))?;
 
                 // Check if type is a record that needs special formatting
                 if matches!(type_def.ty, Type::Record { .. }) {
@@ -453,4 +704,181 @@ mod tests {
             "\"Doc with \\\"quotes\\\"\""
         );
     }
+
+    #[test]
+    fn test_source_comments() {
+        let at = amalgam_core::Provenance::new("crds/widgets.yaml").with_pointer("/spec");
+        let mut module = create_test_module();
+        module.types.push(amalgam_core::ir::TypeDefinition {
+            name: "Widget".to_string(),
+            ty: Type::Record {
+                fields: BTreeMap::from([(
+                    "size".to_string(),
+                    Field {
+                        ty: Type::Integer,
+                        required: true,
+                        description: None,
+                        default: None,
+                        read_only: false,
+                        provenance: Some(at.child("size")),
+                    },
+                )]),
+                open: false,
+            },
+            documentation: None,
+            annotations: BTreeMap::new(),
+            provenance: Some(at),
+        });
+        let mut ir = IR::new();
+        ir.add_module(module);
+
+        let plain = NickelCodegen::new().generate(&ir).unwrap();
+        assert!(!plain.contains("# Source:"));
+
+        let commented = NickelCodegen::new()
+            .with_source_comments(true)
+            .generate(&ir)
+            .unwrap();
+        assert!(commented.contains("  # Source: crds/widgets.yaml#/spec\n  Widget = {"));
+        assert!(commented.contains("    # Source: crds/widgets.yaml#/spec/size\n    size | Number"));
+    }
+
+    #[test]
+    fn test_errors_name_the_type_and_source() {
+        let error = CodegenError::UnsupportedType("Dyn".to_string()).in_type(
+            "Widget",
+            Some(&amalgam_core::Provenance::new("crds/widgets.yaml").with_pointer("/spec")),
+        );
+        assert_eq!(
+            error.to_string(),
+            "Widget (crds/widgets.yaml#/spec): Unsupported type: Dyn"
+        );
+    }
+
+    #[test]
+    fn test_contract_generation() {
+        let mut codegen = NickelCodegen::new();
+        let module = create_test_module();
+        let contract = |base: Type, predicate: &str| Type::Contract {
+            base: Box::new(base),
+            predicate: predicate.to_string(),
+        };
+
+        assert_eq!(
+            codegen
+                .type_to_nickel(
+                    &contract(Type::Integer, "std.number.is_integer"),
+                    &module,
+                    0
+                )
+                .unwrap(),
+            "std.contract.Sequence [ Number, std.contract.from_predicate std.number.is_integer ]"
+        );
+        assert_eq!(
+            codegen
+                .type_to_nickel(&contract(Type::Any, "fun x => x != null"), &module, 0)
+                .unwrap(),
+            "std.contract.from_predicate (fun x => x != null)"
+        );
+        assert_eq!(
+            codegen
+                .type_to_nickel(&contract(Type::String, "std.string.NonEmpty"), &module, 0)
+                .unwrap(),
+            "std.contract.Sequence [ String, std.string.NonEmpty ]"
+        );
+    }
+
+    #[test]
+    fn test_enum_modes() {
+        let module = create_test_module();
+        let policy = Type::Enum(vec!["Always".into(), "OnFailure".into(), "x-y".into()]);
+        let ports = Type::Enum(vec![80.into(), 443.into()]);
+
+        let mut strings = NickelCodegen::new();
+        assert_eq!(
+            strings.type_to_nickel(&policy, &module, 0).unwrap(),
+            r#"std.contract.from_predicate (fun value => std.array.elem value [ "Always", "OnFailure", "x-y" ])"#
+        );
+
+        let mut tags = NickelCodegen::new().with_enum_mode(EnumMode::Tags);
+        assert_eq!(
+            tags.type_to_nickel(&policy, &module, 0).unwrap(),
+            r#"std.contract.Sequence [ std.enum.TagOrString, [| 'Always, 'OnFailure, 'x-y |] ]"#
+        );
+        assert_eq!(
+            tags.type_to_nickel(&ports, &module, 0).unwrap(),
+            "std.contract.from_predicate (fun value => std.array.elem value [ 80, 443 ])"
+        );
+        assert_eq!("tags".parse::<EnumMode>(), Ok(EnumMode::Tags));
+    }
+
+    #[test]
+    fn test_constructor_generation() {
+        let codegen = NickelCodegen::new();
+        assert_eq!(
+            codegen.constructor("apps/v1", "Deployment", "DeploymentInput", 1),
+            r#"fun { name, ..rest } =>
+    ({ apiVersion = "apps/v1", kind = "Deployment", metadata.name = name } & rest)
+    | DeploymentInput"#
+        );
+    }
+
+    #[test]
+    fn test_tagged_union_generation() {
+        let mut codegen = NickelCodegen::new();
+        let module = create_test_module();
+        let union = Type::TaggedUnion {
+            tag_field: "kind".to_string(),
+            variants: BTreeMap::from([(
+                "git".to_string(),
+                Type::Record {
+                    fields: BTreeMap::from([(
+                        "url".to_string(),
+                        Field {
+                            ty: Type::String,
+                            required: true,
+                            description: None,
+                            default: None,
+                            read_only: false,
+                            provenance: None,
+                        },
+                    )]),
+                    open: false,
+                },
+            )]),
+        };
+
+        assert_eq!(
+            codegen.type_to_nickel(&union, &module, 0).unwrap(),
+            r#"std.contract.custom (fun label value =>
+  let variants = {
+    git = {
+      kind | std.contract.from_predicate (fun value => std.array.elem value [ "git" ]),
+      url | String,
+    },
+  } in
+  if !(std.is_record value) || !(std.record.has_field "kind" value) then
+    'Error { message = "expected a record with a `kind` field" }
+  else if !(std.is_string value.kind) || !(std.record.has_field value.kind variants) then
+    'Error { message = "`kind` must be one of: git" }
+  else
+    'Ok (std.contract.apply (std.record.get value.kind variants) label value)
+)"#
+        );
+    }
+
+    #[test]
+    fn test_value_formatting() {
+        let codegen = NickelCodegen::new();
+        let value = serde_json::json!({
+            "labels": { "app.kubernetes.io/name": "widget", "tier": "web" },
+            "script": "echo \"%{HOME}\"\nexit 1",
+        });
+
+        assert_eq!(
+            codegen.format_value(&value, 0),
+            "{\n  labels = {\n    \"app.kubernetes.io/name\" = \"widget\",\n    tier = \"web\"\n  },\n  \
+             script = \"echo \\\"\\%{HOME}\\\"\\nexit 1\"\n}"
+        );
+    }
 }
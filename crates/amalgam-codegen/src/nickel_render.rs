@@ -0,0 +1,192 @@
+//! Nickel helpers rendering resources as Kubernetes manifests
+//!
+//! Each generated package gets a `lib.ncl` indexing the contracts of its
+//! resource kinds by `apiVersion` and `kind`, with functions that collect
+//! the resources of a configuration, check each against the contract of its
+//! kind, put CRDs and namespaces first and render them as multi-document
+//! YAML:
+//!
+//! ```nickel
+//! let crossplane = import "crossplane/lib.ncl" in
+//! crossplane.render { database = ..., bucket = ... }
+//! ```
+
+use crate::nickel::format_string;
+use std::collections::BTreeMap;
+
+/// File name of the helpers module at the root of each package
+pub const LIB_FILE: &str = "lib.ncl";
+
+/// Kinds rendered before any other, in this order
+pub const FIRST_KINDS: &[&str] = &["CustomResourceDefinition", "Namespace"];
+
+/// A resource kind of a package's index
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResourceKind {
+    /// e.g. `apps/v1`, or `v1` for the core group
+    pub api_version: String,
+    pub kind: String,
+    /// Nickel expression of the contract resources are checked against,
+    /// relative to the package root
+    pub contract: String,
+}
+
+/// The `lib.ncl` of a package with the given resource kinds
+pub fn lib_module(package: &str, kinds: &[ResourceKind]) -> String {
+    let mut by_version: BTreeMap<&str, Vec<&ResourceKind>> = BTreeMap::new();
+    for kind in kinds {
+        by_version.entry(&kind.api_version).or_default().push(kind);
+    }
+
+    let mut index = String::from("{\n");
+    for (api_version, kinds) in by_version {
+        index.push_str(&format!("    {} = {{\n", format_string(api_version)));
+        for kind in kinds {
+            index.push_str(&format!("      {} = {},\n", kind.kind, kind.contract));
+        }
+        index.push_str("    },\n");
+    }
+    index.push_str("  }");
+
+    format!(
+        "# Rendering helpers for {}\n# Auto-generated by amalgam\n{{\n  index = {},\n\n{}}}\n",
+        package,
+        index,
+        helpers()
+    )
+}
+
+/// A Nickel program evaluating to the ordered, checked resources of the
+/// file at `value`, using the merged indexes of the `libs` files
+///
+/// This is what `amalgam render` exports.
+pub fn render_program(libs: &[String], value: &str) -> String {
+    let libs: Vec<String> = libs
+        .iter()
+        .map(|lib| format!("    import {},\n", format_string(lib)))
+        .collect();
+    format!(
+        "let lib = {{\n  index = std.array.fold_left (fun index lib => index & lib.index) {{}} [\n{}  ],\n\n{}}} in\nlib.documents (import {})\n",
+        libs.concat(),
+        helpers(),
+        format_string(value)
+    )
+}
+
+/// Fields shared by every `lib.ncl`, relying on an `index` field
+fn helpers() -> String {
+    let mut rank = String::from("  rank = fun resource =>\n");
+    for (position, kind) in FIRST_KINDS.iter().enumerate() {
+        rank.push_str(&format!(
+            "    {}if resource.kind == {} then {}\n",
+            if position == 0 { "" } else { "else " },
+            format_string(kind),
+            position
+        ));
+    }
+    rank.push_str(&format!("    else {},\n", FIRST_KINDS.len()));
+
+    format!(
+        r#"  # Whether a value is a Kubernetes resource
+  is_resource = fun value =>
+    std.is_record value
+    && std.record.has_field "apiVersion" value
+    && std.record.has_field "kind" value
+    && std.is_string value.apiVersion
+    && std.is_string value.kind,
+
+  # The resources of a value, searching nested records and arrays
+  collect = fun value =>
+    if is_resource value then
+      [value]
+    else if std.is_array value then
+      std.array.flat_map collect value
+    else if std.is_record value then
+      std.array.flat_map collect (std.record.values value)
+    else
+      [],
+
+  # Check a resource against the contract of its kind in `index`; kinds
+  # from other packages are left as they are
+  validate_with = fun index resource =>
+    let kinds =
+      if std.record.has_field resource.apiVersion index then
+        std.record.get resource.apiVersion index
+      else
+        {{}}
+    in
+    if std.record.has_field resource.kind kinds then
+      resource | std.record.get resource.kind kinds
+    else
+      resource,
+
+  # Position of a resource's kind in the output
+{rank}
+  # Resources by rank, keeping their order otherwise
+  order = fun resources =>
+    std.array.range 0 {ranks}
+    |> std.array.flat_map (fun position =>
+      std.array.filter (fun resource => rank resource == position) resources
+    ),
+
+  # The checked resources of a value in the order to apply them
+  documents = fun value =>
+    collect value
+    |> std.array.map (validate_with index)
+    |> order,
+
+  # The resources of a value as multi-document YAML
+  render = fun value =>
+    documents value
+    |> std.array.map (fun resource => std.serialize 'Yaml resource)
+    |> std.string.join "---\n",
+"#,
+        rank = rank,
+        ranks = FIRST_KINDS.len() + 1
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lib_module_indexes_kinds() {
+        let lib = lib_module(
+            "example",
+            &[
+                ResourceKind {
+                    api_version: "example.io/v1".to_string(),
+                    kind: "Widget".to_string(),
+                    contract: "(import \"./example.io/v1/widget.ncl\").WidgetInput".to_string(),
+                },
+                ResourceKind {
+                    api_version: "example.io/v1".to_string(),
+                    kind: "Gadget".to_string(),
+                    contract: "(import \"./example.io/v1/gadget.ncl\").GadgetInput".to_string(),
+                },
+            ],
+        );
+        assert!(lib.starts_with(
+            r#"# Rendering helpers for example
+# Auto-generated by amalgam
+{
+  index = {
+    "example.io/v1" = {
+      Widget = (import "./example.io/v1/widget.ncl").WidgetInput,
+      Gadget = (import "./example.io/v1/gadget.ncl").GadgetInput,
+    },
+  },
+"#
+        ));
+        assert!(lib.contains(
+            r#"  rank = fun resource =>
+    if resource.kind == "CustomResourceDefinition" then 0
+    else if resource.kind == "Namespace" then 1
+    else 2,
+"#
+        ));
+        assert!(lib.contains("std.array.range 0 3"));
+        assert!(lib.ends_with("|> std.string.join \"---\\n\",\n}\n"));
+    }
+}
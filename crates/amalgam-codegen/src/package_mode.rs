@@ -81,7 +81,7 @@ impl PackageMode {
                     // Auto-generate package ID based on detected package
                     let base = std::env::var("NICKEL_PACKAGE_BASE")
                         .unwrap_or_else(|_| "github:seryl/nickel-pkgs".to_string());
-                    let package_id = format!("{}/{}", base, &dep.package_name);
+                    let package_id = format!("{}/{}", base, dep.package_name);
 
                     let version = if dep.is_core_type {
                         ">=1.31.0".to_string()
@@ -277,6 +277,7 @@ mod tests {
             ty: amalgam_core::types::Type::String,
             documentation: None,
             annotations: BTreeMap::new(),
+            provenance: None,
         });
 
         let resolved = resolver.resolve("MyType", &module, &ResolutionContext::default());
@@ -0,0 +1,780 @@
+//! Structural deduplication of inlined record types
+//!
+//! CRDs that embed the same Go struct each carry their own inlined copy of
+//! its schema. [`Deduplicator`] finds anonymous records that occur more than
+//! once across a set of definitions, hoists each shape into a named
+//! [`TypeDefinition`] and replaces every copy with a [`Type::Reference`].
+//! Records that match a known type, such as a Kubernetes core type, are
+//! replaced with a reference to that type instead.
+//!
+//! Shapes are compared structurally: field names, types, requiredness,
+//! defaults and openness must agree, while descriptions may differ. The
+//! hoisted definition keeps the descriptions of the first copy found.
+
+use crate::ir::TypeDefinition;
+use crate::types::{Field, Type};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+
+/// Hash of a type's structure, ignoring descriptions
+///
+/// Types that are [`same_shape`] hash equally.
+pub fn structural_hash(ty: &Type) -> u64 {
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    hash_type(ty, &mut hasher);
+    hasher.finish()
+}
+
+/// Whether two types have the same structure, ignoring descriptions
+pub fn same_shape(a: &Type, b: &Type) -> bool {
+    match (a, b) {
+        (Type::Array(a), Type::Array(b)) | (Type::Optional(a), Type::Optional(b)) => {
+            same_shape(a, b)
+        }
+        (
+            Type::Map {
+                key: a_key,
+                value: a_value,
+            },
+            Type::Map {
+                key: b_key,
+                value: b_value,
+            },
+        ) => same_shape(a_key, b_key) && same_shape(a_value, b_value),
+        (
+            Type::Record {
+                fields: a_fields,
+                open: a_open,
+            },
+            Type::Record {
+                fields: b_fields,
+                open: b_open,
+            },
+        ) => {
+            a_open == b_open
+                && a_fields.len() == b_fields.len()
+                && a_fields
+                    .iter()
+                    .zip(b_fields)
+                    .all(|((a_name, a), (b_name, b))| a_name == b_name && same_field(a, b))
+        }
+        (Type::Union(a), Type::Union(b)) => {
+            a.len() == b.len() && a.iter().zip(b).all(|(a, b)| same_shape(a, b))
+        }
+        (
+            Type::TaggedUnion {
+                tag_field: a_tag,
+                variants: a_variants,
+            },
+            Type::TaggedUnion {
+                tag_field: b_tag,
+                variants: b_variants,
+            },
+        ) => {
+            a_tag == b_tag
+                && a_variants.len() == b_variants.len()
+                && a_variants
+                    .iter()
+                    .zip(b_variants)
+                    .all(|((a_tag, a), (b_tag, b))| a_tag == b_tag && same_shape(a, b))
+        }
+        (
+            Type::Contract {
+                base: a_base,
+                predicate: a_predicate,
+            },
+            Type::Contract {
+                base: b_base,
+                predicate: b_predicate,
+            },
+        ) => a_predicate == b_predicate && same_shape(a_base, b_base),
+        _ => a == b,
+    }
+}
+
+fn same_field(a: &Field, b: &Field) -> bool {
+    a.required == b.required
+        && a.read_only == b.read_only
+        && a.default == b.default
+        && same_shape(&a.ty, &b.ty)
+}
+
+fn hash_type<H: Hasher>(ty: &Type, state: &mut H) {
+    std::mem::discriminant(ty).hash(state);
+    match ty {
+        Type::Array(inner) | Type::Optional(inner) => hash_type(inner, state),
+        Type::Map { key, value } => {
+            hash_type(key, state);
+            hash_type(value, state);
+        }
+        Type::Record { fields, open } => {
+            open.hash(state);
+            for (name, field) in fields {
+                name.hash(state);
+                field.required.hash(state);
+                field.read_only.hash(state);
+                field.default.as_ref().map(|d| d.to_string()).hash(state);
+                hash_type(&field.ty, state);
+            }
+        }
+        Type::Union(types) => {
+            types.len().hash(state);
+            for ty in types {
+                hash_type(ty, state);
+            }
+        }
+        Type::TaggedUnion {
+            tag_field,
+            variants,
+        } => {
+            tag_field.hash(state);
+            for (tag, ty) in variants {
+                tag.hash(state);
+                hash_type(ty, state);
+            }
+        }
+        Type::Reference(name) => name.hash(state),
+        Type::Contract { base, predicate } => {
+            predicate.hash(state);
+            hash_type(base, state);
+        }
+        Type::Enum(values) => {
+            for value in values {
+                value.to_string().hash(state);
+            }
+        }
+        Type::String | Type::Number | Type::Integer | Type::Bool | Type::Null | Type::Any => {}
+    }
+}
+
+/// A record shape hoisted out of the definitions it was repeated in
+#[derive(Debug, Clone)]
+pub struct SharedType {
+    pub definition: TypeDefinition,
+    /// Name the copies now reference it by
+    pub reference: String,
+    /// Indices of the input definitions that held a copy
+    pub users: Vec<usize>,
+}
+
+/// Hoists repeated anonymous records into shared definitions
+#[derive(Debug, Clone)]
+pub struct Deduplicator {
+    min_fields: usize,
+    known: BTreeMap<String, Type>,
+}
+
+impl Default for Deduplicator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Deduplicator {
+    pub fn new() -> Self {
+        Self {
+            min_fields: 2,
+            known: BTreeMap::new(),
+        }
+    }
+
+    /// Only hoist records with at least this many fields (default 2)
+    pub fn with_min_fields(mut self, min_fields: usize) -> Self {
+        self.min_fields = min_fields.max(1);
+        self
+    }
+
+    /// Replace records shaped like `ty` with a reference to `reference`
+    ///
+    /// References inside known types are resolved against the other known
+    /// types, by full name or else by the name after the last `.`, so an
+    /// inlined copy matches however deeply the known type is split up.
+    pub fn with_known_type(mut self, reference: impl Into<String>, ty: Type) -> Self {
+        self.known.insert(reference.into(), ty);
+        self
+    }
+
+    pub fn with_known_types(mut self, types: impl IntoIterator<Item = (String, Type)>) -> Self {
+        self.known.extend(types);
+        self
+    }
+
+    /// Deduplicate the records inside `definitions`, rewriting them in place
+    ///
+    /// Records with fields marked read-only are left inlined so that input
+    /// variants can still strip those fields. `reference` names the
+    /// reference to a new shared type, given its definition name and the
+    /// indices of the definitions that held a copy, e.g. to qualify it with
+    /// the module it will be written to.
+    pub fn deduplicate(
+        &self,
+        definitions: &mut [TypeDefinition],
+        reference: impl Fn(&str, &[usize]) -> String,
+    ) -> Vec<SharedType> {
+        if !self.known.is_empty() {
+            for definition in definitions.iter_mut() {
+                if let Type::Record { fields, .. } = &mut definition.ty {
+                    for field in fields.values_mut() {
+                        self.replace_known(&mut field.ty);
+                    }
+                }
+            }
+        }
+
+        let mut taken: HashSet<String> =
+            definitions.iter().map(|d| d.name.to_lowercase()).collect();
+        let mut shared: Vec<SharedType> = Vec::new();
+
+        loop {
+            let mut candidates: HashMap<u64, Vec<Candidate>> = HashMap::new();
+            for (index, definition) in definitions.iter().enumerate() {
+                self.collect(
+                    &definition.ty,
+                    &definition.name,
+                    true,
+                    &[index],
+                    &mut candidates,
+                );
+            }
+            for shared_type in &shared {
+                self.collect(
+                    &shared_type.definition.ty,
+                    &shared_type.definition.name,
+                    true,
+                    &shared_type.users,
+                    &mut candidates,
+                );
+            }
+
+            // Hoist the largest repeated shape first, so that shapes nested in
+            // it are only counted once its copies have been replaced
+            let Some(best) = candidates
+                .into_values()
+                .flatten()
+                .filter(|c| c.count >= 2)
+                .max_by(|a, b| {
+                    a.size
+                        .cmp(&b.size)
+                        .then_with(|| b.preferred_name().cmp(&a.preferred_name()))
+                })
+            else {
+                break;
+            };
+
+            let name = unique_name(&best.preferred_name(), &mut taken);
+            let users: Vec<usize> = best.users.iter().copied().collect();
+            let target = reference(&name, &users);
+
+            for definition in definitions.iter_mut() {
+                replace_shape(&mut definition.ty, &best.shape, &target, true);
+            }
+            for shared_type in &mut shared {
+                replace_shape(&mut shared_type.definition.ty, &best.shape, &target, true);
+            }
+
+            shared.push(SharedType {
+                definition: TypeDefinition {
+                    name,
+                    ty: best.shape,
+                    documentation: None,
+                    annotations: BTreeMap::new(),
+                    provenance: None,
+                },
+                reference: target,
+                users,
+            });
+        }
+
+        shared
+    }
+
+    /// Record every anonymous record below `ty` as a hoisting candidate
+    fn collect(
+        &self,
+        ty: &Type,
+        context: &str,
+        root: bool,
+        users: &[usize],
+        candidates: &mut HashMap<u64, Vec<Candidate>>,
+    ) {
+        match ty {
+            Type::Record { fields, .. } => {
+                if !root && fields.len() >= self.min_fields && !has_read_only(ty) {
+                    let bucket = candidates.entry(structural_hash(ty)).or_default();
+                    let candidate = match bucket.iter_mut().position(|c| same_shape(&c.shape, ty)) {
+                        Some(position) => &mut bucket[position],
+                        None => {
+                            bucket.push(Candidate {
+                                shape: ty.clone(),
+                                size: type_size(ty),
+                                count: 0,
+                                names: BTreeMap::new(),
+                                users: BTreeSet::new(),
+                            });
+                            bucket.last_mut().expect("just pushed")
+                        }
+                    };
+                    candidate.count += 1;
+                    *candidate.names.entry(type_name(context)).or_default() += 1;
+                    candidate.users.extend(users);
+                }
+                for (name, field) in fields {
+                    self.collect(&field.ty, name, false, users, candidates);
+                }
+            }
+            Type::Array(inner) => {
+                self.collect(inner, &format!("{}Item", context), false, users, candidates)
+            }
+            Type::Map { value, .. } => self.collect(
+                value,
+                &format!("{}Value", context),
+                false,
+                users,
+                candidates,
+            ),
+            Type::Optional(inner) | Type::Contract { base: inner, .. } => {
+                self.collect(inner, context, false, users, candidates)
+            }
+            Type::Union(types) => {
+                for ty in types {
+                    self.collect(ty, context, false, users, candidates);
+                }
+            }
+            Type::TaggedUnion { variants, .. } => {
+                for (tag, ty) in variants {
+                    self.collect(ty, tag, false, users, candidates);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Replace records matching a known type with a reference to it
+    fn replace_known(&self, ty: &mut Type) {
+        if let Type::Record { fields, .. } = ty {
+            if fields.len() >= self.min_fields {
+                let known = self
+                    .known
+                    .iter()
+                    .find(|(_, known)| self.matches_known(ty, known, &mut Vec::new()));
+                if let Some((reference, _)) = known {
+                    *ty = Type::Reference(reference.clone());
+                    return;
+                }
+            }
+        }
+        match ty {
+            Type::Record { fields, .. } => {
+                for field in fields.values_mut() {
+                    self.replace_known(&mut field.ty);
+                }
+            }
+            Type::Array(inner)
+            | Type::Optional(inner)
+            | Type::Contract { base: inner, .. }
+            | Type::Map { value: inner, .. } => self.replace_known(inner),
+            Type::Union(types) => types.iter_mut().for_each(|ty| self.replace_known(ty)),
+            Type::TaggedUnion { variants, .. } => {
+                variants.values_mut().for_each(|ty| self.replace_known(ty))
+            }
+            _ => {}
+        }
+    }
+
+    /// Whether an inlined type has the shape of a known one
+    fn matches_known<'a>(
+        &'a self,
+        ty: &Type,
+        known: &'a Type,
+        resolving: &mut Vec<&'a str>,
+    ) -> bool {
+        match (ty, known) {
+            (Type::Reference(a), Type::Reference(b)) if a == b => true,
+            (_, Type::Reference(name)) => {
+                // Recursive known types match wherever they recur
+                if resolving.contains(&name.as_str()) {
+                    return true;
+                }
+                let Some(resolved) = self.resolve_known(name) else {
+                    return false;
+                };
+                resolving.push(name);
+                let matched = self.matches_known(ty, resolved, resolving);
+                resolving.pop();
+                matched
+            }
+            (Type::Array(a), Type::Array(b)) | (Type::Optional(a), Type::Optional(b)) => {
+                self.matches_known(a, b, resolving)
+            }
+            (
+                Type::Map {
+                    key: a_key,
+                    value: a_value,
+                },
+                Type::Map {
+                    key: b_key,
+                    value: b_value,
+                },
+            ) => {
+                self.matches_known(a_key, b_key, resolving)
+                    && self.matches_known(a_value, b_value, resolving)
+            }
+            (
+                Type::Record {
+                    fields: a_fields,
+                    open: a_open,
+                },
+                Type::Record {
+                    fields: b_fields,
+                    open: b_open,
+                },
+            ) => {
+                a_open == b_open
+                    && a_fields.len() == b_fields.len()
+                    && a_fields
+                        .iter()
+                        .zip(b_fields)
+                        .all(|((a_name, a), (b_name, b))| {
+                            a_name == b_name
+                                && a.required == b.required
+                                && self.matches_known(&a.ty, &b.ty, resolving)
+                        })
+            }
+            (Type::Union(a), Type::Union(b)) => {
+                a.len() == b.len()
+                    && a.iter()
+                        .zip(b)
+                        .all(|(a, b)| self.matches_known(a, b, resolving))
+            }
+            _ => same_shape(ty, known),
+        }
+    }
+
+    fn resolve_known(&self, name: &str) -> Option<&Type> {
+        if let Some(ty) = self.known.get(name) {
+            return Some(ty);
+        }
+        let short = name.rsplit('.').next().unwrap_or(name);
+        let mut matches = self
+            .known
+            .iter()
+            .filter(|(known, _)| known.rsplit('.').next() == Some(short));
+        match (matches.next(), matches.next()) {
+            (Some((_, ty)), None) => Some(ty),
+            _ => None,
+        }
+    }
+}
+
+/// A repeated record shape found while scanning
+struct Candidate {
+    shape: Type,
+    size: usize,
+    count: usize,
+    /// Names suggested by where the copies appear, with how often
+    names: BTreeMap<String, usize>,
+    users: BTreeSet<usize>,
+}
+
+impl Candidate {
+    /// The most frequently suggested name, alphabetically first on ties
+    fn preferred_name(&self) -> String {
+        let mut best: Option<(&String, usize)> = None;
+        for (name, &count) in &self.names {
+            if best.is_none_or(|(_, best_count)| count > best_count) {
+                best = Some((name, count));
+            }
+        }
+        best.map(|(name, _)| name.clone()).unwrap_or_default()
+    }
+}
+
+/// Replace every copy of `shape` below `ty` with a reference to `target`
+fn replace_shape(ty: &mut Type, shape: &Type, target: &str, root: bool) {
+    if !root && matches!(ty, Type::Record { .. }) && same_shape(ty, shape) {
+        *ty = Type::Reference(target.to_string());
+        return;
+    }
+    match ty {
+        Type::Record { fields, .. } => {
+            for field in fields.values_mut() {
+                replace_shape(&mut field.ty, shape, target, false);
+            }
+        }
+        Type::Array(inner)
+        | Type::Optional(inner)
+        | Type::Contract { base: inner, .. }
+        | Type::Map { value: inner, .. } => replace_shape(inner, shape, target, false),
+        Type::Union(types) => {
+            for ty in types {
+                replace_shape(ty, shape, target, false);
+            }
+        }
+        Type::TaggedUnion { variants, .. } => {
+            for ty in variants.values_mut() {
+                replace_shape(ty, shape, target, false);
+            }
+        }
+        _ => {}
+    }
+}
+
+pub(crate) fn has_read_only(ty: &Type) -> bool {
+    match ty {
+        Type::Record { fields, .. } => fields
+            .values()
+            .any(|field| field.read_only || has_read_only(&field.ty)),
+        Type::Array(inner)
+        | Type::Optional(inner)
+        | Type::Contract { base: inner, .. }
+        | Type::Map { value: inner, .. } => has_read_only(inner),
+        Type::Union(types) => types.iter().any(has_read_only),
+        Type::TaggedUnion { variants, .. } => variants.values().any(has_read_only),
+        _ => false,
+    }
+}
+
+/// Number of type nodes in `ty`
+fn type_size(ty: &Type) -> usize {
+    1 + match ty {
+        Type::Record { fields, .. } => fields.values().map(|f| type_size(&f.ty)).sum(),
+        Type::Array(inner)
+        | Type::Optional(inner)
+        | Type::Contract { base: inner, .. }
+        | Type::Map { value: inner, .. } => type_size(inner),
+        Type::Union(types) => types.iter().map(type_size).sum(),
+        Type::TaggedUnion { variants, .. } => variants.values().map(type_size).sum(),
+        _ => 0,
+    }
+}
+
+/// PascalCase type name for a field name such as `providerConfigRef`
+pub(crate) fn type_name(context: &str) -> String {
+    let mut name = String::new();
+    let mut upper = true;
+    for c in context.chars() {
+        if c.is_ascii_alphanumeric() {
+            if upper {
+                name.push(c.to_ascii_uppercase());
+            } else {
+                name.push(c);
+            }
+            upper = false;
+        } else {
+            upper = true;
+        }
+    }
+    if name.is_empty() || name.starts_with(|c: char| c.is_ascii_digit()) {
+        name.insert_str(0, "Shared");
+    }
+    name
+}
+
+/// `name`, or `name` with the lowest numeric suffix not yet taken
+///
+/// Names are compared case-insensitively since each becomes a file name.
+pub(crate) fn unique_name(name: &str, taken: &mut HashSet<String>) -> String {
+    let mut candidate = name.to_string();
+    let mut suffix = 2;
+    while !taken.insert(candidate.to_lowercase()) {
+        candidate = format!("{}{}", name, suffix);
+        suffix += 1;
+    }
+    candidate
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn field(ty: Type, required: bool, description: &str) -> Field {
+        Field {
+            ty,
+            required,
+            description: Some(description.to_string()),
+            default: None,
+            read_only: false,
+            provenance: None,
+        }
+    }
+
+    fn record(fields: Vec<(&str, Field)>) -> Type {
+        Type::Record {
+            fields: fields
+                .into_iter()
+                .map(|(name, field)| (name.to_string(), field))
+                .collect(),
+            open: false,
+        }
+    }
+
+    fn secret_ref(description: &str) -> Type {
+        record(vec![
+            ("key", field(Type::String, true, description)),
+            ("name", field(Type::String, true, description)),
+        ])
+    }
+
+    fn definition(name: &str, ty: Type) -> TypeDefinition {
+        TypeDefinition {
+            name: name.to_string(),
+            ty,
+            documentation: None,
+            annotations: BTreeMap::new(),
+            provenance: None,
+        }
+    }
+
+    fn field_type<'a>(ty: &'a Type, name: &str) -> &'a Type {
+        match ty {
+            Type::Record { fields, .. } => &fields[name].ty,
+            _ => panic!("not a record"),
+        }
+    }
+
+    #[test]
+    fn test_shape_ignores_descriptions() {
+        let a = secret_ref("the key");
+        let b = secret_ref("Key of the secret");
+        assert!(same_shape(&a, &b));
+        assert_eq!(structural_hash(&a), structural_hash(&b));
+
+        let optional = record(vec![
+            ("key", field(Type::String, false, "")),
+            ("name", field(Type::String, true, "")),
+        ]);
+        assert!(!same_shape(&a, &optional));
+    }
+
+    #[test]
+    fn test_repeated_records_are_hoisted() {
+        let mut definitions = vec![
+            definition(
+                "Database",
+                record(vec![
+                    ("passwordSecretRef", field(secret_ref("a"), true, "")),
+                    ("size", field(Type::Integer, false, "")),
+                ]),
+            ),
+            definition(
+                "Bucket",
+                record(vec![(
+                    "credentials",
+                    field(
+                        record(vec![
+                            ("passwordSecretRef", field(secret_ref("b"), true, "")),
+                            ("region", field(Type::String, false, "")),
+                        ]),
+                        false,
+                        "",
+                    ),
+                )]),
+            ),
+            definition(
+                "Cache",
+                record(vec![(
+                    "passwordSecretRef",
+                    field(secret_ref("c"), false, ""),
+                )]),
+            ),
+        ];
+
+        let shared = Deduplicator::new().deduplicate(&mut definitions, |name, users| {
+            format!("shared/{}@{:?}", name, users)
+        });
+
+        assert_eq!(shared.len(), 1);
+        assert_eq!(shared[0].definition.name, "PasswordSecretRef");
+        assert_eq!(shared[0].users, vec![0, 1, 2]);
+        assert_eq!(shared[0].reference, "shared/PasswordSecretRef@[0, 1, 2]");
+
+        let reference = Type::Reference(shared[0].reference.clone());
+        assert_eq!(
+            field_type(&definitions[0].ty, "passwordSecretRef"),
+            &reference
+        );
+        assert_eq!(
+            field_type(
+                field_type(&definitions[1].ty, "credentials"),
+                "passwordSecretRef"
+            ),
+            &reference
+        );
+        // The single remaining `credentials` record is left inlined
+        assert!(matches!(
+            field_type(&definitions[1].ty, "credentials"),
+            Type::Record { .. }
+        ));
+    }
+
+    #[test]
+    fn test_outer_shapes_are_hoisted_before_inner_ones() {
+        let connection = || {
+            record(vec![
+                ("secretRef", field(secret_ref(""), true, "")),
+                ("endpoint", field(Type::String, true, "")),
+            ])
+        };
+        let mut definitions = vec![
+            definition(
+                "Database",
+                record(vec![("connection", field(connection(), true, ""))]),
+            ),
+            definition(
+                "Bucket",
+                record(vec![("connection", field(connection(), true, ""))]),
+            ),
+        ];
+
+        let shared = Deduplicator::new().deduplicate(&mut definitions, |name, _| name.to_string());
+
+        // The secret ref now only occurs once, inside Connection
+        assert_eq!(shared.len(), 1);
+        assert_eq!(shared[0].definition.name, "Connection");
+        assert!(matches!(
+            field_type(&shared[0].definition.ty, "secretRef"),
+            Type::Record { .. }
+        ));
+    }
+
+    #[test]
+    fn test_known_types_are_referenced() {
+        let core = "io.k8s.api.core.v1.SecretKeySelector";
+        let deduplicator = Deduplicator::new().with_known_type(
+            core,
+            record(vec![
+                ("key", field(Type::String, true, "")),
+                ("name", field(Type::Reference("Name".to_string()), true, "")),
+            ]),
+        );
+        let deduplicator = deduplicator.with_known_type("io.k8s.api.core.v1.Name", Type::String);
+
+        let mut definitions = vec![definition(
+            "Database",
+            record(vec![("secret", field(secret_ref(""), true, ""))]),
+        )];
+        let shared = deduplicator.deduplicate(&mut definitions, |name, _| name.to_string());
+
+        assert!(shared.is_empty());
+        assert_eq!(
+            field_type(&definitions[0].ty, "secret"),
+            &Type::Reference(core.to_string())
+        );
+    }
+
+    #[test]
+    fn test_hoisted_names_are_unique() {
+        let mut definitions = vec![
+            definition(
+                "SecretRef",
+                record(vec![("secretRef", field(secret_ref(""), true, ""))]),
+            ),
+            definition(
+                "Other",
+                record(vec![("secretRef", field(secret_ref(""), true, ""))]),
+            ),
+        ];
+        let shared = Deduplicator::new().deduplicate(&mut definitions, |name, _| name.to_string());
+        assert_eq!(shared[0].definition.name, "SecretRef2");
+    }
+}
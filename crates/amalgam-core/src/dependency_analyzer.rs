@@ -4,6 +4,7 @@
 //! for specific packages like k8s_io or crossplane.
 
 use crate::types::Type;
+use crate::visit::{TypePath, Visitor};
 use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet};
 use std::path::Path;
@@ -165,56 +166,12 @@ impl DependencyAnalyzer {
 
     /// Analyze a type definition to find external dependencies
     pub fn analyze_type(&self, ty: &Type, current_package: &str) -> HashSet<TypeReference> {
-        let mut refs = HashSet::new();
-        self.collect_type_references(ty, &mut refs, current_package);
-        refs
-    }
-
-    /// Recursively collect type references
-    fn collect_type_references(
-        &self,
-        ty: &Type,
-        refs: &mut HashSet<TypeReference>,
-        location: &str,
-    ) {
-        match ty {
-            Type::Reference(name) => {
-                // Check if this is an external type reference
-                if let Some(type_ref) = self.parse_type_reference(name, location) {
-                    refs.insert(type_ref);
-                }
-            }
-            Type::Array(inner) => {
-                self.collect_type_references(inner, refs, location);
-            }
-            Type::Optional(inner) => {
-                self.collect_type_references(inner, refs, location);
-            }
-            Type::Map { value, .. } => {
-                self.collect_type_references(value, refs, location);
-            }
-            Type::Record { fields, .. } => {
-                for (field_name, field) in fields {
-                    let field_location = format!("{}.{}", location, field_name);
-                    self.collect_type_references(&field.ty, refs, &field_location);
-                }
-            }
-            Type::Union(types) => {
-                for t in types {
-                    self.collect_type_references(t, refs, location);
-                }
-            }
-            Type::TaggedUnion { variants, .. } => {
-                for (variant_name, t) in variants {
-                    let variant_location = format!("{}[{}]", location, variant_name);
-                    self.collect_type_references(t, refs, &variant_location);
-                }
-            }
-            Type::Contract { base, .. } => {
-                self.collect_type_references(base, refs, location);
-            }
-            _ => {}
-        }
+        let mut collector = ReferenceCollector {
+            analyzer: self,
+            refs: HashSet::new(),
+        };
+        collector.visit_type(ty, &TypePath::new(current_package));
+        collector.refs
     }
 
     /// Parse a type reference to determine if it's external
@@ -354,6 +311,21 @@ impl Default for DependencyAnalyzer {
     }
 }
 
+/// Collects the external references of a type, located by their path
+struct ReferenceCollector<'a> {
+    analyzer: &'a DependencyAnalyzer,
+    refs: HashSet<TypeReference>,
+}
+
+impl Visitor for ReferenceCollector<'_> {
+    fn visit_reference(&mut self, name: &str, path: &TypePath) {
+        // Check if this is an external type reference
+        if let Some(type_ref) = self.analyzer.parse_type_reference(name, &path.to_string()) {
+            self.refs.insert(type_ref);
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -0,0 +1,440 @@
+//! Schema diffing between two versions of the same IR
+//!
+//! Compares type definitions module by module and classifies every
+//! difference as breaking or non-breaking from the point of view of
+//! someone writing configurations against the schema.
+
+use crate::ir::{TypeDefinition, IR};
+use crate::types::{Type, TypeSystem};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// What kind of change was detected
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ChangeKind {
+    TypeAdded,
+    TypeRemoved,
+    FieldAdded,
+    FieldRemoved,
+    /// An optional field became required
+    MadeRequired,
+    /// A required field became optional
+    MadeOptional,
+    /// The new type accepts a subset of the old values
+    TypeNarrowed,
+    /// The new type accepts a superset of the old values
+    TypeWidened,
+    /// The types are unrelated
+    TypeChanged,
+    EnumValueAdded,
+    EnumValueRemoved,
+}
+
+/// Impact of a change on existing configurations
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Severity {
+    Breaking,
+    NonBreaking,
+}
+
+/// A single detected difference
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SchemaChange {
+    pub kind: ChangeKind,
+    pub severity: Severity,
+    /// Location of the change, e.g. `Widget.v1.example.io::Widget.spec.replicas`
+    pub path: String,
+    pub description: String,
+}
+
+/// Result of comparing two IRs
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DiffReport {
+    pub changes: Vec<SchemaChange>,
+}
+
+impl DiffReport {
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// Whether any change would break existing configurations
+    pub fn has_breaking(&self) -> bool {
+        self.changes
+            .iter()
+            .any(|c| c.severity == Severity::Breaking)
+    }
+
+    pub fn breaking(&self) -> impl Iterator<Item = &SchemaChange> {
+        self.changes
+            .iter()
+            .filter(|c| c.severity == Severity::Breaking)
+    }
+
+    pub fn non_breaking(&self) -> impl Iterator<Item = &SchemaChange> {
+        self.changes
+            .iter()
+            .filter(|c| c.severity == Severity::NonBreaking)
+    }
+
+    fn push(&mut self, kind: ChangeKind, path: &str, description: String) {
+        let severity = match kind {
+            ChangeKind::TypeRemoved
+            | ChangeKind::FieldRemoved
+            | ChangeKind::MadeRequired
+            | ChangeKind::TypeNarrowed
+            | ChangeKind::TypeChanged
+            | ChangeKind::EnumValueRemoved => Severity::Breaking,
+            ChangeKind::TypeAdded
+            | ChangeKind::FieldAdded
+            | ChangeKind::MadeOptional
+            | ChangeKind::TypeWidened
+            | ChangeKind::EnumValueAdded => Severity::NonBreaking,
+        };
+        self.changes.push(SchemaChange {
+            kind,
+            severity,
+            path: path.to_string(),
+            description,
+        });
+    }
+}
+
+/// Compare two IRs and report every type-level difference
+pub fn diff_ir(old: &IR, new: &IR) -> DiffReport {
+    let old_types = index_types(old);
+    let new_types = index_types(new);
+    let mut report = DiffReport::default();
+
+    for (key, old_def) in &old_types {
+        let path = format!("{}::{}", key.0, key.1);
+        match new_types.get(key) {
+            Some(new_def) => diff_type(&old_def.ty, &new_def.ty, &path, &mut report),
+            None => report.push(
+                ChangeKind::TypeRemoved,
+                &path,
+                format!("type {} was removed", key.1),
+            ),
+        }
+    }
+
+    for key in new_types.keys() {
+        if !old_types.contains_key(key) {
+            let path = format!("{}::{}", key.0, key.1);
+            report.push(
+                ChangeKind::TypeAdded,
+                &path,
+                format!("type {} was added", key.1),
+            );
+        }
+    }
+
+    report
+}
+
+fn index_types(ir: &IR) -> BTreeMap<(String, String), &TypeDefinition> {
+    ir.modules
+        .iter()
+        .flat_map(|m| {
+            m.types
+                .iter()
+                .map(move |t| ((m.name.clone(), t.name.clone()), t))
+        })
+        .collect()
+}
+
+fn diff_type(old: &Type, new: &Type, path: &str, report: &mut DiffReport) {
+    if old == new {
+        return;
+    }
+
+    match (old, new) {
+        (
+            Type::Record {
+                fields: old_fields,
+                open: old_open,
+            },
+            Type::Record {
+                fields: new_fields,
+                open: new_open,
+            },
+        ) => {
+            if *old_open && !*new_open {
+                report.push(
+                    ChangeKind::TypeNarrowed,
+                    path,
+                    "record no longer accepts additional fields".to_string(),
+                );
+            } else if !*old_open && *new_open {
+                report.push(
+                    ChangeKind::TypeWidened,
+                    path,
+                    "record now accepts additional fields".to_string(),
+                );
+            }
+
+            for (name, old_field) in old_fields {
+                let field_path = format!("{}.{}", path, name);
+                match new_fields.get(name) {
+                    Some(new_field) => {
+                        if !old_field.required && new_field.required {
+                            report.push(
+                                ChangeKind::MadeRequired,
+                                &field_path,
+                                format!("field {} is now required", name),
+                            );
+                        } else if old_field.required && !new_field.required {
+                            report.push(
+                                ChangeKind::MadeOptional,
+                                &field_path,
+                                format!("field {} is now optional", name),
+                            );
+                        }
+                        diff_type(&old_field.ty, &new_field.ty, &field_path, report);
+                    }
+                    None => report.push(
+                        ChangeKind::FieldRemoved,
+                        &field_path,
+                        format!("field {} was removed", name),
+                    ),
+                }
+            }
+
+            for (name, new_field) in new_fields {
+                if old_fields.contains_key(name) {
+                    continue;
+                }
+                let field_path = format!("{}.{}", path, name);
+                if new_field.required {
+                    report.push(
+                        ChangeKind::MadeRequired,
+                        &field_path,
+                        format!("required field {} was added", name),
+                    );
+                } else {
+                    report.push(
+                        ChangeKind::FieldAdded,
+                        &field_path,
+                        format!("optional field {} was added", name),
+                    );
+                }
+            }
+        }
+        (Type::Array(old_elem), Type::Array(new_elem)) => {
+            diff_type(old_elem, new_elem, &format!("{}[]", path), report);
+        }
+        (
+            Type::Map {
+                key: old_key,
+                value: old_value,
+            },
+            Type::Map {
+                key: new_key,
+                value: new_value,
+            },
+        ) => {
+            diff_type(old_key, new_key, &format!("{}{{key}}", path), report);
+            diff_type(old_value, new_value, &format!("{}{{}}", path), report);
+        }
+        (Type::Optional(old_inner), Type::Optional(new_inner)) => {
+            diff_type(old_inner, new_inner, path, report);
+        }
+        (Type::Optional(old_inner), _) => {
+            report.push(
+                ChangeKind::TypeNarrowed,
+                path,
+                "null is no longer accepted".to_string(),
+            );
+            diff_type(old_inner, new, path, report);
+        }
+        (_, Type::Optional(new_inner)) => {
+            report.push(
+                ChangeKind::TypeWidened,
+                path,
+                "null is now accepted".to_string(),
+            );
+            diff_type(old, new_inner, path, report);
+        }
+        (Type::Enum(old_values), Type::Enum(new_values)) => {
+            for value in old_values {
+                if !new_values.contains(value) {
+                    report.push(
+                        ChangeKind::EnumValueRemoved,
+                        path,
+                        format!("enum value {} was removed", value),
+                    );
+                }
+            }
+            for value in new_values {
+                if !old_values.contains(value) {
+                    report.push(
+                        ChangeKind::EnumValueAdded,
+                        path,
+                        format!("enum value {} was added", value),
+                    );
+                }
+            }
+        }
+        (
+            Type::TaggedUnion {
+                tag_field: old_tag,
+                variants: old_variants,
+            },
+            Type::TaggedUnion {
+                tag_field: new_tag,
+                variants: new_variants,
+            },
+        ) if old_tag == new_tag => {
+            let tags: BTreeSet<_> = old_variants.keys().chain(new_variants.keys()).collect();
+            for tag in tags {
+                let variant_path = format!("{}[{}={}]", path, old_tag, tag);
+                match (old_variants.get(tag), new_variants.get(tag)) {
+                    (Some(o), Some(n)) => diff_type(o, n, &variant_path, report),
+                    (Some(_), None) => report.push(
+                        ChangeKind::TypeNarrowed,
+                        &variant_path,
+                        format!("variant {} was removed", tag),
+                    ),
+                    (None, Some(_)) => report.push(
+                        ChangeKind::TypeWidened,
+                        &variant_path,
+                        format!("variant {} was added", tag),
+                    ),
+                    (None, None) => {}
+                }
+            }
+        }
+        _ => {
+            // Fall back to value-set compatibility between the two types
+            let ts = TypeSystem::new();
+            let description = format!("type changed from {} to {}", old.describe(), new.describe());
+            if ts.is_compatible(old, new) {
+                report.push(ChangeKind::TypeWidened, path, description);
+            } else if ts.is_compatible(new, old) {
+                report.push(ChangeKind::TypeNarrowed, path, description);
+            } else {
+                report.push(ChangeKind::TypeChanged, path, description);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ir::IRBuilder;
+    use crate::types::Field;
+
+    fn field(ty: Type, required: bool) -> Field {
+        Field {
+            ty,
+            required,
+            description: None,
+            default: None,
+            read_only: false,
+            provenance: None,
+        }
+    }
+
+    fn record(fields: Vec<(&str, Field)>) -> Type {
+        Type::Record {
+            fields: fields
+                .into_iter()
+                .map(|(n, f)| (n.to_string(), f))
+                .collect(),
+            open: false,
+        }
+    }
+
+    fn single(ty: Type) -> IR {
+        IRBuilder::new()
+            .module("widgets")
+            .add_type("Widget", ty)
+            .build()
+    }
+
+    #[test]
+    fn test_identical_ir_has_no_changes() {
+        let ty = record(vec![("name", field(Type::String, true))]);
+        let report = diff_ir(&single(ty.clone()), &single(ty));
+        assert!(report.is_empty());
+    }
+
+    #[test]
+    fn test_field_changes_are_classified() {
+        let old = record(vec![
+            ("name", field(Type::String, true)),
+            ("replicas", field(Type::Number, false)),
+            ("legacy", field(Type::Bool, false)),
+            (
+                "mode",
+                field(Type::Enum(vec!["a".into(), "b".into()]), false),
+            ),
+        ]);
+        let new = record(vec![
+            ("name", field(Type::String, false)),
+            ("replicas", field(Type::Integer, true)),
+            ("labels", field(Type::String, false)),
+            (
+                "mode",
+                field(Type::Enum(vec!["a".into(), "c".into()]), false),
+            ),
+        ]);
+
+        let report = diff_ir(&single(old), &single(new));
+        let kinds: Vec<_> = report
+            .changes
+            .iter()
+            .map(|c| (c.kind, c.path.as_str()))
+            .collect();
+
+        assert!(kinds.contains(&(ChangeKind::MadeOptional, "widgets::Widget.name")));
+        assert!(kinds.contains(&(ChangeKind::MadeRequired, "widgets::Widget.replicas")));
+        assert!(kinds.contains(&(ChangeKind::TypeNarrowed, "widgets::Widget.replicas")));
+        assert!(kinds.contains(&(ChangeKind::FieldRemoved, "widgets::Widget.legacy")));
+        assert!(kinds.contains(&(ChangeKind::FieldAdded, "widgets::Widget.labels")));
+        assert!(kinds.contains(&(ChangeKind::EnumValueRemoved, "widgets::Widget.mode")));
+        assert!(kinds.contains(&(ChangeKind::EnumValueAdded, "widgets::Widget.mode")));
+        assert!(report.has_breaking());
+        assert_eq!(report.non_breaking().count(), 3);
+    }
+
+    #[test]
+    fn test_widening_is_not_breaking() {
+        let old = record(vec![("count", field(Type::Integer, false))]);
+        let new = record(vec![(
+            "count",
+            field(Type::Union(vec![Type::Integer, Type::String]), false),
+        )]);
+
+        let report = diff_ir(&single(old), &single(new));
+        assert_eq!(report.changes.len(), 1);
+        assert_eq!(report.changes[0].kind, ChangeKind::TypeWidened);
+        assert!(!report.has_breaking());
+    }
+
+    #[test]
+    fn test_type_added_and_removed() {
+        let old = IRBuilder::new()
+            .module("m")
+            .add_type("Old", Type::String)
+            .build();
+        let new = IRBuilder::new()
+            .module("m")
+            .add_type("New", Type::String)
+            .build();
+
+        let report = diff_ir(&old, &new);
+        assert_eq!(report.breaking().count(), 1);
+        assert_eq!(
+            report.breaking().next().unwrap().kind,
+            ChangeKind::TypeRemoved
+        );
+        assert_eq!(
+            report.non_breaking().next().unwrap().kind,
+            ChangeKind::TypeAdded
+        );
+    }
+}
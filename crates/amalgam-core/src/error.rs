@@ -13,4 +13,16 @@ pub enum CoreError {
 
     #[error("Internal error: {0}")]
     Internal(String),
+
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Serialization error: {0}")]
+    Serialization(#[from] serde_json::Error),
+
+    #[error("Unsupported IR format version {found} (this build supports up to {supported})")]
+    UnsupportedFormatVersion { found: u32, supported: u32 },
+
+    #[error("IR pass {pass} failed: {message}")]
+    Pass { pass: String, message: String },
 }
@@ -25,6 +25,20 @@ pub struct ContentFingerprint {
     pub source_info: SourceInfo,
     /// Version of amalgam that created this fingerprint
     pub amalgam_version: String,
+    /// Hash of the generation settings folded into `content_hash`, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub settings_hash: Option<String>,
+    /// Where the generated package was last pushed, if anywhere
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub published: Option<PublishedArtifact>,
+}
+
+/// A generated package pushed to an OCI registry
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PublishedArtifact {
+    pub reference: String,
+    /// Digest of the pushed manifest
+    pub digest: String,
 }
 
 /// Source-specific information for different ingest types
@@ -63,6 +77,33 @@ pub enum SourceInfo {
         version: String,
         openapi_hash: String,
         spec_url: String,
+        /// Validators of the spec, sent back to skip unchanged downloads
+        #[serde(default)]
+        etag: Option<String>,
+        #[serde(default)]
+        last_modified: Option<DateTime<Utc>>,
+    },
+    /// Helm chart directory or packaged archive
+    HelmChart {
+        chart: String,
+        name: String,
+        version: String,
+        app_version: Option<String>,
+    },
+    /// OLM bundle directory
+    OlmBundle {
+        bundle: String,
+        /// Name of the bundle's ClusterServiceVersion
+        csv: String,
+        version: Option<String>,
+    },
+    /// Image or artifact in an OCI registry
+    OciArtifact {
+        reference: String,
+        manifest_digest: String,
+        layer_digests: Vec<String>,
+        /// Globs selecting files in the layers; empty for all of them
+        paths: Vec<String>,
     },
 }
 
@@ -126,6 +167,8 @@ impl FingerprintBuilder {
                     file_sizes: vec![0],
                 }),
             amalgam_version: env!("CARGO_PKG_VERSION").to_string(),
+            settings_hash: None,
+            published: None,
         }
     }
 
@@ -187,6 +230,35 @@ impl ContentFingerprint {
         self.content_hash != other.content_hash
     }
 
+    /// Hash of generation settings, as stored in `settings_hash`
+    pub fn hash_settings(settings: &str) -> String {
+        format!("{:x}", Sha256::digest(settings.as_bytes()))
+    }
+
+    /// Fold generation settings (filters, overrides, ...) into the content
+    /// hash, so that changing them counts as a content change
+    ///
+    /// A fingerprint already carrying the same settings is returned as is.
+    pub fn with_settings(mut self, settings: &str) -> Self {
+        let settings_hash = Self::hash_settings(settings);
+        if self.settings_hash.as_deref() == Some(settings_hash.as_str()) {
+            return self;
+        }
+
+        let mut hasher = Sha256::new();
+        hasher.update(self.content_hash.as_bytes());
+        hasher.update(settings_hash.as_bytes());
+        self.content_hash = format!("{:x}", hasher.finalize());
+
+        let mut hasher = Sha256::new();
+        hasher.update(self.content_hash.as_bytes());
+        hasher.update(self.metadata_hash.as_bytes());
+        self.combined_hash = format!("{:x}", hasher.finalize());
+
+        self.settings_hash = Some(settings_hash);
+        self
+    }
+
     /// Get a short hash for display purposes
     pub fn short_hash(&self) -> String {
         self.combined_hash.chars().take(12).collect()
@@ -293,4 +365,19 @@ mod tests {
         assert!(fp1.content_changed(&fp3));
         assert!(!fp1.metadata_changed(&fp3));
     }
+
+    #[test]
+    fn test_settings_change_content() {
+        let mut builder = FingerprintBuilder::new();
+        builder.add_content_str("content");
+        let plain = builder.build();
+
+        let filtered = plain.clone().with_settings("include = Bucket*");
+        assert!(filtered.content_changed(&plain));
+        assert!(!filtered.content_matches(&plain.clone().with_settings("include = Queue*")));
+
+        // Applying the same settings again is a no-op
+        let again = filtered.clone().with_settings("include = Bucket*");
+        assert!(again.content_matches(&filtered));
+    }
 }
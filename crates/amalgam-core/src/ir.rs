@@ -1,15 +1,34 @@
 //! Intermediate representation for cross-language transformations
 
+use crate::provenance::Provenance;
 use crate::types::Type;
+use crate::CoreError;
 use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// Current version of the serialized IR format
+///
+/// Bump this whenever a change to the IR would make previously written
+/// bundles deserialize incorrectly.
+pub const IR_FORMAT_VERSION: u32 = 1;
+
+/// File name used when an IR bundle is written next to generated output
+pub const IR_BUNDLE_FILE: &str = "amalgam-ir.json";
 
 /// Intermediate representation of a schema/module
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct IR {
+    /// Serialization format version, see [`IR_FORMAT_VERSION`]
+    #[serde(default = "default_format_version")]
+    pub format_version: u32,
     pub modules: Vec<Module>,
 }
 
+fn default_format_version() -> u32 {
+    IR_FORMAT_VERSION
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Module {
     pub name: String,
@@ -26,12 +45,53 @@ pub struct Import {
     pub items: Vec<String>, // Specific items to import
 }
 
+/// Type annotation marking a definition from a deprecated API version
+pub const DEPRECATED_ANNOTATION: &str = "deprecated";
+
+/// Type annotation carrying the human-readable deprecation message
+pub const DEPRECATION_WARNING_ANNOTATION: &str = "deprecationWarning";
+
+/// Type annotation carrying the `apiVersion` of a Kubernetes resource kind,
+/// e.g. `example.io/v1`
+pub const API_VERSION_ANNOTATION: &str = "apiVersion";
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TypeDefinition {
     pub name: String,
     pub ty: Type,
     pub documentation: Option<String>,
     pub annotations: BTreeMap<String, serde_json::Value>,
+    /// Where the definition was parsed from, if the parser knew
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provenance: Option<Provenance>,
+}
+
+impl TypeDefinition {
+    /// Deprecation message to show users, if the definition is deprecated
+    pub fn deprecation_notice(&self) -> Option<String> {
+        let warning = self
+            .annotations
+            .get(DEPRECATION_WARNING_ANNOTATION)
+            .and_then(|w| w.as_str());
+        let deprecated = self
+            .annotations
+            .get(DEPRECATED_ANNOTATION)
+            .and_then(|d| d.as_bool())
+            .unwrap_or(false);
+        match warning {
+            Some(warning) => Some(warning.to_string()),
+            None if deprecated => Some("this API version is deprecated".to_string()),
+            None => None,
+        }
+    }
+
+    /// The `apiVersion` resources of this kind are written with, if the
+    /// definition is a Kubernetes resource kind
+    pub fn api_version(&self) -> Option<&str> {
+        self.annotations
+            .get(API_VERSION_ANNOTATION)
+            .and_then(|v| v.as_str())
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -54,6 +114,7 @@ pub struct Metadata {
 impl IR {
     pub fn new() -> Self {
         Self {
+            format_version: IR_FORMAT_VERSION,
             modules: Vec::new(),
         }
     }
@@ -73,6 +134,43 @@ impl IR {
         self.modules.extend(other.modules);
         self
     }
+
+    /// Serialize the IR as a pretty-printed JSON bundle
+    pub fn to_json(&self) -> Result<String, CoreError> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// Deserialize an IR bundle, rejecting formats newer than this build understands
+    pub fn from_json(content: &str) -> Result<IR, CoreError> {
+        let ir: IR = serde_json::from_str(content)?;
+        if ir.format_version > IR_FORMAT_VERSION {
+            return Err(CoreError::UnsupportedFormatVersion {
+                found: ir.format_version,
+                supported: IR_FORMAT_VERSION,
+            });
+        }
+        Ok(ir)
+    }
+
+    /// Write the IR bundle to a file, creating parent directories as needed
+    pub fn save_to_file(&self, path: &Path) -> Result<(), CoreError> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, self.to_json()?)?;
+        Ok(())
+    }
+
+    /// Load an IR bundle from a file
+    pub fn load_from_file(path: &Path) -> Result<IR, CoreError> {
+        let content = std::fs::read_to_string(path)?;
+        Self::from_json(&content)
+    }
+
+    /// Path of the IR bundle inside a generated package directory
+    pub fn bundle_path(output_dir: &Path) -> PathBuf {
+        output_dir.join(IR_BUNDLE_FILE)
+    }
 }
 
 impl Default for IR {
@@ -116,6 +214,7 @@ impl IRBuilder {
                 ty,
                 documentation: None,
                 annotations: BTreeMap::new(),
+                provenance: None,
             });
         }
         self
@@ -166,4 +265,30 @@ mod tests {
         assert!(my_type.is_some());
         assert_eq!(my_type.unwrap().ty, Type::String);
     }
+
+    #[test]
+    fn test_ir_bundle_roundtrip() {
+        let ir = IRBuilder::new()
+            .module("test")
+            .add_type("MyType", Type::String)
+            .build();
+
+        let json = ir.to_json().unwrap();
+        let loaded = IR::from_json(&json).unwrap();
+        assert_eq!(loaded.format_version, IR_FORMAT_VERSION);
+        assert_eq!(loaded.modules[0].types[0].ty, Type::String);
+
+        // Bundles written before versioning are read as the current format
+        let legacy = IR::from_json(r#"{"modules": []}"#).unwrap();
+        assert_eq!(legacy.format_version, IR_FORMAT_VERSION);
+
+        let future = format!(
+            r#"{{"format_version": {}, "modules": []}}"#,
+            IR_FORMAT_VERSION + 1
+        );
+        assert!(matches!(
+            IR::from_json(&future),
+            Err(CoreError::UnsupportedFormatVersion { .. })
+        ));
+    }
 }
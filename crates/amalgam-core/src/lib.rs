@@ -1,11 +1,17 @@
 //! Core intermediate representation and type system for amalgam
 
+pub mod dedup;
 pub mod dependency_analyzer;
+pub mod diff;
 pub mod error;
 pub mod fingerprint;
 pub mod ir;
+pub mod nested;
+pub mod provenance;
 pub mod types;
+pub mod visit;
 
 pub use error::CoreError;
 pub use ir::IR;
-pub use types::{Type, TypeSystem};
+pub use provenance::Provenance;
+pub use types::{Incompatibility, Type, TypeSystem};
@@ -0,0 +1,248 @@
+//! Extraction of nested records into named type definitions
+//!
+//! A CRD schema is one inlined tree, so each kind generates as a single
+//! deeply nested record. [`NestedTypeExtractor`] lifts every nested record
+//! into its own [`TypeDefinition`] named after its path, so that
+//! `Deployment.spec.template` becomes `DeploymentSpecTemplate`, and leaves
+//! a [`Type::Reference`] in its place.
+
+use crate::dedup::{has_read_only, type_name, unique_name};
+use crate::ir::TypeDefinition;
+use crate::provenance::Provenance;
+use crate::types::{Field, Type};
+use std::collections::{BTreeMap, HashSet};
+
+/// Lifts nested records out of type definitions
+#[derive(Debug, Clone)]
+pub struct NestedTypeExtractor {
+    min_fields: usize,
+}
+
+impl Default for NestedTypeExtractor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl NestedTypeExtractor {
+    pub fn new() -> Self {
+        Self { min_fields: 1 }
+    }
+
+    /// Only extract records with at least this many fields (default 1)
+    pub fn with_min_fields(mut self, min_fields: usize) -> Self {
+        self.min_fields = min_fields.max(1);
+        self
+    }
+
+    /// Extract the nested records of `definitions`, rewriting them in place
+    ///
+    /// Names join the enclosing type's name with the field name, and get a
+    /// numeric suffix when already taken by one of `definitions` or another
+    /// extracted type. Items of arrays and values of maps are named after
+    /// their field too. Records with read-only fields are left inlined so
+    /// that input variants can still strip those fields. `reference` names
+    /// the reference to an extracted type.
+    pub fn extract(
+        &self,
+        definitions: &mut [TypeDefinition],
+        reference: impl Fn(&str) -> String,
+    ) -> Vec<TypeDefinition> {
+        let mut taken: HashSet<String> =
+            definitions.iter().map(|d| d.name.to_lowercase()).collect();
+        let mut extracted = Vec::new();
+
+        for definition in definitions.iter_mut() {
+            if let Type::Record { fields, .. } = &mut definition.ty {
+                Lifter {
+                    extractor: self,
+                    reference: &reference,
+                    taken: &mut taken,
+                    extracted: &mut extracted,
+                }
+                .lift_fields(fields, &definition.name);
+            }
+        }
+
+        extracted
+    }
+}
+
+struct Lifter<'a, F> {
+    extractor: &'a NestedTypeExtractor,
+    reference: &'a F,
+    taken: &'a mut HashSet<String>,
+    extracted: &'a mut Vec<TypeDefinition>,
+}
+
+impl<F: Fn(&str) -> String> Lifter<'_, F> {
+    /// Lift the records within the fields of a record named `parent`
+    fn lift_fields(&mut self, fields: &mut BTreeMap<String, Field>, parent: &str) {
+        for (name, field) in fields.iter_mut() {
+            self.lift(
+                &mut field.ty,
+                &format!("{}{}", parent, type_name(name)),
+                field.description.as_deref(),
+                field.provenance.as_ref(),
+            );
+        }
+    }
+
+    /// Lift the records within `ty`, naming them `name`
+    fn lift(
+        &mut self,
+        ty: &mut Type,
+        name: &str,
+        description: Option<&str>,
+        provenance: Option<&Provenance>,
+    ) {
+        match ty {
+            Type::Record { fields, .. }
+                if fields.len() >= self.extractor.min_fields && !has_read_only(ty) =>
+            {
+                let name = unique_name(name, self.taken);
+                // Reserve the slot so parents come before their children
+                let slot = self.extracted.len();
+                self.extracted.push(TypeDefinition {
+                    name: name.clone(),
+                    ty: Type::Any,
+                    documentation: description.map(str::to_string),
+                    annotations: BTreeMap::new(),
+                    provenance: provenance.cloned(),
+                });
+
+                if let Type::Record { fields, .. } = ty {
+                    self.lift_fields(fields, &name);
+                }
+                let reference = Type::Reference((self.reference)(&name));
+                self.extracted[slot].ty = std::mem::replace(ty, reference);
+            }
+            Type::Record { fields, .. } => self.lift_fields(fields, name),
+            Type::Array(inner)
+            | Type::Optional(inner)
+            | Type::Contract { base: inner, .. }
+            | Type::Map { value: inner, .. } => self.lift(inner, name, description, provenance),
+            Type::Union(types) => {
+                for ty in types {
+                    self.lift(ty, name, description, provenance);
+                }
+            }
+            Type::TaggedUnion { variants, .. } => {
+                for (tag, ty) in variants.iter_mut() {
+                    let name = format!("{}{}", name, type_name(tag));
+                    self.lift(ty, &name, description, provenance);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn field(ty: Type) -> Field {
+        Field {
+            ty,
+            required: false,
+            description: None,
+            default: None,
+            read_only: false,
+            provenance: None,
+        }
+    }
+
+    fn record(fields: Vec<(&str, Field)>) -> Type {
+        Type::Record {
+            fields: fields
+                .into_iter()
+                .map(|(name, field)| (name.to_string(), field))
+                .collect(),
+            open: false,
+        }
+    }
+
+    fn definition(name: &str, ty: Type) -> TypeDefinition {
+        TypeDefinition {
+            name: name.to_string(),
+            ty,
+            documentation: None,
+            annotations: BTreeMap::new(),
+            provenance: None,
+        }
+    }
+
+    fn deployment() -> TypeDefinition {
+        let container = record(vec![
+            ("name", field(Type::String)),
+            ("image", field(Type::String)),
+        ]);
+        let template = record(vec![(
+            "containers",
+            field(Type::Array(Box::new(container))),
+        )]);
+        let spec = record(vec![
+            ("replicas", field(Type::Integer)),
+            ("template", field(template)),
+        ]);
+        definition("Deployment", record(vec![("spec", field(spec))]))
+    }
+
+    #[test]
+    fn test_nested_records_are_named_by_path() {
+        let mut definitions = vec![deployment()];
+        let extracted = NestedTypeExtractor::new()
+            .extract(&mut definitions, |name| format!("apps/v1.{}", name));
+
+        let names: Vec<_> = extracted.iter().map(|d| d.name.as_str()).collect();
+        assert_eq!(
+            names,
+            vec![
+                "DeploymentSpec",
+                "DeploymentSpecTemplate",
+                "DeploymentSpecTemplateContainers"
+            ]
+        );
+
+        let Type::Record { fields, .. } = &definitions[0].ty else {
+            panic!("Deployment should stay a record");
+        };
+        assert_eq!(
+            fields["spec"].ty,
+            Type::Reference("apps/v1.DeploymentSpec".to_string())
+        );
+        let Type::Record { fields, .. } = &extracted[1].ty else {
+            panic!("DeploymentSpecTemplate should be a record");
+        };
+        assert_eq!(
+            fields["containers"].ty,
+            Type::Array(Box::new(Type::Reference(
+                "apps/v1.DeploymentSpecTemplateContainers".to_string()
+            )))
+        );
+    }
+
+    #[test]
+    fn test_names_avoid_collisions() {
+        let mut definitions = vec![
+            deployment(),
+            definition("DeploymentSpec", record(vec![("x", field(Type::String))])),
+        ];
+        let extracted = NestedTypeExtractor::new().extract(&mut definitions, str::to_string);
+        assert_eq!(extracted[0].name, "DeploymentSpec2");
+        assert_eq!(extracted[1].name, "DeploymentSpec2Template");
+    }
+
+    #[test]
+    fn test_read_only_records_stay_inline() {
+        let mut status = field(Type::String);
+        status.read_only = true;
+        let mut definitions = vec![definition(
+            "Widget",
+            record(vec![("status", field(record(vec![("phase", status)])))]),
+        )];
+        let extracted = NestedTypeExtractor::new().extract(&mut definitions, str::to_string);
+        assert!(extracted.is_empty());
+    }
+}
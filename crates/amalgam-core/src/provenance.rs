@@ -0,0 +1,91 @@
+//! Where parsed types and fields came from
+//!
+//! Parsers attach a [`Provenance`] to the type definitions and fields they
+//! produce, so a wrong piece of generated code can be traced back to the
+//! file, CRD version and schema location it was generated from.
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Source document and location within it of a type or field
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Provenance {
+    /// File path or URL of the document, or a name for it when it was not
+    /// read from a file
+    pub source: String,
+    /// JSON pointer into the document, e.g.
+    /// `/spec/versions/0/schema/openAPIV3Schema/properties/spec`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pointer: Option<String>,
+    /// 1-based line, for sources such as Go files
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub line: Option<usize>,
+}
+
+impl Provenance {
+    pub fn new(source: impl Into<String>) -> Self {
+        Self {
+            source: source.into(),
+            pointer: None,
+            line: None,
+        }
+    }
+
+    pub fn with_pointer(mut self, pointer: impl Into<String>) -> Self {
+        self.pointer = Some(pointer.into());
+        self
+    }
+
+    pub fn with_line(mut self, line: usize) -> Self {
+        self.line = Some(line);
+        self
+    }
+
+    /// The location one JSON pointer segment below this one
+    ///
+    /// `~` and `/` in the segment are escaped as RFC 6901 requires.
+    pub fn child(&self, segment: &str) -> Self {
+        let segment = segment.replace('~', "~0").replace('/', "~1");
+        let mut child = self.clone();
+        child.pointer = Some(format!(
+            "{}/{}",
+            self.pointer.as_deref().unwrap_or(""),
+            segment
+        ));
+        child
+    }
+}
+
+impl fmt::Display for Provenance {
+    /// `file#/json/pointer`, `file:line` or just `file`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.source)?;
+        if let Some(line) = self.line {
+            write!(f, ":{}", line)?;
+        }
+        if let Some(pointer) = &self.pointer {
+            write!(f, "#{}", pointer)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_child_pointers_are_escaped() {
+        let root = Provenance::new("crds/widgets.yaml")
+            .with_pointer("/spec/versions/0/schema/openAPIV3Schema");
+        let label = root.child("properties").child("app.kubernetes.io/name~x");
+        assert_eq!(
+            label.to_string(),
+            "crds/widgets.yaml#/spec/versions/0/schema/openAPIV3Schema/properties/app.kubernetes.io~1name~0x"
+        );
+        assert_eq!(
+            Provenance::new("types.go").with_line(42).to_string(),
+            "types.go:42"
+        );
+    }
+}
@@ -1,7 +1,9 @@
 //! Unified type system using algebraic data types
 
+use crate::provenance::Provenance;
 use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
+use std::fmt;
 
 /// Core type representation - algebraic data types
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -41,20 +43,166 @@ pub enum Type {
     Reference(String),
 
     /// Contract/refinement type
+    ///
+    /// `predicate` is a Nickel expression: either the name of a contract,
+    /// such as `std.string.NonEmpty`, or a predicate function such as
+    /// `std.number.is_integer` that values of `base` must satisfy.
     Contract {
         base: Box<Type>,
-        predicate: String, // For now, just store as string
+        predicate: String,
     },
+
+    /// Enumeration of allowed literal values (JSON Schema `enum`)
+    Enum(Vec<serde_json::Value>),
 }
 
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+impl Type {
+    /// The primitive type shared by all values of an enum, or `Any` if they differ
+    pub fn enum_base(values: &[serde_json::Value]) -> Type {
+        let mut base: Option<Type> = None;
+        for value in values {
+            let ty = match value {
+                serde_json::Value::String(_) => Type::String,
+                serde_json::Value::Bool(_) => Type::Bool,
+                serde_json::Value::Null => Type::Null,
+                serde_json::Value::Number(n) if n.is_i64() || n.is_u64() => Type::Integer,
+                serde_json::Value::Number(_) => Type::Number,
+                _ => return Type::Any,
+            };
+            base = match base {
+                None => Some(ty),
+                Some(Type::Integer) if ty == Type::Number => Some(Type::Number),
+                Some(Type::Number) if ty == Type::Integer => Some(Type::Number),
+                Some(prev) if prev == ty => Some(prev),
+                Some(_) => return Type::Any,
+            };
+        }
+        base.unwrap_or(Type::Any)
+    }
+
+    /// Short human-readable name for the type, used in messages
+    pub fn describe(&self) -> String {
+        match self {
+            Type::String => "String".to_string(),
+            Type::Number => "Number".to_string(),
+            Type::Integer => "Integer".to_string(),
+            Type::Bool => "Bool".to_string(),
+            Type::Null => "Null".to_string(),
+            Type::Any => "Any".to_string(),
+            Type::Array(elem) => format!("Array {}", elem.describe()),
+            Type::Map { value, .. } => format!("Map {}", value.describe()),
+            Type::Optional(inner) => format!("Optional {}", inner.describe()),
+            Type::Record { .. } => "Record".to_string(),
+            Type::Union(types) => types
+                .iter()
+                .map(Type::describe)
+                .collect::<Vec<_>>()
+                .join(" | "),
+            Type::TaggedUnion { tag_field, .. } => format!("TaggedUnion({})", tag_field),
+            Type::Reference(name) => name.clone(),
+            Type::Contract { base, .. } => format!("Contract({})", base.describe()),
+            Type::Enum(values) => format!("Enum({} values)", values.len()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Field {
     pub ty: Type,
     pub required: bool,
     pub description: Option<String>,
     pub default: Option<serde_json::Value>,
+    /// Populated by the server; users should not set it (JSON Schema `readOnly`)
+    #[serde(default)]
+    pub read_only: bool,
+    /// Where the field was parsed from, if the parser knew
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provenance: Option<Provenance>,
+}
+
+/// Fields are equal when they describe the same field, wherever they were
+/// parsed from
+impl PartialEq for Field {
+    fn eq(&self, other: &Self) -> bool {
+        self.ty == other.ty
+            && self.required == other.required
+            && self.description == other.description
+            && self.default == other.default
+            && self.read_only == other.read_only
+    }
+}
+
+/// Why values of one type are not all valid values of another
+#[derive(Debug, Clone, PartialEq)]
+pub struct Incompatibility {
+    /// Location of the mismatch inside the types, e.g. `.spec.replicas`,
+    /// `.items[]` or `{}` for map values; empty at the top level
+    pub path: String,
+    pub reason: IncompatibilityReason,
+}
+
+/// The specific mismatch behind an [`Incompatibility`]
+#[derive(Debug, Clone, PartialEq)]
+pub enum IncompatibilityReason {
+    /// The types have unrelated shapes
+    Mismatch { source: String, target: String },
+    /// The target requires a field the source does not have
+    MissingField(String),
+    /// The target requires a field the source leaves optional
+    OptionalField(String),
+    /// The source has a field the closed target record does not allow
+    UnexpectedField(String),
+    /// The source record allows additional fields the closed target does not
+    OpenRecord,
+    /// The source accepts null but the target does not
+    Nullable,
+    /// An enum value the target does not accept
+    EnumValue(serde_json::Value),
+    /// The source matches no member of the target union
+    NoMatchingMember(String),
+    /// The tagged unions are discriminated by different fields
+    TagField { source: String, target: String },
+    /// A variant of the source tagged union that the target lacks
+    MissingVariant(String),
+    /// The target's contract is not known to hold for source values
+    Contract(String),
+    /// A reference to a type that is not registered
+    UnresolvedReference(String),
+}
+
+impl fmt::Display for IncompatibilityReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Mismatch { source, target } => write!(f, "{} is not a {}", source, target),
+            Self::MissingField(name) => write!(f, "required field {} is missing", name),
+            Self::OptionalField(name) => write!(f, "field {} is required but optional", name),
+            Self::UnexpectedField(name) => write!(f, "field {} is not allowed", name),
+            Self::OpenRecord => write!(f, "additional fields are not allowed"),
+            Self::Nullable => write!(f, "null is not accepted"),
+            Self::EnumValue(value) => write!(f, "enum value {} is not accepted", value),
+            Self::NoMatchingMember(source) => write!(f, "{} matches no union member", source),
+            Self::TagField { source, target } => {
+                write!(f, "tagged by {} instead of {}", source, target)
+            }
+            Self::MissingVariant(tag) => write!(f, "variant {} is not accepted", tag),
+            Self::Contract(predicate) => write!(f, "contract {} may not hold", predicate),
+            Self::UnresolvedReference(name) => write!(f, "unknown type {}", name),
+        }
+    }
+}
+
+impl fmt::Display for Incompatibility {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.path.is_empty() {
+            write!(f, "{}", self.reason)
+        } else {
+            write!(f, "{}: {}", self.path, self.reason)
+        }
+    }
 }
 
+impl std::error::Error for Incompatibility {}
+
 /// Type system operations
 pub struct TypeSystem {
     types: BTreeMap<String, Type>,
@@ -75,30 +223,241 @@ impl TypeSystem {
         self.types.get(name)
     }
 
+    /// Whether every value of `source` is also a value of `target`
     pub fn is_compatible(&self, source: &Type, target: &Type) -> bool {
+        self.check_compatible(source, target).is_ok()
+    }
+
+    /// Check that every value of `source` is a value of `target`, explaining why not
+    ///
+    /// Records are compared structurally: the source may have extra fields
+    /// when the target is open, and must have every field the target
+    /// requires, as required fields. Field, element, map key and map value
+    /// types are compared recursively. Tagged unions must share their tag
+    /// field and the target must accept every source variant.
+    pub fn check_compatible(&self, source: &Type, target: &Type) -> Result<(), Incompatibility> {
+        self.check(source, target, "", &mut Vec::new())
+    }
+
+    /// The type a reference points to, or the type itself if it is not one
+    fn resolve_at<'a>(&'a self, ty: &'a Type, path: &str) -> Result<&'a Type, Incompatibility> {
+        match ty {
+            Type::Reference(name) => self.resolve(name).ok_or_else(|| Incompatibility {
+                path: path.to_string(),
+                reason: IncompatibilityReason::UnresolvedReference(name.clone()),
+            }),
+            ty => Ok(ty),
+        }
+    }
+
+    fn check(
+        &self,
+        source: &Type,
+        target: &Type,
+        path: &str,
+        assumed: &mut Vec<(*const Type, *const Type)>,
+    ) -> Result<(), Incompatibility> {
+        let fail = |reason| {
+            Err(Incompatibility {
+                path: path.to_string(),
+                reason,
+            })
+        };
+        let mismatch = || {
+            fail(IncompatibilityReason::Mismatch {
+                source: source.describe(),
+                target: target.describe(),
+            })
+        };
+
         match (source, target) {
-            (Type::Any, _) | (_, Type::Any) => true,
-            (Type::Null, Type::Optional(_)) => true,
-            (s, Type::Optional(t)) => self.is_compatible(s, t),
-            (Type::Integer, Type::Number) => true,
-            (Type::Reference(s), t) => {
-                if let Some(resolved) = self.resolve(s) {
-                    self.is_compatible(resolved, t)
-                } else {
-                    false
+            (Type::Any, _) | (_, Type::Any) => Ok(()),
+            (Type::Reference(s), Type::Reference(t)) if s == t => Ok(()),
+            (Type::Reference(_), _) | (_, Type::Reference(_)) => {
+                // Recursive types are compatible if they are wherever they recur
+                let pair = (source as *const Type, target as *const Type);
+                if assumed.contains(&pair) {
+                    return Ok(());
+                }
+                let (resolved_source, resolved_target) = (
+                    self.resolve_at(source, path)?,
+                    self.resolve_at(target, path)?,
+                );
+                assumed.push(pair);
+                let result = self.check(resolved_source, resolved_target, path, assumed);
+                assumed.pop();
+                result
+            }
+
+            (Type::Union(members), _) => members
+                .iter()
+                .try_for_each(|member| self.check(member, target, path, assumed)),
+            (Type::Optional(s), Type::Optional(t)) => self.check(s, t, path, assumed),
+            (Type::Null, Type::Optional(_)) => Ok(()),
+            (_, Type::Optional(t)) => self.check(source, t, path, assumed),
+            (Type::Optional(s), _) => {
+                if self.check(&Type::Null, target, path, assumed).is_err() {
+                    return fail(IncompatibilityReason::Nullable);
                 }
+                self.check(s, target, path, assumed)
             }
-            (s, Type::Reference(t)) => {
-                if let Some(resolved) = self.resolve(t) {
-                    self.is_compatible(s, resolved)
+            (_, Type::Union(members)) => {
+                if members
+                    .iter()
+                    .any(|member| self.check(source, member, path, assumed).is_ok())
+                {
+                    Ok(())
                 } else {
-                    false
+                    fail(IncompatibilityReason::NoMatchingMember(source.describe()))
+                }
+            }
+
+            (Type::Integer, Type::Number) => Ok(()),
+            (Type::Enum(s), Type::Enum(t)) => match s.iter().find(|v| !t.contains(v)) {
+                Some(value) => fail(IncompatibilityReason::EnumValue(value.clone())),
+                None => Ok(()),
+            },
+            (Type::Enum(values), _) => self.check(&Type::enum_base(values), target, path, assumed),
+
+            (
+                Type::Contract {
+                    base: s,
+                    predicate: source_predicate,
+                },
+                Type::Contract {
+                    base: t,
+                    predicate: target_predicate,
+                },
+            ) => {
+                if source_predicate != target_predicate {
+                    return fail(IncompatibilityReason::Contract(target_predicate.clone()));
+                }
+                self.check(s, t, path, assumed)
+            }
+            // A refinement's values are values of its base
+            (Type::Contract { base, .. }, _) => self.check(base, target, path, assumed),
+            (_, Type::Contract { predicate, .. }) => {
+                fail(IncompatibilityReason::Contract(predicate.clone()))
+            }
+
+            (Type::Array(s), Type::Array(t)) => self.check(s, t, &format!("{}[]", path), assumed),
+            (
+                Type::Map {
+                    key: source_key,
+                    value: source_value,
+                },
+                Type::Map {
+                    key: target_key,
+                    value: target_value,
+                },
+            ) => {
+                self.check(source_key, target_key, &format!("{}{{key}}", path), assumed)?;
+                self.check(
+                    source_value,
+                    target_value,
+                    &format!("{}{{}}", path),
+                    assumed,
+                )
+            }
+
+            (
+                Type::Record {
+                    fields: source_fields,
+                    open: source_open,
+                },
+                Type::Record {
+                    fields: target_fields,
+                    open: target_open,
+                },
+            ) => {
+                for (name, target_field) in target_fields {
+                    let field_path = format!("{}.{}", path, name);
+                    match source_fields.get(name) {
+                        Some(source_field) => {
+                            if target_field.required && !source_field.required {
+                                return fail(IncompatibilityReason::OptionalField(name.clone()));
+                            }
+                            self.check(&source_field.ty, &target_field.ty, &field_path, assumed)?;
+                        }
+                        None if target_field.required => {
+                            return fail(IncompatibilityReason::MissingField(name.clone()));
+                        }
+                        None => {}
+                    }
                 }
+                if !target_open {
+                    if let Some(name) = source_fields
+                        .keys()
+                        .find(|n| !target_fields.contains_key(*n))
+                    {
+                        return fail(IncompatibilityReason::UnexpectedField(name.clone()));
+                    }
+                    if *source_open {
+                        return fail(IncompatibilityReason::OpenRecord);
+                    }
+                }
+                Ok(())
+            }
+            // A closed record is a map from its field names to its field values
+            (Type::Record { fields, open }, Type::Map { key, value }) => {
+                self.check(&Type::String, key, &format!("{}{{key}}", path), assumed)?;
+                for (name, field) in fields {
+                    self.check(&field.ty, value, &format!("{}.{}", path, name), assumed)?;
+                }
+                if *open && **value != Type::Any {
+                    return fail(IncompatibilityReason::OpenRecord);
+                }
+                Ok(())
+            }
+            // A map fits an open record whose fields are all optional
+            (Type::Map { value, .. }, Type::Record { fields, open: true }) => {
+                for (name, field) in fields {
+                    if field.required {
+                        return fail(IncompatibilityReason::MissingField(name.clone()));
+                    }
+                    self.check(value, &field.ty, &format!("{}.{}", path, name), assumed)?;
+                }
+                Ok(())
             }
-            (Type::Array(s), Type::Array(t)) => self.is_compatible(s, t),
-            (Type::Union(variants), t) => variants.iter().all(|v| self.is_compatible(v, t)),
-            (s, Type::Union(variants)) => variants.iter().any(|v| self.is_compatible(s, v)),
-            _ => source == target,
+
+            (
+                Type::TaggedUnion {
+                    tag_field: source_tag,
+                    variants: source_variants,
+                },
+                Type::TaggedUnion {
+                    tag_field: target_tag,
+                    variants: target_variants,
+                },
+            ) => {
+                if source_tag != target_tag {
+                    return fail(IncompatibilityReason::TagField {
+                        source: source_tag.clone(),
+                        target: target_tag.clone(),
+                    });
+                }
+                for (tag, source_variant) in source_variants {
+                    let Some(target_variant) = target_variants.get(tag) else {
+                        return fail(IncompatibilityReason::MissingVariant(tag.clone()));
+                    };
+                    let variant_path = format!("{}[{}={}]", path, source_tag, tag);
+                    self.check(source_variant, target_variant, &variant_path, assumed)?;
+                }
+                Ok(())
+            }
+            (
+                Type::TaggedUnion {
+                    tag_field,
+                    variants,
+                },
+                _,
+            ) => variants.iter().try_for_each(|(tag, variant)| {
+                let variant_path = format!("{}[{}={}]", path, tag_field, tag);
+                self.check(variant, target, &variant_path, assumed)
+            }),
+
+            _ if source == target => Ok(()),
+            _ => mismatch(),
         }
     }
 }
@@ -134,4 +493,216 @@ mod tests {
         assert!(ts.is_compatible(&Type::Number, &union));
         assert!(!ts.is_compatible(&Type::Bool, &union));
     }
+
+    #[test]
+    fn test_enum_compatibility() {
+        let ts = TypeSystem::new();
+        let small = Type::Enum(vec!["Always".into(), "Never".into()]);
+        let large = Type::Enum(vec!["Always".into(), "Never".into(), "OnFailure".into()]);
+
+        assert!(ts.is_compatible(&small, &large));
+        assert!(!ts.is_compatible(&large, &small));
+        assert!(ts.is_compatible(&small, &Type::String));
+        assert!(!ts.is_compatible(&Type::String, &small));
+        assert_eq!(Type::enum_base(&[1.into(), 2.5.into()]), Type::Number);
+        assert_eq!(Type::enum_base(&["a".into(), 1.into()]), Type::Any);
+    }
+
+    fn field(ty: Type, required: bool) -> Field {
+        Field {
+            ty,
+            required,
+            description: None,
+            default: None,
+            read_only: false,
+            provenance: None,
+        }
+    }
+
+    fn record(fields: &[(&str, Type, bool)], open: bool) -> Type {
+        Type::Record {
+            fields: fields
+                .iter()
+                .map(|(name, ty, required)| (name.to_string(), field(ty.clone(), *required)))
+                .collect(),
+            open,
+        }
+    }
+
+    #[test]
+    fn test_record_subtyping() {
+        let ts = TypeSystem::new();
+        let base = record(&[("name", Type::String, true)], false);
+        let wider = record(
+            &[("name", Type::String, true), ("size", Type::Integer, false)],
+            false,
+        );
+        let open = record(&[("name", Type::String, true)], true);
+
+        // Structurally identical records from different sources
+        assert!(ts.is_compatible(&base, &base.clone()));
+
+        // Extra fields only fit an open target
+        assert!(ts.is_compatible(&wider, &open));
+        assert_eq!(
+            ts.check_compatible(&wider, &base).unwrap_err().reason,
+            IncompatibilityReason::UnexpectedField("size".to_string())
+        );
+        assert_eq!(
+            ts.check_compatible(&open, &base).unwrap_err().reason,
+            IncompatibilityReason::OpenRecord
+        );
+
+        // Optional target fields may be missing, required ones may not
+        assert!(ts.is_compatible(&base, &wider));
+        let optional_name = record(&[("name", Type::String, false)], false);
+        assert!(ts.is_compatible(&base, &optional_name));
+        assert_eq!(
+            ts.check_compatible(&optional_name, &base)
+                .unwrap_err()
+                .reason,
+            IncompatibilityReason::OptionalField("name".to_string())
+        );
+        assert_eq!(
+            ts.check_compatible(&record(&[], false), &base)
+                .unwrap_err()
+                .reason,
+            IncompatibilityReason::MissingField("name".to_string())
+        );
+    }
+
+    #[test]
+    fn test_nested_incompatibility_path() {
+        let ts = TypeSystem::new();
+        let spec = |replicas: Type| {
+            record(
+                &[(
+                    "spec",
+                    record(
+                        &[(
+                            "containers",
+                            Type::Array(Box::new(record(&[("replicas", replicas, true)], false))),
+                            false,
+                        )],
+                        false,
+                    ),
+                    true,
+                )],
+                false,
+            )
+        };
+
+        // Depth subtyping
+        assert!(ts.is_compatible(&spec(Type::Integer), &spec(Type::Number)));
+
+        let error = ts
+            .check_compatible(&spec(Type::Number), &spec(Type::Integer))
+            .unwrap_err();
+        assert_eq!(error.path, ".spec.containers[].replicas");
+        assert_eq!(
+            error.to_string(),
+            ".spec.containers[].replicas: Number is not a Integer"
+        );
+    }
+
+    #[test]
+    fn test_map_and_union_compatibility() {
+        let ts = TypeSystem::new();
+        let map = |value: Type| Type::Map {
+            key: Box::new(Type::String),
+            value: Box::new(value),
+        };
+
+        assert!(ts.is_compatible(&map(Type::Integer), &map(Type::Number)));
+        let error = ts
+            .check_compatible(&map(Type::Number), &map(Type::Integer))
+            .unwrap_err();
+        assert_eq!(error.path, "{}");
+
+        // Closed records are maps of their fields
+        let labels = record(&[("app", Type::String, true)], false);
+        assert!(ts.is_compatible(&labels, &map(Type::String)));
+        assert!(!ts.is_compatible(&labels, &map(Type::Bool)));
+
+        // Nullable sources need a target that accepts null
+        let nullable = Type::Optional(Box::new(Type::String));
+        assert_eq!(
+            ts.check_compatible(&nullable, &Type::String)
+                .unwrap_err()
+                .reason,
+            IncompatibilityReason::Nullable
+        );
+        assert!(ts.is_compatible(&nullable, &Type::Union(vec![Type::Null, Type::String])));
+    }
+
+    #[test]
+    fn test_tagged_union_compatibility() {
+        let ts = TypeSystem::new();
+        let tagged = |tag: &str, variants: &[&str]| Type::TaggedUnion {
+            tag_field: tag.to_string(),
+            variants: variants
+                .iter()
+                .map(|v| {
+                    (
+                        v.to_string(),
+                        record(&[("value", Type::String, true)], false),
+                    )
+                })
+                .collect(),
+        };
+
+        assert!(ts.is_compatible(&tagged("type", &["a"]), &tagged("type", &["a", "b"])));
+        assert_eq!(
+            ts.check_compatible(&tagged("type", &["a", "b"]), &tagged("type", &["a"]))
+                .unwrap_err()
+                .reason,
+            IncompatibilityReason::MissingVariant("b".to_string())
+        );
+        assert!(matches!(
+            ts.check_compatible(&tagged("type", &["a"]), &tagged("kind", &["a"]))
+                .unwrap_err()
+                .reason,
+            IncompatibilityReason::TagField { .. }
+        ));
+    }
+
+    #[test]
+    fn test_contract_and_recursive_compatibility() {
+        let mut ts = TypeSystem::new();
+        let port = Type::Contract {
+            base: Box::new(Type::Integer),
+            predicate: "std.number.is_integer".to_string(),
+        };
+        assert!(ts.is_compatible(&port, &Type::Number));
+        assert!(ts.is_compatible(&port, &port.clone()));
+        assert!(!ts.is_compatible(&Type::Integer, &port));
+
+        // Two recursive lists with the same shape
+        for name in ["ListA", "ListB"] {
+            ts.register(
+                name.to_string(),
+                record(
+                    &[
+                        ("value", Type::String, true),
+                        (
+                            "next",
+                            Type::Optional(Box::new(Type::Reference(name.to_string()))),
+                            false,
+                        ),
+                    ],
+                    false,
+                ),
+            );
+        }
+        assert!(ts.is_compatible(
+            &Type::Reference("ListA".to_string()),
+            &Type::Reference("ListB".to_string())
+        ));
+        assert_eq!(
+            ts.check_compatible(&Type::Reference("Missing".to_string()), &Type::String)
+                .unwrap_err()
+                .reason,
+            IncompatibilityReason::UnresolvedReference("Missing".to_string())
+        );
+    }
 }
@@ -0,0 +1,580 @@
+//! Traversal and rewriting of types, modules and whole IRs
+//!
+//! [`Visitor`] walks an IR read-only and [`Fold`] rebuilds it. Both have a
+//! method per kind of node whose default recurses into the node's children
+//! (through the `walk_*` and `rebuild_*` functions), so an implementation
+//! only overrides the nodes it cares about. Every type is visited with the
+//! [`TypePath`] leading to it from its type definition, such as
+//! `Deployment.spec.containers[].image`.
+//!
+//! [`Pipeline`] runs a sequence of [`Pass`]es over an IR, so that custom
+//! transforms can be registered alongside the built-in ones.
+
+use crate::ir::{Constant, Module, TypeDefinition, IR};
+use crate::types::{Field, Type};
+use crate::CoreError;
+use std::collections::BTreeSet;
+use std::fmt;
+
+/// One step of a [`TypePath`]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum PathSegment {
+    /// The type definition or constant the path starts from
+    Root(String),
+    /// A field of a record
+    Field(String),
+    /// The items of an array, shown as `[]`
+    Item,
+    /// The keys of a map, shown as `{key}`
+    Key,
+    /// The values of a map, shown as `{}`
+    Value,
+    /// An alternative of a union by position, shown as `(0)`
+    Alternative(usize),
+    /// A variant of a tagged union, shown as `[tag]`
+    Variant(String),
+}
+
+/// Location of a type within the definition that contains it
+///
+/// `Optional` and `Contract` wrappers add no segment, so a field has the
+/// same path whether or not it is nullable.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct TypePath {
+    segments: Vec<PathSegment>,
+}
+
+impl TypePath {
+    /// The path of the definition or constant named `root`
+    pub fn new(root: impl Into<String>) -> Self {
+        Self {
+            segments: vec![PathSegment::Root(root.into())],
+        }
+    }
+
+    pub fn segments(&self) -> &[PathSegment] {
+        &self.segments
+    }
+
+    /// This path extended by `segment`
+    pub fn child(&self, segment: PathSegment) -> Self {
+        let mut child = self.clone();
+        child.segments.push(segment);
+        child
+    }
+
+    /// The name of the field this path ends at, if it ends at a field
+    pub fn field_name(&self) -> Option<&str> {
+        match self.segments.last() {
+            Some(PathSegment::Field(name)) => Some(name),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for TypePath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, segment) in self.segments.iter().enumerate() {
+            match segment {
+                PathSegment::Root(name) => write!(f, "{}", name)?,
+                PathSegment::Field(name) if i == 0 => write!(f, "{}", name)?,
+                PathSegment::Field(name) => write!(f, ".{}", name)?,
+                PathSegment::Item => write!(f, "[]")?,
+                PathSegment::Key => write!(f, "{{key}}")?,
+                PathSegment::Value => write!(f, "{{}}")?,
+                PathSegment::Alternative(index) => write!(f, "({})", index)?,
+                PathSegment::Variant(tag) => write!(f, "[{}]", tag)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Read-only traversal of the IR
+pub trait Visitor {
+    fn visit_ir(&mut self, ir: &IR) {
+        walk_ir(self, ir)
+    }
+
+    fn visit_module(&mut self, module: &Module) {
+        walk_module(self, module)
+    }
+
+    fn visit_type_definition(&mut self, definition: &TypeDefinition) {
+        walk_type_definition(self, definition)
+    }
+
+    fn visit_constant(&mut self, constant: &Constant) {
+        walk_constant(self, constant)
+    }
+
+    /// Visit a record field; `path` ends with the field's name
+    fn visit_field(&mut self, field: &Field, path: &TypePath) {
+        walk_field(self, field, path)
+    }
+
+    fn visit_type(&mut self, ty: &Type, path: &TypePath) {
+        walk_type(self, ty, path)
+    }
+
+    fn visit_reference(&mut self, _name: &str, _path: &TypePath) {}
+}
+
+pub fn walk_ir<V: Visitor + ?Sized>(visitor: &mut V, ir: &IR) {
+    for module in &ir.modules {
+        visitor.visit_module(module);
+    }
+}
+
+pub fn walk_module<V: Visitor + ?Sized>(visitor: &mut V, module: &Module) {
+    for definition in &module.types {
+        visitor.visit_type_definition(definition);
+    }
+    for constant in &module.constants {
+        visitor.visit_constant(constant);
+    }
+}
+
+pub fn walk_type_definition<V: Visitor + ?Sized>(visitor: &mut V, definition: &TypeDefinition) {
+    visitor.visit_type(&definition.ty, &TypePath::new(&definition.name));
+}
+
+pub fn walk_constant<V: Visitor + ?Sized>(visitor: &mut V, constant: &Constant) {
+    visitor.visit_type(&constant.ty, &TypePath::new(&constant.name));
+}
+
+pub fn walk_field<V: Visitor + ?Sized>(visitor: &mut V, field: &Field, path: &TypePath) {
+    visitor.visit_type(&field.ty, path);
+}
+
+/// Visit the types directly within `ty`, or its name if it is a reference
+pub fn walk_type<V: Visitor + ?Sized>(visitor: &mut V, ty: &Type, path: &TypePath) {
+    match ty {
+        Type::Reference(name) => visitor.visit_reference(name, path),
+        Type::Array(inner) => visitor.visit_type(inner, &path.child(PathSegment::Item)),
+        Type::Optional(inner) | Type::Contract { base: inner, .. } => {
+            visitor.visit_type(inner, path)
+        }
+        Type::Map { key, value } => {
+            visitor.visit_type(key, &path.child(PathSegment::Key));
+            visitor.visit_type(value, &path.child(PathSegment::Value));
+        }
+        Type::Record { fields, .. } => {
+            for (name, field) in fields {
+                visitor.visit_field(field, &path.child(PathSegment::Field(name.clone())));
+            }
+        }
+        Type::Union(types) => {
+            for (index, ty) in types.iter().enumerate() {
+                visitor.visit_type(ty, &path.child(PathSegment::Alternative(index)));
+            }
+        }
+        Type::TaggedUnion { variants, .. } => {
+            for (tag, ty) in variants {
+                visitor.visit_type(ty, &path.child(PathSegment::Variant(tag.clone())));
+            }
+        }
+        Type::String
+        | Type::Number
+        | Type::Integer
+        | Type::Bool
+        | Type::Null
+        | Type::Any
+        | Type::Enum(_) => {}
+    }
+}
+
+/// Rewriting traversal of the IR, taking each node and returning its
+/// replacement
+pub trait Fold {
+    fn fold_ir(&mut self, ir: IR) -> IR {
+        rebuild_ir(self, ir)
+    }
+
+    fn fold_module(&mut self, module: Module) -> Module {
+        rebuild_module(self, module)
+    }
+
+    fn fold_type_definition(&mut self, definition: TypeDefinition) -> TypeDefinition {
+        rebuild_type_definition(self, definition)
+    }
+
+    fn fold_constant(&mut self, constant: Constant) -> Constant {
+        rebuild_constant(self, constant)
+    }
+
+    /// Fold a record field; `path` ends with the field's name
+    fn fold_field(&mut self, field: Field, path: &TypePath) -> Field {
+        rebuild_field(self, field, path)
+    }
+
+    fn fold_type(&mut self, ty: Type, path: &TypePath) -> Type {
+        rebuild_type(self, ty, path)
+    }
+
+    /// Replace a reference, possibly with an inline type
+    fn fold_reference(&mut self, name: String, _path: &TypePath) -> Type {
+        Type::Reference(name)
+    }
+}
+
+pub fn rebuild_ir<F: Fold + ?Sized>(folder: &mut F, mut ir: IR) -> IR {
+    ir.modules = ir
+        .modules
+        .into_iter()
+        .map(|module| folder.fold_module(module))
+        .collect();
+    ir
+}
+
+pub fn rebuild_module<F: Fold + ?Sized>(folder: &mut F, mut module: Module) -> Module {
+    module.types = module
+        .types
+        .into_iter()
+        .map(|definition| folder.fold_type_definition(definition))
+        .collect();
+    module.constants = module
+        .constants
+        .into_iter()
+        .map(|constant| folder.fold_constant(constant))
+        .collect();
+    module
+}
+
+pub fn rebuild_type_definition<F: Fold + ?Sized>(
+    folder: &mut F,
+    mut definition: TypeDefinition,
+) -> TypeDefinition {
+    let path = TypePath::new(&definition.name);
+    definition.ty = folder.fold_type(definition.ty, &path);
+    definition
+}
+
+pub fn rebuild_constant<F: Fold + ?Sized>(folder: &mut F, mut constant: Constant) -> Constant {
+    let path = TypePath::new(&constant.name);
+    constant.ty = folder.fold_type(constant.ty, &path);
+    constant
+}
+
+pub fn rebuild_field<F: Fold + ?Sized>(folder: &mut F, mut field: Field, path: &TypePath) -> Field {
+    field.ty = folder.fold_type(field.ty, path);
+    field
+}
+
+/// Fold the types directly within `ty`, or its name if it is a reference
+pub fn rebuild_type<F: Fold + ?Sized>(folder: &mut F, ty: Type, path: &TypePath) -> Type {
+    match ty {
+        Type::Reference(name) => folder.fold_reference(name, path),
+        Type::Array(inner) => Type::Array(Box::new(
+            folder.fold_type(*inner, &path.child(PathSegment::Item)),
+        )),
+        Type::Optional(inner) => Type::Optional(Box::new(folder.fold_type(*inner, path))),
+        Type::Contract { base, predicate } => Type::Contract {
+            base: Box::new(folder.fold_type(*base, path)),
+            predicate,
+        },
+        Type::Map { key, value } => Type::Map {
+            key: Box::new(folder.fold_type(*key, &path.child(PathSegment::Key))),
+            value: Box::new(folder.fold_type(*value, &path.child(PathSegment::Value))),
+        },
+        Type::Record { fields, open } => Type::Record {
+            fields: fields
+                .into_iter()
+                .map(|(name, field)| {
+                    let field =
+                        folder.fold_field(field, &path.child(PathSegment::Field(name.clone())));
+                    (name, field)
+                })
+                .collect(),
+            open,
+        },
+        Type::Union(types) => Type::Union(
+            types
+                .into_iter()
+                .enumerate()
+                .map(|(index, ty)| {
+                    folder.fold_type(ty, &path.child(PathSegment::Alternative(index)))
+                })
+                .collect(),
+        ),
+        Type::TaggedUnion {
+            tag_field,
+            variants,
+        } => Type::TaggedUnion {
+            tag_field,
+            variants: variants
+                .into_iter()
+                .map(|(tag, ty)| {
+                    let ty = folder.fold_type(ty, &path.child(PathSegment::Variant(tag.clone())));
+                    (tag, ty)
+                })
+                .collect(),
+        },
+        ty @ (Type::String
+        | Type::Number
+        | Type::Integer
+        | Type::Bool
+        | Type::Null
+        | Type::Any
+        | Type::Enum(_)) => ty,
+    }
+}
+
+/// Fold `ty` in place
+pub fn fold_in_place<F: Fold + ?Sized>(folder: &mut F, ty: &mut Type) {
+    let taken = std::mem::replace(ty, Type::Any);
+    *ty = folder.fold_type(taken, &TypePath::default());
+}
+
+/// The names of every type referenced within `ty`
+pub fn references(ty: &Type) -> BTreeSet<String> {
+    struct References(BTreeSet<String>);
+
+    impl Visitor for References {
+        fn visit_reference(&mut self, name: &str, _path: &TypePath) {
+            self.0.insert(name.to_string());
+        }
+    }
+
+    let mut collector = References(BTreeSet::new());
+    collector.visit_type(ty, &TypePath::default());
+    collector.0
+}
+
+/// Rename the references within `ty` for which `rename` returns a new name
+pub fn rename_references(ty: &mut Type, rename: impl FnMut(&str) -> Option<String>) {
+    struct Rename<R>(R);
+
+    impl<R: FnMut(&str) -> Option<String>> Fold for Rename<R> {
+        fn fold_reference(&mut self, name: String, _path: &TypePath) -> Type {
+            Type::Reference((self.0)(&name).unwrap_or(name))
+        }
+    }
+
+    fold_in_place(&mut Rename(rename), ty);
+}
+
+/// A named transform of a whole IR, run as part of a [`Pipeline`]
+pub trait Pass {
+    fn name(&self) -> &str;
+
+    fn run(&mut self, ir: IR) -> Result<IR, CoreError>;
+}
+
+/// A [`Pass`] that applies a [`Fold`]
+pub struct FoldPass<F> {
+    name: String,
+    fold: F,
+}
+
+impl<F: Fold> FoldPass<F> {
+    pub fn new(name: impl Into<String>, fold: F) -> Self {
+        Self {
+            name: name.into(),
+            fold,
+        }
+    }
+}
+
+impl<F: Fold> Pass for FoldPass<F> {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn run(&mut self, ir: IR) -> Result<IR, CoreError> {
+        Ok(self.fold.fold_ir(ir))
+    }
+}
+
+/// An ordered sequence of passes over an IR
+#[derive(Default)]
+pub struct Pipeline {
+    passes: Vec<Box<dyn Pass>>,
+}
+
+impl Pipeline {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_pass(mut self, pass: impl Pass + 'static) -> Self {
+        self.add_pass(Box::new(pass));
+        self
+    }
+
+    pub fn with_fold(self, name: impl Into<String>, fold: impl Fold + 'static) -> Self {
+        self.with_pass(FoldPass::new(name, fold))
+    }
+
+    /// Register a pass to run after those already registered
+    pub fn add_pass(&mut self, pass: Box<dyn Pass>) {
+        self.passes.push(pass);
+    }
+
+    /// Names of the registered passes, in the order they run
+    pub fn pass_names(&self) -> Vec<&str> {
+        self.passes.iter().map(|pass| pass.name()).collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.passes.is_empty()
+    }
+
+    /// Run every pass in order, stopping at the first that fails
+    pub fn run(&mut self, mut ir: IR) -> Result<IR, CoreError> {
+        for pass in &mut self.passes {
+            tracing::debug!("Running IR pass {}", pass.name());
+            ir = pass.run(ir).map_err(|e| CoreError::Pass {
+                pass: pass.name().to_string(),
+                message: e.to_string(),
+            })?;
+        }
+        Ok(ir)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ir::IRBuilder;
+    use std::collections::BTreeMap;
+
+    fn field(ty: Type) -> Field {
+        Field {
+            ty,
+            required: false,
+            description: None,
+            default: None,
+            read_only: false,
+            provenance: None,
+        }
+    }
+
+    fn deployment() -> Type {
+        let container = Type::Record {
+            fields: BTreeMap::from([
+                ("image".to_string(), field(Type::String)),
+                (
+                    "resources".to_string(),
+                    field(Type::Optional(Box::new(Type::Reference(
+                        "ResourceRequirements".to_string(),
+                    )))),
+                ),
+            ]),
+            open: false,
+        };
+        Type::Record {
+            fields: BTreeMap::from([
+                (
+                    "metadata".to_string(),
+                    field(Type::Reference("ObjectMeta".to_string())),
+                ),
+                (
+                    "containers".to_string(),
+                    field(Type::Array(Box::new(container))),
+                ),
+                (
+                    "source".to_string(),
+                    field(Type::TaggedUnion {
+                        tag_field: "kind".to_string(),
+                        variants: BTreeMap::from([(
+                            "git".to_string(),
+                            Type::Contract {
+                                base: Box::new(Type::Reference("GitSource".to_string())),
+                                predicate: "nonEmpty".to_string(),
+                            },
+                        )]),
+                    }),
+                ),
+            ]),
+            open: false,
+        }
+    }
+
+    #[test]
+    fn test_visitor_tracks_paths() {
+        struct Paths(Vec<String>);
+
+        impl Visitor for Paths {
+            fn visit_reference(&mut self, name: &str, path: &TypePath) {
+                self.0.push(format!("{} -> {}", path, name));
+            }
+        }
+
+        let ir = IRBuilder::new()
+            .module("apps")
+            .add_type("Deployment", deployment())
+            .build();
+        let mut paths = Paths(Vec::new());
+        paths.visit_ir(&ir);
+        assert_eq!(
+            paths.0,
+            vec![
+                "Deployment.containers[].resources -> ResourceRequirements",
+                "Deployment.metadata -> ObjectMeta",
+                "Deployment.source[git] -> GitSource",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_reference_helpers_reach_every_node() {
+        let mut ty = deployment();
+        assert_eq!(
+            references(&ty).into_iter().collect::<Vec<_>>(),
+            vec!["GitSource", "ObjectMeta", "ResourceRequirements"]
+        );
+
+        rename_references(&mut ty, |name| Some(format!("k8s.{}", name)));
+        assert_eq!(
+            references(&ty).into_iter().collect::<Vec<_>>(),
+            vec![
+                "k8s.GitSource",
+                "k8s.ObjectMeta",
+                "k8s.ResourceRequirements"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_pipeline_runs_passes_in_order() {
+        struct Prefix(&'static str);
+
+        impl Fold for Prefix {
+            fn fold_type_definition(&mut self, mut definition: TypeDefinition) -> TypeDefinition {
+                definition.name = format!("{}{}", self.0, definition.name);
+                definition
+            }
+        }
+
+        struct Reject;
+
+        impl Pass for Reject {
+            fn name(&self) -> &str {
+                "reject"
+            }
+
+            fn run(&mut self, _ir: IR) -> Result<IR, CoreError> {
+                Err(CoreError::InvalidType("no".to_string()))
+            }
+        }
+
+        let ir = IRBuilder::new()
+            .module("apps")
+            .add_type("Deployment", Type::String)
+            .build();
+
+        let mut pipeline = Pipeline::new()
+            .with_fold("inner", Prefix("Inner"))
+            .with_fold("outer", Prefix("Outer"));
+        assert_eq!(pipeline.pass_names(), vec!["inner", "outer"]);
+        let ir = pipeline.run(ir).unwrap();
+        assert_eq!(ir.modules[0].types[0].name, "OuterInnerDeployment");
+
+        let error = Pipeline::new().with_pass(Reject).run(ir).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "IR pass reject failed: Invalid type definition: no"
+        );
+    }
+}
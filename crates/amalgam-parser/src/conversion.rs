@@ -0,0 +1,461 @@
+//! Field mappings and conversion functions between versions of a CRD kind
+//!
+//! When an operator graduates an API (`v1alpha1` -> `v1`), fields are often
+//! renamed, moved under a different parent, or dropped. This module compares
+//! the schemas of two versions of the same kind, works out where each field
+//! of the old version ended up, and renders a Nickel function that rewrites
+//! a configuration from one version to the other.
+
+use amalgam_core::types::{Type, TypeSystem};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Top-level fields every resource carries that are rebuilt rather than mapped
+const ENVELOPE_FIELDS: &[&str] = &["apiVersion", "kind", "metadata"];
+
+/// Where a field of the source version ends up in the target version
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FieldMapping {
+    /// The field exists at the same path in both versions
+    Copied { path: Vec<String> },
+    /// The field kept its parent and type but changed name
+    Renamed { from: Vec<String>, to: Vec<String> },
+    /// The field kept its name and type but moved to a different parent
+    Moved { from: Vec<String>, to: Vec<String> },
+    /// The field has no counterpart in the target version
+    Dropped { path: Vec<String> },
+    /// The field only exists in the target version
+    Added { path: Vec<String> },
+}
+
+impl FieldMapping {
+    /// Source and target paths for mappings that carry a value across
+    pub fn source_and_target(&self) -> Option<(&[String], &[String])> {
+        match self {
+            FieldMapping::Copied { path } => Some((path, path)),
+            FieldMapping::Renamed { from, to } | FieldMapping::Moved { from, to } => {
+                Some((from, to))
+            }
+            FieldMapping::Dropped { .. } | FieldMapping::Added { .. } => None,
+        }
+    }
+}
+
+/// Compute how the fields of `from` map onto the fields of `to`
+///
+/// Both types are flattened into leaf paths. Paths present in both versions
+/// with related types are copied. Remaining source fields are matched to a
+/// target field with the same name and a related type under a different
+/// parent (a move), then to the only remaining target field of a related
+/// type under the same parent (a rename). Anything left over is dropped or
+/// added.
+pub fn compute_field_mappings(from: &Type, to: &Type) -> Vec<FieldMapping> {
+    let mut old_leaves = BTreeMap::new();
+    collect_leaves(from, &mut Vec::new(), &mut old_leaves);
+    let mut new_leaves = BTreeMap::new();
+    collect_leaves(to, &mut Vec::new(), &mut new_leaves);
+
+    let mut mappings = Vec::new();
+    let mut unmatched_old = BTreeSet::new();
+    for (path, old_ty) in &old_leaves {
+        match new_leaves.get(path) {
+            Some(new_ty) if related(old_ty, new_ty) => {
+                mappings.push(FieldMapping::Copied { path: path.clone() });
+            }
+            _ => {
+                unmatched_old.insert(path.clone());
+            }
+        }
+    }
+    let mut unmatched_new: BTreeSet<Vec<String>> = new_leaves
+        .iter()
+        .filter(|(path, new_ty)| {
+            !old_leaves
+                .get(*path)
+                .is_some_and(|old_ty| related(old_ty, new_ty))
+        })
+        .map(|(path, _)| path.clone())
+        .collect();
+
+    // Moves: same field name and type under a different parent
+    for old_path in unmatched_old.clone() {
+        let candidates: Vec<_> = unmatched_new
+            .iter()
+            .filter(|new_path| {
+                new_path.last() == old_path.last()
+                    && related(&old_leaves[&old_path], &new_leaves[*new_path])
+            })
+            .cloned()
+            .collect();
+        if let [new_path] = candidates.as_slice() {
+            unmatched_old.remove(&old_path);
+            unmatched_new.remove(new_path);
+            mappings.push(FieldMapping::Moved {
+                from: old_path,
+                to: new_path.clone(),
+            });
+        }
+    }
+
+    // Renames: the only unmatched fields of a related type under the same parent
+    for old_path in unmatched_old.clone() {
+        let parent = &old_path[..old_path.len() - 1];
+        let old_ty = &old_leaves[&old_path];
+        let same_parent_type = |path: &&Vec<String>, ty: &Type| {
+            &path[..path.len() - 1] == parent && related(old_ty, ty)
+        };
+        let candidates: Vec<_> = unmatched_new
+            .iter()
+            .filter(|p| same_parent_type(p, &new_leaves[*p]))
+            .cloned()
+            .collect();
+        let rivals = unmatched_old
+            .iter()
+            .filter(|p| same_parent_type(p, &old_leaves[*p]))
+            .count();
+        if let ([new_path], 1) = (candidates.as_slice(), rivals) {
+            unmatched_old.remove(&old_path);
+            unmatched_new.remove(new_path);
+            mappings.push(FieldMapping::Renamed {
+                from: old_path,
+                to: new_path.clone(),
+            });
+        }
+    }
+
+    mappings.extend(
+        unmatched_old
+            .into_iter()
+            .map(|path| FieldMapping::Dropped { path }),
+    );
+    mappings.extend(
+        unmatched_new
+            .into_iter()
+            .map(|path| FieldMapping::Added { path }),
+    );
+    mappings
+}
+
+/// Flatten a record type into its leaf field paths
+///
+/// Open or empty records are kept as leaves so unknown keys are copied whole.
+fn collect_leaves(ty: &Type, prefix: &mut Vec<String>, out: &mut BTreeMap<Vec<String>, Type>) {
+    let ty = match ty {
+        Type::Optional(inner) => inner.as_ref(),
+        other => other,
+    };
+    match ty {
+        Type::Record { fields, open } if !*open && !fields.is_empty() => {
+            for (name, field) in fields {
+                prefix.push(name.clone());
+                collect_leaves(&field.ty, prefix, out);
+                prefix.pop();
+            }
+        }
+        _ if !prefix.is_empty() => {
+            out.insert(prefix.clone(), ty.clone());
+        }
+        _ => {}
+    }
+}
+
+/// Whether a value of one type could plausibly be carried into the other
+///
+/// Narrowing counts as related: the target contract still validates the value.
+fn related(old: &Type, new: &Type) -> bool {
+    let type_system = TypeSystem::new();
+    old == new || type_system.is_compatible(old, new) || type_system.is_compatible(new, old)
+}
+
+/// A conversion from one version of a kind to another
+#[derive(Debug, Clone)]
+pub struct VersionConversion {
+    pub group: String,
+    pub kind: String,
+    pub from_version: String,
+    pub to_version: String,
+    pub mappings: Vec<FieldMapping>,
+}
+
+impl VersionConversion {
+    /// Build a conversion between two versions of the same kind
+    ///
+    /// `apiVersion`, `kind` and `metadata` are not mapped; the generated
+    /// function sets the first two for the target version and keeps `metadata`.
+    pub fn new(
+        group: impl Into<String>,
+        kind: impl Into<String>,
+        from_version: impl Into<String>,
+        from: &Type,
+        to_version: impl Into<String>,
+        to: &Type,
+    ) -> Self {
+        Self {
+            group: group.into(),
+            kind: kind.into(),
+            from_version: from_version.into(),
+            to_version: to_version.into(),
+            mappings: compute_field_mappings(&strip_envelope(from), &strip_envelope(to)),
+        }
+    }
+
+    /// Name of the generated Nickel function, e.g. `v1alpha1_to_v1`
+    pub fn function_name(&self) -> String {
+        format!("{}_to_{}", self.from_version, self.to_version)
+    }
+
+    /// Render the conversion as a Nickel record field
+    ///
+    /// The function relies on the `convert` helper emitted by
+    /// [`conversions_module`].
+    pub fn to_nickel(&self, indent: usize) -> String {
+        let pad = "  ".repeat(indent);
+        let mut out = format!(
+            "{}# {} {} -> {}\n",
+            pad, self.kind, self.from_version, self.to_version
+        );
+        for mapping in &self.mappings {
+            let note = match mapping {
+                FieldMapping::Copied { .. } => continue,
+                FieldMapping::Renamed { from, to } => {
+                    format!("renamed: {} -> {}", from.join("."), to.join("."))
+                }
+                FieldMapping::Moved { from, to } => {
+                    format!("moved: {} -> {}", from.join("."), to.join("."))
+                }
+                FieldMapping::Dropped { path } => format!("dropped: {}", path.join(".")),
+                FieldMapping::Added { path } => {
+                    format!("added: {} (no source field)", path.join("."))
+                }
+            };
+            out.push_str(&format!("{}# {}\n", pad, note));
+        }
+
+        out.push_str(&format!(
+            "{}{} = fun source =>\n{}  convert\n{}    [\n",
+            pad,
+            self.function_name(),
+            pad,
+            pad
+        ));
+        for (from, to) in self.mappings.iter().filter_map(|m| m.source_and_target()) {
+            out.push_str(&format!(
+                "{}      {{ from = {}, to = {} }},\n",
+                pad,
+                nickel_path(from),
+                nickel_path(to)
+            ));
+        }
+        out.push_str(&format!(
+            "{}    ]\n{}    {{ apiVersion = {}, kind = {} }}\n{}    source,\n",
+            pad,
+            pad,
+            nickel_string(&format!("{}/{}", self.group, self.to_version)),
+            nickel_string(&self.kind),
+            pad
+        ));
+        out
+    }
+}
+
+/// Render the conversions module for a group
+///
+/// Conversions are grouped by kind: `Kind.v1alpha1_to_v1 source`.
+pub fn conversions_module(group: &str, conversions: &[VersionConversion]) -> String {
+    let mut content = String::new();
+    content.push_str(&format!("# Version conversions for {} kinds\n", group));
+    content.push_str("# Auto-generated by amalgam\n\n");
+    content.push_str(CONVERSION_HELPERS);
+    content.push_str("{\n");
+
+    let mut by_kind: BTreeMap<&str, Vec<&VersionConversion>> = BTreeMap::new();
+    for conversion in conversions {
+        by_kind
+            .entry(&conversion.kind)
+            .or_default()
+            .push(conversion);
+    }
+    for (kind, kind_conversions) in by_kind {
+        content.push_str(&format!("  {} = {{\n", kind));
+        for conversion in kind_conversions {
+            content.push_str(&conversion.to_nickel(2));
+        }
+        content.push_str("  },\n");
+    }
+
+    content.push_str("}\n");
+    content
+}
+
+/// Nickel helpers shared by every generated conversion function
+const CONVERSION_HELPERS: &str = r#"let rec has_path = fun path value =>
+  std.array.length path == 0
+  || (
+    let key = std.array.first path in
+    std.is_record value
+    && std.record.has_field key value
+    && has_path (std.array.drop_first path) value."%{key}"
+  )
+in
+let get_path = fun path value =>
+  std.array.fold_left (fun acc key => acc."%{key}") value path
+in
+let nest = fun path value =>
+  std.array.fold_right (fun key acc => { "%{key}" = acc }) value path
+in
+let convert = fun mappings base source =>
+  let base =
+    if std.record.has_field "metadata" source then
+      base & { metadata = source.metadata }
+    else
+      base
+  in
+  std.array.fold_left
+    (fun acc mapping =>
+      if has_path mapping.from source then
+        acc & nest mapping.to (get_path mapping.from source)
+      else
+        acc
+    )
+    base
+    mappings
+in
+"#;
+
+fn strip_envelope(ty: &Type) -> Type {
+    match ty {
+        Type::Record { fields, open } => Type::Record {
+            fields: fields
+                .iter()
+                .filter(|(name, _)| !ENVELOPE_FIELDS.contains(&name.as_str()))
+                .map(|(name, field)| (name.clone(), field.clone()))
+                .collect(),
+            open: *open,
+        },
+        other => other.clone(),
+    }
+}
+
+fn nickel_path(path: &[String]) -> String {
+    let segments: Vec<_> = path.iter().map(|s| nickel_string(s)).collect();
+    format!("[{}]", segments.join(", "))
+}
+
+fn nickel_string(s: &str) -> String {
+    format!(
+        "\"{}\"",
+        s.replace('\\', "\\\\")
+            .replace('"', "\\\"")
+            .replace("%{", "\\%{")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use amalgam_core::types::Field;
+
+    fn record(fields: Vec<(&str, Type)>) -> Type {
+        Type::Record {
+            fields: fields
+                .into_iter()
+                .map(|(name, ty)| {
+                    (
+                        name.to_string(),
+                        Field {
+                            ty,
+                            required: false,
+                            description: None,
+                            default: None,
+                            read_only: false,
+                            provenance: None,
+                        },
+                    )
+                })
+                .collect(),
+            open: false,
+        }
+    }
+
+    fn path(p: &str) -> Vec<String> {
+        p.split('.').map(String::from).collect()
+    }
+
+    #[test]
+    fn test_copy_rename_move_and_drop() {
+        let old = record(vec![(
+            "spec",
+            record(vec![
+                ("replicas", Type::Integer),
+                ("image", Type::String),
+                ("legacy", Type::Bool),
+                ("port", Type::Integer),
+                (
+                    "size",
+                    Type::Map {
+                        key: Box::new(Type::String),
+                        value: Box::new(Type::String),
+                    },
+                ),
+            ]),
+        )]);
+        let new = record(vec![(
+            "spec",
+            record(vec![
+                ("replicas", Type::Integer),
+                ("imageRef", Type::String),
+                ("network", record(vec![("port", Type::Integer)])),
+                ("size", Type::Integer),
+                ("paused", Type::Bool),
+                ("zone", Type::Integer),
+            ]),
+        )]);
+
+        let mappings = compute_field_mappings(&old, &new);
+
+        assert!(mappings.contains(&FieldMapping::Copied {
+            path: path("spec.replicas")
+        }));
+        assert!(mappings.contains(&FieldMapping::Moved {
+            from: path("spec.port"),
+            to: path("spec.network.port"),
+        }));
+        assert!(mappings.contains(&FieldMapping::Renamed {
+            from: path("spec.image"),
+            to: path("spec.imageRef"),
+        }));
+        assert!(mappings.contains(&FieldMapping::Renamed {
+            from: path("spec.legacy"),
+            to: path("spec.paused"),
+        }));
+        // Same path but unrelated type and no unique rename target
+        assert!(mappings.contains(&FieldMapping::Dropped {
+            path: path("spec.size")
+        }));
+        assert!(mappings.contains(&FieldMapping::Added {
+            path: path("spec.zone")
+        }));
+    }
+
+    #[test]
+    fn test_conversion_nickel_output() {
+        let old = record(vec![
+            ("apiVersion", Type::String),
+            ("spec", record(vec![("alphaField", Type::String)])),
+        ]);
+        let new = record(vec![
+            ("apiVersion", Type::String),
+            ("spec", record(vec![("stableField", Type::String)])),
+        ]);
+
+        let conversion = VersionConversion::new("test.io", "Widget", "v1alpha1", &old, "v1", &new);
+        assert_eq!(conversion.function_name(), "v1alpha1_to_v1");
+        assert_eq!(conversion.mappings.len(), 1);
+
+        let module = conversions_module("test.io", &[conversion]);
+        assert!(module.contains("Widget = {"));
+        assert!(module.contains("# renamed: spec.alphaField -> spec.stableField"));
+        assert!(module
+            .contains("{ from = [\"spec\", \"alphaField\"], to = [\"spec\", \"stableField\"] }"));
+        assert!(module.contains("{ apiVersion = \"test.io/v1\", kind = \"Widget\" }"));
+    }
+}
@@ -2,8 +2,12 @@
 
 use crate::{imports::ImportResolver, k8s_authoritative::K8sTypePatterns, Parser, ParserError};
 use amalgam_core::{
-    ir::{IRBuilder, IR},
+    ir::{
+        IRBuilder, API_VERSION_ANNOTATION, DEPRECATED_ANNOTATION, DEPRECATION_WARNING_ANNOTATION,
+        IR,
+    },
     types::Type,
+    Provenance,
 };
 use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
@@ -16,6 +20,26 @@ pub struct CRD {
     pub kind: String,
     pub metadata: CRDMetadata,
     pub spec: CRDSpec,
+    /// File path or URL the CRD was read from, recorded as the provenance
+    /// of the types parsed from it
+    #[serde(skip)]
+    pub source: Option<String>,
+}
+
+impl CRD {
+    /// Record the file path or URL the CRD was read from
+    pub fn with_source(mut self, source: impl Into<String>) -> Self {
+        self.source = Some(source.into());
+        self
+    }
+
+    /// Location of a version's schema, in the source file if known and
+    /// otherwise named after the CRD
+    fn schema_provenance(&self, version_index: usize) -> Provenance {
+        Provenance::new(self.source.as_deref().unwrap_or(&self.metadata.name)).with_pointer(
+            format!("/spec/versions/{}/schema/openAPIV3Schema", version_index),
+        )
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -35,6 +59,14 @@ pub struct CRDVersion {
     pub name: String,
     pub served: bool,
     pub storage: bool,
+    #[serde(default)]
+    pub deprecated: bool,
+    #[serde(
+        default,
+        rename = "deprecationWarning",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub deprecation_warning: Option<String>,
     pub schema: Option<CRDSchema>,
 }
 
@@ -54,6 +86,7 @@ pub struct CRDNames {
 pub struct CRDParser {
     _import_resolver: ImportResolver,
     k8s_patterns: K8sTypePatterns,
+    skip_deprecated: bool,
 }
 
 impl Parser for CRDParser {
@@ -62,28 +95,13 @@ impl Parser for CRDParser {
     fn parse(&self, input: Self::Input) -> Result<IR, ParserError> {
         let mut ir = IR::new();
 
-        // Create a separate module for each version
-        for version in input.spec.versions {
-            if let Some(schema) = version.schema {
-                let module_name = format!(
-                    "{}.{}.{}",
-                    input.spec.names.kind, version.name, input.spec.group
-                );
-                let mut builder = IRBuilder::new().module(module_name);
-
-                let type_name = input.spec.names.kind.clone();
-                let ty = self.json_schema_to_type(&schema.openapi_v3_schema)?;
-
-                // Enhance the type with proper k8s fields
-                let enhanced_ty = self.enhance_kubernetes_type(ty)?;
-
-                builder = builder.add_type(type_name, enhanced_ty);
-
-                // Add this version's module to the IR
-                let version_ir = builder.build();
-                for module in version_ir.modules {
-                    ir.add_module(module);
-                }
+        // Create a separate module for each served version
+        for version in &input.spec.versions {
+            if !version.served || (self.skip_deprecated && version.deprecated) {
+                continue;
+            }
+            if version.schema.is_some() {
+                ir = ir.merge(self.parse_version(&input, &version.name)?);
             }
         }
 
@@ -103,20 +121,33 @@ impl CRDParser {
         Self {
             _import_resolver: ImportResolver::new(),
             k8s_patterns: K8sTypePatterns::new(),
+            skip_deprecated: false,
         }
     }
 
+    /// Leave versions marked `deprecated` out of [`Parser::parse`]
+    pub fn with_skip_deprecated(mut self, skip: bool) -> Self {
+        self.skip_deprecated = skip;
+        self
+    }
+
     /// Parse a specific version of a CRD
     pub fn parse_version(&self, crd: &CRD, version_name: &str) -> Result<IR, ParserError> {
         // Find the specific version
-        let version = crd
+        let (version_index, version) = crd
             .spec
             .versions
             .iter()
-            .find(|v| v.name == version_name)
+            .enumerate()
+            .find(|(_, v)| v.name == version_name)
             .ok_or_else(|| {
-                ParserError::Parse(format!("Version {} not found in CRD", version_name))
+                ParserError::Parse(format!(
+                    "Version {} not found in CRD {}",
+                    version_name,
+                    crd.source.as_deref().unwrap_or(&crd.metadata.name)
+                ))
             })?;
+        let provenance = crd.schema_provenance(version_index);
 
         if let Some(schema) = &version.schema {
             let module_name = format!(
@@ -126,17 +157,45 @@ impl CRDParser {
             let mut builder = IRBuilder::new().module(module_name);
 
             let type_name = crd.spec.names.kind.clone();
-            let ty = self.json_schema_to_type(&schema.openapi_v3_schema)?;
+            let ty = self.schema_to_type(&schema.openapi_v3_schema, Some(&provenance))?;
 
             // Enhance the type with proper k8s fields
             let enhanced_ty = self.enhance_kubernetes_type(ty)?;
 
             builder = builder.add_type(type_name, enhanced_ty);
-            Ok(builder.build())
+            let mut ir = builder.build();
+            for module in &mut ir.modules {
+                module.metadata.version = Some(version.name.clone());
+                let custom = &mut module.metadata.custom;
+                custom.insert("served".to_string(), version.served.into());
+                custom.insert("storage".to_string(), version.storage.into());
+                custom.insert("deprecated".to_string(), version.deprecated.into());
+
+                for type_def in &mut module.types {
+                    type_def.documentation = Some(version_status(crd, version));
+                    type_def.provenance = Some(provenance.clone());
+                    type_def.annotations.insert(
+                        API_VERSION_ANNOTATION.to_string(),
+                        format!("{}/{}", crd.spec.group, version.name).into(),
+                    );
+                    if version.deprecated {
+                        type_def
+                            .annotations
+                            .insert(DEPRECATED_ANNOTATION.to_string(), true.into());
+                    }
+                    if let Some(warning) = &version.deprecation_warning {
+                        type_def.annotations.insert(
+                            DEPRECATION_WARNING_ANNOTATION.to_string(),
+                            warning.clone().into(),
+                        );
+                    }
+                }
+            }
+            Ok(ir)
         } else {
             Err(ParserError::Parse(format!(
-                "Version {} has no schema",
-                version_name
+                "Version {} has no schema in {}",
+                version_name, provenance
             )))
         }
     }
@@ -286,9 +345,32 @@ impl CRDParser {
         }
     }
 
+    pub fn json_schema_to_type(&self, schema: &serde_json::Value) -> Result<Type, ParserError> {
+        self.schema_to_type(schema, None)
+    }
+
+    /// Convert a schema found at `at`, recording where each field came from
     #[allow(clippy::only_used_in_recursion)]
-    fn json_schema_to_type(&self, schema: &serde_json::Value) -> Result<Type, ParserError> {
+    fn schema_to_type(
+        &self,
+        schema: &serde_json::Value,
+        at: Option<&Provenance>,
+    ) -> Result<Type, ParserError> {
         use serde_json::Value;
+        let child = |segments: &[&str]| {
+            at.map(|at| {
+                segments
+                    .iter()
+                    .fold(at.clone(), |p, segment| p.child(segment))
+            })
+        };
+
+        // Enumerated values constrain the type regardless of its base type
+        if let Some(Value::Array(values)) = schema.get("enum") {
+            if !values.is_empty() {
+                return Ok(Type::Enum(values.clone()));
+            }
+        }
 
         let schema_type = schema.get("type").and_then(|v| v.as_str());
 
@@ -301,7 +383,7 @@ impl CRDParser {
             Some("array") => {
                 let items = schema
                     .get("items")
-                    .map(|i| self.json_schema_to_type(i))
+                    .map(|i| self.schema_to_type(i, child(&["items"]).as_ref()))
                     .transpose()?
                     .unwrap_or(Type::Any);
                 Ok(Type::Array(Box::new(items)))
@@ -321,7 +403,8 @@ impl CRDParser {
                         .unwrap_or_default();
 
                     for (name, prop_schema) in props {
-                        let ty = self.json_schema_to_type(prop_schema)?;
+                        let provenance = child(&["properties", name]);
+                        let ty = self.schema_to_type(prop_schema, provenance.as_ref())?;
                         fields.insert(
                             name.clone(),
                             amalgam_core::types::Field {
@@ -332,6 +415,11 @@ impl CRDParser {
                                     .and_then(|d| d.as_str())
                                     .map(String::from),
                                 default: prop_schema.get("default").cloned(),
+                                read_only: prop_schema
+                                    .get("readOnly")
+                                    .and_then(|v| v.as_bool())
+                                    .unwrap_or(false),
+                                provenance,
                             },
                         );
                     }
@@ -349,7 +437,10 @@ impl CRDParser {
                 if let Some(Value::Array(schemas)) = schema.get("oneOf") {
                     let types = schemas
                         .iter()
-                        .map(|s| self.json_schema_to_type(s))
+                        .enumerate()
+                        .map(|(i, s)| {
+                            self.schema_to_type(s, child(&["oneOf", &i.to_string()]).as_ref())
+                        })
                         .collect::<Result<Vec<_>, _>>()?;
                     return Ok(Type::Union(types));
                 }
@@ -357,7 +448,10 @@ impl CRDParser {
                 if let Some(Value::Array(schemas)) = schema.get("anyOf") {
                     let types = schemas
                         .iter()
-                        .map(|s| self.json_schema_to_type(s))
+                        .enumerate()
+                        .map(|(i, s)| {
+                            self.schema_to_type(s, child(&["anyOf", &i.to_string()]).as_ref())
+                        })
                         .collect::<Result<Vec<_>, _>>()?;
                     return Ok(Type::Union(types));
                 }
@@ -368,6 +462,38 @@ impl CRDParser {
     }
 }
 
+/// Describe a version's lifecycle: served, storage, deprecated
+///
+/// Rendered as the type's documentation so generated files show which API
+/// version is current and which ones configs should migrate away from.
+fn version_status(crd: &CRD, version: &CRDVersion) -> String {
+    let mut flags = vec![if version.served {
+        "served"
+    } else {
+        "not served"
+    }];
+    if version.storage {
+        flags.push("storage version");
+    }
+    if version.deprecated {
+        flags.push("deprecated");
+    }
+
+    let mut doc = format!(
+        "{} {}/{} ({})",
+        crd.spec.names.kind,
+        crd.spec.group,
+        version.name,
+        flags.join(", ")
+    );
+    if !version.storage {
+        if let Some(storage) = crd.spec.versions.iter().find(|v| v.storage) {
+            doc.push_str(&format!("\nStorage version: {}", storage.name));
+        }
+    }
+    doc
+}
+
 impl Default for CRDParser {
     fn default() -> Self {
         Self::new()
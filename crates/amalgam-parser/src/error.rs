@@ -20,6 +20,9 @@ pub enum ParserError {
     #[error("Network error: {0}")]
     Network(String),
 
+    #[error("Git error: {0}")]
+    Git(String),
+
     #[error("Unsupported feature: {0}")]
     UnsupportedFeature(String),
 }
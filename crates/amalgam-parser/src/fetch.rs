@@ -1,29 +1,51 @@
 //! Fetch CRDs from URLs, GitHub repos, etc.
 
 use crate::crd::CRD;
+use crate::http::HttpClient;
 use anyhow::Result;
+use chrono::{DateTime, Utc};
 use futures::StreamExt;
 use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use reqwest::header::{ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED};
+use reqwest::StatusCode;
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::sync::Mutex;
 
 pub struct CRDFetcher {
-    client: reqwest::Client,
+    client: HttpClient,
     multi_progress: Arc<MultiProgress>,
 }
 
 impl CRDFetcher {
     pub fn new() -> Result<Self> {
         Ok(Self {
-            client: reqwest::Client::builder()
-                .timeout(Duration::from_secs(30))
-                .user_agent("amalgam")
-                .build()?,
+            client: HttpClient::from_env()?,
             multi_progress: Arc::new(MultiProgress::new()),
         })
     }
 
+    /// Create a fetcher that reuses an HTTP client and progress display
+    ///
+    /// Cloned clients share one connection pool, so fetchers built from the
+    /// same client reuse connections to the same hosts.
+    pub fn with_client(client: HttpClient, multi_progress: Arc<MultiProgress>) -> Self {
+        Self {
+            client,
+            multi_progress,
+        }
+    }
+
+    /// HTTP client used for all requests of this fetcher
+    pub fn client(&self) -> &HttpClient {
+        &self.client
+    }
+
+    /// Progress display the fetcher's progress bars are added to
+    pub fn multi_progress(&self) -> Arc<MultiProgress> {
+        self.multi_progress.clone()
+    }
+
     /// Fetch CRDs from a URL
     /// Supports:
     /// - Direct YAML files
@@ -55,9 +77,9 @@ impl CRDFetcher {
             } else {
                 println!("Downloading YAML file from {}", url);
             }
-            let content = self.client.get(url).send().await?.text().await?;
+            let content = self.client.send(self.client.get(url)).await?.text().await?;
             let crd: CRD = serde_yaml::from_str(&content)?;
-            Ok(vec![crd])
+            Ok(vec![crd.with_source(url)])
         } else {
             // Try to fetch as directory listing
             self.fetch_directory(url).await
@@ -78,101 +100,50 @@ impl CRDFetcher {
 
     /// Fetch CRDs from a GitHub repository or directory
     async fn fetch_from_github(&self, url: &str, is_tty: bool) -> Result<Vec<CRD>> {
-        // Convert GitHub URL to raw content URL
-        let parts: Vec<&str> = url.split('/').collect();
-        if parts.len() < 5 {
-            return Err(anyhow::anyhow!("Invalid GitHub URL"));
-        }
-
-        let owner = parts[3];
-        let repo = parts[4];
-
-        // Find the path after tree/branch
-        let (path, branch) = if let Some(tree_idx) = parts.iter().position(|&p| p == "tree") {
-            if parts.len() > tree_idx + 2 {
-                let branch = parts[tree_idx + 1];
-                let path = parts[tree_idx + 2..].join("/");
-                (path, branch)
-            } else if parts.len() > tree_idx + 1 {
-                let branch = parts[tree_idx + 1];
-                (String::new(), branch)
-            } else {
-                (String::new(), "main")
-            }
-        } else if let Some(blob_idx) = parts.iter().position(|&p| p == "blob") {
-            // Single file
-            if parts.len() > blob_idx + 2 {
-                let branch = parts[blob_idx + 1];
-                let file_path = parts[blob_idx + 2..].join("/");
-                let raw_url = format!(
-                    "https://raw.githubusercontent.com/{}/{}/{}/{}",
-                    owner, repo, branch, file_path
-                );
-
+        let (api_url, location) = match parse_github_url(url)? {
+            GitHubTarget::File { raw_url, path } => {
                 let pb = if is_tty {
                     let pb = self.multi_progress.add(ProgressBar::new_spinner());
                     pb.set_style(
                         ProgressStyle::default_spinner().template("{spinner:.cyan} {msg}")?,
                     );
                     pb.enable_steady_tick(Duration::from_millis(100));
-                    pb.set_message(format!("Downloading {}", file_path));
+                    pb.set_message(format!("Downloading {}", path));
                     Some(pb)
                 } else {
-                    println!("Downloading {}", file_path);
+                    println!("Downloading {}", path);
                     None
                 };
 
-                let content = self.client.get(&raw_url).send().await?.text().await?;
+                let content = self
+                    .client
+                    .send(self.client.get(&raw_url))
+                    .await?
+                    .text()
+                    .await?;
                 let crd: CRD = serde_yaml::from_str(&content)?;
 
                 if let Some(pb) = pb {
-                    pb.finish_with_message(format!("✓ Downloaded {}", file_path));
+                    pb.finish_with_message(format!("✓ Downloaded {}", path));
                 }
 
-                return Ok(vec![crd]);
+                return Ok(vec![crd.with_source(url)]);
             }
-            (String::new(), "main")
-        } else {
-            (String::new(), "main")
+            GitHubTarget::Directory { api_url, location } => (api_url, location),
         };
 
-        // Use GitHub API to list directory contents
-        let api_url = format!(
-            "https://api.github.com/repos/{}/{}/contents/{}?ref={}",
-            owner, repo, path, branch
-        );
-
         let listing_pb = if is_tty {
             let pb = self.multi_progress.add(ProgressBar::new_spinner());
             pb.set_style(ProgressStyle::default_spinner().template("{spinner:.cyan} {msg}")?);
             pb.enable_steady_tick(Duration::from_millis(100));
-            pb.set_message(format!("Listing files from {}/{}/{}", owner, repo, path));
+            pb.set_message(format!("Listing files from {}", location));
             Some(pb)
         } else {
-            println!("Listing files from {}/{}/{}", owner, repo, path);
+            println!("Listing files from {}", location);
             None
         };
 
-        let response = self
-            .client
-            .get(&api_url)
-            .header("Accept", "application/vnd.github.v3+json")
-            .send()
-            .await?;
-
-        if !response.status().is_success() {
-            let status = response.status();
-            let text = response.text().await?;
-            return Err(anyhow::anyhow!("GitHub API error ({}): {}", status, text));
-        }
-
-        let files: Vec<GitHubContent> = response.json().await?;
-
-        // Filter for YAML files that look like CRDs
-        let yaml_files: Vec<_> = files
-            .iter()
-            .filter(|item| item.name.ends_with(".yaml") || item.name.ends_with(".yml"))
-            .collect();
+        let yaml_files = self.list_github_yaml_files(&api_url).await?;
 
         if let Some(pb) = listing_pb {
             pb.finish_with_message(format!("✓ Found {} YAML files", yaml_files.len()));
@@ -304,6 +275,105 @@ impl CRDFetcher {
         Ok(crds)
     }
 
+    /// List the YAML files in a GitHub directory via the contents API
+    async fn list_github_yaml_files(&self, api_url: &str) -> Result<Vec<GitHubContent>> {
+        let request = self
+            .client
+            .get(api_url)
+            .header("Accept", "application/vnd.github.v3+json");
+        let response = self.client.send(request).await?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let text = response.text().await?;
+            return Err(anyhow::anyhow!("GitHub API error ({}): {}", status, text));
+        }
+
+        let files: Vec<GitHubContent> = response.json().await?;
+
+        // Filter for YAML files that look like CRDs
+        Ok(files
+            .into_iter()
+            .filter(|item| item.name.ends_with(".yaml") || item.name.ends_with(".yml"))
+            .collect())
+    }
+
+    /// Resolve a URL accepted by [`Self::fetch_from_url`] into the URLs of
+    /// the individual documents it covers
+    pub async fn document_urls(&self, url: &str) -> Result<Vec<String>> {
+        if url.contains("github.com") {
+            match parse_github_url(url)? {
+                GitHubTarget::File { raw_url, .. } => Ok(vec![raw_url]),
+                GitHubTarget::Directory { api_url, .. } => Ok(self
+                    .list_github_yaml_files(&api_url)
+                    .await?
+                    .into_iter()
+                    .filter_map(|item| item.download_url)
+                    .collect()),
+            }
+        } else if url.ends_with(".yaml") || url.ends_with(".yml") {
+            Ok(vec![url.to_string()])
+        } else {
+            Err(anyhow::anyhow!(
+                "Directory listing not supported for non-GitHub URLs"
+            ))
+        }
+    }
+
+    /// Fetch a document unless it is unchanged since the given validators
+    ///
+    /// Sends `If-None-Match` / `If-Modified-Since` when validators are given
+    /// and reports [`ConditionalFetch::NotModified`] on `304 Not Modified`.
+    pub async fn fetch_if_modified(
+        &self,
+        url: &str,
+        etag: Option<&str>,
+        last_modified: Option<DateTime<Utc>>,
+    ) -> Result<ConditionalFetch> {
+        let mut request = self.client.get(url);
+        if let Some(etag) = etag {
+            request = request.header(IF_NONE_MATCH, etag);
+        }
+        if let Some(last_modified) = last_modified {
+            request = request.header(
+                IF_MODIFIED_SINCE,
+                last_modified
+                    .format("%a, %d %b %Y %H:%M:%S GMT")
+                    .to_string(),
+            );
+        }
+
+        let response = self.client.send(request).await?;
+        if response.status() == StatusCode::NOT_MODIFIED {
+            return Ok(ConditionalFetch::NotModified);
+        }
+        if !response.status().is_success() {
+            return Err(anyhow::anyhow!(
+                "Failed to fetch {}: {}",
+                url,
+                response.status()
+            ));
+        }
+
+        let headers = response.headers();
+        let etag = headers
+            .get(ETAG)
+            .and_then(|v| v.to_str().ok())
+            .map(String::from);
+        let last_modified = headers
+            .get(LAST_MODIFIED)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| DateTime::parse_from_rfc2822(v).ok())
+            .map(|dt| dt.with_timezone(&Utc));
+
+        Ok(ConditionalFetch::Modified(FetchedDocument {
+            url: url.to_string(),
+            content: response.text().await?,
+            etag,
+            last_modified,
+        }))
+    }
+
     async fn fetch_directory(&self, _url: &str) -> Result<Vec<CRD>> {
         // For now, just try to list files
         // In a real implementation, would need directory listing support
@@ -318,12 +388,12 @@ impl CRDFetcher {
     }
 }
 
-async fn fetch_single_crd(client: &reqwest::Client, url: &str) -> Result<CRD> {
-    let content = client.get(url).send().await?.text().await?;
+async fn fetch_single_crd(client: &HttpClient, url: &str) -> Result<CRD> {
+    let content = client.send(client.get(url)).await?.text().await?;
 
     // Most CRDs are single YAML documents, try that first
     if let Ok(crd) = serde_yaml::from_str::<CRD>(&content) {
-        return Ok(crd);
+        return Ok(crd.with_source(url));
     }
 
     // If that fails, try parsing as a Value first to check kind
@@ -334,12 +404,83 @@ async fn fetch_single_crd(client: &reqwest::Client, url: &str) -> Result<CRD> {
         ))
     {
         let crd: CRD = serde_yaml::from_value(value)?;
-        return Ok(crd);
+        return Ok(crd.with_source(url));
     }
 
     Err(anyhow::anyhow!("Not a valid CRD"))
 }
 
+/// A downloaded document with the cache validators the server sent
+#[derive(Debug, Clone)]
+pub struct FetchedDocument {
+    pub url: String,
+    pub content: String,
+    pub etag: Option<String>,
+    pub last_modified: Option<DateTime<Utc>>,
+}
+
+/// Result of [`CRDFetcher::fetch_if_modified`]
+#[derive(Debug, Clone)]
+pub enum ConditionalFetch {
+    /// The server confirmed the document is unchanged
+    NotModified,
+    Modified(FetchedDocument),
+}
+
+/// Where a GitHub URL points
+enum GitHubTarget {
+    /// A single file (`/blob/...`)
+    File { raw_url: String, path: String },
+    /// A directory listed through the contents API
+    Directory { api_url: String, location: String },
+}
+
+fn parse_github_url(url: &str) -> Result<GitHubTarget> {
+    let parts: Vec<&str> = url.split('/').collect();
+    if parts.len() < 5 {
+        return Err(anyhow::anyhow!("Invalid GitHub URL"));
+    }
+
+    let owner = parts[3];
+    let repo = parts[4];
+
+    // Find the path after tree/branch
+    let (path, branch) = if let Some(tree_idx) = parts.iter().position(|&p| p == "tree") {
+        if parts.len() > tree_idx + 2 {
+            (parts[tree_idx + 2..].join("/"), parts[tree_idx + 1])
+        } else if parts.len() > tree_idx + 1 {
+            (String::new(), parts[tree_idx + 1])
+        } else {
+            (String::new(), "main")
+        }
+    } else if let Some(blob_idx) = parts.iter().position(|&p| p == "blob") {
+        // Single file
+        if parts.len() > blob_idx + 2 {
+            let branch = parts[blob_idx + 1];
+            let file_path = parts[blob_idx + 2..].join("/");
+            return Ok(GitHubTarget::File {
+                raw_url: format!(
+                    "https://raw.githubusercontent.com/{}/{}/{}/{}",
+                    owner, repo, branch, file_path
+                ),
+                path: file_path,
+            });
+        }
+        (String::new(), "main")
+    } else {
+        (String::new(), "main")
+    };
+
+    // Use GitHub API to list directory contents
+    Ok(GitHubTarget::Directory {
+        api_url: format!(
+            "https://api.github.com/repos/{}/{}/contents/{}?ref={}",
+            owner, repo, path, branch
+        ),
+        location: format!("{}/{}/{}", owner, repo, path),
+    })
+}
+
 #[derive(Debug, serde::Deserialize)]
 struct GitHubContent {
     name: String,
@@ -422,7 +563,7 @@ mod tests {
             .await;
 
         let fetcher = CRDFetcher::new().unwrap();
-        let url = format!("{}/test.yaml", &mock_server.uri());
+        let url = format!("{}/test.yaml", mock_server.uri());
         let crds = fetcher.fetch_from_url(&url).await.unwrap();
 
         assert_eq!(crds.len(), 1);
@@ -441,7 +582,7 @@ mod tests {
             .await;
 
         let fetcher = CRDFetcher::new().unwrap();
-        let url = format!("{}/missing.yaml", &mock_server.uri());
+        let url = format!("{}/missing.yaml", mock_server.uri());
         let result = fetcher.fetch_from_url(&url).await;
 
         assert!(result.is_err());
@@ -458,7 +599,7 @@ mod tests {
             .await;
 
         let fetcher = CRDFetcher::new().unwrap();
-        let url = format!("{}/invalid.yaml", &mock_server.uri());
+        let url = format!("{}/invalid.yaml", mock_server.uri());
         let result = fetcher.fetch_from_url(&url).await;
 
         assert!(result.is_err());
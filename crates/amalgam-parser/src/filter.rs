@@ -0,0 +1,301 @@
+//! Selecting which CRDs and versions of a package get generated
+
+use crate::crd::{CRDVersion, CRD};
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+
+/// Filter over the CRDs of a package
+///
+/// A version is generated when it is served, matches at least one `include`
+/// rule (or there are none), and matches no `exclude` rule. `latest_only`
+/// and `storage_only` then narrow each CRD down to a single version, chosen
+/// among the versions left after deprecated ones are skipped.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CrdFilter {
+    /// Rules selecting what to generate; empty selects everything
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub include: Vec<FilterRule>,
+    /// Rules selecting what to leave out
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exclude: Vec<FilterRule>,
+    /// Keep only the newest served version of each CRD
+    #[serde(default)]
+    pub latest_only: bool,
+    /// Keep only the storage version of each CRD
+    #[serde(default)]
+    pub storage_only: bool,
+}
+
+/// Glob patterns on a CRD's group, kind and version
+///
+/// Patterns support `*` (any run of characters) and `?` (one character).
+/// An omitted pattern matches anything.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FilterRule {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kind: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+}
+
+impl FilterRule {
+    /// Whether the rule matches a version of a CRD
+    pub fn matches(&self, group: &str, kind: &str, version: &str) -> bool {
+        let matches = |pattern: &Option<String>, value: &str| {
+            pattern.as_deref().is_none_or(|p| glob_match(p, value))
+        };
+        matches(&self.group, group) && matches(&self.kind, kind) && matches(&self.version, version)
+    }
+}
+
+impl CrdFilter {
+    /// Whether the filter selects everything
+    pub fn is_empty(&self) -> bool {
+        self == &Self::default()
+    }
+
+    /// Served versions of a CRD the filter selects, leaving out deprecated
+    /// versions when `skip_deprecated` is set
+    pub fn select_versions<'a>(&self, crd: &'a CRD, skip_deprecated: bool) -> Vec<&'a CRDVersion> {
+        let group = &crd.spec.group;
+        let kind = &crd.spec.names.kind;
+
+        let mut versions: Vec<&CRDVersion> = crd
+            .spec
+            .versions
+            .iter()
+            .filter(|v| v.served)
+            .filter(|v| {
+                self.include.is_empty()
+                    || self.include.iter().any(|r| r.matches(group, kind, &v.name))
+            })
+            .filter(|v| !self.exclude.iter().any(|r| r.matches(group, kind, &v.name)))
+            .filter(|v| {
+                if skip_deprecated && v.deprecated {
+                    tracing::info!(
+                        "Skipping deprecated version {}/{} of {}",
+                        group,
+                        v.name,
+                        kind
+                    );
+                    return false;
+                }
+                true
+            })
+            .collect();
+
+        if self.storage_only {
+            versions.retain(|v| v.storage);
+        }
+        if self.latest_only {
+            versions = versions
+                .into_iter()
+                .max_by(|a, b| compare_versions(&a.name, &b.name))
+                .into_iter()
+                .collect();
+        }
+        versions
+    }
+}
+
+/// Order Kubernetes API versions by priority
+///
+/// GA versions rank above beta, and beta above alpha; within a level the
+/// higher major, then minor, number wins: `v1 > v1beta2 > v1beta1 > v1alpha1`.
+/// Versions not following the convention rank lowest, alphabetically.
+pub fn compare_versions(a: &str, b: &str) -> Ordering {
+    match (parse_version(a), parse_version(b)) {
+        (Some(a), Some(b)) => a.cmp(&b),
+        (Some(_), None) => Ordering::Greater,
+        (None, Some(_)) => Ordering::Less,
+        (None, None) => b.cmp(a),
+    }
+}
+
+/// (stability, major, minor) where stability is 0 for alpha, 1 beta, 2 GA
+fn parse_version(version: &str) -> Option<(u8, u32, u32)> {
+    let rest = version.strip_prefix('v')?;
+    let digits = rest
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(rest.len());
+    let major = rest[..digits].parse().ok()?;
+    let qualifier = &rest[digits..];
+    if qualifier.is_empty() {
+        return Some((2, major, 0));
+    }
+    let (stability, minor) = match qualifier.strip_prefix("beta") {
+        Some(minor) => (1, minor),
+        None => (0, qualifier.strip_prefix("alpha")?),
+    };
+    Some((stability, major, minor.parse().ok()?))
+}
+
+/// Match text against a glob pattern with `*` and `?` wildcards
+pub(crate) fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    // Position of the last `*` and the text position it was tried at
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match backtrack {
+                Some((star, star_t)) => {
+                    p = star + 1;
+                    t = star_t + 1;
+                    backtrack = Some((star, star_t + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crd::{CRDMetadata, CRDNames, CRDSpec};
+
+    fn crd(versions: &[(&str, bool)]) -> CRD {
+        crd_with_deprecated(versions, &[])
+    }
+
+    fn crd_with_deprecated(versions: &[(&str, bool)], deprecated: &[&str]) -> CRD {
+        CRD {
+            api_version: "apiextensions.k8s.io/v1".to_string(),
+            kind: "CustomResourceDefinition".to_string(),
+            source: None,
+            metadata: CRDMetadata {
+                name: "buckets.s3.aws.upbound.io".to_string(),
+            },
+            spec: CRDSpec {
+                group: "s3.aws.upbound.io".to_string(),
+                names: CRDNames {
+                    kind: "Bucket".to_string(),
+                    plural: "buckets".to_string(),
+                    singular: "bucket".to_string(),
+                },
+                versions: versions
+                    .iter()
+                    .map(|(name, storage)| CRDVersion {
+                        name: name.to_string(),
+                        served: true,
+                        storage: *storage,
+                        deprecated: deprecated.contains(name),
+                        deprecation_warning: None,
+                        schema: None,
+                    })
+                    .collect(),
+            },
+        }
+    }
+
+    fn names(versions: Vec<&CRDVersion>) -> Vec<&str> {
+        versions.iter().map(|v| v.name.as_str()).collect()
+    }
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("*.aws.upbound.io", "s3.aws.upbound.io"));
+        assert!(glob_match("Bucket*", "BucketPolicy"));
+        assert!(glob_match("v1?eta*", "v1beta1"));
+        assert!(!glob_match("*.gcp.upbound.io", "s3.aws.upbound.io"));
+        assert!(!glob_match("Bucket", "BucketPolicy"));
+    }
+
+    #[test]
+    fn test_compare_versions() {
+        let mut versions = vec!["v1alpha1", "v2", "v1beta2", "v1", "v1beta1", "custom"];
+        versions.sort_by(|a, b| compare_versions(a, b));
+        assert_eq!(
+            versions,
+            vec!["custom", "v1alpha1", "v1beta1", "v1beta2", "v1", "v2"]
+        );
+    }
+
+    #[test]
+    fn test_select_versions() {
+        let crd = crd(&[("v1alpha1", false), ("v1beta1", true), ("v1beta2", false)]);
+
+        assert_eq!(
+            names(CrdFilter::default().select_versions(&crd, false)).len(),
+            3
+        );
+
+        let latest = CrdFilter {
+            latest_only: true,
+            ..Default::default()
+        };
+        assert_eq!(names(latest.select_versions(&crd, false)), vec!["v1beta2"]);
+
+        let storage = CrdFilter {
+            storage_only: true,
+            ..Default::default()
+        };
+        assert_eq!(names(storage.select_versions(&crd, false)), vec!["v1beta1"]);
+
+        let no_alpha = CrdFilter {
+            include: vec![FilterRule {
+                group: Some("*.aws.upbound.io".to_string()),
+                ..Default::default()
+            }],
+            exclude: vec![FilterRule {
+                version: Some("*alpha*".to_string()),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        assert_eq!(
+            names(no_alpha.select_versions(&crd, false)),
+            vec!["v1beta1", "v1beta2"]
+        );
+
+        let other_kind = CrdFilter {
+            include: vec![FilterRule {
+                kind: Some("Queue".to_string()),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        assert!(other_kind.select_versions(&crd, false).is_empty());
+    }
+
+    #[test]
+    fn test_select_versions_skips_deprecated_first() {
+        let crd = crd_with_deprecated(
+            &[("v1alpha1", false), ("v1beta1", true), ("v1beta2", false)],
+            &["v1beta1", "v1beta2"],
+        );
+
+        assert_eq!(
+            names(CrdFilter::default().select_versions(&crd, true)),
+            vec!["v1alpha1"]
+        );
+
+        // The newest version left, not the newest one then dropped
+        let latest = CrdFilter {
+            latest_only: true,
+            ..Default::default()
+        };
+        assert_eq!(names(latest.select_versions(&crd, false)), vec!["v1beta2"]);
+        assert_eq!(names(latest.select_versions(&crd, true)), vec!["v1alpha1"]);
+
+        let storage = CrdFilter {
+            storage_only: true,
+            ..Default::default()
+        };
+        assert!(storage.select_versions(&crd, true).is_empty());
+    }
+}
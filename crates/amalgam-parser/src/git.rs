@@ -0,0 +1,412 @@
+//! Reading CRDs from a local clone of a git repository
+//!
+//! Repositories are cloned once into a cache directory and fetched again on
+//! later runs, so any forge the `git` command can reach works, including
+//! private repositories and `file://` URLs. The requested ref is resolved to
+//! a commit and checked out before CRD files are read from the working tree.
+
+use crate::{
+    crd::CRD,
+    filter::glob_match,
+    helm::{parse_crd_document, qualify_sources, split_documents},
+    ParserError,
+};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use tracing::debug;
+
+/// A git repository to read CRDs from
+#[derive(Debug, Clone)]
+pub struct GitSource {
+    url: String,
+    git_ref: Option<String>,
+    paths: Vec<String>,
+    cache_dir: PathBuf,
+}
+
+/// Files read from a checked-out commit
+#[derive(Debug, Clone)]
+pub struct GitCheckout {
+    pub url: String,
+    /// Full hash of the checked-out commit
+    pub commit: String,
+    /// Working tree of the clone
+    pub workdir: PathBuf,
+    /// Matched YAML files relative to the repository root, with their content
+    pub files: Vec<(String, String)>,
+    /// Whether the files were chosen by path patterns rather than being
+    /// every YAML file of the repository
+    pub selected: bool,
+}
+
+impl GitSource {
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            git_ref: None,
+            paths: Vec::new(),
+            cache_dir: default_cache_dir(),
+        }
+    }
+
+    /// Branch, tag or commit to check out; the remote's HEAD when unset
+    pub fn with_ref(mut self, git_ref: Option<String>) -> Self {
+        self.git_ref = git_ref;
+        self
+    }
+
+    /// Glob patterns selecting CRD files, relative to the repository root
+    ///
+    /// `*` also matches across directories. Without patterns every YAML file
+    /// in the repository is read.
+    pub fn with_paths(mut self, paths: Vec<String>) -> Self {
+        self.paths = paths;
+        self
+    }
+
+    /// Directory holding the clones
+    pub fn with_cache_dir(mut self, cache_dir: PathBuf) -> Self {
+        self.cache_dir = cache_dir;
+        self
+    }
+
+    /// Clone or fetch the repository, check out the ref, and read the matched files
+    ///
+    /// Checkouts of one repository share its clone, so they wait for each
+    /// other, also across processes using the same cache directory.
+    /// Checkouts of different repositories run concurrently. This blocks on
+    /// `git`; async callers run it with `spawn_blocking`.
+    pub fn checkout(&self) -> Result<GitCheckout, ParserError> {
+        // Keep both from being read as git options
+        for value in std::iter::once(&self.url).chain(&self.git_ref) {
+            if value.starts_with('-') {
+                return Err(ParserError::Git(format!("invalid url or ref: {}", value)));
+            }
+        }
+
+        let repo = self.clone_dir();
+        let _lock = self.lock_clone(&repo)?;
+
+        if !repo.join(".git").exists() {
+            fs::create_dir_all(&repo)?;
+            git(&repo, &["init", "--quiet"])?;
+            git(&repo, &["remote", "add", "origin", &self.url])?;
+        }
+        git(
+            &repo,
+            &[
+                "fetch",
+                "--quiet",
+                "--force",
+                "--tags",
+                "origin",
+                "+HEAD:refs/remotes/origin/HEAD",
+                "+refs/heads/*:refs/remotes/origin/*",
+            ],
+        )?;
+
+        let commit = self.resolve_commit(&repo)?;
+        git(
+            &repo,
+            &["checkout", "--quiet", "--force", "--detach", &commit],
+        )?;
+
+        let mut files = Vec::new();
+        for path in git(&repo, &["ls-tree", "-r", "--name-only", &commit])?.lines() {
+            if self.selects(path) {
+                files.push((path.to_string(), fs::read_to_string(repo.join(path))?));
+            }
+        }
+
+        Ok(GitCheckout {
+            url: self.url.clone(),
+            commit,
+            workdir: repo,
+            files,
+            selected: !self.paths.is_empty(),
+        })
+    }
+
+    /// Commit the requested ref points to
+    fn resolve_commit(&self, repo: &Path) -> Result<String, ParserError> {
+        let candidates = match &self.git_ref {
+            None => vec!["refs/remotes/origin/HEAD".to_string()],
+            Some(git_ref) => vec![
+                format!("refs/remotes/origin/{}", git_ref),
+                format!("refs/tags/{}", git_ref),
+                git_ref.clone(),
+            ],
+        };
+        for candidate in &candidates {
+            let rev = format!("{}^{{commit}}", candidate);
+            if let Ok(commit) = git(repo, &["rev-parse", "--verify", "--quiet", &rev]) {
+                return Ok(commit);
+            }
+        }
+
+        // Refs the remote does not advertise, such as a commit outside any
+        // branch, have to be fetched by name
+        let git_ref = self.git_ref.as_deref().unwrap_or("HEAD");
+        git(repo, &["fetch", "--quiet", "origin", git_ref])
+            .map_err(|e| ParserError::Git(format!("cannot resolve ref {}: {}", git_ref, e)))?;
+        git(repo, &["rev-parse", "--verify", "FETCH_HEAD^{commit}"])
+    }
+
+    fn selects(&self, path: &str) -> bool {
+        (path.ends_with(".yaml") || path.ends_with(".yml"))
+            && (self.paths.is_empty() || self.paths.iter().any(|p| glob_match(p, path)))
+    }
+
+    /// Hold an exclusive lock on a clone until the returned file is dropped
+    fn lock_clone(&self, repo: &Path) -> Result<fs::File, ParserError> {
+        fs::create_dir_all(&self.cache_dir)?;
+        let mut lock_path = repo.as_os_str().to_owned();
+        lock_path.push(".lock");
+        let lock = fs::OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(PathBuf::from(lock_path))?;
+        lock.lock()?;
+        Ok(lock)
+    }
+
+    /// Clone directory, unique per repository URL
+    fn clone_dir(&self) -> PathBuf {
+        let hash = format!("{:x}", Sha256::digest(self.url.as_bytes()));
+        let name = self
+            .url
+            .trim_end_matches('/')
+            .trim_end_matches(".git")
+            .rsplit(['/', ':'])
+            .next()
+            .unwrap_or("repo");
+        self.cache_dir.join(format!("{}-{}", name, &hash[..12]))
+    }
+}
+
+impl GitCheckout {
+    /// CRDs in the matched files
+    ///
+    /// Without path patterns every YAML file is read, so files that are not
+    /// valid YAML, such as templates, are skipped rather than failing.
+    pub fn crds(&self) -> Result<Vec<CRD>, ParserError> {
+        let mut crds = Vec::new();
+        for (path, content) in &self.files {
+            for document in split_documents(content) {
+                match parse_crd_document(path, &document) {
+                    Ok(crd) => crds.extend(crd),
+                    Err(e) if !self.selected => debug!("Skipping {}", e),
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+        qualify_sources(&mut crds, &format!("{}@{}", self.url, self.commit));
+        Ok(crds)
+    }
+}
+
+/// Where clones are kept unless configured otherwise
+///
+/// `$AMALGAM_CACHE_DIR/git`, falling back to the user's cache directory.
+pub fn default_cache_dir() -> PathBuf {
+    let base = std::env::var_os("AMALGAM_CACHE_DIR")
+        .map(PathBuf::from)
+        .or_else(|| {
+            std::env::var_os("XDG_CACHE_HOME").map(|dir| PathBuf::from(dir).join("amalgam"))
+        })
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache/amalgam")))
+        .unwrap_or_else(|| std::env::temp_dir().join("amalgam"));
+    base.join("git")
+}
+
+/// Run git in `repo` and return its trimmed standard output
+fn git(repo: &Path, args: &[&str]) -> Result<String, ParserError> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(repo)
+        .args(args)
+        // Fail instead of waiting for credentials nobody can type
+        .env("GIT_TERMINAL_PROMPT", "0")
+        .output()
+        .map_err(|e| ParserError::Git(format!("failed to run git: {}", e)))?;
+    if !output.status.success() {
+        return Err(ParserError::Git(format!(
+            "git {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn crd_yaml(kind: &str) -> String {
+        format!(
+            r#"apiVersion: apiextensions.k8s.io/v1
+kind: CustomResourceDefinition
+metadata:
+  name: {plural}.example.com
+spec:
+  group: example.com
+  names:
+    kind: {kind}
+    plural: {plural}
+    singular: {singular}
+  versions:
+    - name: v1
+      served: true
+      storage: true
+"#,
+            kind = kind,
+            plural = format!("{}s", kind.to_lowercase()),
+            singular = kind.to_lowercase(),
+        )
+    }
+
+    fn commit(repo: &Path, message: &str) -> String {
+        git(repo, &["add", "-A"]).unwrap();
+        git(
+            repo,
+            &[
+                "-c",
+                "user.name=test",
+                "-c",
+                "user.email=test@example.com",
+                "commit",
+                "--quiet",
+                "-m",
+                message,
+            ],
+        )
+        .unwrap();
+        git(repo, &["rev-parse", "HEAD"]).unwrap()
+    }
+
+    /// An upstream repository with a tagged first commit and a newer second one
+    fn upstream() -> (TempDir, String, String) {
+        let dir = TempDir::new().unwrap();
+        let repo = dir.path();
+        git(repo, &["init", "--quiet"]).unwrap();
+
+        fs::create_dir_all(repo.join("config/crd")).unwrap();
+        fs::write(repo.join("config/crd/widgets.yaml"), crd_yaml("Widget")).unwrap();
+        fs::write(repo.join("values.yaml"), "replicas: 1\n").unwrap();
+        fs::write(
+            repo.join("template.yaml"),
+            "replicas: {{ .Values.replicas\n",
+        )
+        .unwrap();
+        let first = commit(repo, "widgets");
+        git(repo, &["tag", "v1.0.0"]).unwrap();
+
+        fs::write(repo.join("config/crd/gadgets.yaml"), crd_yaml("Gadget")).unwrap();
+        let second = commit(repo, "gadgets");
+        (dir, first, second)
+    }
+
+    fn kinds(checkout: &GitCheckout) -> Vec<String> {
+        checkout
+            .crds()
+            .unwrap()
+            .into_iter()
+            .map(|crd| crd.spec.names.kind)
+            .collect()
+    }
+
+    #[test]
+    fn test_checkout_refs_from_file_url() {
+        let (upstream, first, second) = upstream();
+        let cache = TempDir::new().unwrap();
+        let url = format!("file://{}", upstream.path().display());
+        let source = |git_ref: Option<&str>| {
+            GitSource::new(&url)
+                .with_ref(git_ref.map(String::from))
+                .with_paths(vec!["config/crd/*.yaml".to_string()])
+                .with_cache_dir(cache.path().to_path_buf())
+        };
+
+        let latest = source(None).checkout().unwrap();
+        assert_eq!(latest.commit, second);
+        assert_eq!(kinds(&latest), vec!["Gadget", "Widget"]);
+
+        let tagged = source(Some("v1.0.0")).checkout().unwrap();
+        assert_eq!(tagged.commit, first);
+        assert_eq!(kinds(&tagged), vec!["Widget"]);
+        assert!(!tagged.workdir.join("config/crd/gadgets.yaml").exists());
+
+        let by_commit = source(Some(&first[..10])).checkout().unwrap();
+        assert_eq!(by_commit.commit, first);
+
+        assert!(source(Some("no-such-ref")).checkout().is_err());
+    }
+
+    #[test]
+    fn test_paths_select_files() {
+        let (upstream, _, _) = upstream();
+        let cache = TempDir::new().unwrap();
+
+        let everything = GitSource::new(format!("file://{}", upstream.path().display()))
+            .with_cache_dir(cache.path().to_path_buf())
+            .checkout()
+            .unwrap();
+        let paths: Vec<&str> = everything.files.iter().map(|(p, _)| p.as_str()).collect();
+        assert_eq!(
+            paths,
+            vec![
+                "config/crd/gadgets.yaml",
+                "config/crd/widgets.yaml",
+                "template.yaml",
+                "values.yaml"
+            ]
+        );
+        // Files that are not CRDs, or not even YAML, are ignored
+        assert_eq!(kinds(&everything), vec!["Gadget", "Widget"]);
+
+        // unless they were asked for
+        let template = GitSource::new(format!("file://{}", upstream.path().display()))
+            .with_paths(vec!["*.yaml".to_string()])
+            .with_cache_dir(cache.path().to_path_buf())
+            .checkout()
+            .unwrap();
+        assert!(template.crds().is_err());
+    }
+
+    #[test]
+    fn test_checkouts_lock_only_their_repository() {
+        use std::sync::mpsc;
+        use std::time::Duration;
+
+        let (first, _, _) = upstream();
+        let (second, _, _) = upstream();
+        let cache = TempDir::new().unwrap();
+        let source = |upstream: &TempDir| {
+            GitSource::new(format!("file://{}", upstream.path().display()))
+                .with_cache_dir(cache.path().to_path_buf())
+        };
+        let checkout_in_background = |source: GitSource| {
+            let (sender, receiver) = mpsc::channel();
+            std::thread::spawn(move || sender.send(source.checkout().is_ok()).unwrap());
+            receiver
+        };
+
+        let held = source(&first);
+        let lock = held.lock_clone(&held.clone_dir()).unwrap();
+
+        // Other repositories do not wait for the held clone
+        let other = checkout_in_background(source(&second));
+        assert!(other.recv_timeout(Duration::from_secs(30)).unwrap());
+
+        // The same repository waits until it is released
+        let same = checkout_in_background(source(&first));
+        assert!(same.recv_timeout(Duration::from_millis(300)).is_err());
+        drop(lock);
+        assert!(same.recv_timeout(Duration::from_secs(30)).unwrap());
+    }
+}
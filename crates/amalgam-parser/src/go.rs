@@ -4,12 +4,15 @@ use crate::{Parser, ParserError};
 use amalgam_core::{
     ir::{IRBuilder, IR},
     types::{Field, Type},
+    Provenance,
 };
 use std::collections::BTreeMap;
 
 /// Simplified Go AST representation
 #[derive(Debug)]
 pub struct GoFile {
+    /// Path of the file, recorded as the provenance of its types
+    pub path: Option<String>,
     pub package: String,
     pub imports: Vec<String>,
     pub types: Vec<GoTypeDecl>,
@@ -19,6 +22,7 @@ pub struct GoFile {
 pub struct GoTypeDecl {
     pub name: String,
     pub ty: GoType,
+    pub line: Option<usize>,
 }
 
 #[derive(Debug)]
@@ -45,6 +49,7 @@ pub struct GoField {
     pub name: String,
     pub ty: GoType,
     pub tag: Option<String>,
+    pub line: Option<usize>,
 }
 
 #[derive(Debug)]
@@ -68,12 +73,21 @@ impl Parser for GoParser {
         }
 
         // Convert types
+        let mut provenances = Vec::new();
         for type_decl in input.types {
-            let ty = self.go_type_to_type(&type_decl.ty)?;
+            let at = locate(input.path.as_deref(), type_decl.line);
+            let ty = self.go_type_to_type(&type_decl.ty, input.path.as_deref())?;
             builder = builder.add_type(type_decl.name, ty);
+            provenances.push(at);
         }
 
-        Ok(builder.build())
+        let mut ir = builder.build();
+        if let Some(module) = ir.modules.last_mut() {
+            for (type_def, provenance) in module.types.iter_mut().zip(provenances) {
+                type_def.provenance = provenance;
+            }
+        }
+        Ok(ir)
     }
 }
 
@@ -82,7 +96,7 @@ impl GoParser {
         Self
     }
 
-    fn go_type_to_type(&self, go_type: &GoType) -> Result<Type, ParserError> {
+    fn go_type_to_type(&self, go_type: &GoType, path: Option<&str>) -> Result<Type, ParserError> {
         match go_type {
             GoType::Basic(name) => match name.as_str() {
                 "string" => Ok(Type::String),
@@ -98,7 +112,7 @@ impl GoParser {
             GoType::Struct { fields } => {
                 let mut record_fields = BTreeMap::new();
                 for field in fields {
-                    let field_type = self.go_type_to_type(&field.ty)?;
+                    let field_type = self.go_type_to_type(&field.ty, path)?;
                     let (name, required) = self.parse_field_tag(&field.name, &field.tag);
                     record_fields.insert(
                         name,
@@ -107,6 +121,8 @@ impl GoParser {
                             required,
                             description: None,
                             default: None,
+                            read_only: false,
+                            provenance: locate(path, field.line),
                         },
                     );
                 }
@@ -115,28 +131,23 @@ impl GoParser {
                     open: false,
                 })
             }
-            GoType::Interface { methods: _ } => {
-                // For now, interfaces become contracts
-                Ok(Type::Contract {
-                    base: Box::new(Type::Any),
-                    predicate: "interface".to_string(),
-                })
-            }
-            GoType::Alias(inner) => self.go_type_to_type(inner),
+            // Method sets have no JSON representation, so any value is accepted
+            GoType::Interface { methods: _ } => Ok(Type::Any),
+            GoType::Alias(inner) => self.go_type_to_type(inner, path),
             GoType::Array(elem) | GoType::Slice(elem) => {
-                let elem_type = self.go_type_to_type(elem)?;
+                let elem_type = self.go_type_to_type(elem, path)?;
                 Ok(Type::Array(Box::new(elem_type)))
             }
             GoType::Map { key, value } => {
-                let key_type = self.go_type_to_type(key)?;
-                let value_type = self.go_type_to_type(value)?;
+                let key_type = self.go_type_to_type(key, path)?;
+                let value_type = self.go_type_to_type(value, path)?;
                 Ok(Type::Map {
                     key: Box::new(key_type),
                     value: Box::new(value_type),
                 })
             }
             GoType::Pointer(inner) => {
-                let inner_type = self.go_type_to_type(inner)?;
+                let inner_type = self.go_type_to_type(inner, path)?;
                 Ok(Type::Optional(Box::new(inner_type)))
             }
         }
@@ -175,3 +186,12 @@ impl Default for GoParser {
         Self::new()
     }
 }
+
+/// Provenance of a declaration at `line` of the file at `path`
+fn locate(path: Option<&str>, line: Option<usize>) -> Option<Provenance> {
+    let provenance = Provenance::new(path?);
+    Some(match line {
+        Some(line) => provenance.with_line(line),
+        None => provenance,
+    })
+}
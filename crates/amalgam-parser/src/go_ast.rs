@@ -1,9 +1,15 @@
 //! Go AST parsing for precise type extraction
 
-use crate::{imports::TypeReference, ParserError};
-use amalgam_core::types::{Field, Type};
+use crate::{
+    http::{HttpClient, HttpConfig},
+    imports::TypeReference,
+    ParserError,
+};
+use amalgam_core::{
+    types::{Field, Type},
+    Provenance,
+};
 use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
-use reqwest;
 use serde::{Deserialize, Serialize};
 use std::collections::{BTreeMap, HashMap};
 use std::process::Command;
@@ -12,7 +18,7 @@ use std::time::Duration;
 
 /// Go AST parser that uses go/ast to extract precise type information
 pub struct GoASTParser {
-    client: reqwest::Client,
+    client: HttpClient,
     /// Cache of parsed Go types by fully qualified name
     type_cache: HashMap<String, GoTypeInfo>,
     multi_progress: Arc<MultiProgress>,
@@ -25,6 +31,22 @@ pub struct GoTypeInfo {
     pub fields: Vec<GoField>,
     pub documentation: Option<String>,
     pub type_kind: GoTypeKind,
+    /// Source file and line of the declaration
+    #[serde(default)]
+    pub file: Option<String>,
+    #[serde(default)]
+    pub line: Option<usize>,
+}
+
+impl GoTypeInfo {
+    /// Where the type was declared, when the parser reported it
+    pub fn provenance(&self) -> Option<Provenance> {
+        let provenance = Provenance::new(self.file.as_ref()?);
+        Some(match self.line {
+            Some(line) => provenance.with_line(line),
+            None => provenance,
+        })
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -36,6 +58,8 @@ pub struct GoField {
     pub tags: HashMap<String, String>,
     pub is_pointer: bool,
     pub is_optional: bool, // Based on omitempty tag
+    #[serde(default)]
+    pub line: Option<usize>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -46,23 +70,27 @@ pub enum GoTypeKind {
     Basic,
 }
 
+/// Uses the default HTTP configuration, without the configuration file or
+/// environment that [`GoASTParser::new`] reads
 impl Default for GoASTParser {
     fn default() -> Self {
-        Self::new()
+        Self {
+            client: HttpClient::new(HttpConfig::default())
+                .expect("the default HTTP configuration is valid"),
+            type_cache: HashMap::new(),
+            multi_progress: Arc::new(MultiProgress::new()),
+        }
     }
 }
 
 impl GoASTParser {
-    pub fn new() -> Self {
-        Self {
-            client: reqwest::Client::builder()
-                .timeout(Duration::from_secs(30))
-                .user_agent("amalgam")
-                .build()
-                .unwrap(),
+    /// Fails when the HTTP settings of the environment are invalid
+    pub fn new() -> Result<Self, ParserError> {
+        Ok(Self {
+            client: HttpClient::from_env()?,
             type_cache: HashMap::new(),
             multi_progress: Arc::new(MultiProgress::new()),
-        }
+        })
     }
 
     /// Fetch and parse Go source files from a repository
@@ -123,9 +151,7 @@ impl GoASTParser {
 
         let response = self
             .client
-            .get(&api_url)
-            .header("User-Agent", "amalgam")
-            .send()
+            .send(self.client.get(&api_url))
             .await
             .map_err(|e| ParserError::Network(e.to_string()))?;
 
@@ -147,7 +173,7 @@ impl GoASTParser {
                 let content = self.fetch_file_content(&file.download_url).await?;
                 go_files.push(GoSourceFile {
                     name: file.name,
-                    _path: file.path,
+                    path: file.path,
                     content,
                 });
             }
@@ -189,9 +215,7 @@ impl GoASTParser {
     async fn fetch_file_content(&self, url: &str) -> Result<String, ParserError> {
         let response = self
             .client
-            .get(url)
-            .header("User-Agent", "amalgam")
-            .send()
+            .send(self.client.get(url))
             .await
             .map_err(|e| ParserError::Network(e.to_string()))?;
 
@@ -247,8 +271,13 @@ impl GoASTParser {
         let type_infos: Vec<GoTypeInfo> = serde_json::from_str(&json_output)
             .map_err(|e| ParserError::Parse(format!("Failed to parse JSON: {}", e)))?;
 
-        // Cache the type information
-        for type_info in type_infos {
+        // Cache the type information, locating types by their repository path
+        for mut type_info in type_infos {
+            if let Some(file) = type_info.file.as_mut() {
+                if let Some(source) = files.iter().find(|f| f.name == *file) {
+                    *file = source.path.clone();
+                }
+            }
             let qualified_name = format!("{}.{}", type_info.package_path, type_info.name);
             self.type_cache.insert(qualified_name, type_info);
         }
@@ -279,6 +308,8 @@ type GoTypeInfo struct {
     Fields      []GoField         `json:"fields"`
     Documentation *string         `json:"documentation"`
     TypeKind    string            `json:"type_kind"`
+    File        string            `json:"file"`
+    Line        int               `json:"line"`
 }
 
 type GoField struct {
@@ -289,6 +320,7 @@ type GoField struct {
     Tags         map[string]string `json:"tags"`
     IsPointer    bool              `json:"is_pointer"`
     IsOptional   bool              `json:"is_optional"`
+    Line         int               `json:"line"`
 }
 
 func main() {
@@ -315,7 +347,7 @@ func main() {
             switch x := n.(type) {
             case *ast.TypeSpec:
                 if structType, ok := x.Type.(*ast.StructType); ok {
-                    typeInfo := extractStructInfo(x, structType, packagePath, node)
+                    typeInfo := extractStructInfo(fset, x, structType, packagePath, node)
                     allTypes = append(allTypes, typeInfo)
                 }
             }
@@ -339,7 +371,7 @@ func main() {
     fmt.Print(string(jsonData))
 }
 
-func extractStructInfo(typeSpec *ast.TypeSpec, structType *ast.StructType, packagePath string, file *ast.File) GoTypeInfo {
+func extractStructInfo(fset *token.FileSet, typeSpec *ast.TypeSpec, structType *ast.StructType, packagePath string, file *ast.File) GoTypeInfo {
     var fields []GoField
     
     for _, field := range structType.Fields.List {
@@ -349,6 +381,7 @@ func extractStructInfo(typeSpec *ast.TypeSpec, structType *ast.StructType, packa
                 GoType:    typeToString(field.Type),
                 Tags:      make(map[string]string),
                 IsPointer: isPointerType(field.Type),
+                Line:      fset.Position(name.Pos()).Line,
             }
             
             // Extract tags
@@ -400,6 +433,8 @@ func extractStructInfo(typeSpec *ast.TypeSpec, structType *ast.StructType, packa
         Fields:        fields,
         Documentation: doc,
         TypeKind:      "Struct",
+        File:          fset.Position(typeSpec.Pos()).Filename,
+        Line:          fset.Position(typeSpec.Pos()).Line,
     }
 }
 
@@ -435,13 +470,15 @@ func isPointerType(expr ast.Expr) bool {
     }
 
     /// Convert a Go type to Nickel type using precise AST information
-    pub fn go_type_to_nickel(&self, go_type_info: &GoTypeInfo) -> Result<Type, ParserError> {
+    ///
+    /// This is a pure type mapping, so it needs no parser or HTTP client.
+    pub fn go_type_to_nickel(go_type_info: &GoTypeInfo) -> Result<Type, ParserError> {
         let mut fields = BTreeMap::new();
 
         for field in &go_type_info.fields {
             let field_name = field.json_name.as_ref().unwrap_or(&field.name).to_string();
 
-            let field_type = self.go_type_string_to_nickel(&field.go_type)?;
+            let field_type = Self::go_type_string_to_nickel(&field.go_type)?;
 
             // Apply pointer and optional semantics
             let final_type = if field.is_pointer || field.is_optional {
@@ -457,6 +494,14 @@ func isPointerType(expr ast.Expr) bool {
                     required: !field.is_optional && !field.is_pointer,
                     description: field.documentation.clone(),
                     default: None,
+                    read_only: false,
+                    provenance: go_type_info.provenance().map(|at| match field.line {
+                        Some(line) => Provenance {
+                            line: Some(line),
+                            ..at
+                        },
+                        None => at,
+                    }),
                 },
             );
         }
@@ -468,8 +513,7 @@ func isPointerType(expr ast.Expr) bool {
     }
 
     /// Convert a Go type string to Nickel type
-    #[allow(clippy::only_used_in_recursion)]
-    fn go_type_string_to_nickel(&self, go_type: &str) -> Result<Type, ParserError> {
+    fn go_type_string_to_nickel(go_type: &str) -> Result<Type, ParserError> {
         match go_type {
             "string" => Ok(Type::String),
             "int" | "int8" | "int16" | "int32" | "int64" | "uint" | "uint8" | "uint16"
@@ -479,7 +523,7 @@ func isPointerType(expr ast.Expr) bool {
             "interface{}" => Ok(Type::Any),
             s if s.starts_with("[]") => {
                 let elem_type = &s[2..];
-                let elem = self.go_type_string_to_nickel(elem_type)?;
+                let elem = Self::go_type_string_to_nickel(elem_type)?;
                 Ok(Type::Array(Box::new(elem)))
             }
             s if s.starts_with("map[") => {
@@ -492,7 +536,7 @@ func isPointerType(expr ast.Expr) bool {
             s if s.starts_with("*") => {
                 // Pointer type - make it optional
                 let inner_type = &s[1..];
-                let inner = self.go_type_string_to_nickel(inner_type)?;
+                let inner = Self::go_type_string_to_nickel(inner_type)?;
                 Ok(Type::Optional(Box::new(inner)))
             }
             // Handle qualified types (e.g., metav1.ObjectMeta)
@@ -541,7 +585,7 @@ struct GitHubFile {
 #[derive(Debug, Clone)]
 struct GoSourceFile {
     name: String,
-    _path: String,
+    path: String,
     content: String,
 }
 
@@ -0,0 +1,388 @@
+//! Extracting CRDs from Helm charts
+//!
+//! Charts ship CRDs either in `crds/`, which Helm installs verbatim, or as
+//! templates. Templates are not rendered: a template document is used when it
+//! is static YAML once standalone control lines such as
+//! `{{- if .Values.installCRDs }}` and `{{- end }}` are dropped. Documents
+//! that still contain template actions, including `else` branches whose
+//! alternatives cannot be told apart without rendering, are skipped, as are
+//! template documents that are not valid YAML.
+
+use crate::{crd::CRD, ParserError};
+use flate2::read::GzDecoder;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use tracing::{debug, warn};
+
+/// CRDs found in a Helm chart, with the chart's identity
+#[derive(Debug, Clone)]
+pub struct HelmChart {
+    pub name: String,
+    pub version: String,
+    pub app_version: Option<String>,
+    pub crds: Vec<CRD>,
+    /// Source YAML of each CRD, in the same order as `crds`
+    pub documents: Vec<String>,
+}
+
+/// The fields of `Chart.yaml` amalgam uses
+#[derive(Debug, Deserialize)]
+struct ChartMetadata {
+    name: String,
+    version: String,
+    #[serde(rename = "appVersion")]
+    app_version: Option<String>,
+}
+
+/// Template actions that only decide whether the enclosed text is rendered
+///
+/// `else` is not one of them: dropping it would join both branches.
+const CONTROL_ACTIONS: &[&str] = &["if", "end", "with", "range", "/*"];
+
+impl HelmChart {
+    /// Load a chart from a directory or a `.tgz` archive
+    pub fn load(path: &Path) -> Result<Self, ParserError> {
+        let files = if path.is_dir() {
+            let mut files = BTreeMap::new();
+            read_chart_dir(path, path, &mut files)?;
+            files
+        } else {
+            read_chart_archive(path)?
+        };
+        let mut chart = Self::from_files(&files)
+            .map_err(|e| ParserError::InvalidSchema(format!("Helm chart {:?}: {}", path, e)))?;
+        qualify_sources(&mut chart.crds, &path.display().to_string());
+        Ok(chart)
+    }
+
+    /// Build a chart from its files, keyed by path relative to the chart root
+    pub fn from_files(files: &BTreeMap<String, String>) -> Result<Self, ParserError> {
+        let metadata = files
+            .get("Chart.yaml")
+            .ok_or_else(|| ParserError::InvalidSchema("no Chart.yaml".to_string()))?;
+        let metadata: ChartMetadata = serde_yaml::from_str(metadata)?;
+
+        let mut chart = Self {
+            name: metadata.name,
+            version: metadata.version,
+            app_version: metadata.app_version,
+            crds: Vec::new(),
+            documents: Vec::new(),
+        };
+
+        for (path, content) in files {
+            let templated = if path.starts_with("crds/") {
+                false
+            } else if path.starts_with("templates/") {
+                true
+            } else {
+                continue;
+            };
+            if !(path.ends_with(".yaml") || path.ends_with(".yml")) {
+                continue;
+            }
+
+            for document in split_documents(content) {
+                let document = if templated {
+                    match static_document(&document) {
+                        Some(document) => document,
+                        None => {
+                            debug!("Skipping templated document in {}", path);
+                            continue;
+                        }
+                    }
+                } else {
+                    document
+                };
+                match chart.add_document(path, document) {
+                    Err(e) if templated => warn!("Skipping template document: {}", e),
+                    result => result?,
+                }
+            }
+        }
+
+        Ok(chart)
+    }
+
+    fn add_document(&mut self, path: &str, document: String) -> Result<(), ParserError> {
+        if let Some(crd) = parse_crd_document(path, &document)? {
+            self.crds.push(crd);
+            self.documents.push(document);
+        }
+        Ok(())
+    }
+}
+
+/// Parse a YAML document read from `path` as a CRD, if it is one
+///
+/// The CRD's source is set to `path`.
+pub(crate) fn parse_crd_document(path: &str, document: &str) -> Result<Option<CRD>, ParserError> {
+    let value: serde_yaml::Value = serde_yaml::from_str(document)
+        .map_err(|e| ParserError::Parse(format!("{}: {}", path, e)))?;
+    if value.get("kind").and_then(|k| k.as_str()) != Some("CustomResourceDefinition") {
+        return Ok(None);
+    }
+    serde_yaml::from_value::<CRD>(value)
+        .map(|crd| Some(crd.with_source(path)))
+        .map_err(|e| ParserError::InvalidSchema(format!("{}: {}", path, e)))
+}
+
+/// Prefix the sources of CRDs read from inside `origin`, a directory,
+/// archive, repository or image, as `origin//path`
+pub(crate) fn qualify_sources(crds: &mut [CRD], origin: &str) {
+    for crd in crds {
+        if let Some(path) = &crd.source {
+            crd.source = Some(format!("{}//{}", origin, path));
+        }
+    }
+}
+
+/// Split a YAML stream into its non-empty documents
+pub(crate) fn split_documents(content: &str) -> Vec<String> {
+    let mut documents = vec![String::new()];
+    for line in content.lines() {
+        if line.trim_end() == "---" || line.starts_with("--- ") {
+            documents.push(String::new());
+            continue;
+        }
+        let current = documents.last_mut().expect("always one document");
+        current.push_str(line);
+        current.push('\n');
+    }
+    documents
+        .into_iter()
+        .filter(|d| {
+            d.lines()
+                .any(|l| !l.trim().is_empty() && !l.trim_start().starts_with('#'))
+        })
+        .collect()
+}
+
+/// A template document with its control lines dropped, if nothing else is templated
+fn static_document(document: &str) -> Option<String> {
+    let mut result = String::new();
+    for line in document.lines() {
+        if is_control_line(line) {
+            continue;
+        }
+        if line.contains("{{") {
+            return None;
+        }
+        result.push_str(line);
+        result.push('\n');
+    }
+    Some(result)
+}
+
+/// Whether a line holds a single control action and nothing else
+fn is_control_line(line: &str) -> bool {
+    let Some(action) = line
+        .trim()
+        .strip_prefix("{{")
+        .and_then(|rest| rest.strip_suffix("}}"))
+    else {
+        return false;
+    };
+    if action.contains("{{") {
+        return false;
+    }
+    let action = action.trim_start_matches('-').trim_end_matches('-').trim();
+    CONTROL_ACTIONS.iter().any(|keyword| {
+        action
+            .strip_prefix(keyword)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with(' ') || *keyword == "/*")
+    })
+}
+
+/// Read the chart files amalgam uses from a chart directory
+fn read_chart_dir(
+    root: &Path,
+    dir: &Path,
+    files: &mut BTreeMap<String, String>,
+) -> Result<(), ParserError> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let relative = path
+            .strip_prefix(root)
+            .expect("entries are under the chart root")
+            .to_string_lossy()
+            .replace('\\', "/");
+        if path.is_dir() {
+            // Subcharts are separate charts with their own CRDs
+            if relative != "charts" {
+                read_chart_dir(root, &path, files)?;
+            }
+        } else if is_chart_file(&relative) {
+            files.insert(relative, fs::read_to_string(&path)?);
+        }
+    }
+    Ok(())
+}
+
+/// Read the chart files amalgam uses from a packaged `.tgz` chart
+///
+/// Packaged charts keep their files under a directory named after the chart;
+/// that first component is stripped.
+fn read_chart_archive(path: &Path) -> Result<BTreeMap<String, String>, ParserError> {
+    let mut archive = tar::Archive::new(GzDecoder::new(fs::File::open(path)?));
+    let mut files = BTreeMap::new();
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let relative: PathBuf = entry
+            .path()?
+            .components()
+            .skip(1)
+            .filter(|c| matches!(c, Component::Normal(_)))
+            .collect();
+        let relative = relative.to_string_lossy().replace('\\', "/");
+        if is_chart_file(&relative) {
+            let mut content = String::new();
+            std::io::Read::read_to_string(&mut entry, &mut content)?;
+            files.insert(relative, content);
+        }
+    }
+    Ok(files)
+}
+
+fn is_chart_file(relative: &str) -> bool {
+    relative == "Chart.yaml" || relative.starts_with("crds/") || relative.starts_with("templates/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CHART_YAML: &str =
+        "apiVersion: v2\nname: widgets\nversion: 1.2.3\nappVersion: \"0.9.0\"\n";
+
+    fn crd_yaml(kind: &str) -> String {
+        format!(
+            r#"apiVersion: apiextensions.k8s.io/v1
+kind: CustomResourceDefinition
+metadata:
+  name: {plural}.example.com
+spec:
+  group: example.com
+  names:
+    kind: {kind}
+    plural: {plural}
+    singular: {singular}
+  versions:
+    - name: v1
+      served: true
+      storage: true
+      schema:
+        openAPIV3Schema:
+          type: object
+          properties:
+            spec:
+              type: object
+"#,
+            kind = kind,
+            plural = format!("{}s", kind.to_lowercase()),
+            singular = kind.to_lowercase(),
+        )
+    }
+
+    fn chart_files() -> BTreeMap<String, String> {
+        BTreeMap::from([
+            ("Chart.yaml".to_string(), CHART_YAML.to_string()),
+            (
+                "crds/widgets.yaml".to_string(),
+                format!("# Widgets\n---\n{}", crd_yaml("Widget")),
+            ),
+            (
+                "templates/gadgets.yaml".to_string(),
+                format!(
+                    "{{{{- if .Values.installCRDs }}}}\n{}{{{{- end }}}}\n---\n{}",
+                    crd_yaml("Gadget"),
+                    crd_yaml("Gizmo").replace(
+                        "  name: gizmos.example.com",
+                        "  name: gizmos.example.com\n  labels:\n    {{- include \"labels\" . | nindent 4 }}",
+                    ),
+                ),
+            ),
+            (
+                "templates/sprockets.yaml".to_string(),
+                format!(
+                    "{{{{- if .Values.v2 }}}}\n{}{{{{- else }}}}\n{}{{{{- end }}}}\n",
+                    crd_yaml("Sprocket"),
+                    crd_yaml("Sprocket").replace("name: v1", "name: v1beta1"),
+                ),
+            ),
+            (
+                "templates/broken.yaml".to_string(),
+                "{{- if .Values.broken }}\nkind: [CustomResourceDefinition\n{{- end }}\n"
+                    .to_string(),
+            ),
+            (
+                "templates/deployment.yaml".to_string(),
+                "apiVersion: apps/v1\nkind: Deployment\nmetadata:\n  name: {{ .Release.Name }}\n"
+                    .to_string(),
+            ),
+        ])
+    }
+
+    fn kinds(chart: &HelmChart) -> Vec<&str> {
+        chart
+            .crds
+            .iter()
+            .map(|crd| crd.spec.names.kind.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn test_static_crds_from_crds_dir_and_templates() {
+        let chart = HelmChart::from_files(&chart_files()).unwrap();
+        assert_eq!(chart.name, "widgets");
+        assert_eq!(chart.version, "1.2.3");
+        assert_eq!(chart.app_version.as_deref(), Some("0.9.0"));
+        // Gizmo still uses a template action once control lines are dropped,
+        // Sprocket has an else branch and broken.yaml is not valid YAML
+        assert_eq!(kinds(&chart), vec!["Widget", "Gadget"]);
+        assert_eq!(chart.documents.len(), 2);
+        assert!(!chart.documents[1].contains("{{"));
+    }
+
+    #[test]
+    fn test_load_directory_and_archive() {
+        let dir = tempfile::tempdir().unwrap();
+        let chart_dir = dir.path().join("widgets");
+        for (path, content) in chart_files() {
+            let path = chart_dir.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+        // Subchart CRDs belong to the subchart
+        let subchart = chart_dir.join("charts/other/crds");
+        fs::create_dir_all(&subchart).unwrap();
+        fs::write(subchart.join("other.yaml"), crd_yaml("Other")).unwrap();
+
+        let from_dir = HelmChart::load(&chart_dir).unwrap();
+        assert_eq!(kinds(&from_dir), vec!["Widget", "Gadget"]);
+
+        let archive_path = dir.path().join("widgets-1.2.3.tgz");
+        let encoder = flate2::write::GzEncoder::new(
+            fs::File::create(&archive_path).unwrap(),
+            flate2::Compression::default(),
+        );
+        let mut builder = tar::Builder::new(encoder);
+        builder.append_dir_all("widgets", &chart_dir).unwrap();
+        builder.into_inner().unwrap().finish().unwrap();
+
+        let from_archive = HelmChart::load(&archive_path).unwrap();
+        assert_eq!(from_archive.version, "1.2.3");
+        assert_eq!(kinds(&from_archive), vec!["Widget", "Gadget"]);
+    }
+
+    #[test]
+    fn test_missing_chart_yaml() {
+        let files = BTreeMap::from([("crds/widgets.yaml".to_string(), crd_yaml("Widget"))]);
+        assert!(HelmChart::from_files(&files).is_err());
+    }
+}
@@ -0,0 +1,422 @@
+//! HTTP client shared by all fetchers
+//!
+//! Configuration is read once from `$AMALGAM_HTTP_CONFIG` (default
+//! `~/.config/amalgam/http.toml`) and the environment:
+//!
+//! ```toml
+//! ca_bundle = "/etc/ssl/internal-ca.pem"
+//! proxy = "http://proxy.internal:3128"
+//! no_proxy = "localhost,.internal"
+//! max_retries = 5
+//!
+//! [tokens]
+//! "git.internal.example.com" = "glpat-..."
+//! ```
+//!
+//! `GITHUB_TOKEN` (or `GH_TOKEN`) and `GITLAB_TOKEN` provide tokens for
+//! github.com and gitlab.com, and `AMALGAM_CA_BUNDLE` a CA bundle. Without an
+//! explicit proxy the usual `HTTPS_PROXY`/`NO_PROXY` variables apply.
+
+use crate::ParserError;
+use reqwest::header::{HeaderMap, AUTHORIZATION, RETRY_AFTER};
+use reqwest::{Method, RequestBuilder, Response, StatusCode, Url};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::warn;
+
+/// Hosts that accept the token configured for github.com
+const GITHUB_HOSTS: &[&str] = &["api.github.com", "raw.githubusercontent.com"];
+
+/// Settings for the shared HTTP client
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub struct HttpConfig {
+    /// Tokens by host, sent as `Authorization: Bearer`
+    pub tokens: BTreeMap<String, String>,
+    /// PEM file with extra root certificates
+    pub ca_bundle: Option<PathBuf>,
+    /// Proxy for all requests
+    pub proxy: Option<String>,
+    /// Comma-separated hosts that bypass `proxy`
+    pub no_proxy: Option<String>,
+    pub timeout_secs: u64,
+    /// Retries after rate limiting, server errors and connection failures
+    pub max_retries: u32,
+    /// Delay before the first retry when the server gives none; doubled each time
+    pub initial_backoff_ms: u64,
+    /// Longest wait before a retry; a server asking for more fails the request
+    pub max_backoff_secs: u64,
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        Self {
+            tokens: BTreeMap::new(),
+            ca_bundle: None,
+            proxy: None,
+            no_proxy: None,
+            timeout_secs: 60,
+            max_retries: 3,
+            initial_backoff_ms: 500,
+            max_backoff_secs: 60,
+        }
+    }
+}
+
+impl HttpConfig {
+    /// Read the configuration file and environment
+    pub fn from_env() -> Result<Self, ParserError> {
+        let explicit = std::env::var_os("AMALGAM_HTTP_CONFIG").map(PathBuf::from);
+        let path = explicit.clone().or_else(|| {
+            std::env::var_os("XDG_CONFIG_HOME")
+                .map(PathBuf::from)
+                .or_else(|| {
+                    std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config"))
+                })
+                .map(|dir| dir.join("amalgam/http.toml"))
+        });
+
+        let mut config = match path {
+            Some(path) if explicit.is_some() || path.exists() => {
+                let content = std::fs::read_to_string(&path)?;
+                toml::from_str(&content)
+                    .map_err(|e| ParserError::Parse(format!("{}: {}", path.display(), e)))?
+            }
+            _ => Self::default(),
+        };
+
+        let env = |name: &str| std::env::var(name).ok().filter(|v| !v.is_empty());
+        if let Some(token) = env("GITHUB_TOKEN").or_else(|| env("GH_TOKEN")) {
+            config.tokens.insert("github.com".to_string(), token);
+        }
+        if let Some(token) = env("GITLAB_TOKEN") {
+            config.tokens.insert("gitlab.com".to_string(), token);
+        }
+        if let Some(ca_bundle) = env("AMALGAM_CA_BUNDLE") {
+            config.ca_bundle = Some(PathBuf::from(ca_bundle));
+        }
+        Ok(config)
+    }
+
+    /// Token to send to the host of `url`, if any
+    ///
+    /// Tokens only go out over HTTPS, or to the local machine.
+    fn token_for(&self, url: &Url) -> Option<&str> {
+        let host = url.host_str()?;
+        if url.scheme() != "https" && !matches!(host, "localhost" | "127.0.0.1" | "[::1]") {
+            return None;
+        }
+        let key = if GITHUB_HOSTS.contains(&host) && !self.tokens.contains_key(host) {
+            "github.com"
+        } else {
+            host
+        };
+        self.tokens.get(key).map(String::as_str)
+    }
+
+    /// How long to wait before retrying a response, if it should be retried
+    ///
+    /// Rate limiting (429, or GitHub's 403 with no requests remaining) and
+    /// transient server errors are retried after `Retry-After`, GitHub's
+    /// `x-ratelimit-reset`, or exponential backoff, in that order.
+    fn retry_delay(
+        &self,
+        status: StatusCode,
+        headers: &HeaderMap,
+        attempt: u32,
+    ) -> Option<Duration> {
+        let header = |name| headers.get(name).and_then(|v| v.to_str().ok());
+        let rate_limited = status == StatusCode::TOO_MANY_REQUESTS
+            || (status == StatusCode::FORBIDDEN && header("x-ratelimit-remaining") == Some("0"));
+        let transient = matches!(
+            status,
+            StatusCode::INTERNAL_SERVER_ERROR
+                | StatusCode::BAD_GATEWAY
+                | StatusCode::SERVICE_UNAVAILABLE
+                | StatusCode::GATEWAY_TIMEOUT
+        );
+        if !rate_limited && !transient {
+            return None;
+        }
+
+        let retry_after = header(RETRY_AFTER.as_str()).and_then(parse_retry_after);
+        let reset = header("x-ratelimit-reset")
+            .and_then(|v| v.parse::<u64>().ok())
+            .map(|reset| {
+                let now = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_secs();
+                Duration::from_secs(reset.saturating_sub(now).max(1))
+            });
+        Some(
+            retry_after
+                .or(if rate_limited { reset } else { None })
+                .unwrap_or_else(|| self.backoff(attempt)),
+        )
+    }
+
+    fn backoff(&self, attempt: u32) -> Duration {
+        Duration::from_millis(self.initial_backoff_ms.saturating_mul(1 << attempt.min(16)))
+            .min(self.max_backoff())
+    }
+
+    fn max_backoff(&self) -> Duration {
+        Duration::from_secs(self.max_backoff_secs)
+    }
+}
+
+/// `Retry-After` in seconds or as an HTTP date
+fn parse_retry_after(value: &str) -> Option<Duration> {
+    if let Ok(seconds) = value.trim().parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    let wait = date.timestamp() - chrono::Utc::now().timestamp();
+    Some(Duration::from_secs(wait.max(0) as u64))
+}
+
+/// `reqwest` client with authentication and retries
+///
+/// Clones share one connection pool and configuration.
+#[derive(Clone)]
+pub struct HttpClient {
+    client: reqwest::Client,
+    config: Arc<HttpConfig>,
+}
+
+impl HttpClient {
+    pub fn new(config: HttpConfig) -> Result<Self, ParserError> {
+        let mut builder = reqwest::Client::builder()
+            .timeout(Duration::from_secs(config.timeout_secs))
+            .user_agent("amalgam");
+
+        if let Some(ca_bundle) = &config.ca_bundle {
+            let pem = std::fs::read(ca_bundle)?;
+            let certificates = reqwest::Certificate::from_pem_bundle(&pem).map_err(|e| {
+                ParserError::Parse(format!("CA bundle {}: {}", ca_bundle.display(), e))
+            })?;
+            for certificate in certificates {
+                builder = builder.add_root_certificate(certificate);
+            }
+        }
+        if let Some(proxy) = &config.proxy {
+            let proxy = reqwest::Proxy::all(proxy)
+                .map_err(|e| ParserError::Parse(format!("proxy {}: {}", proxy, e)))?
+                .no_proxy(
+                    config
+                        .no_proxy
+                        .as_deref()
+                        .and_then(reqwest::NoProxy::from_string),
+                );
+            builder = builder.proxy(proxy);
+        }
+
+        Ok(Self {
+            client: builder
+                .build()
+                .map_err(|e| ParserError::Network(e.to_string()))?,
+            config: Arc::new(config),
+        })
+    }
+
+    /// Client configured from the configuration file and environment
+    pub fn from_env() -> Result<Self, ParserError> {
+        Self::new(HttpConfig::from_env()?)
+    }
+
+    /// GET request to `url`, authenticated if a token is configured for its host
+    pub fn get(&self, url: &str) -> RequestBuilder {
+        self.request(Method::GET, url)
+    }
+
+    /// Request to `url`, authenticated if a token is configured for its host
+    pub fn request(&self, method: Method, url: &str) -> RequestBuilder {
+        let request = self.client.request(method, url);
+        match Url::parse(url)
+            .ok()
+            .as_ref()
+            .and_then(|url| self.config.token_for(url))
+        {
+            Some(token) => request.header(AUTHORIZATION, format!("Bearer {}", token)),
+            None => request,
+        }
+    }
+
+    /// Request to `url` authenticated with `token` instead of the configured one
+    pub fn request_with_token(&self, method: Method, url: &str, token: &str) -> RequestBuilder {
+        self.client
+            .request(method, url)
+            .header(AUTHORIZATION, format!("Bearer {}", token))
+    }
+
+    /// Send a request, retrying rate-limited and transient failures
+    pub async fn send(&self, request: RequestBuilder) -> reqwest::Result<Response> {
+        let mut attempt = 0;
+        loop {
+            // Requests with streaming bodies cannot be replayed
+            let Some(this_try) = request.try_clone() else {
+                return request.send().await;
+            };
+
+            let result = this_try.send().await;
+            let delay = match &result {
+                Ok(response) => {
+                    self.config
+                        .retry_delay(response.status(), response.headers(), attempt)
+                }
+                Err(e) if e.is_timeout() || e.is_connect() => Some(self.config.backoff(attempt)),
+                Err(_) => None,
+            };
+
+            match delay {
+                Some(delay)
+                    if attempt < self.config.max_retries && delay <= self.config.max_backoff() =>
+                {
+                    let reason = match &result {
+                        Ok(response) => response.status().to_string(),
+                        Err(e) => e.to_string(),
+                    };
+                    warn!("Retrying in {:?} after {}", delay, reason);
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+                _ => return result,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::matchers::{header, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn test_config() -> HttpConfig {
+        HttpConfig {
+            initial_backoff_ms: 1,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_token_for_host() {
+        let config = HttpConfig {
+            tokens: BTreeMap::from([
+                ("github.com".to_string(), "gh".to_string()),
+                ("git.internal".to_string(), "internal".to_string()),
+            ]),
+            ..Default::default()
+        };
+        let token = |url: &str| config.token_for(&Url::parse(url).unwrap());
+
+        assert_eq!(token("https://api.github.com/repos/a/b"), Some("gh"));
+        assert_eq!(
+            token("https://raw.githubusercontent.com/a/b/x.yaml"),
+            Some("gh")
+        );
+        assert_eq!(
+            token("https://git.internal/api/v4/projects"),
+            Some("internal")
+        );
+        assert_eq!(token("https://gitlab.com/a/b"), None);
+        // Never sent in the clear
+        assert_eq!(token("http://git.internal/api/v4/projects"), None);
+    }
+
+    #[test]
+    fn test_retry_delay() {
+        let config = test_config();
+        let headers = |pairs: &[(&'static str, &str)]| {
+            let mut map = HeaderMap::new();
+            for (name, value) in pairs {
+                map.insert(*name, value.parse().unwrap());
+            }
+            map
+        };
+
+        assert_eq!(
+            config.retry_delay(StatusCode::NOT_FOUND, &HeaderMap::new(), 0),
+            None
+        );
+        // A plain 403 is a permission error, not rate limiting
+        assert_eq!(
+            config.retry_delay(StatusCode::FORBIDDEN, &HeaderMap::new(), 0),
+            None
+        );
+        assert_eq!(
+            config.retry_delay(
+                StatusCode::TOO_MANY_REQUESTS,
+                &headers(&[("retry-after", "7")]),
+                0
+            ),
+            Some(Duration::from_secs(7))
+        );
+        assert_eq!(
+            config.retry_delay(StatusCode::SERVICE_UNAVAILABLE, &HeaderMap::new(), 3),
+            Some(Duration::from_millis(8))
+        );
+
+        let reset = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+            + 120;
+        let delay = config
+            .retry_delay(
+                StatusCode::FORBIDDEN,
+                &headers(&[
+                    ("x-ratelimit-remaining", "0"),
+                    ("x-ratelimit-reset", &reset.to_string()),
+                ]),
+                0,
+            )
+            .unwrap();
+        assert!(delay > Duration::from_secs(100) && delay <= Duration::from_secs(120));
+    }
+
+    #[tokio::test]
+    async fn test_send_retries_then_succeeds() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/crd.yaml"))
+            .respond_with(ResponseTemplate::new(503))
+            .up_to_n_times(2)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/crd.yaml"))
+            .and(header("authorization", "Bearer secret"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("ok"))
+            .mount(&server)
+            .await;
+
+        let client = HttpClient::new(HttpConfig {
+            tokens: BTreeMap::from([("127.0.0.1".to_string(), "secret".to_string())]),
+            ..test_config()
+        })
+        .unwrap();
+        let url = format!("{}/crd.yaml", server.uri());
+        let response = client.send(client.get(&url)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.text().await.unwrap(), "ok");
+    }
+
+    #[tokio::test]
+    async fn test_send_gives_up_on_long_rate_limit() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(429).insert_header("retry-after", "3600"))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let client = HttpClient::new(test_config()).unwrap();
+        let response = client.send(client.get(&server.uri())).await.unwrap();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    }
+}
@@ -1,7 +1,45 @@
 //! Import resolution for cross-package type references
 
-use amalgam_core::types::Type;
+use amalgam_core::{types::Type, visit::references};
+use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+
+/// Directory layout of the generated k8s-core package
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum K8sLayout {
+    /// All API groups share one directory per version: `v1/pod.ncl`
+    #[default]
+    Flat,
+    /// One directory per API group and version: `core/v1/pod.ncl`
+    Grouped,
+}
+
+impl K8sLayout {
+    /// Directories below the package root holding a group's version module
+    pub fn module_dir(self, api_group: &str, version: &str) -> Vec<String> {
+        match self {
+            K8sLayout::Flat => vec![version.to_string()],
+            K8sLayout::Grouped => vec![api_group.to_string(), version.to_string()],
+        }
+    }
+}
+
+impl FromStr for K8sLayout {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "flat" => Ok(K8sLayout::Flat),
+            "grouped" => Ok(K8sLayout::Grouped),
+            other => Err(format!(
+                "unknown layout '{}', expected 'flat' or 'grouped'",
+                other
+            )),
+        }
+    }
+}
 
 /// Represents a type reference that needs to be imported
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
@@ -40,7 +78,7 @@ impl TypeReference {
                 // Special case for apimachinery types
                 let version = parts[parts.len() - 2].to_string();
                 let kind = parts[parts.len() - 1].to_string();
-                return Some(Self::new("k8s.io".to_string(), version, kind));
+                return Some(Self::new("meta.k8s.io".to_string(), version, kind));
             } else if name.starts_with("io.k8s.api.") && parts.len() >= 5 {
                 // Standard API types
                 let group = if parts[3] == "core" {
@@ -82,8 +120,43 @@ impl TypeReference {
         None
     }
 
+    /// Kubernetes API group directory for this reference, if it is a k8s type
+    ///
+    /// The core group (`k8s.io`) maps to `core`, and `apps.k8s.io` to `apps`.
+    pub fn k8s_api_group(&self) -> Option<&str> {
+        if self.group == "k8s.io" {
+            Some("core")
+        } else {
+            self.group.strip_suffix(".k8s.io")
+        }
+    }
+
+    /// Fully qualified name as it appears in `Type::Reference`
+    pub fn qualified_name(&self) -> String {
+        match self.k8s_api_group() {
+            Some("meta") => format!(
+                "io.k8s.apimachinery.pkg.apis.meta.{}.{}",
+                self.version, self.kind
+            ),
+            Some(api_group) => format!("io.k8s.api.{}.{}.{}", api_group, self.version, self.kind),
+            None => format!("{}/{}.{}", self.group, self.version, self.kind),
+        }
+    }
+
     /// Get the import path for this reference relative to a base path
+    ///
+    /// Assumes the k8s-core package uses the flat `version/kind.ncl` layout.
     pub fn import_path(&self, from_group: &str, from_version: &str) -> String {
+        self.import_path_with_layout(from_group, from_version, K8sLayout::Flat)
+    }
+
+    /// Get the import path for this reference given the k8s-core package layout
+    pub fn import_path_with_layout(
+        &self,
+        from_group: &str,
+        from_version: &str,
+        layout: K8sLayout,
+    ) -> String {
         // Generic approach: Calculate the relative path between any two files
         // Package layout convention:
         //   vendor_dir/
@@ -91,6 +164,36 @@ impl TypeReference {
         //     │   └── [group_path]/version/file.ncl
         //     └── other_package/
         //         └── [group_path]/version/file.ncl
+        let from = TypeReference::new(
+            from_group.to_string(),
+            from_version.to_string(),
+            String::new(),
+        );
+        let from_components = from.module_components(layout);
+
+        let mut to_components = self.module_components(layout);
+        to_components.push(format!("{}.ncl", self.kind.to_lowercase()));
+
+        // Calculate the relative path
+        // From a file at: vendor/package1/group/version/file.ncl
+        // We need to go up to vendor/ then down to package2/...
+        // The number of ../ equals the depth from the file to the vendor directory
+        // which is the number of path components minus the vendor itself
+        let up_count = from_components.len();
+        let up_dirs = "../".repeat(up_count);
+        let down_path = to_components.join("/");
+
+        format!("{}{}", up_dirs, down_path)
+    }
+
+    /// Directory components from the vendor directory down to this reference's module
+    fn module_components(&self, layout: K8sLayout) -> Vec<String> {
+        // All Kubernetes API groups share the k8s_io package
+        if let Some(api_group) = self.k8s_api_group() {
+            let mut components = vec!["k8s_io".to_string()];
+            components.extend(layout.module_dir(api_group, &self.version));
+            return components;
+        }
 
         // Helper to derive package directory from group name
         let group_to_package = |group: &str| -> String {
@@ -103,24 +206,14 @@ impl TypeReference {
             // If it ends with a common TLD pattern, extract the org name
             if group.contains('.') {
                 // For domains like "apiextensions.crossplane.io", we want "crossplane"
-                // For domains like "k8s.io", we want "k8s_io"
                 let parts: Vec<&str> = group.split('.').collect();
-                if parts.len() >= 2
+                if parts.len() >= 3
                     && (parts.last() == Some(&"io")
                         || parts.last() == Some(&"com")
                         || parts.last() == Some(&"org"))
                 {
-                    // If there's a clear org name, use it
-                    if parts.len() == 2 {
-                        // Simple case like "k8s.io" -> "k8s_io"
-                        sanitized
-                    } else if parts.len() >= 3 {
-                        // Complex case like "apiextensions.crossplane.io"
-                        // Take the second-to-last part as the org name
-                        parts[parts.len() - 2].to_string()
-                    } else {
-                        sanitized
-                    }
+                    // Take the second-to-last part as the org name
+                    parts[parts.len() - 2].to_string()
                 } else {
                     sanitized
                 }
@@ -129,49 +222,23 @@ impl TypeReference {
             }
         };
 
-        // Helper to determine if a group needs its own subdirectory within the package
-        let needs_group_subdir = |group: &str, package: &str| -> bool {
-            // If the package name is derived from only part of the group,
-            // we need a subdirectory for the full group
-            let sanitized = group.replace('.', "_");
-            sanitized != package && group.contains('.')
-        };
-
-        // Build the from path components
-        let from_package = group_to_package(from_group);
-        let mut from_components: Vec<String> = Vec::new();
-        from_components.push(from_package.clone());
-
-        if needs_group_subdir(from_group, &from_package) {
-            from_components.push(from_group.to_string());
-        }
-        from_components.push(from_version.to_string());
-
-        // Build the target path components
-        let target_package = group_to_package(&self.group);
-        let mut to_components: Vec<String> = Vec::new();
-        to_components.push(target_package.clone());
+        let package = group_to_package(&self.group);
+        let mut components = vec![package.clone()];
 
-        if needs_group_subdir(&self.group, &target_package) {
-            to_components.push(self.group.clone());
+        // If the package name is derived from only part of the group,
+        // we need a subdirectory for the full group
+        if self.group.replace('.', "_") != package && self.group.contains('.') {
+            components.push(self.group.clone());
         }
-        to_components.push(self.version.clone());
-        to_components.push(format!("{}.ncl", self.kind.to_lowercase()));
-
-        // Calculate the relative path
-        // From a file at: vendor/package1/group/version/file.ncl
-        // We need to go up to vendor/ then down to package2/...
-        // The number of ../ equals the depth from the file to the vendor directory
-        // which is the number of path components minus the vendor itself
-        let up_count = from_components.len();
-        let up_dirs = "../".repeat(up_count);
-        let down_path = to_components.join("/");
-
-        format!("{}{}", up_dirs, down_path)
+        components.push(self.version.clone());
+        components
     }
 
     /// Get the module alias for imports
     pub fn module_alias(&self) -> String {
+        if self.k8s_api_group().is_some() {
+            return format!("k8s_io_{}", self.version.replace('-', "_"));
+        }
         format!(
             "{}_{}",
             self.group.replace(['.', '-'], "_"),
@@ -209,38 +276,17 @@ impl ImportResolver {
 
     /// Analyze a type and collect external references
     pub fn analyze_type(&mut self, ty: &Type) {
-        match ty {
-            Type::Reference(name) => {
-                // Check if this is an external reference
-                if !self.local_types.contains(name) {
-                    if let Some(type_ref) = TypeReference::from_qualified_name(name) {
-                        tracing::trace!("ImportResolver: found external reference: {:?}", type_ref);
-                        self.references.insert(type_ref);
-                    } else {
-                        tracing::trace!("ImportResolver: could not parse reference: {}", name);
-                    }
-                }
-            }
-            Type::Array(inner) => self.analyze_type(inner),
-            Type::Optional(inner) => self.analyze_type(inner),
-            Type::Map { value, .. } => self.analyze_type(value),
-            Type::Record { fields, .. } => {
-                for field in fields.values() {
-                    self.analyze_type(&field.ty);
-                }
-            }
-            Type::Union(types) => {
-                for ty in types {
-                    self.analyze_type(ty);
-                }
+        for name in references(ty) {
+            // Check if this is an external reference
+            if self.local_types.contains(&name) {
+                continue;
             }
-            Type::TaggedUnion { variants, .. } => {
-                for ty in variants.values() {
-                    self.analyze_type(ty);
-                }
+            if let Some(type_ref) = TypeReference::from_qualified_name(&name) {
+                tracing::trace!("ImportResolver: found external reference: {:?}", type_ref);
+                self.references.insert(type_ref);
+            } else {
+                tracing::trace!("ImportResolver: could not parse reference: {}", name);
             }
-            Type::Contract { base, .. } => self.analyze_type(base),
-            _ => {}
         }
     }
 
@@ -379,4 +425,48 @@ mod tests {
         let path2 = type_ref.import_path("example.io", "v1");
         assert_eq!(path2, "../../k8s_io/v1/objectmeta.ncl");
     }
+
+    #[test]
+    fn test_k8s_groups_share_package() {
+        let meta =
+            TypeReference::from_qualified_name("io.k8s.apimachinery.pkg.apis.meta.v1.ObjectMeta")
+                .unwrap();
+        assert_eq!(meta.group, "meta.k8s.io");
+        assert_eq!(meta.k8s_api_group(), Some("meta"));
+        assert_eq!(
+            meta.qualified_name(),
+            "io.k8s.apimachinery.pkg.apis.meta.v1.ObjectMeta"
+        );
+
+        let deployment =
+            TypeReference::from_qualified_name("io.k8s.api.apps.v1.Deployment").unwrap();
+        assert_eq!(deployment.k8s_api_group(), Some("apps"));
+        assert_eq!(deployment.qualified_name(), "io.k8s.api.apps.v1.Deployment");
+        assert_eq!(
+            deployment.import_path("example.io", "v1"),
+            "../../k8s_io/v1/deployment.ncl"
+        );
+    }
+
+    #[test]
+    fn test_import_path_grouped_layout() {
+        let type_ref = TypeReference::new(
+            "meta.k8s.io".to_string(),
+            "v1".to_string(),
+            "ObjectMeta".to_string(),
+        );
+        let path = type_ref.import_path_with_layout(
+            "apiextensions.crossplane.io",
+            "v1",
+            K8sLayout::Grouped,
+        );
+        assert_eq!(path, "../../../k8s_io/meta/v1/objectmeta.ncl");
+
+        // Between groups of the k8s-core package itself
+        let path = type_ref.import_path_with_layout("apps.k8s.io", "v1", K8sLayout::Grouped);
+        assert_eq!(path, "../../../k8s_io/meta/v1/objectmeta.ncl");
+
+        assert_eq!("grouped".parse::<K8sLayout>(), Ok(K8sLayout::Grouped));
+        assert!("nested".parse::<K8sLayout>().is_err());
+    }
 }
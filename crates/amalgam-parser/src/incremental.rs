@@ -3,9 +3,18 @@
 //! This module implements fingerprinting for different source types
 //! to enable intelligent change detection and incremental updates.
 
+use crate::fetch::{CRDFetcher, ConditionalFetch, FetchedDocument};
+use crate::git::GitCheckout;
+use crate::helm::HelmChart;
+use crate::k8s_types::K8sTypesFetcher;
+use crate::oci::{OciArtifact, OciClient, OciReference};
+use crate::olm::OlmBundle;
 use amalgam_core::fingerprint::{
-    ContentFingerprint, FingerprintBuilder, Fingerprintable, SourceInfo,
+    ContentFingerprint, FingerprintBuilder, Fingerprintable, PublishedArtifact, SourceInfo,
 };
+use chrono::{DateTime, Utc};
+use futures::{StreamExt, TryStreamExt};
+use std::collections::HashMap;
 use std::path::Path;
 
 /// URL-based source fingerprinting (GitHub, GitLab, etc.)
@@ -13,6 +22,30 @@ pub struct UrlSource {
     pub base_url: String,
     pub urls: Vec<String>,
     pub contents: Vec<String>,
+    /// ETag per URL, reused for conditional requests next time
+    pub etags: Vec<Option<String>>,
+    /// Last-Modified per URL, reused for conditional requests next time
+    pub last_modified: Vec<Option<DateTime<Utc>>>,
+}
+
+impl UrlSource {
+    /// Build a source from downloaded documents
+    pub fn from_documents(base_url: impl Into<String>, documents: Vec<FetchedDocument>) -> Self {
+        let mut source = Self {
+            base_url: base_url.into(),
+            urls: Vec::new(),
+            contents: Vec::new(),
+            etags: Vec::new(),
+            last_modified: Vec::new(),
+        };
+        for document in documents {
+            source.urls.push(document.url);
+            source.contents.push(document.content);
+            source.etags.push(document.etag);
+            source.last_modified.push(document.last_modified);
+        }
+        source
+    }
 }
 
 impl Fingerprintable for UrlSource {
@@ -28,12 +61,15 @@ impl Fingerprintable for UrlSource {
         builder.add_metadata("base_url", &self.base_url);
         builder.add_metadata("url_count", &self.urls.len().to_string());
 
-        // TODO: Add ETags and Last-Modified headers when available
         let source_info = SourceInfo::UrlCollection {
             base_url: self.base_url.clone(),
             urls: self.urls.clone(),
-            etags: vec![None; self.urls.len()], // Will be populated from HTTP headers
-            last_modified: vec![None; self.urls.len()],
+            etags: (0..self.urls.len())
+                .map(|i| self.etags.get(i).cloned().flatten())
+                .collect(),
+            last_modified: (0..self.urls.len())
+                .map(|i| self.last_modified.get(i).copied().flatten())
+                .collect(),
         };
 
         builder.with_source_info(source_info);
@@ -87,6 +123,8 @@ pub struct K8sCoreSource {
     pub version: String,
     pub openapi_spec: String,
     pub spec_url: String,
+    pub etag: Option<String>,
+    pub last_modified: Option<DateTime<Utc>>,
 }
 
 impl Fingerprintable for K8sCoreSource {
@@ -110,6 +148,8 @@ impl Fingerprintable for K8sCoreSource {
             version: self.version.clone(),
             openapi_hash,
             spec_url: self.spec_url.clone(),
+            etag: self.etag.clone(),
+            last_modified: self.last_modified,
         };
 
         builder.with_source_info(source_info);
@@ -175,6 +215,19 @@ pub struct GitRepoSource {
     pub contents: Vec<String>,
 }
 
+impl GitRepoSource {
+    /// Build a source from the files read at a checked-out commit
+    pub fn from_checkout(checkout: &GitCheckout) -> Self {
+        let (paths, contents) = checkout.files.iter().cloned().unzip();
+        Self {
+            url: checkout.url.clone(),
+            commit: checkout.commit.clone(),
+            paths,
+            contents,
+        }
+    }
+}
+
 impl Fingerprintable for GitRepoSource {
     fn create_fingerprint(&self) -> Result<ContentFingerprint, Box<dyn std::error::Error>> {
         let mut builder = FingerprintBuilder::new();
@@ -201,6 +254,380 @@ impl Fingerprintable for GitRepoSource {
     }
 }
 
+/// Helm chart fingerprinting
+pub struct HelmChartSource {
+    /// Chart directory or archive path
+    pub chart: String,
+    pub name: String,
+    pub version: String,
+    pub app_version: Option<String>,
+    /// Source YAML of the CRDs extracted from the chart
+    pub contents: Vec<String>,
+}
+
+impl HelmChartSource {
+    /// Build a source from a loaded chart
+    pub fn from_chart(chart: impl Into<String>, helm_chart: &HelmChart) -> Self {
+        Self {
+            chart: chart.into(),
+            name: helm_chart.name.clone(),
+            version: helm_chart.version.clone(),
+            app_version: helm_chart.app_version.clone(),
+            contents: helm_chart.documents.clone(),
+        }
+    }
+}
+
+impl Fingerprintable for HelmChartSource {
+    fn create_fingerprint(&self) -> Result<ContentFingerprint, Box<dyn std::error::Error>> {
+        let mut builder = FingerprintBuilder::new();
+
+        // Only the CRDs affect generation, not the rest of the chart
+        for content in &self.contents {
+            builder.add_content_str(content);
+        }
+
+        builder.add_metadata("chart", &self.chart);
+        builder.add_metadata("chart_version", &self.version);
+
+        let source_info = SourceInfo::HelmChart {
+            chart: self.chart.clone(),
+            name: self.name.clone(),
+            version: self.version.clone(),
+            app_version: self.app_version.clone(),
+        };
+
+        builder.with_source_info(source_info);
+        Ok(builder.build())
+    }
+}
+
+/// OLM bundle fingerprinting
+pub struct OlmBundleSource {
+    /// Bundle directory path
+    pub bundle: String,
+    pub csv: String,
+    pub version: Option<String>,
+    /// Source YAML of the CSV and the owned CRDs
+    pub contents: Vec<String>,
+}
+
+impl OlmBundleSource {
+    /// Build a source from a loaded bundle
+    pub fn from_bundle(bundle: impl Into<String>, olm_bundle: &OlmBundle) -> Self {
+        Self {
+            bundle: bundle.into(),
+            csv: olm_bundle.name.clone(),
+            version: olm_bundle.version.clone(),
+            contents: olm_bundle.documents.clone(),
+        }
+    }
+}
+
+impl Fingerprintable for OlmBundleSource {
+    fn create_fingerprint(&self) -> Result<ContentFingerprint, Box<dyn std::error::Error>> {
+        let mut builder = FingerprintBuilder::new();
+
+        // The CSV is content: its descriptors and examples change the output
+        for content in &self.contents {
+            builder.add_content_str(content);
+        }
+
+        builder.add_metadata("bundle", &self.bundle);
+
+        let source_info = SourceInfo::OlmBundle {
+            bundle: self.bundle.clone(),
+            csv: self.csv.clone(),
+            version: self.version.clone(),
+        };
+
+        builder.with_source_info(source_info);
+        Ok(builder.build())
+    }
+}
+
+/// OCI image or artifact fingerprinting
+pub struct OciArtifactSource {
+    pub reference: String,
+    pub manifest_digest: String,
+    pub layer_digests: Vec<String>,
+    /// Globs the files were selected with
+    pub paths: Vec<String>,
+    /// Selected YAML files
+    pub contents: Vec<String>,
+}
+
+impl OciArtifactSource {
+    /// Build a source from a pulled artifact whose files were selected with `paths`
+    pub fn from_artifact(artifact: &OciArtifact, paths: &[String]) -> Self {
+        Self {
+            reference: artifact.reference.clone(),
+            manifest_digest: artifact.manifest_digest.clone(),
+            layer_digests: artifact.layer_digests.clone(),
+            paths: paths.to_vec(),
+            contents: artifact
+                .files
+                .iter()
+                .map(|(_, content)| content.clone())
+                .collect(),
+        }
+    }
+}
+
+impl Fingerprintable for OciArtifactSource {
+    fn create_fingerprint(&self) -> Result<ContentFingerprint, Box<dyn std::error::Error>> {
+        let mut builder = FingerprintBuilder::new();
+
+        for content in &self.contents {
+            builder.add_content_str(content);
+        }
+
+        // A retag to identical content only changes metadata
+        builder.add_metadata("oci_reference", &self.reference);
+        builder.add_metadata("oci_manifest_digest", &self.manifest_digest);
+
+        let source_info = SourceInfo::OciArtifact {
+            reference: self.reference.clone(),
+            manifest_digest: self.manifest_digest.clone(),
+            layer_digests: self.layer_digests.clone(),
+            paths: self.paths.clone(),
+        };
+
+        builder.with_source_info(source_info);
+        Ok(builder.build())
+    }
+}
+
+/// A source confirmed unchanged since its recorded fingerprint
+///
+/// Produced when every document of a URL source answered `304 Not Modified`,
+/// or when an OCI reference still resolves to the recorded manifest digest;
+/// it reproduces the previous fingerprint without downloading any content.
+pub struct UnchangedSource {
+    pub fingerprint: ContentFingerprint,
+}
+
+impl Fingerprintable for UnchangedSource {
+    fn create_fingerprint(&self) -> Result<ContentFingerprint, Box<dyn std::error::Error>> {
+        Ok(self.fingerprint.clone())
+    }
+}
+
+/// A source together with the settings that shape what is generated from it
+///
+/// The settings are folded into the content hash, so changing them triggers
+/// regeneration even when the source is unchanged.
+pub struct ConfiguredSource {
+    pub source: Box<dyn Fingerprintable>,
+    pub settings: String,
+}
+
+impl Fingerprintable for ConfiguredSource {
+    fn create_fingerprint(&self) -> Result<ContentFingerprint, Box<dyn std::error::Error>> {
+        Ok(self
+            .source
+            .create_fingerprint()?
+            .with_settings(&self.settings))
+    }
+}
+
+/// A source whose generated package was pushed to a registry
+///
+/// Records where the package went in the fingerprint.
+pub struct PublishedSource {
+    pub source: Box<dyn Fingerprintable>,
+    pub published: Option<PublishedArtifact>,
+}
+
+impl Fingerprintable for PublishedSource {
+    fn create_fingerprint(&self) -> Result<ContentFingerprint, Box<dyn std::error::Error>> {
+        let mut fingerprint = self.source.create_fingerprint()?;
+        fingerprint.published = self.published.clone();
+        Ok(fingerprint)
+    }
+}
+
+/// Number of documents fetched concurrently while fingerprinting
+const MAX_CONCURRENT_FETCHES: usize = 5;
+
+/// Fingerprint a URL source from the content it currently serves
+///
+/// Documents are requested conditionally with the ETag/Last-Modified values
+/// recorded in `previous`. If the document list is the same and every
+/// document answers `304 Not Modified`, the previous fingerprint is reused;
+/// otherwise the full content of every document is hashed.
+pub async fn fetch_url_source(
+    fetcher: &CRDFetcher,
+    url: &str,
+    base_url: &str,
+    previous: Option<&ContentFingerprint>,
+) -> anyhow::Result<Box<dyn Fingerprintable>> {
+    let mut urls = fetcher.document_urls(url).await?;
+    urls.sort();
+
+    // Validators recorded by the previous run, keyed by URL
+    let mut validators = HashMap::new();
+    let mut same_documents = false;
+    if let Some(SourceInfo::UrlCollection {
+        urls: previous_urls,
+        etags,
+        last_modified,
+        ..
+    }) = previous.map(|p| &p.source_info)
+    {
+        for (i, previous_url) in previous_urls.iter().enumerate() {
+            let etag = etags.get(i).cloned().flatten();
+            let modified = last_modified.get(i).copied().flatten();
+            validators.insert(previous_url.clone(), (etag, modified));
+        }
+        let mut previous_urls = previous_urls.clone();
+        previous_urls.sort();
+        same_documents = previous_urls == urls;
+    }
+
+    let responses: Vec<ConditionalFetch> = futures::stream::iter(urls.iter())
+        .map(|document_url| {
+            let (etag, modified) = validators
+                .get(document_url)
+                .cloned()
+                .unwrap_or((None, None));
+            async move {
+                fetcher
+                    .fetch_if_modified(document_url, etag.as_deref(), modified)
+                    .await
+            }
+        })
+        .buffered(MAX_CONCURRENT_FETCHES)
+        .try_collect()
+        .await?;
+
+    let all_not_modified = responses
+        .iter()
+        .all(|r| matches!(r, ConditionalFetch::NotModified));
+    if let (true, true, Some(previous)) = (same_documents, all_not_modified, previous) {
+        return Ok(Box::new(UnchangedSource {
+            fingerprint: previous.clone(),
+        }));
+    }
+
+    // Something changed: hash the full content, downloading again anything
+    // the server only confirmed as unchanged
+    let mut documents = Vec::new();
+    for (document_url, response) in urls.iter().zip(responses) {
+        let document = match response {
+            ConditionalFetch::Modified(document) => document,
+            ConditionalFetch::NotModified => {
+                match fetcher.fetch_if_modified(document_url, None, None).await? {
+                    ConditionalFetch::Modified(document) => document,
+                    ConditionalFetch::NotModified => anyhow::bail!(
+                        "Server answered 304 to an unconditional request for {}",
+                        document_url
+                    ),
+                }
+            }
+        };
+        documents.push(document);
+    }
+
+    Ok(Box::new(UrlSource::from_documents(base_url, documents)))
+}
+
+/// Fingerprint an OCI artifact, pulling its layers only when needed
+///
+/// If the reference still resolves to the manifest digest recorded in
+/// `previous` with the same path globs, the previous fingerprint is reused
+/// without downloading any layer.
+pub async fn fetch_oci_source(
+    client: &OciClient,
+    reference: &OciReference,
+    paths: &[String],
+    previous: Option<&ContentFingerprint>,
+) -> anyhow::Result<Box<dyn Fingerprintable>> {
+    if let Some(previous) = previous {
+        if let SourceInfo::OciArtifact {
+            reference: previous_reference,
+            manifest_digest,
+            paths: previous_paths,
+            ..
+        } = &previous.source_info
+        {
+            if *previous_reference == reference.to_string()
+                && previous_paths.as_slice() == paths
+                && *manifest_digest == client.resolve(reference).await?
+            {
+                return Ok(Box::new(UnchangedSource {
+                    fingerprint: previous.clone(),
+                }));
+            }
+        }
+    }
+
+    let mut artifact = client.pull(reference).await?;
+    artifact.retain_paths(paths);
+    Ok(Box::new(OciArtifactSource::from_artifact(&artifact, paths)))
+}
+
+/// Result of [`fetch_k8s_core_source`]
+pub enum K8sCoreFetch {
+    /// The spec answered `304 Not Modified`, so the previous fingerprint stands
+    Unchanged(UnchangedSource),
+    /// The spec was downloaded; generation reuses its content
+    Fetched(K8sCoreSource),
+}
+
+/// Fingerprint the Kubernetes core types from the OpenAPI spec they are
+/// generated from
+///
+/// The spec is requested conditionally with the ETag/Last-Modified values
+/// recorded in `previous` for the same URL, like [`fetch_url_source`].
+pub async fn fetch_k8s_core_source(
+    fetcher: &CRDFetcher,
+    version: &str,
+    previous: Option<&ContentFingerprint>,
+) -> anyhow::Result<K8sCoreFetch> {
+    let spec_url = K8sTypesFetcher::openapi_spec_url(version);
+    fetch_k8s_core_spec(fetcher, version, spec_url, previous).await
+}
+
+async fn fetch_k8s_core_spec(
+    fetcher: &CRDFetcher,
+    version: &str,
+    spec_url: String,
+    previous: Option<&ContentFingerprint>,
+) -> anyhow::Result<K8sCoreFetch> {
+    let (etag, modified) = match previous.map(|p| &p.source_info) {
+        Some(SourceInfo::K8sCore {
+            spec_url: previous_url,
+            etag,
+            last_modified,
+            ..
+        }) if *previous_url == spec_url => (etag.clone(), *last_modified),
+        _ => (None, None),
+    };
+
+    match fetcher
+        .fetch_if_modified(&spec_url, etag.as_deref(), modified)
+        .await?
+    {
+        ConditionalFetch::Modified(document) => Ok(K8sCoreFetch::Fetched(K8sCoreSource {
+            version: version.to_string(),
+            openapi_spec: document.content,
+            spec_url,
+            etag: document.etag,
+            last_modified: document.last_modified,
+        })),
+        ConditionalFetch::NotModified => match previous {
+            Some(previous) => Ok(K8sCoreFetch::Unchanged(UnchangedSource {
+                fingerprint: previous.clone(),
+            })),
+            None => anyhow::bail!(
+                "Server answered 304 to an unconditional request for {}",
+                spec_url
+            ),
+        },
+    }
+}
+
 /// High-level function to check if a package needs regeneration
 pub fn needs_regeneration(
     output_dir: &Path,
@@ -280,6 +707,8 @@ mod tests {
             base_url: "https://example.com".to_string(),
             urls: vec!["file1.yaml".to_string(), "file2.yaml".to_string()],
             contents: vec!["content1".to_string(), "content2".to_string()],
+            etags: Vec::new(),
+            last_modified: Vec::new(),
         };
 
         let fingerprint = source.create_fingerprint().unwrap();
@@ -291,6 +720,8 @@ mod tests {
             base_url: "https://example.com".to_string(),
             urls: vec!["file1.yaml".to_string(), "file2.yaml".to_string()],
             contents: vec!["content1".to_string(), "content2".to_string()],
+            etags: Vec::new(),
+            last_modified: Vec::new(),
         };
         let fingerprint2 = source2.create_fingerprint().unwrap();
         assert!(fingerprint.content_matches(&fingerprint2));
@@ -305,6 +736,8 @@ mod tests {
             base_url: "https://example.com".to_string(),
             urls: vec!["file1.yaml".to_string()],
             contents: vec!["content1".to_string()],
+            etags: Vec::new(),
+            last_modified: Vec::new(),
         };
 
         // First time should need regeneration
@@ -321,10 +754,112 @@ mod tests {
             base_url: "https://example.com".to_string(),
             urls: vec!["file1.yaml".to_string()],
             contents: vec!["different_content".to_string()],
+            etags: Vec::new(),
+            last_modified: Vec::new(),
         };
         assert!(needs_regeneration(output_dir, &changed_source).unwrap());
     }
 
+    #[test]
+    fn test_configured_source_settings_trigger_regeneration() {
+        let temp_dir = TempDir::new().unwrap();
+        let output_dir = temp_dir.path();
+
+        let configured = |settings: &str| ConfiguredSource {
+            source: Box::new(LocalFilesSource {
+                paths: vec!["crd.yaml".to_string()],
+                contents: vec!["content".to_string()],
+            }),
+            settings: settings.to_string(),
+        };
+
+        save_fingerprint(output_dir, &configured("latest_only")).unwrap();
+        assert!(matches!(
+            detect_change_type(output_dir, &configured("latest_only")).unwrap(),
+            ChangeType::NoChange
+        ));
+        assert!(matches!(
+            detect_change_type(output_dir, &configured("storage_only")).unwrap(),
+            ChangeType::ContentChanged
+        ));
+    }
+
+    #[test]
+    fn test_helm_chart_version_is_metadata() {
+        let temp_dir = TempDir::new().unwrap();
+        let output_dir = temp_dir.path();
+
+        let chart = |version: &str, crd: &str| HelmChartSource {
+            chart: "charts/widgets".to_string(),
+            name: "widgets".to_string(),
+            version: version.to_string(),
+            app_version: None,
+            contents: vec![crd.to_string()],
+        };
+
+        save_fingerprint(output_dir, &chart("1.0.0", "crd")).unwrap();
+        let saved =
+            ContentFingerprint::load_from_file(&ContentFingerprint::fingerprint_path(output_dir))
+                .unwrap();
+        assert!(matches!(
+            saved.source_info,
+            SourceInfo::HelmChart { ref version, .. } if version == "1.0.0"
+        ));
+
+        assert!(matches!(
+            detect_change_type(output_dir, &chart("1.0.1", "crd")).unwrap(),
+            ChangeType::MetadataOnly
+        ));
+        assert!(matches!(
+            detect_change_type(output_dir, &chart("1.1.0", "changed crd")).unwrap(),
+            ChangeType::ContentChanged
+        ));
+    }
+
+    #[test]
+    fn test_oci_retag_is_metadata_and_push_is_recorded() {
+        let temp_dir = TempDir::new().unwrap();
+        let output_dir = temp_dir.path();
+
+        let artifact = |tag: &str, digest: &str, crd: &str| OciArtifactSource {
+            reference: format!("ghcr.io/org/widgets:{}", tag),
+            manifest_digest: digest.to_string(),
+            layer_digests: vec!["sha256:layer".to_string()],
+            paths: Vec::new(),
+            contents: vec![crd.to_string()],
+        };
+        let published = PublishedArtifact {
+            reference: "ghcr.io/org/widgets-nickel:v1".to_string(),
+            digest: "sha256:pushed".to_string(),
+        };
+
+        save_fingerprint(
+            output_dir,
+            &PublishedSource {
+                source: Box::new(artifact("v1", "sha256:a", "crd")),
+                published: Some(published.clone()),
+            },
+        )
+        .unwrap();
+        let saved =
+            ContentFingerprint::load_from_file(&ContentFingerprint::fingerprint_path(output_dir))
+                .unwrap();
+        assert_eq!(saved.published, Some(published));
+
+        assert!(matches!(
+            detect_change_type(output_dir, &artifact("v1", "sha256:a", "crd")).unwrap(),
+            ChangeType::NoChange
+        ));
+        assert!(matches!(
+            detect_change_type(output_dir, &artifact("v1.0", "sha256:b", "crd")).unwrap(),
+            ChangeType::MetadataOnly
+        ));
+        assert!(matches!(
+            detect_change_type(output_dir, &artifact("v2", "sha256:c", "changed crd")).unwrap(),
+            ChangeType::ContentChanged
+        ));
+    }
+
     #[test]
     fn test_change_type_detection() {
         let temp_dir = TempDir::new().unwrap();
@@ -334,6 +869,8 @@ mod tests {
             base_url: "https://example.com".to_string(),
             urls: vec!["file1.yaml".to_string()],
             contents: vec!["content1".to_string()],
+            etags: Vec::new(),
+            last_modified: Vec::new(),
         };
 
         // First generation
@@ -356,10 +893,162 @@ mod tests {
             base_url: "https://example.com".to_string(),
             urls: vec!["file1.yaml".to_string()],
             contents: vec!["different_content".to_string()],
+            etags: Vec::new(),
+            last_modified: Vec::new(),
         };
         match detect_change_type(output_dir, &changed_source).unwrap() {
             ChangeType::ContentChanged => {}
             other => panic!("Expected ContentChanged, got {:?}", other),
         }
     }
+
+    #[tokio::test]
+    async fn test_url_source_conditional_fetch() {
+        use wiremock::{
+            matchers::{header, method, path},
+            Mock, MockServer, ResponseTemplate,
+        };
+
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/crd.yaml"))
+            .and(header("If-None-Match", "\"v1\""))
+            .respond_with(ResponseTemplate::new(304))
+            .with_priority(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/crd.yaml"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("ETag", "\"v1\"")
+                    .set_body_string("kind: CustomResourceDefinition"),
+            )
+            .mount(&mock_server)
+            .await;
+
+        let temp_dir = TempDir::new().unwrap();
+        let output_dir = temp_dir.path();
+        let fetcher = CRDFetcher::new().unwrap();
+        let url = format!("{}/crd.yaml", mock_server.uri());
+
+        // First run downloads the content and records its ETag
+        let source = fetch_url_source(&fetcher, &url, &url, None).await.unwrap();
+        let fingerprint = source.create_fingerprint().unwrap();
+        match &fingerprint.source_info {
+            SourceInfo::UrlCollection { etags, .. } => {
+                assert_eq!(etags, &vec![Some("\"v1\"".to_string())]);
+            }
+            other => panic!("Expected UrlCollection, got {:?}", other),
+        }
+        save_fingerprint(output_dir, source.as_ref()).unwrap();
+
+        // Second run is answered with 304 and reuses the fingerprint
+        let previous =
+            ContentFingerprint::load_from_file(&ContentFingerprint::fingerprint_path(output_dir))
+                .unwrap();
+        let source = fetch_url_source(&fetcher, &url, &url, Some(&previous))
+            .await
+            .unwrap();
+        match detect_change_type(output_dir, source.as_ref()).unwrap() {
+            ChangeType::NoChange => {}
+            other => panic!("Expected NoChange, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_k8s_core_source_conditional_fetch() {
+        use wiremock::{
+            matchers::{header, method, path},
+            Mock, MockServer, ResponseTemplate,
+        };
+
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/swagger.json"))
+            .and(header("If-None-Match", "\"spec\""))
+            .respond_with(ResponseTemplate::new(304))
+            .with_priority(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/swagger.json"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("ETag", "\"spec\"")
+                    .set_body_string("{\"definitions\": {}}"),
+            )
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let temp_dir = TempDir::new().unwrap();
+        let output_dir = temp_dir.path();
+        let fetcher = CRDFetcher::new().unwrap();
+        let spec_url = format!("{}/swagger.json", mock_server.uri());
+
+        // First run downloads the spec, which generation reuses
+        let K8sCoreFetch::Fetched(source) =
+            fetch_k8s_core_spec(&fetcher, "v1.31.0", spec_url.clone(), None)
+                .await
+                .unwrap()
+        else {
+            panic!("Expected the spec to be downloaded");
+        };
+        assert_eq!(source.openapi_spec, "{\"definitions\": {}}");
+        assert_eq!(source.etag.as_deref(), Some("\"spec\""));
+        save_fingerprint(output_dir, &source).unwrap();
+
+        // Second run is answered with 304 and reuses the fingerprint
+        let previous =
+            ContentFingerprint::load_from_file(&ContentFingerprint::fingerprint_path(output_dir))
+                .unwrap();
+        let K8sCoreFetch::Unchanged(source) =
+            fetch_k8s_core_spec(&fetcher, "v1.31.0", spec_url, Some(&previous))
+                .await
+                .unwrap()
+        else {
+            panic!("Expected the spec to be confirmed unchanged");
+        };
+        match detect_change_type(output_dir, &source).unwrap() {
+            ChangeType::NoChange => {}
+            other => panic!("Expected NoChange, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_url_source_detects_upstream_change() {
+        use wiremock::{
+            matchers::{method, path},
+            Mock, MockServer, ResponseTemplate,
+        };
+
+        let temp_dir = TempDir::new().unwrap();
+        let output_dir = temp_dir.path();
+        let fetcher = CRDFetcher::new().unwrap();
+
+        let mock_server = MockServer::start().await;
+        let url = format!("{}/crd.yaml", mock_server.uri());
+        Mock::given(method("GET"))
+            .and(path("/crd.yaml"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("spec: old"))
+            .up_to_n_times(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/crd.yaml"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("spec: new"))
+            .mount(&mock_server)
+            .await;
+
+        let source = fetch_url_source(&fetcher, &url, &url, None).await.unwrap();
+        save_fingerprint(output_dir, source.as_ref()).unwrap();
+
+        // Same URL, different content
+        let source = fetch_url_source(&fetcher, &url, &url, None).await.unwrap();
+        match detect_change_type(output_dir, source.as_ref()).unwrap() {
+            ChangeType::ContentChanged => {}
+            other => panic!("Expected ContentChanged, got {:?}", other),
+        }
+    }
 }
@@ -0,0 +1,260 @@
+//! Input variants of Kubernetes resource types
+//!
+//! A generated kind describes what the API server returns, including
+//! `status` and server-populated metadata. The input variant describes what
+//! a user may write: the same record with `status`, read-only fields, and
+//! server-populated metadata removed, so Nickel rejects them at authoring time.
+
+use amalgam_core::{
+    ir::TypeDefinition,
+    types::{Field, Type},
+};
+use std::collections::BTreeMap;
+
+/// Suffix appended to a kind's name for its input variant
+pub const INPUT_SUFFIX: &str = "Input";
+
+/// `ObjectMeta` fields populated by the API server
+pub const READ_ONLY_METADATA_FIELDS: &[&str] = &[
+    "creationTimestamp",
+    "deletionGracePeriodSeconds",
+    "deletionTimestamp",
+    "generation",
+    "managedFields",
+    "resourceVersion",
+    "selfLink",
+    "uid",
+];
+
+/// Build the input variant of a resource type definition
+///
+/// Returns `None` for types that are not resources, i.e. records without a
+/// `metadata` or `status` field.
+pub fn input_definition(type_def: &TypeDefinition) -> Option<TypeDefinition> {
+    let Type::Record { fields, open } = &type_def.ty else {
+        return None;
+    };
+    if !fields.contains_key("metadata") && !fields.contains_key("status") {
+        return None;
+    }
+
+    let mut input_fields = BTreeMap::new();
+    for (name, field) in fields {
+        if name == "status" || field.read_only {
+            continue;
+        }
+        let mut field = field.clone();
+        field.ty = if name == "metadata" {
+            input_metadata(&field.ty)
+        } else {
+            strip_read_only(&field.ty)
+        };
+        input_fields.insert(name.clone(), field);
+    }
+
+    Some(TypeDefinition {
+        name: format!("{}{}", type_def.name, INPUT_SUFFIX),
+        ty: Type::Record {
+            fields: input_fields,
+            open: *open,
+        },
+        documentation: Some(format!(
+            "Fields of {} a user may set: no status or server-populated metadata",
+            type_def.name
+        )),
+        annotations: type_def.annotations.clone(),
+        provenance: type_def.provenance.clone(),
+    })
+}
+
+/// The `apiVersion` of a resource kind that declares the `apiVersion`,
+/// `kind` and `metadata` fields that manifests of it set
+///
+/// Returns `None` for types the parser did not record an `apiVersion` for,
+/// such as nested types extracted from a kind.
+pub fn resource_api_version(type_def: &TypeDefinition) -> Option<&str> {
+    let Type::Record { fields, .. } = &type_def.ty else {
+        return None;
+    };
+    ["apiVersion", "kind", "metadata"]
+        .iter()
+        .all(|name| fields.contains_key(*name))
+        .then(|| type_def.api_version())
+        .flatten()
+}
+
+/// Remove fields marked `readOnly` at any depth
+fn strip_read_only(ty: &Type) -> Type {
+    match ty {
+        Type::Record { fields, open } => Type::Record {
+            fields: fields
+                .iter()
+                .filter(|(_, field)| !field.read_only)
+                .map(|(name, field)| {
+                    let mut field = field.clone();
+                    field.ty = strip_read_only(&field.ty);
+                    (name.clone(), field)
+                })
+                .collect(),
+            open: *open,
+        },
+        Type::Array(inner) => Type::Array(Box::new(strip_read_only(inner))),
+        Type::Optional(inner) => Type::Optional(Box::new(strip_read_only(inner))),
+        Type::Map { key, value } => Type::Map {
+            key: key.clone(),
+            value: Box::new(strip_read_only(value)),
+        },
+        other => other.clone(),
+    }
+}
+
+/// Metadata a user may set
+///
+/// Inline metadata records keep their own fields minus the server-populated
+/// ones; a reference to `ObjectMeta` is replaced with its user-settable subset.
+fn input_metadata(ty: &Type) -> Type {
+    match ty {
+        Type::Record { fields, open } if !fields.is_empty() => Type::Record {
+            fields: fields
+                .iter()
+                .filter(|(name, _)| !READ_ONLY_METADATA_FIELDS.contains(&name.as_str()))
+                .map(|(name, field)| (name.clone(), field.clone()))
+                .collect(),
+            open: *open,
+        },
+        _ => object_meta_input(),
+    }
+}
+
+/// User-settable subset of `io.k8s.apimachinery.pkg.apis.meta.v1.ObjectMeta`
+fn object_meta_input() -> Type {
+    let string_map = || Type::Map {
+        key: Box::new(Type::String),
+        value: Box::new(Type::String),
+    };
+    let owner_reference = Type::Record {
+        fields: BTreeMap::from([
+            ("apiVersion".to_string(), field(Type::String, true)),
+            ("blockOwnerDeletion".to_string(), field(Type::Bool, false)),
+            ("controller".to_string(), field(Type::Bool, false)),
+            ("kind".to_string(), field(Type::String, true)),
+            ("name".to_string(), field(Type::String, true)),
+            ("uid".to_string(), field(Type::String, true)),
+        ]),
+        open: false,
+    };
+
+    Type::Record {
+        fields: BTreeMap::from([
+            ("annotations".to_string(), field(string_map(), false)),
+            (
+                "finalizers".to_string(),
+                field(Type::Array(Box::new(Type::String)), false),
+            ),
+            ("generateName".to_string(), field(Type::String, false)),
+            ("labels".to_string(), field(string_map(), false)),
+            ("name".to_string(), field(Type::String, false)),
+            ("namespace".to_string(), field(Type::String, false)),
+            (
+                "ownerReferences".to_string(),
+                field(Type::Array(Box::new(owner_reference)), false),
+            ),
+        ]),
+        open: false,
+    }
+}
+
+fn field(ty: Type, required: bool) -> Field {
+    Field {
+        ty,
+        required,
+        description: None,
+        default: None,
+        read_only: false,
+        provenance: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resource() -> TypeDefinition {
+        TypeDefinition {
+            name: "Widget".to_string(),
+            ty: Type::Record {
+                fields: BTreeMap::from([
+                    (
+                        "metadata".to_string(),
+                        field(
+                            Type::Reference(
+                                "io.k8s.apimachinery.pkg.apis.meta.v1.ObjectMeta".to_string(),
+                            ),
+                            false,
+                        ),
+                    ),
+                    (
+                        "spec".to_string(),
+                        field(
+                            Type::Record {
+                                fields: BTreeMap::from([
+                                    ("size".to_string(), field(Type::Integer, false)),
+                                    (
+                                        "observedHash".to_string(),
+                                        Field {
+                                            read_only: true,
+                                            ..field(Type::String, false)
+                                        },
+                                    ),
+                                ]),
+                                open: false,
+                            },
+                            false,
+                        ),
+                    ),
+                    ("status".to_string(), field(Type::Any, false)),
+                ]),
+                open: false,
+            },
+            documentation: None,
+            annotations: BTreeMap::new(),
+            provenance: None,
+        }
+    }
+
+    #[test]
+    fn test_input_definition_strips_server_fields() {
+        let input = input_definition(&resource()).unwrap();
+        assert_eq!(input.name, "WidgetInput");
+
+        let Type::Record { fields, .. } = &input.ty else {
+            panic!("input should be a record");
+        };
+        assert!(!fields.contains_key("status"));
+
+        let Type::Record { fields: meta, .. } = &fields["metadata"].ty else {
+            panic!("metadata should be inlined");
+        };
+        assert!(meta.contains_key("labels"));
+        assert!(!meta.contains_key("uid"));
+        assert!(!meta.contains_key("managedFields"));
+
+        let Type::Record { fields: spec, .. } = &fields["spec"].ty else {
+            panic!("spec should be a record");
+        };
+        assert!(spec.contains_key("size"));
+        assert!(!spec.contains_key("observedHash"));
+    }
+
+    #[test]
+    fn test_non_resource_has_no_input() {
+        let type_def = TypeDefinition {
+            name: "Port".to_string(),
+            ty: Type::Integer,
+            documentation: None,
+            annotations: BTreeMap::new(),
+            provenance: None,
+        };
+        assert!(input_definition(&type_def).is_none());
+    }
+}
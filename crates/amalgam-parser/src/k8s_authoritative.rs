@@ -32,7 +32,7 @@ impl K8sAuthoritativeTypes {
 
     /// Initialize with authoritative Kubernetes types from Go source
     pub async fn initialize(&mut self) -> Result<(), ParserError> {
-        let mut parser = GoASTParser::new();
+        let mut parser = GoASTParser::new()?;
 
         // Parse core Kubernetes types
         let k8s_types = parser.parse_k8s_core_types().await?;
@@ -120,14 +120,14 @@ impl K8sAuthoritativeTypes {
         &self,
         go_type: &GoTypeInfo,
     ) -> Result<TypeDefinition, ParserError> {
-        let parser = GoASTParser::new();
-        let nickel_type = parser.go_type_to_nickel(go_type)?;
+        let nickel_type = GoASTParser::go_type_to_nickel(go_type)?;
 
         Ok(TypeDefinition {
             name: go_type.name.clone(),
             ty: nickel_type,
             documentation: go_type.documentation.clone(),
             annotations: BTreeMap::new(),
+            provenance: None,
         })
     }
 
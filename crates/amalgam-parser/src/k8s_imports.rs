@@ -1,44 +1,14 @@
 //! Special handling for k8s.io internal imports
 
-use amalgam_core::types::Type;
+use amalgam_core::{types::Type, visit::references};
 use std::collections::HashSet;
 
 /// Analyze a type to find k8s.io type references that need imports
 pub fn find_k8s_type_references(ty: &Type) -> HashSet<String> {
-    let mut refs = HashSet::new();
-    collect_references(ty, &mut refs);
-    refs
-}
-
-fn collect_references(ty: &Type, refs: &mut HashSet<String>) {
-    match ty {
-        Type::Reference(name) => {
-            // Check if this is a k8s type that needs importing
-            if is_k8s_type(name) {
-                refs.insert(name.clone());
-            }
-        }
-        Type::Array(inner) => collect_references(inner, refs),
-        Type::Optional(inner) => collect_references(inner, refs),
-        Type::Map { value, .. } => collect_references(value, refs),
-        Type::Record { fields, .. } => {
-            for field in fields.values() {
-                collect_references(&field.ty, refs);
-            }
-        }
-        Type::Union(types) => {
-            for t in types {
-                collect_references(t, refs);
-            }
-        }
-        Type::TaggedUnion { variants, .. } => {
-            for t in variants.values() {
-                collect_references(t, refs);
-            }
-        }
-        Type::Contract { base, .. } => collect_references(base, refs),
-        _ => {}
-    }
+    references(ty)
+        .into_iter()
+        .filter(|name| is_k8s_type(name))
+        .collect()
 }
 
 /// Check if a type name is a k8s.io type
@@ -1,44 +1,75 @@
 //! Kubernetes core types fetcher and generator
 
-use crate::{imports::TypeReference, ParserError};
+use crate::{
+    http::{HttpClient, HttpConfig},
+    imports::TypeReference,
+    ParserError,
+};
 use amalgam_core::{
-    ir::{Module, TypeDefinition},
+    ir::{Module, TypeDefinition, API_VERSION_ANNOTATION, IR},
     types::{Field, Type},
+    visit::rename_references,
+    Provenance,
 };
-use indicatif::{ProgressBar, ProgressStyle};
-use reqwest;
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
 use serde_json::Value;
 use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
 use std::time::Duration;
 
 /// Fetches and generates k8s.io core types
 pub struct K8sTypesFetcher {
-    client: reqwest::Client,
+    client: HttpClient,
+    multi_progress: Option<Arc<MultiProgress>>,
 }
 
+/// Uses the default HTTP configuration, without the configuration file or
+/// environment that [`K8sTypesFetcher::new`] reads
 impl Default for K8sTypesFetcher {
     fn default() -> Self {
-        Self::new()
+        Self {
+            client: HttpClient::new(HttpConfig::default())
+                .expect("the default HTTP configuration is valid"),
+            multi_progress: None,
+        }
     }
 }
 
 impl K8sTypesFetcher {
-    pub fn new() -> Self {
+    /// Fails when the HTTP settings of the environment are invalid
+    pub fn new() -> Result<Self, ParserError> {
+        Ok(Self {
+            client: HttpClient::from_env()?,
+            multi_progress: None,
+        })
+    }
+
+    /// Create a fetcher that reuses an HTTP client and adds its progress
+    /// bars to a shared display
+    pub fn with_client(client: HttpClient, multi_progress: Arc<MultiProgress>) -> Self {
         Self {
-            client: reqwest::Client::builder()
-                .timeout(Duration::from_secs(60))
-                .user_agent("amalgam")
-                .build()
-                .unwrap(),
+            client,
+            multi_progress: Some(multi_progress),
         }
     }
 
+    /// URL of the OpenAPI spec for a Kubernetes version
+    pub fn openapi_spec_url(version: &str) -> String {
+        format!(
+            "https://raw.githubusercontent.com/kubernetes/kubernetes/{}/api/openapi-spec/swagger.json",
+            version
+        )
+    }
+
     /// Fetch the Kubernetes OpenAPI schema
     pub async fn fetch_k8s_openapi(&self, version: &str) -> Result<Value, ParserError> {
         let is_tty = atty::is(atty::Stream::Stdout);
 
         let pb = if is_tty {
-            let pb = ProgressBar::new_spinner();
+            let pb = match &self.multi_progress {
+                Some(multi_progress) => multi_progress.add(ProgressBar::new_spinner()),
+                None => ProgressBar::new_spinner(),
+            };
             pb.set_style(
                 ProgressStyle::default_spinner()
                     .template("{spinner:.cyan} {msg}")
@@ -54,15 +85,11 @@ impl K8sTypesFetcher {
         };
 
         // We can use the official k8s OpenAPI spec
-        let url = format!(
-            "https://raw.githubusercontent.com/kubernetes/kubernetes/{}/api/openapi-spec/swagger.json",
-            version
-        );
+        let url = Self::openapi_spec_url(version);
 
         let response = self
             .client
-            .get(&url)
-            .send()
+            .send(self.client.get(&url))
             .await
             .map_err(|e| ParserError::Network(e.to_string()))?;
 
@@ -104,6 +131,12 @@ impl K8sTypesFetcher {
     ) -> Result<HashMap<TypeReference, TypeDefinition>, ParserError> {
         let mut types = HashMap::new();
         let mut processed = std::collections::HashSet::new();
+        // Definitions are traced back to the published spec they came from
+        let source = openapi
+            .pointer("/info/version")
+            .and_then(|v| v.as_str())
+            .map(Self::openapi_spec_url)
+            .unwrap_or_else(|| "kubernetes-openapi".to_string());
         let mut to_process = std::collections::VecDeque::new();
 
         // Seed types that will trigger recursive discovery
@@ -154,7 +187,10 @@ impl K8sTypesFetcher {
                     // Try to parse this as a k8s type reference
                     match self.parse_type_reference(&full_name) {
                         Ok(type_ref) => {
-                            match self.schema_to_type_definition(&short_name, schema) {
+                            let provenance = Provenance::new(source.as_str())
+                                .child("definitions")
+                                .child(&full_name);
+                            match self.schema_to_type_definition(&short_name, schema, provenance) {
                                 Ok(type_def) => {
                                     // Collect all type references from this type
                                     let mut refs = std::collections::HashSet::new();
@@ -232,8 +268,10 @@ impl K8sTypesFetcher {
             )));
         }
 
-        let group = if parts[3] == "core" || parts[2] == "apimachinery" {
-            "k8s.io".to_string() // core and apimachinery types are under k8s.io
+        let group = if full_name.starts_with("io.k8s.apimachinery.pkg.apis.meta.") {
+            "meta.k8s.io".to_string()
+        } else if parts[3] == "core" || parts[2] == "apimachinery" {
+            "k8s.io".to_string() // core and other apimachinery types are under k8s.io
         } else {
             format!("{}.k8s.io", parts[3])
         };
@@ -265,8 +303,28 @@ impl K8sTypesFetcher {
         &self,
         name: &str,
         schema: &Value,
+        provenance: Provenance,
     ) -> Result<TypeDefinition, ParserError> {
-        let ty = self.json_schema_to_type(schema)?;
+        let ty = self.json_schema_to_type(schema, &provenance)?;
+
+        // Kinds served under exactly one group and version record its
+        // apiVersion, `v1` for the core group
+        let mut annotations = BTreeMap::new();
+        if let Some([gvk]) = schema
+            .get("x-kubernetes-group-version-kind")
+            .and_then(|gvk| gvk.as_array())
+            .map(Vec::as_slice)
+        {
+            let group = gvk.get("group").and_then(|g| g.as_str()).unwrap_or("");
+            if let Some(version) = gvk.get("version").and_then(|v| v.as_str()) {
+                let api_version = if group.is_empty() {
+                    version.to_string()
+                } else {
+                    format!("{}/{}", group, version)
+                };
+                annotations.insert(API_VERSION_ANNOTATION.to_string(), api_version.into());
+            }
+        }
 
         Ok(TypeDefinition {
             name: name.to_string(),
@@ -275,12 +333,13 @@ impl K8sTypesFetcher {
                 .get("description")
                 .and_then(|d| d.as_str())
                 .map(String::from),
-            annotations: BTreeMap::new(),
+            annotations,
+            provenance: Some(provenance),
         })
     }
 
     #[allow(clippy::only_used_in_recursion)]
-    fn json_schema_to_type(&self, schema: &Value) -> Result<Type, ParserError> {
+    fn json_schema_to_type(&self, schema: &Value, at: &Provenance) -> Result<Type, ParserError> {
         // Check for top-level $ref first
         if let Some(ref_path) = schema.get("$ref").and_then(|r| r.as_str()) {
             let type_name = ref_path.trim_start_matches("#/definitions/");
@@ -303,6 +362,12 @@ impl K8sTypesFetcher {
             });
         }
 
+        if let Some(Value::Array(values)) = schema.get("enum") {
+            if !values.is_empty() {
+                return Ok(Type::Enum(values.clone()));
+            }
+        }
+
         let schema_type = schema.get("type").and_then(|v| v.as_str());
 
         match schema_type {
@@ -313,7 +378,7 @@ impl K8sTypesFetcher {
             Some("array") => {
                 let items = schema
                     .get("items")
-                    .map(|i| self.json_schema_to_type(i))
+                    .map(|i| self.json_schema_to_type(i, &at.child("items")))
                     .transpose()?
                     .unwrap_or(Type::Any);
                 Ok(Type::Array(Box::new(items)))
@@ -334,6 +399,7 @@ impl K8sTypesFetcher {
                         .unwrap_or_default();
 
                     for (field_name, field_schema) in props {
+                        let provenance = at.child("properties").child(field_name);
                         // Check for $ref
                         if let Some(ref_path) = field_schema.get("$ref").and_then(|r| r.as_str()) {
                             // Convert ref to type reference
@@ -380,6 +446,8 @@ impl K8sTypesFetcher {
                                         .and_then(|d| d.as_str())
                                         .map(String::from),
                                     default: None,
+                                    read_only: false,
+                                    provenance: Some(provenance),
                                 },
                             );
                         } else {
@@ -416,13 +484,15 @@ impl K8sTypesFetcher {
                                             required: required.contains(field_name),
                                             description: None,
                                             default: None,
+                                            read_only: false,
+                                            provenance: Some(provenance),
                                         },
                                     );
                                     continue;
                                 }
                             }
 
-                            let field_type = self.json_schema_to_type(field_schema)?;
+                            let field_type = self.json_schema_to_type(field_schema, &provenance)?;
                             fields.insert(
                                 field_name.clone(),
                                 Field {
@@ -433,6 +503,11 @@ impl K8sTypesFetcher {
                                         .and_then(|d| d.as_str())
                                         .map(String::from),
                                     default: field_schema.get("default").cloned(),
+                                    read_only: field_schema
+                                        .get("readOnly")
+                                        .and_then(|v| v.as_bool())
+                                        .unwrap_or(false),
+                                    provenance: Some(provenance),
                                 },
                             );
                         }
@@ -482,6 +557,8 @@ pub fn generate_k8s_package() -> Module {
                         required: false,
                         description: Some("Name must be unique within a namespace".to_string()),
                         default: None,
+                        read_only: false,
+                        provenance: None,
                     },
                 );
                 fields.insert(
@@ -494,6 +571,8 @@ pub fn generate_k8s_package() -> Module {
                                 .to_string(),
                         ),
                         default: None,
+                        read_only: false,
+                        provenance: None,
                     },
                 );
                 fields.insert(
@@ -509,6 +588,8 @@ pub fn generate_k8s_package() -> Module {
                                 .to_string(),
                         ),
                         default: None,
+                        read_only: false,
+                        provenance: None,
                     },
                 );
                 fields.insert(
@@ -523,6 +604,8 @@ pub fn generate_k8s_package() -> Module {
                             "Annotations is an unstructured key value map".to_string(),
                         ),
                         default: None,
+                        read_only: false,
+                        provenance: None,
                     },
                 );
                 fields.insert(
@@ -534,6 +617,8 @@ pub fn generate_k8s_package() -> Module {
                             "UID is the unique in time and space value for this object".to_string(),
                         ),
                         default: None,
+                        read_only: false,
+                        provenance: None,
                     },
                 );
                 fields.insert(
@@ -546,6 +631,8 @@ pub fn generate_k8s_package() -> Module {
                                 .to_string(),
                         ),
                         default: None,
+                        read_only: false,
+                        provenance: None,
                     },
                 );
                 fields
@@ -556,6 +643,7 @@ pub fn generate_k8s_package() -> Module {
             "ObjectMeta is metadata that all persisted resources must have".to_string(),
         ),
         annotations: BTreeMap::new(),
+        provenance: None,
     };
 
     module.types.push(object_meta);
@@ -565,3 +653,48 @@ pub fn generate_k8s_package() -> Module {
 
     module
 }
+
+/// Shapes of the types in a k8s-core IR bundle, keyed by qualified name
+///
+/// Used to point inlined copies of core types in CRDs back at the k8s-core
+/// package, see [`amalgam_core::dedup::Deduplicator::with_known_types`].
+/// References between core types are qualified too, preferring the
+/// referencing type's own group and version.
+pub fn core_type_shapes(ir: &IR) -> Vec<(String, Type)> {
+    // Module names are `<group or k8s.io>.<version>.<kind>`
+    let mut refs = Vec::new();
+    for module in &ir.modules {
+        let mut parts = module.name.rsplitn(3, '.');
+        let (Some(_), Some(version), Some(group)) = (parts.next(), parts.next(), parts.next())
+        else {
+            continue;
+        };
+        for type_def in &module.types {
+            let type_ref = TypeReference::new(
+                group.to_string(),
+                version.to_string(),
+                type_def.name.clone(),
+            );
+            refs.push((type_ref, type_def.ty.clone()));
+        }
+    }
+
+    let qualify = |name: &str, from: &TypeReference| {
+        let kind = name.rsplit('.').next().unwrap_or(name);
+        let same_version = |r: &&TypeReference| r.kind == kind && r.version == from.version;
+        refs.iter()
+            .map(|(r, _)| r)
+            .filter(same_version)
+            .min_by_key(|r| r.group != from.group)
+            .map(|r| r.qualified_name())
+            .unwrap_or_else(|| name.to_string())
+    };
+
+    refs.iter()
+        .map(|(type_ref, ty)| {
+            let mut ty = ty.clone();
+            rename_references(&mut ty, |name| Some(qualify(name, type_ref)));
+            (type_ref.qualified_name(), ty)
+        })
+        .collect()
+}
@@ -1,18 +1,28 @@
 //! Schema parsers for various formats
 
+pub mod conversion;
 pub mod crd;
 pub mod dependency_graph;
 pub mod error;
 pub mod fetch;
+pub mod filter;
+pub mod git;
 pub mod go;
 pub mod go_ast;
+pub mod helm;
+pub mod http;
 pub mod imports;
 pub mod incremental;
+pub mod input;
 pub mod k8s_authoritative;
 pub mod k8s_imports;
 pub mod k8s_types;
+pub mod oci;
+pub mod olm;
 pub mod openapi;
+pub mod overrides;
 pub mod package;
+pub mod transforms;
 
 use amalgam_core::IR;
 
@@ -0,0 +1,834 @@
+//! Pulling CRD bundles from and pushing packages to OCI registries
+//!
+//! Registries are reached through the OCI distribution HTTP API. Pulling reads
+//! the YAML files of an image's or artifact's layers: tar layers, gzipped or
+//! not, are unpacked, and any other layer is a single file named by its
+//! `org.opencontainers.image.title` annotation. Pushing uploads a package
+//! directory as a single-layer artifact.
+//!
+//! References look like `ghcr.io/org/bundle:v1.2.0` or
+//! `ghcr.io/org/bundle@sha256:...`; an `http://` prefix selects a plain-HTTP
+//! registry such as a local one.
+
+use crate::{
+    crd::CRD,
+    filter::glob_match,
+    helm::{parse_crd_document, qualify_sources, split_documents},
+    http::HttpClient,
+    ParserError,
+};
+use flate2::read::GzDecoder;
+use reqwest::header::{ACCEPT, CONTENT_TYPE, LOCATION, WWW_AUTHENTICATE};
+use reqwest::{Method, RequestBuilder, Response, StatusCode, Url};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::io::Read;
+use std::path::Path;
+use std::str::FromStr;
+use tokio::sync::Mutex;
+
+/// `artifactType` of pushed Nickel packages
+pub const PACKAGE_ARTIFACT_TYPE: &str = "application/vnd.amalgam.nickel.package.v1";
+
+const OCI_MANIFEST: &str = "application/vnd.oci.image.manifest.v1+json";
+const OCI_INDEX: &str = "application/vnd.oci.image.index.v1+json";
+const DOCKER_MANIFEST: &str = "application/vnd.docker.distribution.manifest.v2+json";
+const DOCKER_MANIFEST_LIST: &str = "application/vnd.docker.distribution.manifest.list.v2+json";
+const LAYER_TAR_GZIP: &str = "application/vnd.oci.image.layer.v1.tar+gzip";
+const EMPTY_CONFIG: &str = "application/vnd.oci.empty.v1+json";
+const TITLE_ANNOTATION: &str = "org.opencontainers.image.title";
+
+/// Location of a manifest in a registry
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OciReference {
+    /// `https`, or `http` for registries without TLS
+    pub scheme: String,
+    /// Registry host, with port if any
+    pub registry: String,
+    pub repository: String,
+    /// Tag or `sha256:` digest
+    pub reference: String,
+}
+
+impl FromStr for OciReference {
+    type Err = ParserError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (scheme, rest) = match s.strip_prefix("http://") {
+            Some(rest) => ("http", rest),
+            None => (
+                "https",
+                s.strip_prefix("https://")
+                    .or_else(|| s.strip_prefix("oci://"))
+                    .unwrap_or(s),
+            ),
+        };
+        let invalid = || ParserError::Parse(format!("invalid OCI reference: {}", s));
+
+        let (registry, path) = rest.split_once('/').ok_or_else(invalid)?;
+        let (repository, reference) = match path.split_once('@') {
+            Some((repository, digest)) => (repository, digest),
+            None => match path.rsplit_once(':') {
+                Some((repository, tag)) if !tag.contains('/') => (repository, tag),
+                _ => (path, "latest"),
+            },
+        };
+        if registry.is_empty() || repository.is_empty() || reference.is_empty() {
+            return Err(invalid());
+        }
+
+        Ok(Self {
+            scheme: scheme.to_string(),
+            registry: registry.to_string(),
+            repository: repository.to_string(),
+            reference: reference.to_string(),
+        })
+    }
+}
+
+impl fmt::Display for OciReference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.scheme == "http" {
+            write!(f, "http://")?;
+        }
+        let separator = if self.reference.contains(':') {
+            '@'
+        } else {
+            ':'
+        };
+        write!(
+            f,
+            "{}/{}{}{}",
+            self.registry, self.repository, separator, self.reference
+        )
+    }
+}
+
+impl OciReference {
+    fn url(&self, kind: &str, name: &str) -> String {
+        format!(
+            "{}://{}/v2/{}/{}/{}",
+            self.scheme, self.registry, self.repository, kind, name
+        )
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Descriptor {
+    media_type: String,
+    digest: String,
+    #[serde(default)]
+    annotations: BTreeMap<String, String>,
+    #[serde(default)]
+    platform: Option<Platform>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Platform {
+    os: String,
+    architecture: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Manifest {
+    #[serde(default)]
+    layers: Vec<Descriptor>,
+    /// Entries of an index
+    #[serde(default)]
+    manifests: Vec<Descriptor>,
+}
+
+/// YAML files pulled from an artifact
+#[derive(Debug, Clone)]
+pub struct OciArtifact {
+    pub reference: String,
+    /// Digest of the manifest (or index) the reference resolved to
+    pub manifest_digest: String,
+    pub layer_digests: Vec<String>,
+    /// YAML files by path inside their layer, with their content
+    pub files: Vec<(String, String)>,
+}
+
+impl OciArtifact {
+    /// Keep only files matching one of the glob patterns; no patterns keep all
+    pub fn retain_paths(&mut self, patterns: &[String]) {
+        if !patterns.is_empty() {
+            self.files
+                .retain(|(path, _)| patterns.iter().any(|p| glob_match(p, path)));
+        }
+    }
+
+    /// CRDs in the artifact's files
+    pub fn crds(&self) -> Result<Vec<CRD>, ParserError> {
+        let mut crds = Vec::new();
+        for (path, content) in &self.files {
+            for document in split_documents(content) {
+                crds.extend(parse_crd_document(path, &document)?);
+            }
+        }
+        qualify_sources(
+            &mut crds,
+            &format!("{}@{}", self.reference, self.manifest_digest),
+        );
+        Ok(crds)
+    }
+}
+
+/// Client for the OCI distribution API
+pub struct OciClient {
+    http: HttpClient,
+    /// Registry tokens by scope, from the registries' token endpoints
+    tokens: Mutex<HashMap<String, String>>,
+}
+
+impl OciClient {
+    pub fn new(http: HttpClient) -> Self {
+        Self {
+            http,
+            tokens: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Digest of the manifest a reference points to, without pulling layers
+    pub async fn resolve(&self, reference: &OciReference) -> Result<String, ParserError> {
+        let (_, digest, _) = self.get_manifest(reference, &reference.reference).await?;
+        Ok(digest)
+    }
+
+    /// Pull the YAML files of an image or artifact
+    ///
+    /// For a multi-platform index the linux/amd64 entry is used, or the first
+    /// entry when there is none.
+    pub async fn pull(&self, reference: &OciReference) -> Result<OciArtifact, ParserError> {
+        let (mut manifest, manifest_digest, media_type) =
+            self.get_manifest(reference, &reference.reference).await?;
+        if media_type == OCI_INDEX || media_type == DOCKER_MANIFEST_LIST {
+            let entry = manifest
+                .manifests
+                .iter()
+                .find(|m| {
+                    m.platform
+                        .as_ref()
+                        .is_some_and(|p| p.os == "linux" && p.architecture == "amd64")
+                })
+                .or_else(|| manifest.manifests.first())
+                .ok_or_else(|| {
+                    ParserError::InvalidSchema(format!("{} is an empty index", reference))
+                })?;
+            let digest = entry.digest.clone();
+            manifest = self.get_manifest(reference, &digest).await?.0;
+        }
+
+        let mut artifact = OciArtifact {
+            reference: reference.to_string(),
+            manifest_digest,
+            layer_digests: Vec::new(),
+            files: Vec::new(),
+        };
+        for layer in &manifest.layers {
+            let blob = self.get_blob(reference, &layer.digest).await?;
+            artifact.files.extend(layer_files(layer, &blob)?);
+            artifact.layer_digests.push(layer.digest.clone());
+        }
+        Ok(artifact)
+    }
+
+    /// Push a package directory as an artifact and return the manifest digest
+    ///
+    /// Hidden files such as the fingerprint are left out. The layer is built
+    /// reproducibly, so an unchanged package pushes to the same digest.
+    pub async fn push_package(
+        &self,
+        dir: &Path,
+        reference: &OciReference,
+    ) -> Result<String, ParserError> {
+        let layer = package_layer(dir)?;
+        let layer_digest = sha256_digest(&layer);
+        let config = b"{}";
+        let config_digest = sha256_digest(config);
+
+        self.upload_blob(reference, config, &config_digest).await?;
+        self.upload_blob(reference, &layer, &layer_digest).await?;
+
+        let title = dir
+            .file_name()
+            .map(|name| format!("{}.tar.gz", name.to_string_lossy()))
+            .unwrap_or_else(|| "package.tar.gz".to_string());
+        let manifest = serde_json::to_vec(&serde_json::json!({
+            "schemaVersion": 2,
+            "mediaType": OCI_MANIFEST,
+            "artifactType": PACKAGE_ARTIFACT_TYPE,
+            "config": {
+                "mediaType": EMPTY_CONFIG,
+                "digest": config_digest,
+                "size": config.len(),
+            },
+            "layers": [{
+                "mediaType": LAYER_TAR_GZIP,
+                "digest": layer_digest,
+                "size": layer.len(),
+                "annotations": { TITLE_ANNOTATION: title },
+            }],
+        }))?;
+
+        let url = reference.url("manifests", &reference.reference);
+        let response = self
+            .send(reference, "pull,push", |token| {
+                self.request(Method::PUT, &url, token)
+                    .header(CONTENT_TYPE, OCI_MANIFEST)
+                    .body(manifest.clone())
+            })
+            .await?;
+        expect_success(response, &url).await?;
+
+        Ok(sha256_digest(&manifest))
+    }
+
+    /// Fetch and parse a manifest, returning it with its digest and media type
+    async fn get_manifest(
+        &self,
+        reference: &OciReference,
+        name: &str,
+    ) -> Result<(Manifest, String, String), ParserError> {
+        let url = reference.url("manifests", name);
+        let accept = [
+            OCI_MANIFEST,
+            OCI_INDEX,
+            DOCKER_MANIFEST,
+            DOCKER_MANIFEST_LIST,
+        ]
+        .join(", ");
+        let response = self
+            .send(reference, "pull", |token| {
+                self.request(Method::GET, &url, token)
+                    .header(ACCEPT, accept.as_str())
+            })
+            .await?;
+        let response = expect_success(response, &url).await?;
+
+        let content_type = response
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .map(String::from);
+        let body = response
+            .bytes()
+            .await
+            .map_err(|e| ParserError::Network(e.to_string()))?;
+        let digest = sha256_digest(&body);
+        if name.starts_with("sha256:") && name != digest {
+            return Err(ParserError::InvalidSchema(format!(
+                "manifest {} has digest {}",
+                name, digest
+            )));
+        }
+
+        let value: serde_json::Value = serde_json::from_slice(&body)?;
+        let media_type = value
+            .get("mediaType")
+            .and_then(|m| m.as_str())
+            .map(String::from)
+            .or(content_type)
+            .unwrap_or_else(|| OCI_MANIFEST.to_string());
+        Ok((serde_json::from_value(value)?, digest, media_type))
+    }
+
+    async fn get_blob(
+        &self,
+        reference: &OciReference,
+        digest: &str,
+    ) -> Result<Vec<u8>, ParserError> {
+        if !digest.starts_with("sha256:") {
+            return Err(ParserError::UnsupportedFeature(format!(
+                "blob digest algorithm of {}",
+                digest
+            )));
+        }
+        let url = reference.url("blobs", digest);
+        let response = self
+            .send(reference, "pull", |token| {
+                self.request(Method::GET, &url, token)
+            })
+            .await?;
+        let blob = expect_success(response, &url)
+            .await?
+            .bytes()
+            .await
+            .map_err(|e| ParserError::Network(e.to_string()))?;
+        if sha256_digest(&blob) != digest {
+            return Err(ParserError::InvalidSchema(format!(
+                "blob {} does not match its digest",
+                digest
+            )));
+        }
+        Ok(blob.to_vec())
+    }
+
+    /// Upload a blob unless the repository already has it
+    async fn upload_blob(
+        &self,
+        reference: &OciReference,
+        data: &[u8],
+        digest: &str,
+    ) -> Result<(), ParserError> {
+        let blob_url = reference.url("blobs", digest);
+        let existing = self
+            .send(reference, "pull,push", |token| {
+                self.request(Method::HEAD, &blob_url, token)
+            })
+            .await?;
+        if existing.status().is_success() {
+            return Ok(());
+        }
+
+        let uploads_url = reference.url("blobs", "uploads/");
+        let response = self
+            .send(reference, "pull,push", |token| {
+                self.request(Method::POST, &uploads_url, token)
+            })
+            .await?;
+        let response = expect_success(response, &uploads_url).await?;
+        let location = response
+            .headers()
+            .get(LOCATION)
+            .and_then(|v| v.to_str().ok())
+            .ok_or_else(|| {
+                ParserError::Network(format!("{} gave no upload location", uploads_url))
+            })?;
+
+        // The location may be relative to the registry
+        let mut upload_url = Url::parse(&uploads_url)
+            .and_then(|base| base.join(location))
+            .map_err(|e| ParserError::Network(format!("upload location {}: {}", location, e)))?;
+        upload_url.query_pairs_mut().append_pair("digest", digest);
+        let upload_url = upload_url.to_string();
+
+        let response = self
+            .send(reference, "pull,push", |token| {
+                self.request(Method::PUT, &upload_url, token)
+                    .header(CONTENT_TYPE, "application/octet-stream")
+                    .body(data.to_vec())
+            })
+            .await?;
+        expect_success(response, &upload_url).await?;
+        Ok(())
+    }
+
+    fn request(&self, method: Method, url: &str, token: Option<&str>) -> RequestBuilder {
+        match token {
+            Some(token) => self.http.request_with_token(method, url, token),
+            None => self.http.request(method, url),
+        }
+    }
+
+    /// Send a request, answering a registry's bearer token challenge if needed
+    async fn send(
+        &self,
+        reference: &OciReference,
+        actions: &str,
+        build: impl Fn(Option<&str>) -> RequestBuilder,
+    ) -> Result<Response, ParserError> {
+        let scope = format!("repository:{}:{}", reference.repository, actions);
+        let token = self.tokens.lock().await.get(&scope).cloned();
+        let response = self
+            .http
+            .send(build(token.as_deref()))
+            .await
+            .map_err(|e| ParserError::Network(e.to_string()))?;
+        if response.status() != StatusCode::UNAUTHORIZED || token.is_some() {
+            return Ok(response);
+        }
+
+        let Some(challenge) = response
+            .headers()
+            .get(WWW_AUTHENTICATE)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "))
+            .map(String::from)
+        else {
+            return Ok(response);
+        };
+        let token = self.fetch_token(&challenge, &scope).await?;
+        self.tokens.lock().await.insert(scope, token.clone());
+        self.http
+            .send(build(Some(&token)))
+            .await
+            .map_err(|e| ParserError::Network(e.to_string()))
+    }
+
+    /// Get a token from the endpoint named in a `WWW-Authenticate: Bearer` challenge
+    async fn fetch_token(&self, challenge: &str, scope: &str) -> Result<String, ParserError> {
+        let params = challenge_params(challenge);
+        let realm = params
+            .get("realm")
+            .ok_or_else(|| ParserError::Network("token challenge without realm".to_string()))?;
+        let mut query = vec![("scope", params.get("scope").map_or(scope, String::as_str))];
+        if let Some(service) = params.get("service") {
+            query.push(("service", service.as_str()));
+        }
+        let url = Url::parse_with_params(realm, &query)
+            .map_err(|e| ParserError::Network(format!("token realm {}: {}", realm, e)))?;
+
+        #[derive(Deserialize)]
+        struct TokenResponse {
+            token: Option<String>,
+            access_token: Option<String>,
+        }
+        let response = self
+            .http
+            .send(self.http.get(url.as_str()))
+            .await
+            .map_err(|e| ParserError::Network(e.to_string()))?;
+        let response: TokenResponse = expect_success(response, realm)
+            .await?
+            .json()
+            .await
+            .map_err(|e| ParserError::Network(e.to_string()))?;
+        response
+            .token
+            .or(response.access_token)
+            .ok_or_else(|| ParserError::Network(format!("{} returned no token", realm)))
+    }
+}
+
+/// `key="value"` pairs of an authentication challenge
+fn challenge_params(challenge: &str) -> HashMap<String, String> {
+    let mut params = HashMap::new();
+    let mut rest = challenge.trim();
+    while let Some((key, value)) = rest.split_once('=') {
+        let key = key.trim().trim_start_matches(',').trim().to_string();
+        let (value, remainder) = match value.strip_prefix('"') {
+            Some(quoted) => quoted.split_once('"').unwrap_or((quoted, "")),
+            None => value.split_once(',').unwrap_or((value, "")),
+        };
+        params.insert(key, value.to_string());
+        rest = remainder;
+    }
+    params
+}
+
+async fn expect_success(response: Response, url: &str) -> Result<Response, ParserError> {
+    if response.status().is_success() {
+        return Ok(response);
+    }
+    let status = response.status();
+    let body = response.text().await.unwrap_or_default();
+    Err(ParserError::Network(format!(
+        "{} returned {}: {}",
+        url,
+        status,
+        body.trim()
+    )))
+}
+
+fn sha256_digest(data: &[u8]) -> String {
+    format!("sha256:{:x}", Sha256::digest(data))
+}
+
+/// YAML files in a layer
+fn layer_files(layer: &Descriptor, blob: &[u8]) -> Result<Vec<(String, String)>, ParserError> {
+    let is_yaml = |path: &str| path.ends_with(".yaml") || path.ends_with(".yml");
+
+    if !layer.media_type.contains("tar") {
+        let title = layer
+            .annotations
+            .get(TITLE_ANNOTATION)
+            .cloned()
+            .unwrap_or_else(|| layer.digest.clone());
+        if !is_yaml(&title) && !layer.media_type.contains("yaml") {
+            return Ok(Vec::new());
+        }
+        let content = String::from_utf8(blob.to_vec())
+            .map_err(|e| ParserError::Parse(format!("{}: {}", title, e)))?;
+        return Ok(vec![(title, content)]);
+    }
+    if layer.media_type.contains("zstd") {
+        return Err(ParserError::UnsupportedFeature(format!(
+            "zstd-compressed layer {}",
+            layer.digest
+        )));
+    }
+
+    let reader: Box<dyn Read + '_> = if blob.starts_with(&[0x1f, 0x8b]) {
+        Box::new(GzDecoder::new(blob))
+    } else {
+        Box::new(blob)
+    };
+    let mut archive = tar::Archive::new(reader);
+    let mut files = Vec::new();
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.to_string_lossy().replace('\\', "/");
+        let path = path.trim_start_matches("./").to_string();
+        if entry.header().entry_type().is_file() && is_yaml(&path) {
+            let mut content = String::new();
+            entry.read_to_string(&mut content)?;
+            files.push((path, content));
+        }
+    }
+    Ok(files)
+}
+
+/// Reproducible `tar+gzip` of a package directory's non-hidden files
+fn package_layer(dir: &Path) -> Result<Vec<u8>, ParserError> {
+    fn collect(root: &Path, dir: &Path, files: &mut Vec<String>) -> std::io::Result<()> {
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path
+                .file_name()
+                .is_some_and(|name| name.to_string_lossy().starts_with('.'))
+            {
+                continue;
+            }
+            if path.is_dir() {
+                collect(root, &path, files)?;
+            } else {
+                let relative = path.strip_prefix(root).expect("entries are under the root");
+                files.push(relative.to_string_lossy().replace('\\', "/"));
+            }
+        }
+        Ok(())
+    }
+
+    let mut files = Vec::new();
+    collect(dir, dir, &mut files)?;
+    files.sort();
+
+    let encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    let mut builder = tar::Builder::new(encoder);
+    for file in files {
+        let content = std::fs::read(dir.join(&file))?;
+        let mut header = tar::Header::new_gnu();
+        header.set_size(content.len() as u64);
+        header.set_mode(0o644);
+        header.set_mtime(0);
+        header.set_uid(0);
+        header.set_gid(0);
+        builder.append_data(&mut header, &file, content.as_slice())?;
+    }
+    Ok(builder.into_inner()?.finish()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::HttpConfig;
+    use wiremock::matchers::{header, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    const CRD_YAML: &str = r#"apiVersion: apiextensions.k8s.io/v1
+kind: CustomResourceDefinition
+metadata:
+  name: widgets.example.com
+spec:
+  group: example.com
+  names:
+    kind: Widget
+    plural: widgets
+    singular: widget
+  versions:
+    - name: v1
+      served: true
+      storage: true
+"#;
+
+    fn client() -> OciClient {
+        OciClient::new(HttpClient::new(HttpConfig::default()).unwrap())
+    }
+
+    fn bundle_layer() -> Vec<u8> {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("manifests")).unwrap();
+        std::fs::write(dir.path().join("manifests/widgets.yaml"), CRD_YAML).unwrap();
+        std::fs::write(dir.path().join("manifests/notes.txt"), "not yaml").unwrap();
+        package_layer(dir.path()).unwrap()
+    }
+
+    /// Serve a manifest with a tar layer and a plain YAML layer under `repo`
+    async fn serve_bundle(server: &MockServer, repo: &str) -> String {
+        let layer = bundle_layer();
+        let extra = b"kind: ConfigMap\n".to_vec();
+        let manifest = serde_json::to_vec(&serde_json::json!({
+            "schemaVersion": 2,
+            "mediaType": OCI_MANIFEST,
+            "config": { "mediaType": EMPTY_CONFIG, "digest": sha256_digest(b"{}"), "size": 2 },
+            "layers": [
+                { "mediaType": LAYER_TAR_GZIP, "digest": sha256_digest(&layer), "size": layer.len() },
+                {
+                    "mediaType": "application/yaml",
+                    "digest": sha256_digest(&extra),
+                    "size": extra.len(),
+                    "annotations": { TITLE_ANNOTATION: "extra.yaml" }
+                },
+            ],
+        }))
+        .unwrap();
+
+        Mock::given(method("GET"))
+            .and(path(format!("/v2/{}/manifests/v1", repo)))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(manifest.clone(), OCI_MANIFEST))
+            .mount(server)
+            .await;
+        for blob in [layer, extra] {
+            Mock::given(method("GET"))
+                .and(path(format!("/v2/{}/blobs/{}", repo, sha256_digest(&blob))))
+                .respond_with(ResponseTemplate::new(200).set_body_bytes(blob))
+                .mount(server)
+                .await;
+        }
+        sha256_digest(&manifest)
+    }
+
+    fn local(server: &MockServer, repo: &str, tag: &str) -> OciReference {
+        format!("{}/{}:{}", server.uri(), repo, tag)
+            .parse()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_parse_reference() {
+        let tagged: OciReference = "ghcr.io/org/bundles/widgets:v1.2".parse().unwrap();
+        assert_eq!(tagged.registry, "ghcr.io");
+        assert_eq!(tagged.repository, "org/bundles/widgets");
+        assert_eq!(tagged.reference, "v1.2");
+        assert_eq!(tagged.to_string(), "ghcr.io/org/bundles/widgets:v1.2");
+
+        let local: OciReference = "http://localhost:5000/widgets".parse().unwrap();
+        assert_eq!(local.scheme, "http");
+        assert_eq!(local.registry, "localhost:5000");
+        assert_eq!(local.reference, "latest");
+
+        let pinned: OciReference = "quay.io/org/widgets@sha256:abc".parse().unwrap();
+        assert_eq!(pinned.reference, "sha256:abc");
+        assert_eq!(pinned.to_string(), "quay.io/org/widgets@sha256:abc");
+
+        assert!("widgets".parse::<OciReference>().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_pull_crds_from_layers() {
+        let server = MockServer::start().await;
+        let manifest_digest = serve_bundle(&server, "org/widgets").await;
+
+        let reference = local(&server, "org/widgets", "v1");
+        let client = client();
+        assert_eq!(client.resolve(&reference).await.unwrap(), manifest_digest);
+
+        let mut artifact = client.pull(&reference).await.unwrap();
+        assert_eq!(artifact.manifest_digest, manifest_digest);
+        assert_eq!(artifact.layer_digests.len(), 2);
+        let paths: Vec<&str> = artifact.files.iter().map(|(p, _)| p.as_str()).collect();
+        assert_eq!(paths, vec!["manifests/widgets.yaml", "extra.yaml"]);
+        assert_eq!(artifact.crds().unwrap()[0].spec.names.kind, "Widget");
+
+        artifact.retain_paths(&["manifests/*".to_string()]);
+        assert_eq!(artifact.files.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_pull_answers_token_challenge() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/token"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "token": "registry-token"
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/v2/private/manifests/v1"))
+            .and(header("authorization", "Bearer registry-token"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_raw(br#"{"schemaVersion":2,"layers":[]}"#.to_vec(), OCI_MANIFEST),
+            )
+            .with_priority(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(401).insert_header(
+                "www-authenticate",
+                format!(
+                    r#"Bearer realm="{}/token",service="registry",scope="repository:private:pull""#,
+                    server.uri()
+                ),
+            ))
+            .with_priority(10)
+            .mount(&server)
+            .await;
+
+        let artifact = client()
+            .pull(&local(&server, "private", "v1"))
+            .await
+            .unwrap();
+        assert!(artifact.files.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_push_package() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v2/org/pkg/blobs/uploads/"))
+            .respond_with(
+                ResponseTemplate::new(202).insert_header("location", "/v2/org/pkg/blobs/uploads/1"),
+            )
+            .mount(&server)
+            .await;
+        Mock::given(method("PUT"))
+            .and(path("/v2/org/pkg/blobs/uploads/1"))
+            .respond_with(ResponseTemplate::new(201))
+            .expect(2)
+            .mount(&server)
+            .await;
+        Mock::given(method("PUT"))
+            .and(path("/v2/org/pkg/manifests/v1"))
+            .and(header("content-type", OCI_MANIFEST))
+            .respond_with(ResponseTemplate::new(201))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let dir = tempfile::tempdir().unwrap();
+        let package = dir.path().join("widgets");
+        std::fs::create_dir_all(package.join("example.com/v1")).unwrap();
+        std::fs::write(package.join("mod.ncl"), "{}").unwrap();
+        std::fs::write(package.join("example.com/v1/widget.ncl"), "{}").unwrap();
+        std::fs::write(package.join(".amalgam-fingerprint.json"), "{}").unwrap();
+
+        let reference = local(&server, "org/pkg", "v1");
+        let digest = client().push_package(&package, &reference).await.unwrap();
+        assert!(digest.starts_with("sha256:"));
+
+        // The manifest digest is reproducible and names the uploaded layer
+        let requests = server.received_requests().await.unwrap();
+        let manifest = requests
+            .iter()
+            .find(|r| r.url.path() == "/v2/org/pkg/manifests/v1")
+            .unwrap();
+        assert_eq!(sha256_digest(&manifest.body), digest);
+        let manifest: serde_json::Value = serde_json::from_slice(&manifest.body).unwrap();
+        assert_eq!(manifest["artifactType"], PACKAGE_ARTIFACT_TYPE);
+        let layer_digest = manifest["layers"][0]["digest"].as_str().unwrap();
+        assert!(requests.iter().any(
+            |r| r.url.query() == Some(&format!("digest={}", layer_digest.replace(':', "%3A")))
+        ));
+
+        let layer = package_layer(&package).unwrap();
+        assert_eq!(sha256_digest(&layer), layer_digest);
+        let files = layer_files(
+            &Descriptor {
+                media_type: LAYER_TAR_GZIP.to_string(),
+                digest: layer_digest.to_string(),
+                annotations: BTreeMap::new(),
+                platform: None,
+            },
+            &layer,
+        )
+        .unwrap();
+        // Only YAML files are read back, so the .ncl files are not listed
+        assert!(files.is_empty());
+    }
+}
@@ -0,0 +1,556 @@
+//! Importing Operator Lifecycle Manager bundles
+//!
+//! A bundle is a directory whose `manifests/` holds the operator's CRDs and
+//! its ClusterServiceVersion (CSV), with `metadata/annotations.yaml` naming
+//! the package. Only the CRDs the CSV lists as owned are imported.
+//!
+//! The CSV's spec and status descriptors document fields the CRD schemas
+//! often leave bare, so a descriptor's description (or display name) fills
+//! in a field description the schema lacks. The `alm-examples` annotation
+//! becomes example Nickel files checked against the generated types.
+
+use crate::{
+    crd::CRD,
+    helm::{parse_crd_document, qualify_sources, split_documents},
+    ParserError,
+};
+use amalgam_codegen::nickel::NickelCodegen;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+const PACKAGE_ANNOTATION: &str = "operators.operatorframework.io.bundle.package.v1";
+const EXAMPLES_ANNOTATION: &str = "alm-examples";
+
+/// Owned CRDs and examples of an OLM bundle, with the CSV's identity
+#[derive(Debug, Clone)]
+pub struct OlmBundle {
+    /// Name of the CSV, e.g. `etcdoperator.v0.9.4`
+    pub name: String,
+    /// Operator package from the bundle annotations, if present
+    pub package: Option<String>,
+    pub version: Option<String>,
+    /// Owned CRDs, with descriptor documentation merged into their schemas
+    pub crds: Vec<CRD>,
+    /// Source YAML of the CSV and each owned CRD
+    pub documents: Vec<String>,
+    /// Names of the APIs the operator requires from elsewhere
+    pub required: Vec<String>,
+    pub examples: Vec<OlmExample>,
+}
+
+/// A custom resource from the CSV's `alm-examples`
+#[derive(Debug, Clone)]
+pub struct OlmExample {
+    pub group: String,
+    pub version: String,
+    pub kind: String,
+    pub value: serde_json::Value,
+}
+
+#[derive(Debug, Deserialize)]
+struct ClusterServiceVersion {
+    metadata: CsvMetadata,
+    spec: CsvSpec,
+}
+
+#[derive(Debug, Deserialize)]
+struct CsvMetadata {
+    name: String,
+    #[serde(default)]
+    annotations: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct CsvSpec {
+    version: Option<String>,
+    #[serde(default, rename = "customresourcedefinitions")]
+    crds: CsvCrds,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct CsvCrds {
+    #[serde(default)]
+    owned: Vec<CrdDescription>,
+    #[serde(default)]
+    required: Vec<CrdDescription>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CrdDescription {
+    name: String,
+    version: String,
+    #[serde(default)]
+    spec_descriptors: Vec<Descriptor>,
+    #[serde(default)]
+    status_descriptors: Vec<Descriptor>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Descriptor {
+    path: String,
+    display_name: Option<String>,
+    description: Option<String>,
+}
+
+impl OlmBundle {
+    /// Load a bundle directory, or a directory holding the manifests directly
+    pub fn load(path: &Path) -> Result<Self, ParserError> {
+        let manifests = path.join("manifests");
+        let manifests = if manifests.is_dir() {
+            manifests
+        } else {
+            path.to_path_buf()
+        };
+
+        let mut files = BTreeMap::new();
+        for entry in fs::read_dir(&manifests)? {
+            let file = entry?.path();
+            let name = file.file_name().unwrap_or_default().to_string_lossy();
+            if file.is_file() && (name.ends_with(".yaml") || name.ends_with(".yml")) {
+                files.insert(format!("manifests/{}", name), fs::read_to_string(&file)?);
+            }
+        }
+        let annotations = path.join("metadata/annotations.yaml");
+        if annotations.is_file() {
+            files.insert(
+                "metadata/annotations.yaml".to_string(),
+                fs::read_to_string(annotations)?,
+            );
+        }
+
+        let mut bundle = Self::from_files(&files)
+            .map_err(|e| ParserError::InvalidSchema(format!("OLM bundle {:?}: {}", path, e)))?;
+        qualify_sources(&mut bundle.crds, &path.display().to_string());
+        Ok(bundle)
+    }
+
+    /// Build a bundle from its files, keyed by path relative to the bundle root
+    pub fn from_files(files: &BTreeMap<String, String>) -> Result<Self, ParserError> {
+        let mut csv = None;
+        let mut crds = Vec::new();
+        for (path, content) in files {
+            if !path.starts_with("manifests/") {
+                continue;
+            }
+            for document in split_documents(content) {
+                if let Some(crd) = parse_crd_document(path, &document)? {
+                    crds.push((crd, document));
+                    continue;
+                }
+                let value: serde_yaml::Value = serde_yaml::from_str(&document)?;
+                if value.get("kind").and_then(|k| k.as_str()) == Some("ClusterServiceVersion") {
+                    if csv.is_some() {
+                        return Err(ParserError::InvalidSchema(
+                            "more than one ClusterServiceVersion".to_string(),
+                        ));
+                    }
+                    let parsed: ClusterServiceVersion = serde_yaml::from_value(value)
+                        .map_err(|e| ParserError::InvalidSchema(format!("{}: {}", path, e)))?;
+                    csv = Some((parsed, document));
+                }
+            }
+        }
+        let (csv, csv_document) =
+            csv.ok_or_else(|| ParserError::InvalidSchema("no ClusterServiceVersion".to_string()))?;
+
+        let package = match files.get("metadata/annotations.yaml") {
+            Some(content) => bundle_package(content)?,
+            None => None,
+        };
+        let examples = match csv.metadata.annotations.get(EXAMPLES_ANNOTATION) {
+            Some(examples) => parse_examples(examples)?,
+            None => Vec::new(),
+        };
+
+        let mut bundle = Self {
+            name: csv.metadata.name,
+            package,
+            version: csv.spec.version,
+            crds: Vec::new(),
+            documents: vec![csv_document],
+            required: csv
+                .spec
+                .crds
+                .required
+                .iter()
+                .map(|r| format!("{}/{}", r.name, r.version))
+                .collect(),
+            examples: Vec::new(),
+        };
+
+        for (mut crd, document) in crds {
+            let owned: Vec<&CrdDescription> = csv
+                .spec
+                .crds
+                .owned
+                .iter()
+                .filter(|o| o.name == crd.metadata.name)
+                .collect();
+            if owned.is_empty() {
+                continue;
+            }
+            for description in owned {
+                describe_fields(&mut crd, description);
+            }
+            bundle.crds.push(crd);
+            bundle.documents.push(document);
+        }
+
+        // Examples of APIs the operator does not own have no generated type
+        bundle.examples = examples
+            .into_iter()
+            .filter(|example| {
+                bundle.crds.iter().any(|crd| {
+                    crd.spec.group == example.group
+                        && crd.spec.names.kind == example.kind
+                        && crd.spec.versions.iter().any(|v| v.name == example.version)
+                })
+            })
+            .collect();
+
+        Ok(bundle)
+    }
+}
+
+impl OlmExample {
+    /// Name of the example file, from the resource's name or else its kind
+    pub fn file_stem(&self) -> String {
+        let name = self
+            .value
+            .pointer("/metadata/name")
+            .and_then(|n| n.as_str())
+            .unwrap_or(&self.kind);
+        name.chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                    c.to_ascii_lowercase()
+                } else {
+                    '-'
+                }
+            })
+            .collect()
+    }
+
+    /// Nickel file for the example, placed in an `examples/` directory next
+    /// to its kind's file and checked against the kind's type
+    pub fn to_nickel(&self) -> String {
+        let module = self.kind.to_lowercase();
+        format!(
+            "# Example {} from the bundle's alm-examples\n\n\
+             let {} = import \"../{}.ncl\" in\n\n\
+             {} | {}.{}\n",
+            self.kind,
+            module,
+            module,
+            NickelCodegen::new().format_value(&self.value, 0),
+            module,
+            self.kind
+        )
+    }
+}
+
+/// Operator package named by `metadata/annotations.yaml`
+fn bundle_package(content: &str) -> Result<Option<String>, ParserError> {
+    #[derive(Deserialize)]
+    struct BundleAnnotations {
+        #[serde(default)]
+        annotations: BTreeMap<String, String>,
+    }
+    let parsed: BundleAnnotations = serde_yaml::from_str(content)?;
+    Ok(parsed.annotations.get(PACKAGE_ANNOTATION).cloned())
+}
+
+/// Resources in the JSON array of the `alm-examples` annotation
+fn parse_examples(annotation: &str) -> Result<Vec<OlmExample>, ParserError> {
+    let values: Vec<serde_json::Value> = serde_json::from_str(annotation).map_err(|e| {
+        ParserError::InvalidSchema(format!("{} annotation: {}", EXAMPLES_ANNOTATION, e))
+    })?;
+
+    let mut examples = Vec::new();
+    for value in values {
+        let api_version = value.get("apiVersion").and_then(|v| v.as_str());
+        let kind = value.get("kind").and_then(|k| k.as_str());
+        let (Some(api_version), Some(kind)) = (api_version, kind) else {
+            continue;
+        };
+        let Some((group, version)) = api_version.split_once('/') else {
+            continue;
+        };
+        examples.push(OlmExample {
+            group: group.to_string(),
+            version: version.to_string(),
+            kind: kind.to_string(),
+            value: value.clone(),
+        });
+    }
+    Ok(examples)
+}
+
+/// Fill in missing field descriptions from a CSV's descriptors for one version
+fn describe_fields(crd: &mut CRD, description: &CrdDescription) {
+    let Some(schema) = crd
+        .spec
+        .versions
+        .iter_mut()
+        .find(|v| v.name == description.version)
+        .and_then(|v| v.schema.as_mut())
+    else {
+        return;
+    };
+
+    let descriptors = [
+        ("spec", &description.spec_descriptors),
+        ("status", &description.status_descriptors),
+    ];
+    for (root, descriptors) in descriptors {
+        for descriptor in descriptors {
+            let Some(doc) = descriptor
+                .description
+                .as_ref()
+                .or(descriptor.display_name.as_ref())
+                .filter(|doc| !doc.trim().is_empty())
+            else {
+                continue;
+            };
+            let Some(field) = schema_field(&mut schema.openapi_v3_schema, root, &descriptor.path)
+            else {
+                continue;
+            };
+            if let Some(field) = field.as_object_mut() {
+                let undocumented = field
+                    .get("description")
+                    .and_then(|d| d.as_str())
+                    .is_none_or(|d| d.trim().is_empty());
+                if undocumented {
+                    field.insert("description".to_string(), doc.clone().into());
+                }
+            }
+        }
+    }
+}
+
+/// Schema of the field at a descriptor path such as `storage.volumes[0].size`
+/// under the top-level `root` property
+fn schema_field<'a>(
+    schema: &'a mut serde_json::Value,
+    root: &str,
+    path: &str,
+) -> Option<&'a mut serde_json::Value> {
+    let mut current = schema.get_mut("properties")?.get_mut(root)?;
+    for segment in path.split('.').filter(|s| !s.is_empty()) {
+        let (name, indexed) = match segment.split_once('[') {
+            Some((name, _)) => (name, true),
+            None => (segment, false),
+        };
+        current = current.get_mut("properties")?.get_mut(name)?;
+        if indexed {
+            current = current.get_mut("items")?;
+        }
+    }
+    Some(current)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CRD_YAML: &str = r#"apiVersion: apiextensions.k8s.io/v1
+kind: CustomResourceDefinition
+metadata:
+  name: widgets.example.com
+spec:
+  group: example.com
+  names:
+    kind: Widget
+    plural: widgets
+    singular: widget
+  versions:
+    - name: v1
+      served: true
+      storage: true
+      schema:
+        openAPIV3Schema:
+          type: object
+          properties:
+            spec:
+              type: object
+              properties:
+                size:
+                  type: integer
+                  description: Number of replicas
+                storage:
+                  type: object
+                  properties:
+                    volumes:
+                      type: array
+                      items:
+                        type: object
+                        properties:
+                          capacity:
+                            type: string
+            status:
+              type: object
+              properties:
+                phase:
+                  type: string
+"#;
+
+    const REQUIRED_CRD_YAML: &str = r#"apiVersion: apiextensions.k8s.io/v1
+kind: CustomResourceDefinition
+metadata:
+  name: gadgets.example.com
+spec:
+  group: example.com
+  names:
+    kind: Gadget
+    plural: gadgets
+    singular: gadget
+  versions:
+    - name: v1
+      served: true
+      storage: true
+"#;
+
+    const CSV_YAML: &str = r#"apiVersion: operators.coreos.com/v1alpha1
+kind: ClusterServiceVersion
+metadata:
+  name: widget-operator.v0.3.0
+  annotations:
+    alm-examples: |-
+      [
+        {
+          "apiVersion": "example.com/v1",
+          "kind": "Widget",
+          "metadata": {
+            "name": "example-widget",
+            "labels": { "app.kubernetes.io/name": "widget" }
+          },
+          "spec": { "size": 3 }
+        },
+        {
+          "apiVersion": "example.com/v1",
+          "kind": "Gadget",
+          "metadata": { "name": "example-gadget" }
+        }
+      ]
+spec:
+  version: 0.3.0
+  customresourcedefinitions:
+    owned:
+      - name: widgets.example.com
+        version: v1
+        kind: Widget
+        specDescriptors:
+          - path: size
+            displayName: Size
+            description: Overridden by the schema
+          - path: storage.volumes[0].capacity
+            displayName: Volume Capacity
+            description: Size of each volume
+        statusDescriptors:
+          - path: phase
+            displayName: Phase
+    required:
+      - name: gadgets.example.com
+        version: v1
+        kind: Gadget
+"#;
+
+    fn bundle_files() -> BTreeMap<String, String> {
+        BTreeMap::from([
+            ("manifests/widgets.crd.yaml".to_string(), CRD_YAML.to_string()),
+            (
+                "manifests/gadgets.crd.yaml".to_string(),
+                REQUIRED_CRD_YAML.to_string(),
+            ),
+            (
+                "manifests/widget-operator.clusterserviceversion.yaml".to_string(),
+                CSV_YAML.to_string(),
+            ),
+            (
+                "metadata/annotations.yaml".to_string(),
+                "annotations:\n  operators.operatorframework.io.bundle.package.v1: widget-operator\n"
+                    .to_string(),
+            ),
+        ])
+    }
+
+    fn property<'a>(crd: &'a CRD, pointer: &str) -> &'a serde_json::Value {
+        crd.spec.versions[0]
+            .schema
+            .as_ref()
+            .unwrap()
+            .openapi_v3_schema
+            .pointer(pointer)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_bundle_imports_owned_crds() {
+        let bundle = OlmBundle::from_files(&bundle_files()).unwrap();
+        assert_eq!(bundle.name, "widget-operator.v0.3.0");
+        assert_eq!(bundle.package.as_deref(), Some("widget-operator"));
+        assert_eq!(bundle.version.as_deref(), Some("0.3.0"));
+        assert_eq!(bundle.required, vec!["gadgets.example.com/v1"]);
+
+        // The required Gadget CRD shipped alongside is not imported
+        assert_eq!(bundle.crds.len(), 1);
+        assert_eq!(bundle.crds[0].spec.names.kind, "Widget");
+        assert_eq!(bundle.documents.len(), 2);
+    }
+
+    #[test]
+    fn test_descriptors_document_fields() {
+        let bundle = OlmBundle::from_files(&bundle_files()).unwrap();
+        let crd = &bundle.crds[0];
+
+        // Schema descriptions win over descriptors
+        assert_eq!(
+            property(crd, "/properties/spec/properties/size/description"),
+            "Number of replicas"
+        );
+        assert_eq!(
+            property(
+                crd,
+                "/properties/spec/properties/storage/properties/volumes/items/properties/capacity/description"
+            ),
+            "Size of each volume"
+        );
+        // Display names stand in for missing descriptions
+        assert_eq!(
+            property(crd, "/properties/status/properties/phase/description"),
+            "Phase"
+        );
+    }
+
+    #[test]
+    fn test_examples_become_nickel() {
+        let bundle = OlmBundle::from_files(&bundle_files()).unwrap();
+
+        // The Gadget example has no owned type to check it against
+        assert_eq!(bundle.examples.len(), 1);
+        let example = &bundle.examples[0];
+        assert_eq!(example.group, "example.com");
+        assert_eq!(example.version, "v1");
+        assert_eq!(example.file_stem(), "example-widget");
+
+        let nickel = example.to_nickel();
+        assert!(nickel.contains("let widget = import \"../widget.ncl\" in"));
+        assert!(nickel.contains("\"app.kubernetes.io/name\" = \"widget\""));
+        assert!(nickel.contains("size = 3"));
+        assert!(nickel.trim_end().ends_with("} | widget.Widget"));
+    }
+
+    #[test]
+    fn test_bundle_without_csv() {
+        let files = BTreeMap::from([(
+            "manifests/widgets.crd.yaml".to_string(),
+            CRD_YAML.to_string(),
+        )]);
+        assert!(OlmBundle::from_files(&files).is_err());
+    }
+}
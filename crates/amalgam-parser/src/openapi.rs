@@ -4,40 +4,70 @@ use crate::{Parser, ParserError};
 use amalgam_core::{
     ir::{IRBuilder, IR},
     types::{Field, Type},
+    Provenance,
 };
 use openapiv3::{OpenAPI, Schema, SchemaKind, Type as OpenAPIType};
 use std::collections::BTreeMap;
 
-pub struct OpenAPIParser;
+pub struct OpenAPIParser {
+    source: Option<String>,
+}
 
 impl Parser for OpenAPIParser {
     type Input = OpenAPI;
 
     fn parse(&self, input: Self::Input) -> Result<IR, ParserError> {
         let mut builder = IRBuilder::new().module("openapi");
+        let document = Provenance::new(self.source.as_deref().unwrap_or(&input.info.title));
+        let mut provenances = BTreeMap::new();
 
         // Parse components/schemas
         if let Some(components) = input.components {
             for (name, schema_ref) in components.schemas {
                 if let openapiv3::ReferenceOr::Item(schema) = schema_ref {
-                    let ty = self.schema_to_type(&schema)?;
-                    builder = builder.add_type(name, ty);
+                    let at = document.child("components").child("schemas").child(&name);
+                    let ty = self.schema_to_type(&schema, &at)?;
+                    builder = builder.add_type(name.clone(), ty);
+                    provenances.insert(name, at);
                 }
             }
         }
 
-        Ok(builder.build())
+        let mut ir = builder.build();
+        for type_def in ir.modules.iter_mut().flat_map(|m| &mut m.types) {
+            type_def.provenance = provenances.remove(&type_def.name);
+        }
+        Ok(ir)
     }
 }
 
 impl OpenAPIParser {
     pub fn new() -> Self {
-        Self
+        Self { source: None }
+    }
+
+    /// Record the file path or URL the spec was read from as the
+    /// provenance of its types, instead of the spec's title
+    pub fn with_source(mut self, source: impl Into<String>) -> Self {
+        self.source = Some(source.into());
+        self
     }
 
     #[allow(clippy::only_used_in_recursion)]
-    fn schema_to_type(&self, schema: &Schema) -> Result<Type, ParserError> {
+    fn schema_to_type(&self, schema: &Schema, at: &Provenance) -> Result<Type, ParserError> {
         match &schema.schema_kind {
+            SchemaKind::Type(OpenAPIType::String(string_type))
+                if !string_type.enumeration.is_empty() =>
+            {
+                Ok(Type::Enum(
+                    string_type
+                        .enumeration
+                        .iter()
+                        .flatten()
+                        .map(|v| serde_json::Value::String(v.clone()))
+                        .collect(),
+                ))
+            }
             SchemaKind::Type(OpenAPIType::String(_)) => Ok(Type::String),
             SchemaKind::Type(OpenAPIType::Number(_)) => Ok(Type::Number),
             SchemaKind::Type(OpenAPIType::Integer(_)) => Ok(Type::Integer),
@@ -47,7 +77,7 @@ impl OpenAPIParser {
                     .items
                     .as_ref()
                     .and_then(|i| i.as_item())
-                    .map(|s| self.schema_to_type(s))
+                    .map(|s| self.schema_to_type(s, &at.child("items")))
                     .transpose()?
                     .unwrap_or(Type::Any);
                 Ok(Type::Array(Box::new(item_type)))
@@ -56,7 +86,8 @@ impl OpenAPIParser {
                 let mut fields = BTreeMap::new();
                 for (field_name, field_schema_ref) in &object_type.properties {
                     if let openapiv3::ReferenceOr::Item(field_schema) = field_schema_ref {
-                        let field_type = self.schema_to_type(field_schema)?;
+                        let provenance = at.child("properties").child(field_name);
+                        let field_type = self.schema_to_type(field_schema, &provenance)?;
                         let required = object_type.required.contains(field_name);
                         fields.insert(
                             field_name.clone(),
@@ -65,6 +96,8 @@ impl OpenAPIParser {
                                 required,
                                 description: field_schema.schema_data.description.clone(),
                                 default: None,
+                                read_only: field_schema.schema_data.read_only,
+                                provenance: Some(provenance),
                             },
                         );
                     }
@@ -76,9 +109,10 @@ impl OpenAPIParser {
             }
             SchemaKind::OneOf { one_of } => {
                 let mut types = Vec::new();
-                for schema_ref in one_of {
+                for (i, schema_ref) in one_of.iter().enumerate() {
                     if let openapiv3::ReferenceOr::Item(schema) = schema_ref {
-                        types.push(self.schema_to_type(schema)?);
+                        let at = at.child("oneOf").child(&i.to_string());
+                        types.push(self.schema_to_type(schema, &at)?);
                     }
                 }
                 Ok(Type::Union(types))
@@ -89,16 +123,18 @@ impl OpenAPIParser {
             }
             SchemaKind::AnyOf { any_of } => {
                 let mut types = Vec::new();
-                for schema_ref in any_of {
+                for (i, schema_ref) in any_of.iter().enumerate() {
                     if let openapiv3::ReferenceOr::Item(schema) = schema_ref {
-                        types.push(self.schema_to_type(schema)?);
+                        let at = at.child("anyOf").child(&i.to_string());
+                        types.push(self.schema_to_type(schema, &at)?);
                     }
                 }
                 Ok(Type::Union(types))
             }
-            SchemaKind::Not { .. } => {
-                Err(ParserError::UnsupportedFeature("'not' schema".to_string()))
-            }
+            SchemaKind::Not { .. } => Err(ParserError::UnsupportedFeature(format!(
+                "'not' schema at {}",
+                at
+            ))),
             SchemaKind::Any(_) => Ok(Type::Any),
         }
     }
@@ -0,0 +1,296 @@
+//! Manifest overrides that fix upstream schemas before code generation
+//!
+//! Each override targets a type by `group/version/Kind` and, optionally, a
+//! dotted field path inside it, then applies one JSON-Patch-like operation:
+//!
+//! ```toml
+//! [[packages.overrides]]
+//! target = "s3.aws.upbound.io/v1beta1/Bucket"
+//! path = "spec.forProvider.tags"
+//! op = "replace"
+//! schema = { type = "object", properties = { team = { type = "string" } } }
+//! ```
+
+use crate::{crd::CRDParser, filter::glob_match, ParserError};
+use amalgam_core::{
+    ir::TypeDefinition,
+    types::{Field, Type},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// One change to a generated type
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TypeOverride {
+    /// `group/version/Kind` of the type, with `*` and `?` wildcards
+    pub target: String,
+    /// Dotted field path inside the type; empty addresses the whole type
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub path: String,
+    #[serde(flatten)]
+    pub operation: OverrideOp,
+}
+
+/// Operation an override applies at its path
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum OverrideOp {
+    /// Replace the type at the path with one built from a JSON schema
+    Replace { schema: Value },
+    /// Add a field, named by the last path segment
+    Add {
+        schema: Value,
+        #[serde(default)]
+        required: bool,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        description: Option<String>,
+    },
+    /// Remove the field at the path
+    Remove,
+    /// Mark the field at the path as required
+    Required,
+    /// Mark the field at the path as optional
+    Optional,
+}
+
+impl TypeOverride {
+    /// Whether the override applies to a type of the given group and version
+    pub fn matches(&self, group: &str, version: &str, kind: &str) -> bool {
+        glob_match(&self.target, &format!("{}/{}/{}", group, version, kind))
+    }
+
+    /// Apply the override to a type definition
+    pub fn apply(&self, type_def: &mut TypeDefinition) -> Result<(), ParserError> {
+        let error = |reason: &str| {
+            ParserError::InvalidSchema(format!(
+                "override {} {}: {}",
+                self.target, self.path, reason
+            ))
+        };
+
+        let segments: Vec<&str> = if self.path.is_empty() {
+            Vec::new()
+        } else {
+            self.path.split('.').collect()
+        };
+
+        // Operations on the whole type
+        let Some((last, parents)) = segments.split_last() else {
+            return match &self.operation {
+                OverrideOp::Replace { schema } => {
+                    type_def.ty = schema_to_type(schema)?;
+                    Ok(())
+                }
+                _ => Err(error("only `replace` applies to a whole type")),
+            };
+        };
+
+        let mut ty = &mut type_def.ty;
+        for segment in parents {
+            ty = &mut record_fields(ty)
+                .and_then(|fields| fields.get_mut(*segment))
+                .ok_or_else(|| error(&format!("no field `{}`", segment)))?
+                .ty;
+        }
+        let fields = record_fields(ty).ok_or_else(|| error("parent is not a record"))?;
+
+        let missing = || error(&format!("no field `{}`", last));
+        match &self.operation {
+            OverrideOp::Add {
+                schema,
+                required,
+                description,
+            } => {
+                fields.insert(
+                    last.to_string(),
+                    Field {
+                        ty: schema_to_type(schema)?,
+                        required: *required,
+                        description: description.clone(),
+                        default: None,
+                        read_only: false,
+                        provenance: None,
+                    },
+                );
+            }
+            OverrideOp::Remove => {
+                fields.remove(*last).ok_or_else(missing)?;
+            }
+            OverrideOp::Replace { schema } => {
+                fields.get_mut(*last).ok_or_else(missing)?.ty = schema_to_type(schema)?;
+            }
+            OverrideOp::Required => fields.get_mut(*last).ok_or_else(missing)?.required = true,
+            OverrideOp::Optional => fields.get_mut(*last).ok_or_else(missing)?.required = false,
+        }
+        Ok(())
+    }
+}
+
+/// Apply every matching override to a type definition, in order
+///
+/// Returns how many overrides applied.
+pub fn apply_overrides(
+    overrides: &[TypeOverride],
+    group: &str,
+    version: &str,
+    type_def: &mut TypeDefinition,
+) -> Result<usize, ParserError> {
+    let mut applied = 0;
+    for type_override in overrides {
+        if type_override.matches(group, version, &type_def.name) {
+            type_override.apply(type_def)?;
+            applied += 1;
+        }
+    }
+    Ok(applied)
+}
+
+/// Fields of the record at or inside `ty`
+///
+/// Optional, array and map types are looked through, so a path can address
+/// the fields of list items and map values directly.
+fn record_fields(ty: &mut Type) -> Option<&mut std::collections::BTreeMap<String, Field>> {
+    match ty {
+        Type::Record { fields, .. } => Some(fields),
+        Type::Optional(inner) | Type::Array(inner) => record_fields(inner),
+        Type::Map { value, .. } => record_fields(value),
+        _ => None,
+    }
+}
+
+fn schema_to_type(schema: &Value) -> Result<Type, ParserError> {
+    CRDParser::new().json_schema_to_type(schema)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    fn bucket() -> TypeDefinition {
+        let field = |ty: Type, required: bool| Field {
+            ty,
+            required,
+            description: None,
+            default: None,
+            read_only: false,
+            provenance: None,
+        };
+        TypeDefinition {
+            name: "Bucket".to_string(),
+            ty: Type::Record {
+                fields: BTreeMap::from([(
+                    "spec".to_string(),
+                    field(
+                        Type::Record {
+                            fields: BTreeMap::from([
+                                ("region".to_string(), field(Type::String, true)),
+                                ("tags".to_string(), field(Type::Any, false)),
+                                ("legacy".to_string(), field(Type::String, false)),
+                            ]),
+                            open: false,
+                        },
+                        true,
+                    ),
+                )]),
+                open: false,
+            },
+            documentation: None,
+            annotations: BTreeMap::new(),
+            provenance: None,
+        }
+    }
+
+    fn spec_fields(type_def: &TypeDefinition) -> &BTreeMap<String, Field> {
+        let Type::Record { fields, .. } = &type_def.ty else {
+            panic!("expected a record");
+        };
+        let Type::Record { fields, .. } = &fields["spec"].ty else {
+            panic!("expected a spec record");
+        };
+        fields
+    }
+
+    #[test]
+    fn test_overrides_from_toml() {
+        #[derive(Deserialize)]
+        struct Package {
+            overrides: Vec<TypeOverride>,
+        }
+
+        let package: Package = toml::from_str(
+            r#"
+            [[overrides]]
+            target = "s3.aws.upbound.io/*/Bucket"
+            path = "spec.tags"
+            op = "replace"
+            schema = { type = "object", properties = { team = { type = "string" } } }
+
+            [[overrides]]
+            target = "s3.aws.upbound.io/v1beta1/Bucket"
+            path = "spec.region"
+            op = "optional"
+
+            [[overrides]]
+            target = "s3.aws.upbound.io/v1beta1/Bucket"
+            path = "spec.legacy"
+            op = "remove"
+
+            [[overrides]]
+            target = "s3.aws.upbound.io/v1beta1/Bucket"
+            path = "spec.owner"
+            op = "add"
+            schema = { type = "string" }
+            required = true
+            "#,
+        )
+        .unwrap();
+
+        let mut type_def = bucket();
+        let applied = apply_overrides(
+            &package.overrides,
+            "s3.aws.upbound.io",
+            "v1beta1",
+            &mut type_def,
+        )
+        .unwrap();
+        assert_eq!(applied, 4);
+
+        let spec = spec_fields(&type_def);
+        let Type::Record { fields: tags, .. } = &spec["tags"].ty else {
+            panic!("tags should be replaced by a record");
+        };
+        assert_eq!(tags["team"].ty, Type::String);
+        assert!(!spec["region"].required);
+        assert!(!spec.contains_key("legacy"));
+        assert!(spec["owner"].required);
+        assert_eq!(spec["owner"].ty, Type::String);
+    }
+
+    #[test]
+    fn test_override_errors() {
+        let missing = TypeOverride {
+            target: "*".to_string(),
+            path: "spec.nothing.here".to_string(),
+            operation: OverrideOp::Required,
+        };
+        assert!(missing.apply(&mut bucket()).is_err());
+
+        let whole_type_remove = TypeOverride {
+            target: "*".to_string(),
+            path: String::new(),
+            operation: OverrideOp::Remove,
+        };
+        assert!(whole_type_remove.apply(&mut bucket()).is_err());
+
+        let other_kind = TypeOverride {
+            target: "*/v1/Queue".to_string(),
+            path: String::new(),
+            operation: OverrideOp::Remove,
+        };
+        assert_eq!(
+            apply_overrides(&[other_kind], "sqs.aws", "v1", &mut bucket()).unwrap(),
+            0
+        );
+    }
+}
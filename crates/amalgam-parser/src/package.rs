@@ -1,17 +1,27 @@
 //! Package-based CRD importing (similar to CUE's approach)
 
 use crate::{
+    conversion::{conversions_module, VersionConversion},
     crd::{CRDParser, CRD},
-    imports::{ImportResolver, TypeReference},
+    filter::CrdFilter,
+    imports::{ImportResolver, K8sLayout, TypeReference},
+    input::{input_definition, resource_api_version},
+    overrides::{apply_overrides, TypeOverride},
+    transforms::{apply_transforms, TransformRule},
     ParserError,
 };
 use amalgam_codegen::{
+    nickel::{EnumMode, NickelCodegen},
     nickel_package::{NickelPackageConfig, NickelPackageGenerator, PackageDependency},
+    nickel_render::{lib_module, ResourceKind},
     Codegen,
 };
 use amalgam_core::{
+    dedup::Deduplicator,
     ir::{Import, Module, TypeDefinition, IR},
+    nested::NestedTypeExtractor,
     types::Type,
+    visit::{references, rename_references},
 };
 use std::collections::HashMap;
 use std::path::PathBuf;
@@ -20,6 +30,15 @@ pub struct PackageGenerator {
     crds: Vec<CRD>,
     package_name: String,
     _base_path: PathBuf,
+    skip_deprecated: bool,
+    k8s_layout: K8sLayout,
+    filter: CrdFilter,
+    overrides: Vec<TypeOverride>,
+    transforms: Vec<TransformRule>,
+    deduplicator: Option<Deduplicator>,
+    extractor: Option<NestedTypeExtractor>,
+    source_comments: bool,
+    enum_mode: EnumMode,
 }
 
 impl PackageGenerator {
@@ -28,9 +47,72 @@ impl PackageGenerator {
             crds: Vec::new(),
             package_name,
             _base_path: base_path,
+            skip_deprecated: false,
+            k8s_layout: K8sLayout::default(),
+            filter: CrdFilter::default(),
+            overrides: Vec::new(),
+            transforms: Vec::new(),
+            deduplicator: None,
+            extractor: None,
+            source_comments: false,
+            enum_mode: EnumMode::default(),
         }
     }
 
+    /// Leave versions marked `deprecated` out of the generated package
+    pub fn with_skip_deprecated(mut self, skip: bool) -> Self {
+        self.skip_deprecated = skip;
+        self
+    }
+
+    /// Layout of the k8s-core package that generated imports point into
+    pub fn with_k8s_layout(mut self, layout: K8sLayout) -> Self {
+        self.k8s_layout = layout;
+        self
+    }
+
+    /// Generate only the CRDs and versions the filter selects
+    pub fn with_filter(mut self, filter: CrdFilter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    /// Fix generated types with manifest overrides before code generation
+    pub fn with_overrides(mut self, overrides: Vec<TypeOverride>) -> Self {
+        self.overrides = overrides;
+        self
+    }
+
+    /// Apply transform rules to generated types, after any overrides
+    pub fn with_transforms(mut self, transforms: Vec<TransformRule>) -> Self {
+        self.transforms = transforms;
+        self
+    }
+
+    /// Hoist record shapes repeated across kinds into shared types
+    pub fn with_deduplicator(mut self, deduplicator: Deduplicator) -> Self {
+        self.deduplicator = Some(deduplicator);
+        self
+    }
+
+    /// Lift nested records into named types, after any deduplication
+    pub fn with_nested_type_extractor(mut self, extractor: NestedTypeExtractor) -> Self {
+        self.extractor = Some(extractor);
+        self
+    }
+
+    /// Comment each generated type and field with the CRD location it came from
+    pub fn with_source_comments(mut self, enabled: bool) -> Self {
+        self.source_comments = enabled;
+        self
+    }
+
+    /// Generate enums as checked strings or as enum tags
+    pub fn with_enum_mode(mut self, mode: EnumMode) -> Self {
+        self.enum_mode = mode;
+        self
+    }
+
     pub fn add_crd(&mut self, crd: CRD) {
         self.crds.push(crd);
     }
@@ -57,25 +139,30 @@ impl PackageGenerator {
     /// ```
     pub fn generate_package(&self) -> Result<NamespacedPackage, ParserError> {
         let mut package = NamespacedPackage::new(self.package_name.clone());
+        package.k8s_layout = self.k8s_layout;
+        package.source_comments = self.source_comments;
+        package.enum_mode = self.enum_mode;
 
         // Group CRDs by group, version, and kind
         for crd in &self.crds {
             let group = &crd.spec.group;
             let kind_lowercase = crd.spec.names.kind.to_lowercase();
             let _kind_original = crd.spec.names.kind.clone();
+            let mut version_types = HashMap::new();
 
-            for version in &crd.spec.versions {
-                if !version.served {
-                    continue; // Skip non-served versions
-                }
-
+            // Only served versions selected by the filter, without skipped
+            // deprecated ones
+            for version in self.filter.select_versions(crd, self.skip_deprecated) {
                 // Parse the CRD for this specific version
                 let parser = CRDParser::new();
                 let ir = parser.parse_version(crd, &version.name)?;
 
                 // Extract the type definition for this version
-                if let Some(module) = ir.modules.first() {
-                    for type_def in &module.types {
+                if let Some(module) = ir.modules.into_iter().next() {
+                    for mut type_def in module.types {
+                        apply_overrides(&self.overrides, group, &version.name, &mut type_def)?;
+                        apply_transforms(&self.transforms, group, &version.name, &mut type_def);
+
                         // Store by group/version/kind structure with lowercase key
                         // but preserve original casing in the type definition
                         package.add_type(
@@ -84,11 +171,40 @@ impl PackageGenerator {
                             kind_lowercase.clone(),
                             type_def.clone(),
                         );
+                        version_types.insert(version.name.clone(), type_def.ty.clone());
+                    }
+                }
+            }
+
+            // Convert every other served version to the storage version
+            if let Some(storage) = crd.spec.versions.iter().find(|v| v.storage) {
+                if let Some(storage_ty) = version_types.get(&storage.name) {
+                    for version in &crd.spec.versions {
+                        if version.name == storage.name {
+                            continue;
+                        }
+                        if let Some(ty) = version_types.get(&version.name) {
+                            package.add_conversion(VersionConversion::new(
+                                group.clone(),
+                                crd.spec.names.kind.clone(),
+                                version.name.clone(),
+                                ty,
+                                storage.name.clone(),
+                                storage_ty,
+                            ));
+                        }
                     }
                 }
             }
         }
 
+        if let Some(deduplicator) = &self.deduplicator {
+            package.deduplicate(deduplicator);
+        }
+        if let Some(extractor) = &self.extractor {
+            package.extract_nested_types(extractor);
+        }
+
         Ok(package)
     }
 }
@@ -98,6 +214,14 @@ pub struct NamespacedPackage {
     pub name: String,
     /// group -> version -> kind -> TypeDefinition
     pub types: HashMap<String, HashMap<String, HashMap<String, TypeDefinition>>>,
+    /// group -> conversions between versions of its kinds
+    pub conversions: HashMap<String, Vec<VersionConversion>>,
+    /// Layout of the k8s-core package that imports point into
+    pub k8s_layout: K8sLayout,
+    /// Whether generated files comment types and fields with their source
+    pub source_comments: bool,
+    /// How enums are represented in generated files
+    pub enum_mode: EnumMode,
 }
 
 impl NamespacedPackage {
@@ -105,6 +229,10 @@ impl NamespacedPackage {
         Self {
             name,
             types: HashMap::new(),
+            conversions: HashMap::new(),
+            k8s_layout: K8sLayout::default(),
+            source_comments: false,
+            enum_mode: EnumMode::default(),
         }
     }
 
@@ -123,6 +251,95 @@ impl NamespacedPackage {
             .insert(kind, type_def);
     }
 
+    /// Hoist record shapes repeated across the package's kinds into shared
+    /// types, returning how many were created
+    ///
+    /// Each shared type is placed in the group and version of the first kind
+    /// that used it and referenced as `group/version.Name`.
+    pub fn deduplicate(&mut self, deduplicator: &Deduplicator) -> usize {
+        let mut keys = Vec::new();
+        let mut definitions = Vec::new();
+        for group in self.groups() {
+            for version in self.versions(&group) {
+                for kind in self.kinds(&group, &version) {
+                    definitions.push(self.types[&group][&version][&kind].clone());
+                    keys.push((group.clone(), version.clone(), kind));
+                }
+            }
+        }
+
+        let shared = deduplicator.deduplicate(&mut definitions, |name, users| {
+            let (group, version, _) = &keys[users[0]];
+            format!("{}/{}.{}", group, version, name)
+        });
+
+        for ((group, version, kind), definition) in keys.iter().zip(definitions) {
+            self.add_type(group.clone(), version.clone(), kind.clone(), definition);
+        }
+        for shared_type in &shared {
+            let (group, version, _) = &keys[shared_type.users[0]];
+            self.add_type(
+                group.clone(),
+                version.clone(),
+                shared_type.definition.name.to_lowercase(),
+                shared_type.definition.clone(),
+            );
+        }
+        shared.len()
+    }
+
+    /// Lift nested records into named types in the same group and version,
+    /// returning how many were created
+    ///
+    /// Extracted types are referenced as `group/version.Name` and written
+    /// to their own files like any other kind.
+    pub fn extract_nested_types(&mut self, extractor: &NestedTypeExtractor) -> usize {
+        let mut count = 0;
+        for group in self.groups() {
+            for version in self.versions(&group) {
+                let kinds = self.kinds(&group, &version);
+                let mut definitions: Vec<_> = kinds
+                    .iter()
+                    .map(|kind| self.types[&group][&version][kind].clone())
+                    .collect();
+
+                let extracted = extractor.extract(&mut definitions, |name| {
+                    format!("{}/{}.{}", group, version, name)
+                });
+
+                for (kind, definition) in kinds.into_iter().zip(definitions) {
+                    self.add_type(group.clone(), version.clone(), kind, definition);
+                }
+                count += extracted.len();
+                for definition in extracted {
+                    let kind = definition.name.to_lowercase();
+                    self.add_type(group.clone(), version.clone(), kind, definition);
+                }
+            }
+        }
+        count
+    }
+
+    /// Resolve a `group/version.Name` reference to a type in this package
+    fn local_type(&self, reference: &str) -> Option<(&str, &str, String)> {
+        let (group, rest) = reference.split_once('/')?;
+        let (version, name) = rest.split_once('.')?;
+        let kind = name.to_lowercase();
+        let (group, versions) = self.types.get_key_value(group)?;
+        let (version, kinds) = versions.get_key_value(version)?;
+        kinds
+            .get(&kind)
+            .filter(|type_def| type_def.name == name)
+            .map(|_| (group.as_str(), version.as_str(), kind))
+    }
+
+    pub fn add_conversion(&mut self, conversion: VersionConversion) {
+        self.conversions
+            .entry(conversion.group.clone())
+            .or_default()
+            .push(conversion);
+    }
+
     /// Generate the main module file
     pub fn generate_main_module(&self) -> String {
         let mut content = String::new();
@@ -147,6 +364,33 @@ impl NamespacedPackage {
         content
     }
 
+    /// Generate the `lib.ncl` rendering helpers, indexing the contracts of
+    /// the package's resource kinds
+    pub fn generate_lib_module(&self) -> String {
+        let mut kinds = Vec::new();
+        for group in self.groups() {
+            for version in self.versions(&group) {
+                for kind in self.kinds(&group, &version) {
+                    let type_def = &self.types[&group][&version][&kind];
+                    let Some(api_version) = resource_api_version(type_def) else {
+                        continue;
+                    };
+                    let contract = input_definition(type_def)
+                        .map_or_else(|| type_def.name.clone(), |input| input.name);
+                    kinds.push(ResourceKind {
+                        api_version: api_version.to_string(),
+                        kind: type_def.name.clone(),
+                        contract: format!(
+                            "(import \"./{}/{}/{}.ncl\").{}",
+                            group, version, kind, contract
+                        ),
+                    });
+                }
+            }
+        }
+        lib_module(&self.name, &kinds)
+    }
+
     /// Generate a group-level module file
     pub fn generate_group_module(&self, group: &str) -> Option<String> {
         self.types.get(group).map(|versions| {
@@ -166,6 +410,10 @@ impl NamespacedPackage {
                 ));
             }
 
+            if self.conversions.contains_key(group) {
+                content.push_str("  conversions = import \"./conversions.ncl\",\n");
+            }
+
             content.push_str("}\n");
             content
         })
@@ -192,6 +440,24 @@ impl NamespacedPackage {
                         capitalize_first(kind)
                     };
                     content.push_str(&format!("  {} = import \"./{}.ncl\",\n", type_name, kind));
+                    if let Some(input) = kinds.get(kind).and_then(input_definition) {
+                        content.push_str(&format!(
+                            "  {} = (import \"./{}.ncl\").{},\n",
+                            input.name, kind, input.name
+                        ));
+                        if let Some(api_version) = kinds.get(kind).and_then(resource_api_version) {
+                            content.push_str(&format!(
+                                "  mk_{} = {},\n",
+                                type_name,
+                                NickelCodegen::new().constructor(
+                                    api_version,
+                                    &type_name,
+                                    &input.name,
+                                    1
+                                )
+                            ));
+                        }
+                    }
                 }
 
                 content.push_str("}\n");
@@ -200,6 +466,14 @@ impl NamespacedPackage {
         })
     }
 
+    /// Generate the conversions file for a group, if any of its kinds have
+    /// more than one version
+    pub fn generate_conversions_module(&self, group: &str) -> Option<String> {
+        self.conversions
+            .get(group)
+            .map(|conversions| conversions_module(group, conversions))
+    }
+
     /// Generate a kind-specific file
     pub fn generate_kind_file(&self, group: &str, version: &str, kind: &str) -> Option<String> {
         self.types.get(group).and_then(|versions| {
@@ -215,18 +489,42 @@ impl NamespacedPackage {
                         metadata: Default::default(),
                     };
 
-                    // Analyze the type for external references and add imports
-                    let mut import_resolver = ImportResolver::new();
-                    import_resolver.analyze_type(&type_def.ty);
-
                     // Build a mapping from full qualified names to alias.TypeName
                     let mut reference_mappings: HashMap<String, String> = HashMap::new();
+                    let mut import_resolver = ImportResolver::new();
+
+                    // Shared types hoisted into this package are imported by
+                    // relative path rather than resolved as external types
+                    for reference in references(&type_def.ty) {
+                        let Some((to_group, to_version, to_kind)) = self.local_type(&reference)
+                        else {
+                            continue;
+                        };
+                        let import_path = if to_group == group && to_version == version {
+                            format!("./{}.ncl", to_kind)
+                        } else {
+                            format!("../../{}/{}/{}.ncl", to_group, to_version, to_kind)
+                        };
+                        let alias = format!("import_{}", module.imports.len());
+                        let name = reference.rsplit('.').next().unwrap_or(&reference);
+                        reference_mappings.insert(reference.clone(), format!("{}.{}", alias, name));
+                        import_resolver.add_local_type(&reference);
+                        module.imports.push(Import {
+                            path: import_path,
+                            alias: Some(alias),
+                            items: vec![],
+                        });
+                    }
+
+                    // Analyze the type for external references and add imports
+                    import_resolver.analyze_type(&type_def.ty);
 
                     // Group references by their import path to avoid duplicates
                     let mut imports_by_path: HashMap<String, Vec<TypeReference>> = HashMap::new();
 
                     for type_ref in import_resolver.references() {
-                        let import_path = type_ref.import_path(group, version);
+                        let import_path =
+                            type_ref.import_path_with_layout(group, version, self.k8s_layout);
                         imports_by_path
                             .entry(import_path)
                             .or_default()
@@ -251,23 +549,7 @@ impl NamespacedPackage {
                         // Create mappings for all types from this import
                         for type_ref in &type_refs {
                             // Build the full qualified name that appears in Type::Reference
-                            let full_name = if type_ref.group == "k8s.io" {
-                                // For k8s types, construct the full io.k8s... name
-                                if type_ref.kind == "ObjectMeta" || type_ref.kind == "ListMeta" {
-                                    format!(
-                                        "io.k8s.apimachinery.pkg.apis.meta.{}.{}",
-                                        type_ref.version, type_ref.kind
-                                    )
-                                } else {
-                                    format!(
-                                        "io.k8s.api.core.{}.{}",
-                                        type_ref.version, type_ref.kind
-                                    )
-                                }
-                            } else {
-                                // For other types, use a simpler format
-                                format!("{}/{}.{}", type_ref.group, type_ref.version, type_ref.kind)
-                            };
+                            let full_name = type_ref.qualified_name();
 
                             // Map to alias.TypeName
                             let mapped_name = format!("{}.{}", alias, type_ref.kind);
@@ -290,10 +572,19 @@ impl NamespacedPackage {
 
                     // Transform the type definition to use the mapped references
                     let mut transformed_type_def = type_def.clone();
-                    transform_type_references(&mut transformed_type_def.ty, &reference_mappings);
+                    rename_references(&mut transformed_type_def.ty, |name| {
+                        reference_mappings.get(name).cloned()
+                    });
 
-                    // Use the transformed type definition
+                    // Use the transformed type definition, followed by its
+                    // status-stripped input variant for resources
                     module.types = vec![transformed_type_def];
+                    if let Some(mut input) = input_definition(type_def) {
+                        rename_references(&mut input.ty, |name| {
+                            reference_mappings.get(name).cloned()
+                        });
+                        module.types.push(input);
+                    }
 
                     tracing::debug!(
                         "Module {} has {} imports",
@@ -325,8 +616,10 @@ impl NamespacedPackage {
                     }
                     package_mode.analyze_and_update_dependencies(&all_types, group);
 
-                    let mut codegen = amalgam_codegen::nickel::NickelCodegen::new()
-                        .with_package_mode(package_mode);
+                    let mut codegen = NickelCodegen::new()
+                        .with_package_mode(package_mode)
+                        .with_source_comments(self.source_comments)
+                        .with_enum_mode(self.enum_mode);
                     let mut generated = codegen
                         .generate(&ir)
                         .unwrap_or_else(|e| format!("# Error generating type: {}\n", e));
@@ -379,6 +672,50 @@ impl NamespacedPackage {
             .unwrap_or_default()
     }
 
+    /// Deprecated API versions included in the package
+    ///
+    /// One entry per kind, e.g. `example.io/v1alpha1 Widget: use v1 instead`.
+    pub fn deprecations(&self) -> Vec<String> {
+        let mut notices = Vec::new();
+        for group in self.groups() {
+            for version in self.versions(&group) {
+                for kind in self.kinds(&group, &version) {
+                    let type_def = &self.types[&group][&version][&kind];
+                    if let Some(notice) = type_def.deprecation_notice() {
+                        notices.push(format!(
+                            "{}/{} {}: {}",
+                            group, version, type_def.name, notice
+                        ));
+                    }
+                }
+            }
+        }
+        notices
+    }
+
+    /// Collect the package's types into an IR bundle
+    ///
+    /// Each kind becomes its own module named `Kind.version.group`, matching
+    /// the modules produced by [`CRDParser`].
+    pub fn to_ir(&self) -> IR {
+        let mut ir = IR::new();
+        for group in self.groups() {
+            for version in self.versions(&group) {
+                for kind in self.kinds(&group, &version) {
+                    let type_def = &self.types[&group][&version][&kind];
+                    ir.add_module(Module {
+                        name: format!("{}.{}.{}", type_def.name, version, group),
+                        imports: Vec::new(),
+                        types: vec![type_def.clone()],
+                        constants: Vec::new(),
+                        metadata: Default::default(),
+                    });
+                }
+            }
+        }
+        ir
+    }
+
     /// Generate a Nickel package manifest (Nickel-pkg.ncl)
     pub fn generate_nickel_manifest(&self, config: Option<NickelPackageConfig>) -> String {
         let config = config.unwrap_or_else(|| NickelPackageConfig {
@@ -470,55 +807,18 @@ fn capitalize_first(s: &str) -> String {
     }
 }
 
-/// Transform Type::Reference values using the provided mappings
-fn transform_type_references(ty: &mut Type, mappings: &HashMap<String, String>) {
-    match ty {
-        Type::Reference(name) => {
-            // Check if we have a mapping for this reference
-            if let Some(mapped) = mappings.get(name) {
-                *name = mapped.clone();
-            }
-        }
-        Type::Array(inner) => transform_type_references(inner, mappings),
-        Type::Optional(inner) => transform_type_references(inner, mappings),
-        Type::Map { value, .. } => transform_type_references(value, mappings),
-        Type::Record { fields, .. } => {
-            for field in fields.values_mut() {
-                transform_type_references(&mut field.ty, mappings);
-            }
-        }
-        Type::Union(types) => {
-            for ty in types {
-                transform_type_references(ty, mappings);
-            }
-        }
-        Type::TaggedUnion { variants, .. } => {
-            for variant_type in variants.values_mut() {
-                transform_type_references(variant_type, mappings);
-            }
-        }
-        _ => {} // Other types don't contain references
-    }
-}
-
 // Alias for tests
 #[allow(dead_code)]
 fn capitalize(s: &str) -> String {
     capitalize_first(s)
 }
 
-#[allow(dead_code)]
 fn needs_k8s_imports(ty: &Type) -> bool {
     // Check if the type references k8s.io types
     // This is a simplified check - would need more sophisticated analysis
-    match ty {
-        Type::Reference(name) => name.contains("k8s.io") || name.contains("ObjectMeta"),
-        Type::Record { fields, .. } => fields.values().any(|field| needs_k8s_imports(&field.ty)),
-        Type::Array(inner) => needs_k8s_imports(inner),
-        Type::Optional(inner) => needs_k8s_imports(inner),
-        Type::Union(types) => types.iter().any(needs_k8s_imports),
-        _ => false,
-    }
+    references(ty)
+        .iter()
+        .any(|name| name.contains("k8s.io") || name.contains("ObjectMeta"))
 }
 
 #[cfg(test)]
@@ -531,6 +831,7 @@ mod tests {
         CRD {
             api_version: "apiextensions.k8s.io/v1".to_string(),
             kind: "CustomResourceDefinition".to_string(),
+            source: None,
             metadata: CRDMetadata {
                 name: format!("{}.{}", kind.to_lowercase(), group),
             },
@@ -545,6 +846,8 @@ mod tests {
                     name: version.to_string(),
                     served: true,
                     storage: true,
+                    deprecated: false,
+                    deprecation_warning: None,
                     schema: Some(CRDSchema {
                         openapi_v3_schema: serde_json::json!({
                             "type": "object",
@@ -577,6 +880,168 @@ mod tests {
         assert!(package.groups().contains(&"example.io".to_string()));
     }
 
+    #[test]
+    fn test_package_to_ir() {
+        let mut generator =
+            PackageGenerator::new("test-package".to_string(), PathBuf::from("/tmp/test"));
+        generator.add_crd(sample_crd("example.io", "v1", "Widget"));
+
+        let ir = generator.generate_package().unwrap().to_ir();
+
+        assert_eq!(ir.modules.len(), 1);
+        assert_eq!(ir.modules[0].name, "Widget.v1.example.io");
+        assert!(ir.find_type("Widget").is_some());
+    }
+
+    #[test]
+    fn test_package_filter() {
+        use crate::filter::FilterRule;
+
+        let mut generator =
+            PackageGenerator::new("test-package".to_string(), PathBuf::from("/tmp/test"))
+                .with_filter(CrdFilter {
+                    exclude: vec![FilterRule {
+                        kind: Some("Gadget*".to_string()),
+                        ..Default::default()
+                    }],
+                    ..Default::default()
+                });
+        generator.add_crd(sample_crd("example.io", "v1", "Widget"));
+        generator.add_crd(sample_crd("example.io", "v1", "GadgetPolicy"));
+
+        let package = generator.generate_package().unwrap();
+        assert_eq!(
+            package.kinds("example.io", "v1"),
+            vec!["widget".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_package_overrides() {
+        use crate::overrides::OverrideOp;
+
+        let mut generator =
+            PackageGenerator::new("test-package".to_string(), PathBuf::from("/tmp/test"))
+                .with_overrides(vec![
+                    TypeOverride {
+                        target: "example.io/v1/Widget".to_string(),
+                        path: "spec.field1".to_string(),
+                        operation: OverrideOp::Required,
+                    },
+                    TypeOverride {
+                        target: "example.io/*/Widget".to_string(),
+                        path: "spec.field2".to_string(),
+                        operation: OverrideOp::Remove,
+                    },
+                ]);
+        generator.add_crd(sample_crd("example.io", "v1", "Widget"));
+
+        let package = generator.generate_package().unwrap();
+        let widget = &package.types["example.io"]["v1"]["widget"];
+        let Type::Record { fields, .. } = &widget.ty else {
+            panic!("Widget should be a record");
+        };
+        let Type::Record { fields: spec, .. } = &fields["spec"].ty else {
+            panic!("spec should be a record");
+        };
+        assert!(spec["field1"].required);
+        assert!(!spec.contains_key("field2"));
+    }
+
+    #[test]
+    fn test_package_transforms_follow_overrides() {
+        use crate::overrides::OverrideOp;
+        use crate::transforms::{NameCase, RuleAction};
+
+        let mut generator =
+            PackageGenerator::new("test-package".to_string(), PathBuf::from("/tmp/test"))
+                .with_overrides(vec![TypeOverride {
+                    target: "example.io/v1/Widget".to_string(),
+                    path: "spec.field1".to_string(),
+                    operation: OverrideOp::Required,
+                }])
+                .with_transforms(vec![TransformRule {
+                    target: "*".to_string(),
+                    field: "spec.*".to_string(),
+                    action: RuleAction::Rename {
+                        to: "{name}Value".to_string(),
+                        case: Some(NameCase::Kebab),
+                    },
+                }]);
+        generator.add_crd(sample_crd("example.io", "v1", "Widget"));
+
+        let package = generator.generate_package().unwrap();
+        let widget = &package.types["example.io"]["v1"]["widget"];
+        let Type::Record { fields, .. } = &widget.ty else {
+            panic!("Widget should be a record");
+        };
+        let Type::Record { fields: spec, .. } = &fields["spec"].ty else {
+            panic!("spec should be a record");
+        };
+        assert!(spec["field1-value"].required);
+        assert!(spec.contains_key("field2-value"));
+    }
+
+    #[test]
+    fn test_package_nested_type_extraction() {
+        let mut generator =
+            PackageGenerator::new("test-package".to_string(), PathBuf::from("/tmp/test"))
+                .with_nested_type_extractor(NestedTypeExtractor::new());
+        generator.add_crd(sample_crd("example.io", "v1", "Widget"));
+
+        let package = generator.generate_package().unwrap();
+        assert_eq!(
+            package.kinds("example.io", "v1"),
+            vec!["widget".to_string(), "widgetspec".to_string()]
+        );
+        assert_eq!(
+            package.types["example.io"]["v1"]["widgetspec"].name,
+            "WidgetSpec"
+        );
+
+        let widget = package
+            .generate_kind_file("example.io", "v1", "widget")
+            .unwrap();
+        assert!(widget.contains("import \"./widgetspec.ncl\""));
+        let module = package.generate_version_module("example.io", "v1").unwrap();
+        assert!(module.contains("WidgetSpec = import \"./widgetspec.ncl\""));
+    }
+
+    #[test]
+    fn test_package_deduplication() {
+        let mut generator =
+            PackageGenerator::new("test-package".to_string(), PathBuf::from("/tmp/test"))
+                .with_deduplicator(Deduplicator::new());
+        generator.add_crd(sample_crd("example.io", "v1", "Widget"));
+        generator.add_crd(sample_crd("other.io", "v1alpha1", "Gadget"));
+
+        let package = generator.generate_package().unwrap();
+        assert_eq!(
+            package.kinds("example.io", "v1"),
+            vec!["spec".to_string(), "widget".to_string()]
+        );
+
+        let Type::Record { fields, .. } = &package.types["other.io"]["v1alpha1"]["gadget"].ty
+        else {
+            panic!("Gadget should be a record");
+        };
+        assert_eq!(
+            fields["spec"].ty,
+            Type::Reference("example.io/v1.Spec".to_string())
+        );
+
+        let gadget = package
+            .generate_kind_file("other.io", "v1alpha1", "gadget")
+            .unwrap();
+        assert!(gadget.contains("import \"../../example.io/v1/spec.ncl\""));
+        assert!(gadget.contains("import_0.Spec"));
+
+        let widget = package
+            .generate_kind_file("example.io", "v1", "widget")
+            .unwrap();
+        assert!(widget.contains("import \"./spec.ncl\""));
+    }
+
     #[test]
     fn test_sanitize_name_function() {
         assert_eq!(super::sanitize_name("some-name"), "some_name");
@@ -0,0 +1,374 @@
+//! Declarative transform rules applied to generated types
+//!
+//! Where overrides fix one field of one upstream type, transform rules
+//! enforce a convention across every type they target, matching fields by
+//! name or dotted path:
+//!
+//! ```toml
+//! # Drop status from every kind
+//! [[config.transforms]]
+//! field = "status"
+//! action = "remove"
+//!
+//! # Make every `*Ref` field use the team's shared reference type
+//! [[packages.transforms]]
+//! target = "*.crossplane.io/*/*"
+//! field = "*Ref"
+//! action = "reference"
+//! reference = "common/v1.ObjectRef"
+//!
+//! # Rename fields to snake_case
+//! [[config.transforms]]
+//! field = "spec.*"
+//! action = "rename"
+//! case = "snake"
+//! ```
+
+use crate::filter::glob_match;
+use amalgam_core::{
+    ir::TypeDefinition,
+    types::{Field, Type},
+    visit::{rebuild_type, Fold, PathSegment, TypePath},
+};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// A change applied to every matching field of the types a rule targets
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TransformRule {
+    /// `group/version/Kind` of the types, with `*` and `?` wildcards
+    #[serde(default = "match_all")]
+    pub target: String,
+    /// Glob over field names, or over dotted field paths such as
+    /// `spec.containers[].image` when it contains a `.`
+    pub field: String,
+    #[serde(flatten)]
+    pub action: RuleAction,
+}
+
+fn match_all() -> String {
+    "*".to_string()
+}
+
+/// What a rule does to the fields it matches
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "lowercase")]
+pub enum RuleAction {
+    /// Drop the field
+    Remove,
+    /// Rename the field from the `to` template, in which `{name}` is the
+    /// current name, then convert it to `case` if given
+    Rename {
+        #[serde(default = "name_placeholder")]
+        to: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        case: Option<NameCase>,
+    },
+    /// Replace the field's type with a reference to another type
+    Reference { reference: String },
+    /// Additionally check the field's value against a contract
+    Contract { contract: String },
+    /// Mark the field as required
+    Required,
+    /// Mark the field as optional
+    Optional,
+}
+
+fn name_placeholder() -> String {
+    "{name}".to_string()
+}
+
+/// Naming convention for renamed fields
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NameCase {
+    /// `forProvider` -> `for_provider`
+    Snake,
+    /// `for_provider` -> `forProvider`
+    Camel,
+    /// `forProvider` -> `for-provider`
+    Kebab,
+}
+
+impl NameCase {
+    pub fn convert(self, name: &str) -> String {
+        let words = words(name);
+        match self {
+            NameCase::Snake => words.join("_"),
+            NameCase::Kebab => words.join("-"),
+            NameCase::Camel => {
+                let mut result = String::new();
+                for (i, word) in words.iter().enumerate() {
+                    let mut chars = word.chars();
+                    match chars.next() {
+                        Some(first) if i > 0 => {
+                            result.extend(first.to_uppercase());
+                            result.push_str(chars.as_str());
+                        }
+                        _ => result.push_str(word),
+                    }
+                }
+                result
+            }
+        }
+    }
+}
+
+/// Lowercase words of a camelCase, snake_case or kebab-case name
+fn words(name: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut current = String::new();
+    let mut previous_lower = false;
+    for c in name.chars() {
+        if c == '_' || c == '-' {
+            if !current.is_empty() {
+                words.push(std::mem::take(&mut current));
+            }
+            previous_lower = false;
+            continue;
+        }
+        if c.is_uppercase() && previous_lower {
+            words.push(std::mem::take(&mut current));
+        }
+        previous_lower = c.is_lowercase() || c.is_ascii_digit();
+        current.extend(c.to_lowercase());
+    }
+    if !current.is_empty() {
+        words.push(current);
+    }
+    words
+}
+
+impl TransformRule {
+    /// Whether the rule applies to a type of the given group and version
+    pub fn matches(&self, group: &str, version: &str, kind: &str) -> bool {
+        glob_match(&self.target, &format!("{}/{}/{}", group, version, kind))
+    }
+
+    /// Whether the rule applies to the field at `path` within its type
+    fn matches_field(&self, path: &TypePath) -> bool {
+        let Some(name) = path.field_name() else {
+            return false;
+        };
+        if self.field.contains('.') {
+            glob_match(&self.field, &path.to_string())
+        } else {
+            glob_match(&self.field, name)
+        }
+    }
+}
+
+/// Apply every rule targeting a type definition, in order
+///
+/// Rules match fields by their name and path before any rule renamed them.
+/// Returns how many times a rule applied to a field.
+pub fn apply_transforms(
+    rules: &[TransformRule],
+    group: &str,
+    version: &str,
+    type_def: &mut TypeDefinition,
+) -> usize {
+    let mut transform = Transform {
+        rules: rules
+            .iter()
+            .filter(|rule| rule.matches(group, version, &type_def.name))
+            .collect(),
+        applied: 0,
+    };
+    if transform.rules.is_empty() {
+        return 0;
+    }
+
+    let ty = std::mem::replace(&mut type_def.ty, Type::Any);
+    type_def.ty = transform.fold_type(ty, &TypePath::default());
+    transform.applied
+}
+
+struct Transform<'a> {
+    rules: Vec<&'a TransformRule>,
+    applied: usize,
+}
+
+impl Fold for Transform<'_> {
+    fn fold_type(&mut self, ty: Type, path: &TypePath) -> Type {
+        match rebuild_type(self, ty, path) {
+            Type::Record { fields, open } => Type::Record {
+                fields: self.transform_fields(fields, path),
+                open,
+            },
+            ty => ty,
+        }
+    }
+}
+
+impl Transform<'_> {
+    fn transform_fields(
+        &mut self,
+        fields: BTreeMap<String, Field>,
+        path: &TypePath,
+    ) -> BTreeMap<String, Field> {
+        let mut result = BTreeMap::new();
+        'fields: for (original, mut field) in fields {
+            let field_path = path.child(PathSegment::Field(original.clone()));
+            let mut name = original.clone();
+            for rule in &self.rules {
+                if !rule.matches_field(&field_path) {
+                    continue;
+                }
+                self.applied += 1;
+                match &rule.action {
+                    RuleAction::Remove => continue 'fields,
+                    RuleAction::Rename { to, case } => {
+                        name = to.replace("{name}", &name);
+                        if let Some(case) = case {
+                            name = case.convert(&name);
+                        }
+                    }
+                    RuleAction::Reference { reference } => {
+                        field.ty = Type::Reference(reference.clone())
+                    }
+                    RuleAction::Contract { contract } => {
+                        field.ty = Type::Contract {
+                            base: Box::new(field.ty),
+                            predicate: contract.clone(),
+                        }
+                    }
+                    RuleAction::Required => field.required = true,
+                    RuleAction::Optional => field.required = false,
+                }
+            }
+            result.insert(name, field);
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn field(ty: Type) -> Field {
+        Field {
+            ty,
+            required: false,
+            description: None,
+            default: None,
+            read_only: false,
+            provenance: None,
+        }
+    }
+
+    fn record(fields: Vec<(&str, Field)>) -> Type {
+        Type::Record {
+            fields: fields
+                .into_iter()
+                .map(|(name, field)| (name.to_string(), field))
+                .collect(),
+            open: false,
+        }
+    }
+
+    fn bucket() -> TypeDefinition {
+        let for_provider = record(vec![
+            ("providerConfigRef", field(Type::Any)),
+            ("objectLockEnabled", field(Type::Bool)),
+        ]);
+        TypeDefinition {
+            name: "Bucket".to_string(),
+            ty: record(vec![
+                (
+                    "spec",
+                    field(record(vec![
+                        ("forProvider", field(for_provider)),
+                        ("writeConnectionSecretToRef", field(Type::Any)),
+                    ])),
+                ),
+                ("status", field(record(vec![("ready", field(Type::Bool))]))),
+            ]),
+            documentation: None,
+            annotations: BTreeMap::new(),
+            provenance: None,
+        }
+    }
+
+    fn fields(ty: &Type) -> &BTreeMap<String, Field> {
+        let Type::Record { fields, .. } = ty else {
+            panic!("expected a record");
+        };
+        fields
+    }
+
+    #[test]
+    fn test_rules_from_toml() {
+        #[derive(Deserialize)]
+        struct Config {
+            transforms: Vec<TransformRule>,
+        }
+
+        let config: Config = toml::from_str(
+            r#"
+            [[transforms]]
+            field = "status"
+            action = "remove"
+
+            [[transforms]]
+            target = "s3.aws.upbound.io/*/*"
+            field = "*Ref"
+            action = "reference"
+            reference = "common/v1.ObjectRef"
+
+            [[transforms]]
+            field = "spec.forProvider.*"
+            action = "rename"
+            case = "snake"
+
+            [[transforms]]
+            target = "*/*/Queue"
+            field = "spec"
+            action = "required"
+            "#,
+        )
+        .unwrap();
+
+        let mut bucket = bucket();
+        let applied = apply_transforms(
+            &config.transforms,
+            "s3.aws.upbound.io",
+            "v1beta1",
+            &mut bucket,
+        );
+        assert_eq!(applied, 5);
+
+        let top = fields(&bucket.ty);
+        assert!(!top.contains_key("status"));
+        assert!(!top["spec"].required);
+        let spec = fields(&top["spec"].ty);
+        assert_eq!(
+            spec["writeConnectionSecretToRef"].ty,
+            Type::Reference("common/v1.ObjectRef".to_string())
+        );
+        let for_provider = fields(&spec["forProvider"].ty);
+        assert_eq!(
+            for_provider.keys().collect::<Vec<_>>(),
+            vec!["object_lock_enabled", "provider_config_ref"]
+        );
+        assert_eq!(
+            for_provider["provider_config_ref"].ty,
+            Type::Reference("common/v1.ObjectRef".to_string())
+        );
+    }
+
+    #[test]
+    fn test_name_cases() {
+        assert_eq!(NameCase::Snake.convert("forProvider"), "for_provider");
+        assert_eq!(
+            NameCase::Kebab.convert("objectLockEnabled"),
+            "object-lock-enabled"
+        );
+        assert_eq!(
+            NameCase::Camel.convert("object_lock_enabled"),
+            "objectLockEnabled"
+        );
+        assert_eq!(NameCase::Snake.convert("tls-config"), "tls_config");
+    }
+}
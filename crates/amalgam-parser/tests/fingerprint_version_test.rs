@@ -10,12 +10,16 @@ fn test_k8s_version_change_triggers_fingerprint_difference() {
         version: "v1.31.0".to_string(),
         openapi_spec: "same_content".to_string(),
         spec_url: "https://dl.k8s.io/v1.31.0/api/openapi-spec/swagger.json".to_string(),
+        etag: None,
+        last_modified: None,
     };
 
     let source_v1_33 = K8sCoreSource {
         version: "v1.33.4".to_string(),
         openapi_spec: "same_content".to_string(),
         spec_url: "https://dl.k8s.io/v1.33.4/api/openapi-spec/swagger.json".to_string(),
+        etag: None,
+        last_modified: None,
     };
 
     // Create fingerprints
@@ -52,12 +56,16 @@ fn test_url_git_ref_change_triggers_fingerprint_difference() {
             "https://github.com/crossplane/crossplane/tree/v1.17.2/cluster/crds".to_string(),
         ],
         contents: vec!["same_content".to_string()],
+        etags: Vec::new(),
+        last_modified: Vec::new(),
     };
 
     let source_v2 = UrlSource {
         base_url: "https://github.com/crossplane/crossplane/tree/v2.0.2/cluster/crds".to_string(),
         urls: vec!["https://github.com/crossplane/crossplane/tree/v2.0.2/cluster/crds".to_string()],
         contents: vec!["same_content".to_string()],
+        etags: Vec::new(),
+        last_modified: Vec::new(),
     };
 
     // Create fingerprints
@@ -91,12 +99,16 @@ fn test_same_version_no_change() {
         version: "v1.33.4".to_string(),
         openapi_spec: "same_content".to_string(),
         spec_url: "https://dl.k8s.io/v1.33.4/api/openapi-spec/swagger.json".to_string(),
+        etag: None,
+        last_modified: None,
     };
 
     let source2 = K8sCoreSource {
         version: "v1.33.4".to_string(),
         openapi_spec: "same_content".to_string(),
         spec_url: "https://dl.k8s.io/v1.33.4/api/openapi-spec/swagger.json".to_string(),
+        etag: None,
+        last_modified: None,
     };
 
     // Create fingerprints
@@ -134,12 +146,16 @@ fn test_metadata_only_change_detected() {
         version: "v1.31.0".to_string(),
         openapi_spec: "identical_spec_content".to_string(),
         spec_url: "https://dl.k8s.io/v1.31.0/api/openapi-spec/swagger.json".to_string(),
+        etag: None,
+        last_modified: None,
     };
 
     let source_new = K8sCoreSource {
         version: "v1.33.4".to_string(),
         openapi_spec: "identical_spec_content".to_string(), // Same content!
         spec_url: "https://dl.k8s.io/v1.33.4/api/openapi-spec/swagger.json".to_string(),
+        etag: None,
+        last_modified: None,
     };
 
     let fingerprint_old = source_old
@@ -126,6 +126,8 @@ fn test_reference_resolution_to_alias() {
             ty: Type::Reference("io.k8s.apimachinery.pkg.apis.meta.v1.ObjectMeta".to_string()),
             required: false,
             default: None,
+            read_only: false,
+            provenance: None,
             description: Some("Standard Kubernetes metadata".to_string()),
         },
     );
@@ -145,6 +147,7 @@ fn test_reference_resolution_to_alias() {
             },
             documentation: None,
             annotations: BTreeMap::new(),
+            provenance: None,
         }],
         constants: vec![],
         metadata: Default::default(),
@@ -241,6 +244,8 @@ fn test_case_insensitive_type_matching() {
             ty: Type::Reference("io.k8s.apimachinery.pkg.apis.meta.v1.ObjectMeta".to_string()),
             required: false,
             default: None,
+            read_only: false,
+            provenance: None,
             description: None,
         },
     );
@@ -261,6 +266,7 @@ fn test_case_insensitive_type_matching() {
             },
             documentation: None,
             annotations: BTreeMap::new(),
+            provenance: None,
         }],
         constants: vec![],
         metadata: Default::default(),
@@ -1,6 +1,7 @@
 //! Integration tests for amalgam-parser
 
 use amalgam_codegen::Codegen;
+use amalgam_core::nested::NestedTypeExtractor;
 use amalgam_parser::{
     crd::{CRDParser, CRD},
     package::PackageGenerator,
@@ -434,6 +435,298 @@ spec:
         .is_some());
 }
 
+#[test]
+fn test_multi_version_conversions() {
+    let crd_yaml = r#"
+apiVersion: apiextensions.k8s.io/v1
+kind: CustomResourceDefinition
+metadata:
+  name: graduated.test.io
+spec:
+  group: test.io
+  names:
+    kind: Graduated
+    plural: graduateds
+    singular: graduated
+  versions:
+  - name: v1alpha1
+    served: true
+    storage: false
+    deprecated: true
+    schema:
+      openAPIV3Schema:
+        type: object
+        properties:
+          spec:
+            type: object
+            properties:
+              replicas:
+                type: integer
+              imageName:
+                type: string
+              legacyMode:
+                type: object
+                additionalProperties: true
+  - name: v1
+    served: true
+    storage: true
+    schema:
+      openAPIV3Schema:
+        type: object
+        properties:
+          spec:
+            type: object
+            properties:
+              replicas:
+                type: integer
+              image:
+                type: string
+"#;
+
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let mut generator =
+        PackageGenerator::new("graduation-test".to_string(), temp_dir.path().to_path_buf());
+    generator.add_crd(load_test_crd(crd_yaml));
+    let package = generator
+        .generate_package()
+        .expect("Failed to generate package");
+
+    let group_mod = package.generate_group_module("test.io").unwrap();
+    assert!(group_mod.contains("conversions = import \"./conversions.ncl\""));
+
+    let conversions = package
+        .generate_conversions_module("test.io")
+        .expect("Should generate conversions");
+    assert!(conversions.contains("v1alpha1_to_v1 = fun source =>"));
+    assert!(conversions.contains("# renamed: spec.imageName -> spec.image"));
+    assert!(conversions.contains("# dropped: spec.legacyMode"));
+    assert!(
+        conversions.contains("{ from = [\"spec\", \"replicas\"], to = [\"spec\", \"replicas\"] }")
+    );
+
+    // Version lifecycle is documented on the generated types
+    let alpha = package
+        .generate_kind_file("test.io", "v1alpha1", "graduated")
+        .unwrap();
+    assert!(alpha.contains("# Graduated test.io/v1alpha1 (served, deprecated)"));
+    assert!(alpha.contains("# Storage version: v1"));
+    let stable = package
+        .generate_kind_file("test.io", "v1", "graduated")
+        .unwrap();
+    assert!(stable.contains("# Graduated test.io/v1 (served, storage version)"));
+}
+
+#[test]
+fn test_deprecated_and_unserved_versions() {
+    let crd_yaml = r#"
+apiVersion: apiextensions.k8s.io/v1
+kind: CustomResourceDefinition
+metadata:
+  name: aging.test.io
+spec:
+  group: test.io
+  names:
+    kind: Aging
+    plural: agings
+    singular: aging
+  versions:
+  - name: v1alpha1
+    served: false
+    storage: false
+    schema:
+      openAPIV3Schema:
+        type: object
+  - name: v1beta1
+    served: true
+    storage: false
+    deprecated: true
+    deprecationWarning: "test.io/v1beta1 Aging is deprecated; use test.io/v1"
+    schema:
+      openAPIV3Schema:
+        type: object
+        properties:
+          spec:
+            type: object
+  - name: v1
+    served: true
+    storage: true
+    schema:
+      openAPIV3Schema:
+        type: object
+        properties:
+          spec:
+            type: object
+"#;
+    let crd = load_test_crd(crd_yaml);
+
+    // Unserved versions are never generated; deprecated ones are annotated
+    let ir = CRDParser::new().parse(crd.clone()).unwrap();
+    let names: Vec<_> = ir.modules.iter().map(|m| m.name.as_str()).collect();
+    assert_eq!(names, vec!["Aging.v1beta1.test.io", "Aging.v1.test.io"]);
+    assert_eq!(
+        ir.modules[0].metadata.custom.get("deprecated"),
+        Some(&serde_json::Value::Bool(true))
+    );
+    assert_eq!(
+        ir.modules[0].types[0].deprecation_notice().as_deref(),
+        Some("test.io/v1beta1 Aging is deprecated; use test.io/v1")
+    );
+    assert_eq!(ir.modules[1].types[0].deprecation_notice(), None);
+
+    let ir = CRDParser::new()
+        .with_skip_deprecated(true)
+        .parse(crd.clone())
+        .unwrap();
+    assert_eq!(ir.modules.len(), 1);
+
+    let temp_dir = TempDir::new().unwrap();
+    let mut generator =
+        PackageGenerator::new("aging-test".to_string(), temp_dir.path().to_path_buf());
+    generator.add_crd(crd.clone());
+    let package = generator.generate_package().unwrap();
+    assert_eq!(
+        package.deprecations(),
+        vec!["test.io/v1beta1 Aging: test.io/v1beta1 Aging is deprecated; use test.io/v1"]
+    );
+    let content = package
+        .generate_kind_file("test.io", "v1beta1", "aging")
+        .unwrap();
+    assert!(content.contains("# DEPRECATED: test.io/v1beta1 Aging is deprecated; use test.io/v1"));
+
+    let mut generator =
+        PackageGenerator::new("aging-test".to_string(), temp_dir.path().to_path_buf())
+            .with_skip_deprecated(true);
+    generator.add_crd(crd);
+    let package = generator.generate_package().unwrap();
+    assert_eq!(package.versions("test.io"), vec!["v1"]);
+    assert!(package.deprecations().is_empty());
+}
+
+#[test]
+fn test_input_variants_omit_status_and_read_only_fields() {
+    let crd_yaml = r#"
+apiVersion: apiextensions.k8s.io/v1
+kind: CustomResourceDefinition
+metadata:
+  name: databases.test.io
+spec:
+  group: test.io
+  names:
+    kind: Database
+    plural: databases
+    singular: database
+  versions:
+  - name: v1
+    served: true
+    storage: true
+    schema:
+      openAPIV3Schema:
+        type: object
+        properties:
+          metadata:
+            type: object
+          spec:
+            type: object
+            properties:
+              engine:
+                type: string
+              connectionSecret:
+                type: string
+                readOnly: true
+          status:
+            type: object
+            properties:
+              ready:
+                type: boolean
+"#;
+
+    let temp_dir = TempDir::new().unwrap();
+    let mut generator =
+        PackageGenerator::new("input-test".to_string(), temp_dir.path().to_path_buf());
+    generator.add_crd(load_test_crd(crd_yaml));
+    let package = generator.generate_package().unwrap();
+
+    let version_mod = package.generate_version_module("test.io", "v1").unwrap();
+    assert!(version_mod.contains("DatabaseInput = (import \"./database.ncl\").DatabaseInput,"));
+
+    let content = package
+        .generate_kind_file("test.io", "v1", "database")
+        .unwrap();
+    let input = &content[content.find("DatabaseInput = {").expect("input variant")..];
+    assert!(input.contains("engine"));
+    assert!(!input.contains("connectionSecret"));
+    assert!(!input.contains("status"));
+    assert!(!input.contains("resourceVersion"));
+    assert!(input.contains("labels"));
+}
+
+#[test]
+fn test_version_module_exports_constructors() {
+    let crd_yaml = r#"
+apiVersion: apiextensions.k8s.io/v1
+kind: CustomResourceDefinition
+metadata:
+  name: caches.test.io
+spec:
+  group: test.io
+  names:
+    kind: Cache
+    plural: caches
+    singular: cache
+  versions:
+  - name: v1
+    served: true
+    storage: true
+    schema:
+      openAPIV3Schema:
+        type: object
+        properties:
+          apiVersion:
+            type: string
+          kind:
+            type: string
+          metadata:
+            type: object
+          spec:
+            type: object
+            properties:
+              size:
+                type: integer
+                default: 1
+"#;
+
+    let temp_dir = TempDir::new().unwrap();
+    let mut generator = PackageGenerator::new(
+        "constructor-test".to_string(),
+        temp_dir.path().to_path_buf(),
+    )
+    .with_nested_type_extractor(NestedTypeExtractor::new());
+    generator.add_crd(load_test_crd(crd_yaml));
+    let package = generator.generate_package().unwrap();
+    assert_eq!(
+        package.types["test.io"]["v1"]["cache"].api_version(),
+        Some("test.io/v1")
+    );
+
+    let version_mod = package.generate_version_module("test.io", "v1").unwrap();
+    assert!(version_mod.contains(
+        "  mk_Cache = fun { name, ..rest } =>\n    ({ apiVersion = \"test.io/v1\", kind = \"Cache\", metadata.name = name } & rest)\n    | CacheInput,\n"
+    ));
+    // Extracted nested types are not resource kinds
+    assert!(version_mod.contains("CacheSpec = import"));
+    assert!(!version_mod.contains("mk_CacheSpec"));
+
+    let spec = package
+        .generate_kind_file("test.io", "v1", "cachespec")
+        .unwrap();
+    assert!(spec.contains("default = 1"));
+
+    let lib = package.generate_lib_module();
+    assert!(lib.contains(
+        "    \"test.io/v1\" = {\n      Cache = (import \"./test.io/v1/cache.ncl\").CacheInput,\n    },\n"
+    ));
+}
+
 #[test]
 fn test_crd_with_validation_rules() {
     let crd_yaml = r#"
@@ -496,3 +789,58 @@ spec:
     // Note: Actual validation constraints would need to be implemented
     // in the code generator to be properly tested here
 }
+
+#[test]
+fn test_provenance_is_recorded_and_commented() {
+    let crd_yaml = r#"
+apiVersion: apiextensions.k8s.io/v1
+kind: CustomResourceDefinition
+metadata:
+  name: widgets.test.io
+spec:
+  group: test.io
+  names:
+    kind: Widget
+    plural: widgets
+    singular: widget
+  versions:
+  - name: v1alpha1
+    served: true
+    storage: false
+    schema:
+      openAPIV3Schema:
+        type: object
+  - name: v1
+    served: true
+    storage: true
+    schema:
+      openAPIV3Schema:
+        type: object
+        properties:
+          spec:
+            type: object
+            properties:
+              size:
+                type: integer
+"#;
+
+    let crd = load_test_crd(crd_yaml).with_source("crds/widgets.yaml");
+    let ir = CRDParser::new().parse_version(&crd, "v1").unwrap();
+    let type_def = &ir.modules[0].types[0];
+    assert_eq!(
+        type_def.provenance.as_ref().unwrap().to_string(),
+        "crds/widgets.yaml#/spec/versions/1/schema/openAPIV3Schema"
+    );
+
+    let temp_dir = TempDir::new().unwrap();
+    let mut generator = PackageGenerator::new("widgets".to_string(), temp_dir.path().to_path_buf())
+        .with_source_comments(true);
+    generator.add_crd(crd);
+    let package = generator.generate_package().unwrap();
+    let content = package
+        .generate_kind_file("test.io", "v1", "widget")
+        .unwrap();
+    assert!(content.contains(
+        "# Source: crds/widgets.yaml#/spec/versions/1/schema/openAPIV3Schema/properties/spec/properties/size"
+    ));
+}
@@ -11,6 +11,7 @@ fn sample_crd() -> CRD {
     CRD {
         api_version: "apiextensions.k8s.io/v1".to_string(),
         kind: "CustomResourceDefinition".to_string(),
+        source: None,
         metadata: CRDMetadata {
             name: "compositions.apiextensions.crossplane.io".to_string(),
         },
@@ -25,6 +26,8 @@ fn sample_crd() -> CRD {
                 name: "v1".to_string(),
                 served: true,
                 storage: true,
+                deprecated: false,
+                deprecation_warning: None,
                 schema: Some(CRDSchema {
                     openapi_v3_schema: serde_json::json!({
                         "type": "object",
@@ -379,6 +379,10 @@
 
               echo ""
               echo "2. Running tests..."
+              # The Nickel evaluation tests need the CLI and must not be skipped
+              export PATH="${nickel-with-packages}/bin:$PATH"
+              unset AMALGAM_SKIP_NICKEL_TESTS
+              nickel --version
               ${rustWithComponents}/bin/cargo test --workspace
 
               echo ""